@@ -1,13 +1,17 @@
 // SPDX-License-Identifier: GPL-3.0-or-later
 // Copyright (C) 2023 Robert Hrusecky <jadedpastabowl@gmail.com>
 
-use std::{f32, num::Wrapping, ops::AddAssign};
+use std::{f32, mem, num::Wrapping, ops::AddAssign};
 
 use bilge::prelude::*;
+use log::debug;
+use serde::{Deserialize, Serialize};
+
+use crate::logging;
 
 use self::{
     noise::NoiseChannel,
-    pulse::{NoSweep, PulseChannel, Sweeper},
+    pulse::{NoSweep, Nrx1, PulseChannel, Sweeper},
     wave::WaveChannel,
 };
 
@@ -16,7 +20,32 @@ mod pulse;
 mod wave;
 
 pub trait ApuBus {
+    /// DIV as the frame sequencer sees it, which in double speed is shifted right by one so that
+    /// it keeps the same pace.
     fn div(&self) -> u8;
+    /// The level on the cartridge's VIN pin, from -1 to 1, which NR50 can mix into either output.
+    /// Nothing drives it unless the cartridge or a peripheral has sound of its own.
+    fn vin(&self) -> f32 {
+        0.0
+    }
+}
+
+/// One of the APU's four sound channels, for muting.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AudioChannel {
+    Pulse1 = 0,
+    Pulse2,
+    Wave,
+    Noise,
+}
+
+impl AudioChannel {
+    pub const ALL: [AudioChannel; 4] = [
+        AudioChannel::Pulse1,
+        AudioChannel::Pulse2,
+        AudioChannel::Wave,
+        AudioChannel::Noise,
+    ];
 }
 
 trait Channel {
@@ -41,7 +70,7 @@ trait PeriodDividerRegs {
     }
 }
 
-#[derive(Default)]
+#[derive(Default, Serialize, Deserialize)]
 struct PeriodDivider {
     div: Wrapping<u16>,
 }
@@ -51,6 +80,16 @@ impl PeriodDivider {
         self.div.0 = regs.period();
     }
 
+    /// Holds off the next step by `cycles` more clocks.
+    fn delay(&mut self, cycles: u16) {
+        self.div += cycles;
+    }
+
+    /// Whether the next clock steps the wave.
+    fn expiring(&self) -> bool {
+        self.div.0 == 1
+    }
+
     fn clock(&mut self, regs: &impl PeriodDividerRegs, wave: impl FnOnce()) {
         self.div -= 1;
         if self.div.0 == 0 {
@@ -69,7 +108,7 @@ trait LengthTimerRegs {
     fn enabled(&self) -> bool;
 }
 
-#[derive(Default)]
+#[derive(Default, Serialize, Deserialize)]
 struct LengthTimer<R: LengthTimerRegs> {
     timer: R::Timer,
 }
@@ -91,7 +130,7 @@ impl<R: LengthTimerRegs> LengthTimer<R> {
     }
 }
 
-#[derive(Default)]
+#[derive(Default, Serialize, Deserialize)]
 struct Envelope {
     volume: u8,
     increase: bool,
@@ -121,7 +160,8 @@ impl Envelope {
 }
 
 #[bitsize(8)]
-#[derive(Default, FromBits, DebugBits, Clone, Copy)]
+#[derive(Default, FromBits, DebugBits, Clone, Copy, Serialize, Deserialize)]
+#[serde(from = "u8", into = "u8")]
 struct Nrx2 {
     sweep_pace: u3,
     increase_envelope: bool,
@@ -141,7 +181,8 @@ impl From<Nrx2> for Envelope {
 }
 
 #[bitsize(8)]
-#[derive(Default, FromBits, DebugBits, Clone, Copy)]
+#[derive(Default, FromBits, DebugBits, Clone, Copy, Serialize, Deserialize)]
+#[serde(from = "u8", into = "u8")]
 struct Nrx4 {
     period_high: u3,
     __: u3,
@@ -150,7 +191,8 @@ struct Nrx4 {
 }
 
 #[bitsize(8)]
-#[derive(Default, FromBits, DebugBits, Clone, Copy)]
+#[derive(Default, FromBits, DebugBits, Clone, Copy, Serialize, Deserialize)]
+#[serde(from = "u8", into = "u8")]
 struct Nr50 {
     vol_right: u3,
     vin_right: bool,
@@ -168,7 +210,8 @@ struct MixerBits {
 }
 
 #[bitsize(8)]
-#[derive(Default, FromBits, DebugBits, Clone, Copy)]
+#[derive(Default, FromBits, DebugBits, Clone, Copy, Serialize, Deserialize)]
+#[serde(from = "u8", into = "u8")]
 struct Nr51 {
     right: MixerBits,
     left: MixerBits,
@@ -185,26 +228,15 @@ struct Nr52 {
     sound_enabled: bool,
 }
 
-#[derive(Default)]
-struct RisingEdgeDetector {
-    edge_seen: bool,
-}
-
-impl RisingEdgeDetector {
-    fn at_edge(&mut self, signal: bool) -> bool {
-        let edge = !self.edge_seen && signal;
-        self.edge_seen = signal;
-        edge
-    }
-}
-
-#[derive(Default)]
+/// The frame sequencer, which steps on each falling edge of bit 4 of [`ApuBus::div`]. Writing DIV
+/// or switching speeds resets the divider, which is a falling edge too if the bit was set. Games
+/// that write DIV often can hold the sequencer back entirely.
+#[derive(Default, Serialize, Deserialize)]
 struct DivCounter {
     last: u8,
     counter: Wrapping<u8>,
-    length: RisingEdgeDetector,
-    envelope: RisingEdgeDetector,
-    sweep: RisingEdgeDetector,
+    /// The step run by the latest clock, if any
+    step: Option<u8>,
 }
 
 impl DivCounter {
@@ -212,35 +244,61 @@ impl DivCounter {
 
     fn clock(&mut self, bus: &mut impl ApuBus) {
         let div = bus.div();
-        if !div & self.last & Self::MASK != 0 {
+        self.step = (!div & self.last & Self::MASK != 0).then(|| {
+            let step = self.counter.0 & 0x7;
             self.counter += 1;
-        }
+            step
+        });
         self.last = div;
     }
 
-    fn length_clock(&mut self) -> bool {
-        self.length.at_edge(self.counter.0 & 0x01 == 0)
+    fn length_clock(&self) -> bool {
+        self.step.is_some_and(|step| step & 0x1 == 0)
     }
 
-    fn envelope_clock(&mut self) -> bool {
-        self.envelope.at_edge(self.counter.0 & 0x7 == 0x7)
+    fn envelope_clock(&self) -> bool {
+        self.step == Some(0x7)
     }
 
-    fn sweep_clock(&mut self) -> bool {
-        self.sweep.at_edge(self.counter.0 & 0x3 == 0x2)
+    fn sweep_clock(&self) -> bool {
+        self.step.is_some_and(|step| step & 0x3 == 0x2)
     }
 }
 
-fn dac(enabled: bool, (input, volume): (u8, u8)) -> f32 {
+/// How long the DACs take to ramp up to full output after the APU is powered on, in machine
+/// cycles (about 4 ms)
+const DAC_RAMP_CYCLES: u16 = 4096;
+
+/// Eases the DACs' output in after the APU is powered on. They jump straight to their DC offset
+/// otherwise, which pops when the game starts playing sound. Games that turn DACs on and off while
+/// the APU is on get them at full output right away, like on hardware.
+#[derive(Default, Serialize, Deserialize)]
+struct DacRamp {
+    cycles: u16,
+}
+
+impl DacRamp {
+    fn clock(&mut self) {
+        if self.cycles < DAC_RAMP_CYCLES {
+            self.cycles += 1;
+        }
+    }
+
+    fn gain(&self) -> f32 {
+        self.cycles as f32 / DAC_RAMP_CYCLES as f32
+    }
+}
+
+fn dac(enabled: bool, ramp: &DacRamp, (input, volume): (u8, u8)) -> f32 {
     if enabled {
-        (volume as i8 - input as i8 * 2) as f32 / 15.0
+        (volume as i8 - input as i8 * 2) as f32 / 15.0 * ramp.gain()
     } else {
         0.0
     }
 }
 
-fn mixer(bits: MixerBits, ch1: f32, ch2: f32, ch3: f32, ch4: f32) -> f32 {
-    let mut out = 0.0;
+fn mixer(bits: MixerBits, ch1: f32, ch2: f32, ch3: f32, ch4: f32, vin: Option<f32>) -> f32 {
+    let mut out = vin.unwrap_or(0.0);
 
     if bits.channel_1() {
         out += ch1;
@@ -258,7 +316,7 @@ fn mixer(bits: MixerBits, ch1: f32, ch2: f32, ch3: f32, ch4: f32) -> f32 {
     out / 4.0
 }
 
-#[derive(Default)]
+#[derive(Default, Serialize, Deserialize)]
 pub struct Apu {
     nr50: Nr50,
     nr51: Nr51,
@@ -268,25 +326,42 @@ pub struct Apu {
     ch3: WaveChannel,
     ch4: NoiseChannel,
     enabled: bool,
+    /// Starts over with everything else when the APU is powered off
+    dac_ramp: DacRamp,
+    /// On the DMG, the length timers can still be loaded while the APU is powered off
+    dmg: bool,
+    /// Belongs to the user rather than the emulated hardware, so it isn't part of save states
+    #[serde(skip)]
+    muted: [bool; 4],
 }
 
 impl Apu {
+    pub fn new(dmg: bool) -> Self {
+        Self {
+            dmg,
+            ..Default::default()
+        }
+    }
+
+    // Unused bits and write-only registers read back as 1s. While the APU is powered off, every
+    // register but NR52 ignores writes.
+
     pub fn nr10(&self) -> u8 {
-        self.ch1.sweeper.nr10.into()
+        0x80 | u8::from(self.ch1.sweeper.nr10)
     }
 
     pub fn set_nr10(&mut self, nr10: u8) {
-        self.ch1.sweeper.nr10 = nr10.into();
+        if self.enabled {
+            self.ch1.sweeper.nr10 = nr10.into();
+        }
     }
 
     pub fn nr11(&self) -> u8 {
-        let mut nrx1 = self.ch1.regs.nrx1;
-        nrx1.set_initial_length_timer(Default::default());
-        nrx1.into()
+        0x3f | u8::from(self.ch1.regs.nrx1)
     }
 
     pub fn set_nr11(&mut self, nr11: u8) {
-        self.ch1.regs.nrx1 = nr11.into();
+        Self::set_nrx1(&mut self.ch1.regs.nrx1, nr11, self.enabled, self.dmg);
     }
 
     pub fn nr12(&self) -> u8 {
@@ -294,35 +369,37 @@ impl Apu {
     }
 
     pub fn set_nr12(&mut self, nr12: u8) {
-        self.ch1.regs.nrx2 = nr12.into();
+        if self.enabled {
+            self.ch1.regs.nrx2 = nr12.into();
+        }
     }
 
     pub fn nr13(&self) -> u8 {
-        self.ch1.regs.nrx3
+        0xff
     }
 
     pub fn set_nr13(&mut self, nr13: u8) {
-        self.ch1.regs.nrx3 = nr13;
+        if self.enabled {
+            self.ch1.regs.nrx3 = nr13;
+        }
     }
 
     pub fn nr14(&self) -> u8 {
-        let mut nrx4 = Nrx4::default();
-        nrx4.set_sound_length_enabled(self.ch1.regs.nrx4.sound_length_enabled());
-        nrx4.into()
+        Self::nrx4(self.ch1.regs.nrx4)
     }
 
     pub fn set_nr14(&mut self, nr14: u8) {
-        self.ch1.regs.nrx4 = nr14.into();
+        if self.enabled {
+            self.ch1.regs.nrx4 = nr14.into();
+        }
     }
 
     pub fn nr21(&self) -> u8 {
-        let mut nrx1 = self.ch1.regs.nrx1;
-        nrx1.set_initial_length_timer(Default::default());
-        nrx1.into()
+        0x3f | u8::from(self.ch2.regs.nrx1)
     }
 
     pub fn set_nr21(&mut self, nr21: u8) {
-        self.ch2.regs.nrx1 = nr21.into();
+        Self::set_nrx1(&mut self.ch2.regs.nrx1, nr21, self.enabled, self.dmg);
     }
 
     pub fn nr22(&self) -> u8 {
@@ -330,72 +407,94 @@ impl Apu {
     }
 
     pub fn set_nr22(&mut self, nr22: u8) {
-        self.ch2.regs.nrx2 = nr22.into();
+        if self.enabled {
+            self.ch2.regs.nrx2 = nr22.into();
+        }
     }
 
     pub fn nr23(&self) -> u8 {
-        self.ch2.regs.nrx3
+        0xff
     }
 
     pub fn set_nr23(&mut self, nr23: u8) {
-        self.ch2.regs.nrx3 = nr23;
+        if self.enabled {
+            self.ch2.regs.nrx3 = nr23;
+        }
     }
 
     pub fn nr24(&self) -> u8 {
-        let mut nrx4 = Nrx4::default();
-        nrx4.set_sound_length_enabled(self.ch2.regs.nrx4.sound_length_enabled());
-        nrx4.into()
+        Self::nrx4(self.ch2.regs.nrx4)
     }
 
     pub fn set_nr24(&mut self, nr24: u8) {
-        self.ch2.regs.nrx4 = nr24.into();
+        if self.enabled {
+            self.ch2.regs.nrx4 = nr24.into();
+        }
     }
 
     pub fn nr30(&self) -> u8 {
-        self.ch3.regs.nr30.into()
+        0x7f | u8::from(self.ch3.regs.nr30)
     }
 
     pub fn set_nr30(&mut self, nr30: u8) {
-        self.ch3.regs.nr30 = nr30.into();
-        self.ch3.enabled &= self.ch3.dac_enabled();
+        if self.enabled {
+            self.ch3.regs.nr30 = nr30.into();
+            self.ch3.enabled &= self.ch3.dac_enabled();
+        }
     }
 
     pub fn nr31(&self) -> u8 {
-        self.ch3.regs.nr31
+        0xff
     }
 
     pub fn set_nr31(&mut self, nr31: u8) {
-        self.ch3.regs.nr31 = nr31;
+        if self.enabled || self.dmg {
+            self.ch3.regs.nr31 = nr31;
+        }
     }
 
     pub fn nr32(&self) -> u8 {
-        self.ch3.regs.nr32.into()
+        0x9f | u8::from(self.ch3.regs.nr32)
     }
 
     pub fn set_nr32(&mut self, nr32: u8) {
-        self.ch3.regs.nr32 = nr32.into();
+        if self.enabled {
+            self.ch3.regs.nr32 = nr32.into();
+        }
     }
 
     pub fn nr33(&self) -> u8 {
-        self.ch3.regs.nr33
+        0xff
     }
 
     pub fn set_nr33(&mut self, nr33: u8) {
-        self.ch3.regs.nr33 = nr33;
+        if self.enabled {
+            self.ch3.regs.nr33 = nr33;
+        }
     }
 
     pub fn nr34(&self) -> u8 {
-        let mut nr34 = Nrx4::default();
-        nr34.set_sound_length_enabled(self.ch3.regs.nr34.sound_length_enabled());
-        nr34.into()
+        Self::nrx4(self.ch3.regs.nr34)
     }
 
     pub fn set_nr34(&mut self, nr34: u8) {
-        self.ch3.regs.nr34 = nr34.into();
+        if self.enabled {
+            self.ch3.regs.nr34 = nr34.into();
+            if self.dmg && self.ch3.regs.nr34.trigger() {
+                self.ch3.retrigger_corruption();
+            }
+        }
+    }
+
+    pub fn nr41(&self) -> u8 {
+        0xff
     }
 
     pub fn set_nr41(&mut self, nr41: u8) {
-        self.ch4.regs.nr41 = nr41.into();
+        // The top two bits are unused, so the whole register is the length
+        if self.enabled || self.dmg {
+            self.ch4.regs.nr41 = nr41.into();
+        }
     }
 
     pub fn nr42(&self) -> u8 {
@@ -403,7 +502,9 @@ impl Apu {
     }
 
     pub fn set_nr42(&mut self, nr42: u8) {
-        self.ch4.regs.nr42 = nr42.into();
+        if self.enabled {
+            self.ch4.regs.nr42 = nr42.into();
+        }
     }
 
     pub fn nr43(&self) -> u8 {
@@ -411,21 +512,25 @@ impl Apu {
     }
 
     pub fn set_nr43(&mut self, nr43: u8) {
-        self.ch4.regs.nr43 = nr43.into();
+        if self.enabled {
+            self.ch4.regs.nr43 = nr43.into();
+        }
     }
 
     pub fn nr44(&self) -> u8 {
-        let mut nrx4 = Nrx4::default();
-        nrx4.set_sound_length_enabled(self.ch4.regs.nr44.sound_length_enabled());
-        nrx4.into()
+        Self::nrx4(self.ch4.regs.nr44)
     }
 
     pub fn set_nr44(&mut self, nr44: u8) {
-        self.ch4.regs.nr44 = nr44.into();
+        if self.enabled {
+            self.ch4.regs.nr44 = nr44.into();
+        }
     }
 
     pub fn set_nr50(&mut self, nr50: u8) {
-        self.nr50 = nr50.into();
+        if self.enabled {
+            self.nr50 = nr50.into();
+        }
     }
 
     pub fn nr50(&self) -> u8 {
@@ -433,7 +538,9 @@ impl Apu {
     }
 
     pub fn set_nr51(&mut self, nr51: u8) {
-        self.nr51 = nr51.into();
+        if self.enabled {
+            self.nr51 = nr51.into();
+        }
     }
 
     pub fn nr51(&self) -> u8 {
@@ -442,6 +549,27 @@ impl Apu {
 
     pub fn set_nr52(&mut self, nr52: u8) {
         let nr52 = Nr52::from(nr52);
+        if self.enabled != nr52.sound_enabled() {
+            debug!(target: logging::APU, "Sound enabled: {}", nr52.sound_enabled());
+        }
+        if self.enabled && !nr52.sound_enabled() {
+            // Powering off clears every register, and the frame sequencer starts over. Only wave
+            // RAM survives, and on the DMG, the length timers too.
+            let old = mem::replace(
+                self,
+                Apu {
+                    muted: self.muted,
+                    ..Self::new(self.dmg)
+                },
+            );
+            self.ch3.wave_ram = old.ch3.wave_ram;
+            if self.dmg {
+                self.ch1.keep_length(old.ch1);
+                self.ch2.keep_length(old.ch2);
+                self.ch3.keep_length(old.ch3);
+                self.ch4.keep_length(old.ch4);
+            }
+        }
         self.enabled = nr52.sound_enabled();
     }
 
@@ -452,7 +580,21 @@ impl Apu {
         nr52.set_channel_2_enabled(self.ch2.enabled());
         nr52.set_channel_3_enabled(self.ch3.enabled());
         nr52.set_channel_4_enabled(self.ch4.enabled());
-        nr52.into()
+        0x70 | u8::from(nr52)
+    }
+
+    /// Only the length enable bit of NRx4 can be read back.
+    fn nrx4(nrx4: impl Into<u8>) -> u8 {
+        0xbf | (nrx4.into() & 0x40)
+    }
+
+    /// Writes NR11 or NR21. While powered off, a DMG still takes the length but not the duty.
+    fn set_nrx1(nrx1: &mut Nrx1, val: u8, enabled: bool, dmg: bool) {
+        if enabled {
+            *nrx1 = val.into();
+        } else if dmg {
+            nrx1.set_initial_length_timer(Nrx1::from(val).initial_length_timer());
+        }
     }
 
     pub fn read_wave_ram(&self, addr: u16) -> u8 {
@@ -463,14 +605,53 @@ impl Apu {
         self.ch3.wave_ram[self.ch3.wave_ram_access_offset(addr)] = val;
     }
 
-    fn frame(&self) -> [f32; 2] {
-        let ch1 = dac(self.ch1.dac_enabled(), self.ch1.sample());
-        let ch2 = dac(self.ch2.dac_enabled(), self.ch2.sample());
-        let ch3 = dac(self.ch3.dac_enabled(), self.ch3.sample());
-        let ch4 = dac(self.ch4.dac_enabled(), self.ch4.sample());
+    pub fn channel_muted(&self, channel: AudioChannel) -> bool {
+        self.muted[channel as usize]
+    }
+
+    /// Silences `channel` in the output without affecting how it runs.
+    pub fn set_channel_muted(&mut self, channel: AudioChannel, muted: bool) {
+        self.muted[channel as usize] = muted;
+    }
+
+    pub fn muted_channels(&self) -> [bool; 4] {
+        self.muted
+    }
 
-        let mut left = mixer(self.nr51.left(), ch1, ch2, ch3, ch4);
-        let mut right = mixer(self.nr51.right(), ch1, ch2, ch3, ch4);
+    pub fn set_muted_channels(&mut self, muted: [bool; 4]) {
+        self.muted = muted;
+    }
+
+    fn frame(&self, vin: f32) -> [f32; 2] {
+        let mut samples = [
+            dac(self.ch1.dac_enabled(), &self.dac_ramp, self.ch1.sample()),
+            dac(self.ch2.dac_enabled(), &self.dac_ramp, self.ch2.sample()),
+            dac(self.ch3.dac_enabled(), &self.dac_ramp, self.ch3.sample()),
+            dac(self.ch4.dac_enabled(), &self.dac_ramp, self.ch4.sample()),
+        ];
+        for (sample, muted) in samples.iter_mut().zip(self.muted) {
+            if muted {
+                *sample = 0.0;
+            }
+        }
+        let [ch1, ch2, ch3, ch4] = samples;
+
+        let mut left = mixer(
+            self.nr51.left(),
+            ch1,
+            ch2,
+            ch3,
+            ch4,
+            self.nr50.vin_left().then_some(vin),
+        );
+        let mut right = mixer(
+            self.nr51.right(),
+            ch1,
+            ch2,
+            ch3,
+            ch4,
+            self.nr50.vin_right().then_some(vin),
+        );
 
         left *= ((self.nr50.vol_left().value() + 1) as f32) / 8.0 / 4.0;
         right *= ((self.nr50.vol_right().value() + 1) as f32) / 8.0 / 4.0;
@@ -480,7 +661,6 @@ impl Apu {
 
     pub fn execute(&mut self, bus: &mut impl ApuBus) -> [[f32; 2]; 2] {
         if !self.enabled {
-            *self = Default::default();
             return [[0.0, 0.0], [0.0, 0.0]];
         }
 
@@ -491,6 +671,8 @@ impl Apu {
         self.ch3.clock();
         self.ch4.clock();
 
+        self.dac_ramp.clock();
+
         if self.div_counter.length_clock() {
             self.ch1.length_clock();
             self.ch2.length_clock();
@@ -508,11 +690,273 @@ impl Apu {
             self.ch1.sweep_clock();
         }
 
-        let frame1 = self.frame();
+        let vin = bus.vin();
+        let frame1 = self.frame(vin);
 
         self.ch3.clock();
-        let frame2 = self.frame();
+        let frame2 = self.frame(vin);
 
         [frame1, frame2]
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct Bus(u8);
+
+    impl ApuBus for Bus {
+        fn div(&self) -> u8 {
+            self.0
+        }
+    }
+
+    #[test]
+    fn frame_sequencer_steps_on_falling_edges() {
+        let mut div_counter = DivCounter::default();
+        let mut steps = Vec::new();
+        // Counting up through bit 4, then DIV being written while bit 4 is clear and while it's
+        // set
+        for div in [0x0f, 0x10, 0x1f, 0x20, 0x2f, 0x00, 0x10, 0x11, 0x00, 0x00] {
+            div_counter.clock(&mut Bus(div));
+            steps.push(div_counter.step);
+        }
+        assert_eq!(
+            steps,
+            [
+                None,
+                None,
+                None,
+                Some(0),
+                None,
+                None,
+                None,
+                None,
+                Some(1),
+                None
+            ]
+        );
+        assert!(!div_counter.length_clock());
+
+        for _ in 0..6 {
+            div_counter.clock(&mut Bus(0x10));
+            div_counter.clock(&mut Bus(0x00));
+        }
+        assert_eq!(div_counter.step, Some(7));
+        assert!(div_counter.envelope_clock());
+        assert!(!div_counter.length_clock() && !div_counter.sweep_clock());
+    }
+
+    #[test]
+    fn mute_channel() {
+        let mut apu = Apu::default();
+        apu.set_nr52(0x80);
+        apu.set_nr50(0x77);
+        apu.set_nr51(0xff);
+        // Full volume on channel 2, triggered
+        apu.set_nr22(0xf0);
+        apu.set_nr24(0x80);
+        assert_ne!(apu.execute(&mut Bus(0))[0], [0.0, 0.0]);
+
+        apu.set_channel_muted(AudioChannel::Pulse2, true);
+        assert!(apu.channel_muted(AudioChannel::Pulse2));
+        assert_eq!(apu.execute(&mut Bus(0)), [[0.0, 0.0], [0.0, 0.0]]);
+
+        // Muting is a setting, so it survives the APU being switched off
+        apu.set_nr52(0x00);
+        apu.execute(&mut Bus(0));
+        assert!(apu.channel_muted(AudioChannel::Pulse2));
+    }
+
+    struct VinBus(f32);
+
+    impl ApuBus for VinBus {
+        fn div(&self) -> u8 {
+            0
+        }
+
+        fn vin(&self) -> f32 {
+            self.0
+        }
+    }
+
+    #[test]
+    fn vin_mixed_through_nr50() {
+        let mut apu = Apu::default();
+        apu.set_nr52(0x80);
+        apu.set_nr50(0x77);
+        assert_eq!(apu.execute(&mut VinBus(1.0)), [[0.0, 0.0], [0.0, 0.0]]);
+
+        // To the left output only
+        apu.set_nr50(0xf7);
+        let [[left, right], _] = apu.execute(&mut VinBus(1.0));
+        assert!(left > 0.0);
+        assert_eq!(right, 0.0);
+    }
+
+    #[test]
+    fn dac_ramps_up_after_power_on() {
+        let mut apu = Apu::default();
+        apu.set_nr52(0x80);
+        apu.set_nr50(0x77);
+        apu.set_nr51(0xff);
+        // Full volume on channel 2, triggered
+        apu.set_nr22(0xf0);
+        apu.set_nr24(0x80);
+        let [[first, _], _] = apu.execute(&mut Bus(0));
+        for _ in 0..DAC_RAMP_CYCLES {
+            apu.execute(&mut Bus(0));
+        }
+        let [[ramped, _], _] = apu.execute(&mut Bus(0));
+        assert!(first.abs() < ramped.abs() / 100.0);
+
+        // Turning the DAC off and on again doesn't
+        apu.set_nr22(0x00);
+        apu.execute(&mut Bus(0));
+        apu.set_nr22(0xf0);
+        apu.set_nr24(0x80);
+        let [[again, _], _] = apu.execute(&mut Bus(0));
+        assert_eq!(again, ramped);
+
+        // Powering the APU off and on does
+        apu.set_nr52(0x00);
+        apu.set_nr52(0x80);
+        apu.set_nr50(0x77);
+        apu.set_nr51(0xff);
+        apu.set_nr22(0xf0);
+        apu.set_nr24(0x80);
+        let [[first, _], _] = apu.execute(&mut Bus(0));
+        assert!(first.abs() < ramped.abs() / 100.0);
+    }
+
+    #[test]
+    fn read_masks() {
+        let mut apu = Apu::default();
+        apu.set_nr52(0x80);
+        assert_eq!(apu.nr52(), 0xf0);
+        apu.set_nr10(0x00);
+        apu.set_nr11(0x00);
+        apu.set_nr30(0x00);
+        apu.set_nr32(0x00);
+        apu.set_nr44(0x00);
+        assert_eq!(apu.nr10(), 0x80);
+        assert_eq!(apu.nr11(), 0x3f);
+        assert_eq!(apu.nr30(), 0x7f);
+        assert_eq!(apu.nr32(), 0x9f);
+        assert_eq!(apu.nr44(), 0xbf);
+
+        // Periods and lengths are write-only
+        apu.set_nr13(0x12);
+        apu.set_nr31(0x34);
+        apu.set_nr24(0x47);
+        assert_eq!(apu.nr13(), 0xff);
+        assert_eq!(apu.nr31(), 0xff);
+        assert_eq!(apu.nr41(), 0xff);
+        assert_eq!(apu.nr24(), 0xff);
+
+        apu.set_nr21(0x80);
+        apu.set_nr42(0x00);
+        apu.set_nr51(0x00);
+        assert_eq!(apu.nr21(), 0xbf);
+        assert_eq!(apu.nr42(), 0x00);
+        assert_eq!(apu.nr51(), 0x00);
+    }
+
+    #[test]
+    fn writes_ignored_while_off() {
+        for dmg in [false, true] {
+            let mut apu = Apu::new(dmg);
+            assert_eq!(apu.nr52(), 0x70);
+            apu.set_nr50(0x77);
+            apu.set_nr12(0xf0);
+            apu.set_nr11(0xff);
+            apu.set_nr31(0x12);
+            apu.set_nr41(0x34);
+            apu.write_wave_ram(0xff30, 0xab);
+            assert_eq!(apu.nr50(), 0x00);
+            assert_eq!(apu.nr12(), 0x00);
+            assert_eq!(apu.nr11(), 0x3f);
+            assert_eq!(apu.read_wave_ram(0xff30), 0xab);
+
+            // Only the DMG still takes the lengths
+            let length = if dmg { 0x3f } else { 0x00 };
+            assert_eq!(apu.ch1.regs.nrx1.initial_length_timer().value(), length);
+            assert_eq!(apu.ch3.regs.nr31, if dmg { 0x12 } else { 0x00 });
+            assert_eq!(u8::from(apu.ch4.regs.nr41), if dmg { 0x34 } else { 0x00 });
+
+            // Powering off keeps which model it is
+            apu.set_nr52(0x80);
+            apu.set_nr52(0x00);
+            apu.set_nr31(0x56);
+            assert_eq!(apu.ch3.regs.nr31 == 0x56, dmg);
+        }
+    }
+
+    #[test]
+    fn dmg_lengths_survive_power_off() {
+        for dmg in [false, true] {
+            let mut apu = Apu::new(dmg);
+            apu.set_nr52(0x80);
+            // Almost out of length on channel 2
+            apu.set_nr21(0x3f);
+            apu.set_nr52(0x00);
+            apu.set_nr52(0x80);
+
+            // Triggered with the length enabled
+            apu.set_nr22(0xf0);
+            apu.set_nr24(0xc0);
+            apu.execute(&mut Bus(0));
+            assert!(apu.ch2.enabled());
+            apu.ch2.length_clock();
+            apu.ch2.length_clock();
+            assert_eq!(apu.ch2.enabled(), !dmg);
+        }
+    }
+
+    /// An APU playing wave RAM of 0x00, 0x11, ..., 0xff on channel 3, as fast as it goes.
+    fn wave_apu(dmg: bool) -> Apu {
+        let mut apu = Apu::new(dmg);
+        for i in 0..16 {
+            apu.write_wave_ram(0xff30 + i, i as u8 * 0x11);
+        }
+        apu.set_nr52(0x80);
+        apu.set_nr30(0x80);
+        apu.set_nr32(0x20);
+        apu.set_nr33(0xff);
+        apu.set_nr34(0x87);
+        apu
+    }
+
+    #[test]
+    fn wave_trigger_delay() {
+        let mut apu = wave_apu(false);
+        // The period is a single clock, but the first read waits three more. Until then, the
+        // empty sample buffer plays, and then sample 0 is skipped.
+        let mut samples = Vec::new();
+        for _ in 0..6 {
+            apu.ch3.clock();
+            samples.push(apu.ch3.wave().0);
+        }
+        assert_eq!(samples, [0x0, 0x0, 0x0, 0x0, 0x1, 0x1]);
+    }
+
+    #[test]
+    fn wave_retrigger_corruption() {
+        for dmg in [false, true] {
+            let mut apu = wave_apu(dmg);
+            // Up to sample 9, so the next read is of byte 5
+            for _ in 0..12 {
+                apu.ch3.clock();
+            }
+            apu.set_nr34(0x87);
+            let expected = if dmg {
+                [0x44, 0x55, 0x66, 0x77]
+            } else {
+                [0x00, 0x11, 0x22, 0x33]
+            };
+            assert_eq!(apu.ch3.wave_ram[..4], expected);
+            assert_eq!(apu.ch3.wave_ram[4..8], [0x44, 0x55, 0x66, 0x77]);
+        }
+    }
+}
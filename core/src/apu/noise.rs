@@ -2,20 +2,23 @@
 // Copyright (C) 2023 Robert Hrusecky <jadedpastabowl@gmail.com>
 
 use bilge::prelude::*;
+use serde::{Deserialize, Serialize};
 
 use super::{
     Channel, Envelope, LengthTimer, LengthTimerRegs, Nrx2, PeriodDivider, PeriodDividerRegs,
 };
 
 #[bitsize(8)]
-#[derive(Default, FromBits, DebugBits, Clone, Copy)]
+#[derive(Default, FromBits, DebugBits, Clone, Copy, Serialize, Deserialize)]
+#[serde(from = "u8", into = "u8")]
 pub(super) struct Nr41 {
     initial_length_timer: u6,
     __: u2,
 }
 
 #[bitsize(8)]
-#[derive(Default, FromBits, DebugBits, Clone, Copy)]
+#[derive(Default, FromBits, DebugBits, Clone, Copy, Serialize, Deserialize)]
+#[serde(from = "u8", into = "u8")]
 pub(super) struct Nr43 {
     clock_divider: u3,
     short_mode: bool,
@@ -23,14 +26,15 @@ pub(super) struct Nr43 {
 }
 
 #[bitsize(8)]
-#[derive(Default, FromBits, DebugBits, Clone, Copy)]
+#[derive(Default, FromBits, DebugBits, Clone, Copy, Serialize, Deserialize)]
+#[serde(from = "u8", into = "u8")]
 pub(super) struct Nr44 {
     __: u6,
     pub(super) sound_length_enabled: bool,
     trigger: bool,
 }
 
-#[derive(Default)]
+#[derive(Default, Serialize, Deserialize)]
 pub(super) struct NoiseRegs {
     pub(super) nr41: Nr41,
     pub(super) nr42: Nrx2,
@@ -64,7 +68,7 @@ impl PeriodDividerRegs for NoiseRegs {
     }
 }
 
-#[derive(Default)]
+#[derive(Default, Serialize, Deserialize)]
 struct Lfsr {
     lfsr: u16,
 }
@@ -89,7 +93,7 @@ impl Lfsr {
     }
 }
 
-#[derive(Default)]
+#[derive(Default, Serialize, Deserialize)]
 pub(super) struct NoiseChannel {
     pub(super) regs: NoiseRegs,
     length_timer: LengthTimer<NoiseRegs>,
@@ -100,6 +104,12 @@ pub(super) struct NoiseChannel {
 }
 
 impl NoiseChannel {
+    /// Carries the length over from `old`, like a DMG does when the APU is powered off.
+    pub(super) fn keep_length(&mut self, old: Self) {
+        self.length_timer = old.length_timer;
+        self.regs.nr41 = old.regs.nr41;
+    }
+
     pub(super) fn dac_enabled(&self) -> bool {
         self.regs.nr42.initial_volume().value() != 0 || self.regs.nr42.increase_envelope()
     }
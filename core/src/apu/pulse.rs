@@ -4,13 +4,15 @@
 use std::num::Wrapping;
 
 use bilge::prelude::*;
+use serde::{Deserialize, Serialize};
 
 use super::{
     Channel, Envelope, LengthTimer, LengthTimerRegs, Nrx2, Nrx4, PeriodDivider, PeriodDividerRegs,
 };
 
 #[bitsize(8)]
-#[derive(Default, FromBits, DebugBits, Clone, Copy)]
+#[derive(Default, FromBits, DebugBits, Clone, Copy, Serialize, Deserialize)]
+#[serde(from = "u8", into = "u8")]
 pub(super) struct Nr10 {
     sweep_slope: u3,
     decrease_sweep: bool,
@@ -29,7 +31,8 @@ pub(super) enum WaveDuty {
 }
 
 #[bitsize(8)]
-#[derive(Default, FromBits, DebugBits, Clone, Copy)]
+#[derive(Default, FromBits, DebugBits, Clone, Copy, Serialize, Deserialize)]
+#[serde(from = "u8", into = "u8")]
 pub(super) struct Nrx1 {
     pub(super) initial_length_timer: u6,
     wave_duty: WaveDuty,
@@ -46,7 +49,7 @@ pub(super) trait Sweep {
     fn clock(&mut self, regs: &impl PeriodDividerRegs) -> SweepAction;
 }
 
-#[derive(Default)]
+#[derive(Default, Serialize, Deserialize)]
 pub(super) struct NoSweep;
 
 impl Sweep for NoSweep {
@@ -57,7 +60,7 @@ impl Sweep for NoSweep {
     }
 }
 
-#[derive(Default)]
+#[derive(Default, Serialize, Deserialize)]
 pub(super) struct Sweeper {
     pub(super) nr10: Nr10,
     count: u8,
@@ -90,7 +93,7 @@ impl Sweep for Sweeper {
     }
 }
 
-#[derive(Default)]
+#[derive(Default, Serialize, Deserialize)]
 pub(super) struct PulseRegs {
     pub(super) nrx1: Nrx1,
     pub(super) nrx2: Nrx2,
@@ -118,7 +121,7 @@ impl PeriodDividerRegs for PulseRegs {
     }
 }
 
-#[derive(Default)]
+#[derive(Default, Serialize, Deserialize)]
 pub(super) struct PulseChannel<S: Sweep> {
     pub(super) sweeper: S,
     pub(super) regs: PulseRegs,
@@ -130,6 +133,14 @@ pub(super) struct PulseChannel<S: Sweep> {
 }
 
 impl<S: Sweep> PulseChannel<S> {
+    /// Carries the length over from `old`, like a DMG does when the APU is powered off.
+    pub(super) fn keep_length(&mut self, old: Self) {
+        self.length_timer = old.length_timer;
+        self.regs
+            .nrx1
+            .set_initial_length_timer(old.regs.nrx1.initial_length_timer());
+    }
+
     pub(super) fn dac_enabled(&self) -> bool {
         self.regs.nrx2.initial_volume().value() != 0 || self.regs.nrx2.increase_envelope()
     }
@@ -4,25 +4,28 @@
 use std::num::Wrapping;
 
 use bilge::prelude::*;
+use serde::{Deserialize, Serialize};
 
 use super::{Channel, LengthTimer, LengthTimerRegs, Nrx4, PeriodDivider, PeriodDividerRegs};
 
 #[bitsize(8)]
-#[derive(Default, FromBits, DebugBits, Clone, Copy)]
+#[derive(Default, FromBits, DebugBits, Clone, Copy, Serialize, Deserialize)]
+#[serde(from = "u8", into = "u8")]
 pub(super) struct Nr30 {
     __: u7,
     dac_enabled: bool,
 }
 
 #[bitsize(8)]
-#[derive(Default, FromBits, DebugBits, Clone, Copy)]
+#[derive(Default, FromBits, DebugBits, Clone, Copy, Serialize, Deserialize)]
+#[serde(from = "u8", into = "u8")]
 pub(super) struct Nr32 {
     _unused1: u5,
     output_level: u2,
     _unused2: u1,
 }
 
-#[derive(Default)]
+#[derive(Default, Serialize, Deserialize)]
 pub(super) struct WaveRegs {
     pub(super) nr30: Nr30,
     pub(super) nr31: u8,
@@ -51,17 +54,30 @@ impl PeriodDividerRegs for WaveRegs {
     }
 }
 
-#[derive(Default)]
+/// How many more clocks than the period the channel waits after a trigger before reading its
+/// first sample
+const TRIGGER_DELAY: u16 = 3;
+
+#[derive(Default, Serialize, Deserialize)]
 pub(super) struct WaveChannel {
     pub(super) wave_ram: [u8; 16],
     pub(super) regs: WaveRegs,
     index: Wrapping<u8>,
+    /// The byte of wave RAM last read, which the channel plays from. Triggering doesn't refill
+    /// it, so the first sample after a trigger is the last one from before.
+    sample_buffer: u8,
     length_timer: LengthTimer<WaveRegs>,
     period_div: PeriodDivider,
     pub(super) enabled: bool,
 }
 
 impl WaveChannel {
+    /// Carries the length over from `old`, like a DMG does when the APU is powered off.
+    pub(super) fn keep_length(&mut self, old: Self) {
+        self.length_timer = old.length_timer;
+        self.regs.nr31 = old.regs.nr31;
+    }
+
     pub(super) fn dac_enabled(&self) -> bool {
         self.regs.nr30.dac_enabled()
     }
@@ -73,6 +89,22 @@ impl WaveChannel {
             addr as usize
         }) & 0xf
     }
+
+    /// On the DMG, retriggering while the channel is about to read wave RAM corrupts the first
+    /// four bytes. They get the byte being read if it's among them, and otherwise the four bytes
+    /// around it.
+    pub(super) fn retrigger_corruption(&mut self) {
+        if !self.enabled || !self.period_div.expiring() {
+            return;
+        }
+        let offset = ((self.index.0 + 1) >> 1) as usize & 0xf;
+        if offset < 4 {
+            self.wave_ram[0] = self.wave_ram[offset];
+        } else {
+            let start = offset & !0x3;
+            self.wave_ram.copy_within(start..start + 4, 0);
+        }
+    }
 }
 
 impl Channel for WaveChannel {
@@ -86,12 +118,10 @@ impl Channel for WaveChannel {
             return (0, 0);
         }
 
-        let index = self.index.0 & 0x1f;
-        let val = self.wave_ram[index as usize >> 1];
-        let val = if index & 0x1 == 0 {
-            val >> 4
+        let val = if self.index.0 & 0x1 == 0 {
+            self.sample_buffer >> 4
         } else {
-            val & 0xf
+            self.sample_buffer & 0xf
         };
         (val >> (output_level - 1), 0xf)
     }
@@ -101,11 +131,16 @@ impl Channel for WaveChannel {
             self.enabled |= self.regs.nr30.dac_enabled();
             self.regs.nr34.set_trigger(false);
             self.period_div.trigger(&self.regs);
+            self.period_div.delay(TRIGGER_DELAY);
             self.length_timer.trigger(&self.regs);
             self.index.0 = 0;
         }
 
-        self.period_div.clock(&self.regs, || self.index += 1);
+        // Sample 0 is skipped after a trigger, since the first read is of sample 1
+        self.period_div.clock(&self.regs, || {
+            self.index += 1;
+            self.sample_buffer = self.wave_ram[(self.index.0 as usize >> 1) & 0xf];
+        });
     }
 
     fn length_clock(&mut self) {
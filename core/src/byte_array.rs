@@ -0,0 +1,85 @@
+// SPDX-License-Identifier: GPL-3.0-or-later
+// Copyright (C) 2023 Robert Hrusecky <jadedpastabowl@gmail.com>
+
+//! Serializes byte arrays, including arrays of byte arrays, as a single byte string. Use with
+//! `#[serde(with = "crate::byte_array")]`. Serde itself only handles arrays of up to 32 elements.
+
+use std::fmt::{self, Formatter};
+
+use serde::{
+    de::{Error, SeqAccess, Visitor},
+    Deserializer, Serializer,
+};
+
+pub trait ByteArray: Sized {
+    fn as_bytes(&self) -> &[u8];
+    fn from_bytes(bytes: &[u8]) -> Option<Self>;
+}
+
+impl<const N: usize> ByteArray for [u8; N] {
+    fn as_bytes(&self) -> &[u8] {
+        self
+    }
+
+    fn from_bytes(bytes: &[u8]) -> Option<Self> {
+        bytes.try_into().ok()
+    }
+}
+
+impl<const N: usize, const M: usize> ByteArray for [[u8; N]; M] {
+    fn as_bytes(&self) -> &[u8] {
+        self.as_flattened()
+    }
+
+    fn from_bytes(bytes: &[u8]) -> Option<Self> {
+        let mut array = [[0; N]; M];
+        if bytes.len() != N * M {
+            return None;
+        }
+        array.as_flattened_mut().copy_from_slice(bytes);
+        Some(array)
+    }
+}
+
+impl<T: ByteArray> ByteArray for Box<T> {
+    fn as_bytes(&self) -> &[u8] {
+        (**self).as_bytes()
+    }
+
+    fn from_bytes(bytes: &[u8]) -> Option<Self> {
+        T::from_bytes(bytes).map(Box::new)
+    }
+}
+
+pub fn serialize<S: Serializer>(array: &impl ByteArray, serializer: S) -> Result<S::Ok, S::Error> {
+    serializer.serialize_bytes(array.as_bytes())
+}
+
+struct BytesVisitor;
+
+impl<'de> Visitor<'de> for BytesVisitor {
+    type Value = Vec<u8>;
+
+    fn expecting(&self, f: &mut Formatter) -> fmt::Result {
+        write!(f, "a byte string")
+    }
+
+    fn visit_bytes<E: Error>(self, bytes: &[u8]) -> Result<Self::Value, E> {
+        Ok(bytes.to_vec())
+    }
+
+    fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<Self::Value, A::Error> {
+        let mut bytes = Vec::with_capacity(seq.size_hint().unwrap_or(0));
+        while let Some(byte) = seq.next_element()? {
+            bytes.push(byte);
+        }
+        Ok(bytes)
+    }
+}
+
+pub fn deserialize<'de, D: Deserializer<'de>, T: ByteArray>(
+    deserializer: D,
+) -> Result<T, D::Error> {
+    let bytes = deserializer.deserialize_bytes(BytesVisitor)?;
+    T::from_bytes(&bytes).ok_or_else(|| D::Error::invalid_length(bytes.len(), &BytesVisitor))
+}
@@ -0,0 +1,65 @@
+// SPDX-License-Identifier: GPL-3.0-or-later
+// Copyright (C) 2023 Robert Hrusecky <jadedpastabowl@gmail.com>
+
+use std::{
+    fmt::{self, Display, Formatter},
+    num::ParseIntError,
+    str::FromStr,
+};
+
+use thiserror::Error;
+
+/// A CPU address qualified with the ROM bank mapped there, written `03:4f20`. The CPU address
+/// alone is ambiguous for 0x4000-0x7fff in banked ROMs. Addresses outside of ROM, or written
+/// without a bank, have no bank.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct BankedAddress {
+    pub bank: Option<usize>,
+    pub addr: u16,
+}
+
+impl BankedAddress {
+    pub fn new(bank: Option<usize>, addr: u16) -> Self {
+        Self { bank, addr }
+    }
+
+    /// Whether this refers to `other`. An address without a bank refers to the same CPU address
+    /// in every bank.
+    pub fn matches(&self, other: BankedAddress) -> bool {
+        self.addr == other.addr && (self.bank.is_none() || self.bank == other.bank)
+    }
+}
+
+impl From<u16> for BankedAddress {
+    fn from(addr: u16) -> Self {
+        Self::new(None, addr)
+    }
+}
+
+impl Display for BankedAddress {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self.bank {
+            Some(bank) => write!(f, "{bank:02x}:{:04x}", self.addr),
+            None => write!(f, "{:04x}", self.addr),
+        }
+    }
+}
+
+#[derive(Error, Debug)]
+#[error("Invalid address {0:?}, expected `bank:addr` or `addr` in hex")]
+pub struct AddressParseError(String, #[source] ParseIntError);
+
+impl FromStr for BankedAddress {
+    type Err = AddressParseError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let error = |e| AddressParseError(s.to_owned(), e);
+        let (bank, addr) = match s.split_once(':') {
+            Some((bank, addr)) => (Some(usize::from_str_radix(bank, 16).map_err(error)?), addr),
+            None => (None, s),
+        };
+        let addr = addr.strip_prefix("0x").unwrap_or(addr);
+        let addr = u16::from_str_radix(addr, 16).map_err(error)?;
+        Ok(Self::new(bank, addr))
+    }
+}
@@ -0,0 +1,206 @@
+// SPDX-License-Identifier: GPL-3.0-or-later
+// Copyright (C) 2023 Robert Hrusecky <jadedpastabowl@gmail.com>
+
+//! The cartridge header, at 0x134-0x14f in ROM, which describes the game and the hardware on its
+//! cartridge.
+
+use super::ROM_BANK_SIZE;
+
+/// How a game uses the CGB's features, from the CGB flag.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CgbSupport {
+    /// A DMG game, which the CGB runs in compatibility mode
+    None,
+    /// Runs on either, with extras on the CGB
+    Enhanced,
+    /// Only runs on the CGB
+    Only,
+}
+
+#[derive(Debug, Clone)]
+pub struct Header {
+    /// Without padding, and with anything that isn't printable ASCII left out
+    pub title: String,
+    /// Four characters that some later games put at the end of the title
+    pub manufacturer: Option<String>,
+    pub cgb: CgbSupport,
+    /// Whether the game uses the SGB's features
+    pub sgb: bool,
+    /// Who published the game. Older games have a byte, written here in hex, and newer ones two
+    /// characters.
+    pub licensee: String,
+    pub cart_type: u8,
+    pub rom_size_id: u8,
+    pub ram_size_id: u8,
+    /// Whether the game was sold in Japan, rather than overseas
+    pub japanese: bool,
+    pub version: u8,
+    pub header_checksum: u8,
+    pub global_checksum: u16,
+    /// The checksums computed from the ROM, to compare against the ones in the header
+    pub expected_header_checksum: u8,
+    pub expected_global_checksum: u16,
+}
+
+impl Header {
+    /// Reads the header from the start of `rom`. A ROM too short to have one reads as zeros.
+    pub fn parse(rom: &[u8]) -> Self {
+        let byte = |addr: usize| rom.get(addr).copied().unwrap_or(0);
+        let text = |range: std::ops::Range<usize>| -> String {
+            let text: String = range
+                .map(byte)
+                .take_while(|&byte| byte != 0)
+                .filter(|byte| byte.is_ascii_graphic() || *byte == b' ')
+                .map(char::from)
+                .collect();
+            text.trim_end().to_owned()
+        };
+
+        let cgb = match byte(0x143) {
+            0xc0 => CgbSupport::Only,
+            flag if flag & 0x80 != 0 => CgbSupport::Enhanced,
+            _ => CgbSupport::None,
+        };
+        // Only CGB games have room for a manufacturer code, and it's always upper case
+        let manufacturer = (0x13f..0x143)
+            .map(byte)
+            .all(|byte| byte.is_ascii_uppercase() || byte.is_ascii_digit());
+        let manufacturer = (cgb != CgbSupport::None && manufacturer).then(|| text(0x13f..0x143));
+        let licensee = match byte(0x14b) {
+            0x33 => text(0x144..0x146),
+            code => format!("{code:02X}"),
+        };
+
+        let expected_header_checksum = (0x134..=0x14c)
+            .map(byte)
+            .fold(0u8, |sum, byte| sum.wrapping_sub(byte).wrapping_sub(1));
+        let expected_global_checksum = rom
+            .iter()
+            .enumerate()
+            .filter(|&(addr, _)| addr != 0x14e && addr != 0x14f)
+            .fold(0u16, |sum, (_, &byte)| sum.wrapping_add(byte.into()));
+
+        // Games from before the CGB use the flag's byte for the title too, and the manufacturer
+        // code takes the end of the title's space
+        let title_end = match (cgb, &manufacturer) {
+            (CgbSupport::None, _) => 0x144,
+            (_, Some(_)) => 0x13f,
+            (_, None) => 0x143,
+        };
+        let title = text(0x134..title_end);
+
+        Self {
+            title,
+            manufacturer,
+            cgb,
+            // The SGB only looks at the flag if the old licensee code says to
+            sgb: byte(0x146) == 0x03 && byte(0x14b) == 0x33,
+            licensee,
+            cart_type: byte(0x147),
+            rom_size_id: byte(0x148),
+            ram_size_id: byte(0x149),
+            japanese: byte(0x14a) == 0x00,
+            version: byte(0x14c),
+            header_checksum: byte(0x14d),
+            global_checksum: u16::from_be_bytes([byte(0x14e), byte(0x14f)]),
+            expected_header_checksum,
+            expected_global_checksum,
+        }
+    }
+
+    /// The size of ROM the header claims, or `None` for an unknown ID.
+    pub fn rom_size(&self) -> Option<usize> {
+        match self.rom_size_id {
+            id @ 0x0..=0x8 => Some(1 << (id + 15)),
+            // Some headers claim 72, 80, or 96 banks, which isn't a power of two
+            id @ 0x52..=0x54 => Some([72, 80, 96][id as usize - 0x52] * ROM_BANK_SIZE),
+            _ => None,
+        }
+    }
+
+    /// The size of RAM the header claims, or `None` for an unknown ID.
+    pub fn ram_size(&self) -> Option<usize> {
+        match self.ram_size_id {
+            0x00 => Some(0),
+            0x02 => Some(0x2000),
+            0x03 => Some(0x8000),
+            0x04 => Some(0x20000),
+            0x05 => Some(0x10000),
+            _ => None,
+        }
+    }
+
+    /// Whether the header checksum is right. The boot ROM refuses to start the game if it isn't.
+    pub fn header_checksum_ok(&self) -> bool {
+        self.header_checksum == self.expected_header_checksum
+    }
+
+    /// Whether the global checksum is right. Nothing checks it on hardware, but a wrong one
+    /// suggests a bad dump or a hacked ROM.
+    pub fn global_checksum_ok(&self) -> bool {
+        self.global_checksum == self.expected_global_checksum
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse() {
+        let mut rom = vec![0; 2 * ROM_BANK_SIZE];
+        rom[0x134..0x143].copy_from_slice(b"POKEMON_SLVAAXE");
+        rom[0x143] = 0xc0;
+        rom[0x144..0x146].copy_from_slice(b"01");
+        rom[0x146] = 0x03;
+        rom[0x147] = 0x10;
+        rom[0x148] = 0x06;
+        rom[0x149] = 0x03;
+        rom[0x14a] = 0x01;
+        rom[0x14b] = 0x33;
+        rom[0x14c] = 0x01;
+        let header = Header::parse(&rom);
+        assert_eq!(header.title, "POKEMON_SLV");
+        assert_eq!(header.manufacturer.as_deref(), Some("AAXE"));
+        assert_eq!(header.cgb, CgbSupport::Only);
+        assert!(header.sgb);
+        assert_eq!(header.licensee, "01");
+        assert_eq!(header.rom_size(), Some(0x200000));
+        assert_eq!(header.ram_size(), Some(0x8000));
+        assert!(!header.japanese);
+        assert_eq!(header.version, 1);
+        assert!(!header.header_checksum_ok());
+        assert!(!header.global_checksum_ok());
+
+        rom[0x14d] = header.expected_header_checksum;
+        let header = Header::parse(&rom);
+        assert!(header.header_checksum_ok());
+        // The global checksum covers the header checksum too, but not itself
+        let [high, low] = header.expected_global_checksum.to_be_bytes();
+        rom[0x14e] = high;
+        rom[0x14f] = low;
+        assert!(Header::parse(&rom).global_checksum_ok());
+    }
+
+    #[test]
+    fn old_header() {
+        let mut rom = vec![0; 2 * ROM_BANK_SIZE];
+        rom[0x134..0x144].copy_from_slice(b"SUPER MARIOLAND ");
+        rom[0x14b] = 0x01;
+        let header = Header::parse(&rom);
+        assert_eq!(header.title, "SUPER MARIOLAND");
+        assert_eq!(header.manufacturer, None);
+        assert_eq!(header.cgb, CgbSupport::None);
+        assert_eq!(header.licensee, "01");
+        assert!(header.japanese);
+    }
+
+    #[test]
+    fn full_length_title() {
+        let mut rom = vec![0; 2 * ROM_BANK_SIZE];
+        rom[0x134..0x144].copy_from_slice(b"SIXTEEN LETTERS!");
+        let header = Header::parse(&rom);
+        assert_eq!(header.cgb, CgbSupport::None);
+        assert_eq!(header.title, "SIXTEEN LETTERS!");
+    }
+}
@@ -1,9 +1,13 @@
 // SPDX-License-Identifier: GPL-3.0-or-later
 // Copyright (C) 2023 Robert Hrusecky <jadedpastabowl@gmail.com>
 
+use serde::{Deserialize, Serialize};
+
+use crate::event::EventLog;
+
 use super::{mem::Mem, save::MbcSave, Mbc};
 
-#[derive(Default)]
+#[derive(Default, Serialize, Deserialize)]
 pub struct Mbc2 {
     rom_bank: u8,
     ram_enabled: bool,
@@ -14,21 +18,6 @@ impl Mbc2 {
         let bank_num = if self.rom_bank == 0 { 1 } else { self.rom_bank };
         (bank_num as usize) << 14
     }
-
-    fn rom_offset(&self, addr: u16) -> usize {
-        let mut offset = (addr & 0x3fff) as usize;
-
-        let upper_area = (addr & 0x4000) != 0;
-        if upper_area {
-            offset |= self.rom_bank_offset();
-        }
-
-        offset
-    }
-
-    fn ram_offset(&self, addr: u16) -> usize {
-        (addr & 0x1fff) as usize
-    }
 }
 
 impl Mbc for Mbc2 {
@@ -36,7 +25,7 @@ impl Mbc for Mbc2 {
         mem.rom.read(self.rom_offset(addr))
     }
 
-    fn write_low(&mut self, addr: u16, val: u8, _mem: &mut Mem) {
+    fn write_low(&mut self, addr: u16, val: u8, _mem: &mut Mem, _events: &EventLog) {
         let reg_num = (addr >> 8) & 0x1;
         match reg_num {
             0 => self.ram_enabled = val & 0xf == 0xa,
@@ -53,12 +42,27 @@ impl Mbc for Mbc2 {
         }
     }
 
-    fn write_high(&mut self, addr: u16, val: u8, mem: &mut Mem) {
+    fn write_high(&mut self, addr: u16, val: u8, mem: &mut Mem, _events: &EventLog) {
         if self.ram_enabled {
             mem.ram.write(self.ram_offset(addr), val & 0x0f);
         }
     }
 
+    fn rom_offset(&self, addr: u16) -> usize {
+        let mut offset = (addr & 0x3fff) as usize;
+
+        let upper_area = (addr & 0x4000) != 0;
+        if upper_area {
+            offset |= self.rom_bank_offset();
+        }
+
+        offset
+    }
+
+    fn ram_offset(&self, addr: u16) -> usize {
+        (addr & 0x1fff) as usize
+    }
+
     fn save(&self) -> MbcSave {
         MbcSave::None
     }
@@ -1,9 +1,13 @@
 // SPDX-License-Identifier: GPL-3.0-or-later
 // Copyright (C) 2023 Robert Hrusecky <jadedpastabowl@gmail.com>
 
+use serde::{Deserialize, Serialize};
+
+use crate::event::{EventKind, EventLog, Severity};
+
 use super::{mem::Mem, rtc::Rtc, save::MbcSave, Mbc};
 
-#[derive(Default)]
+#[derive(Default, Serialize, Deserialize)]
 pub struct Mbc3 {
     rom_bank: u8,
     ram_bank: u8,
@@ -27,27 +31,14 @@ impl Mbc3 {
         self.rtc = Some(rtc);
     }
 
+    pub fn rtc_mut(&mut self) -> Option<&mut Rtc> {
+        self.rtc.as_mut()
+    }
+
     fn rom_bank_offset(&self) -> usize {
         let bank_num = if self.rom_bank == 0 { 1 } else { self.rom_bank };
         (bank_num as usize) << 14
     }
-
-    fn rom_offset(&self, addr: u16) -> usize {
-        let mut offset = (addr & 0x3fff) as usize;
-
-        let upper_area = (addr & 0x4000) != 0;
-        if upper_area {
-            offset |= self.rom_bank_offset();
-        }
-
-        offset
-    }
-
-    fn ram_offset(&self, addr: u16) -> usize {
-        let mut offset = (addr & 0x1fff) as usize;
-        offset |= ((self.ram_bank & 0x3) as usize) << 13;
-        offset
-    }
 }
 
 impl Mbc for Mbc3 {
@@ -55,15 +46,27 @@ impl Mbc for Mbc3 {
         mem.rom.read(self.rom_offset(addr))
     }
 
-    fn write_low(&mut self, addr: u16, val: u8, _mem: &mut Mem) {
+    fn write_low(&mut self, addr: u16, val: u8, _mem: &mut Mem, events: &EventLog) {
         let reg_num = (addr >> 13) & 0x3;
         match reg_num {
             0 => self.ram_enabled = val & 0xf == 0xa,
             1 => self.rom_bank = val & 0x7f,
-            2 => self.ram_bank = val,
+            2 => {
+                let valid = match val {
+                    0x00..=0x03 => true,
+                    0x08..=0x0c => self.has_rtc(),
+                    _ => false,
+                };
+                if !valid {
+                    events.push(Severity::Warning, EventKind::SuspiciousMbcWrite(addr, val));
+                }
+                self.ram_bank = val;
+            }
             3 => {
                 if let Some(rtc) = &mut self.rtc {
-                    rtc.latch(val & 0x1 != 0);
+                    if rtc.latch(val & 0x1 != 0) {
+                        events.push(Severity::Info, EventKind::RtcOverflow);
+                    }
                 }
             }
             _ => unreachable!(),
@@ -91,7 +94,7 @@ impl Mbc for Mbc3 {
         }
     }
 
-    fn write_high(&mut self, addr: u16, val: u8, mem: &mut Mem) {
+    fn write_high(&mut self, addr: u16, val: u8, mem: &mut Mem, _events: &EventLog) {
         match self {
             Self {
                 ram_enabled: false, ..
@@ -112,6 +115,23 @@ impl Mbc for Mbc3 {
         }
     }
 
+    fn rom_offset(&self, addr: u16) -> usize {
+        let mut offset = (addr & 0x3fff) as usize;
+
+        let upper_area = (addr & 0x4000) != 0;
+        if upper_area {
+            offset |= self.rom_bank_offset();
+        }
+
+        offset
+    }
+
+    fn ram_offset(&self, addr: u16) -> usize {
+        let mut offset = (addr & 0x1fff) as usize;
+        offset |= ((self.ram_bank & 0x3) as usize) << 13;
+        offset
+    }
+
     fn save(&self) -> MbcSave {
         if let Some(rtc) = &self.rtc {
             MbcSave::Rtc(rtc.save())
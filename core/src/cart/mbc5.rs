@@ -0,0 +1,102 @@
+// SPDX-License-Identifier: GPL-3.0-or-later
+// Copyright (C) 2023 Robert Hrusecky <jadedpastabowl@gmail.com>
+
+use serde::{Deserialize, Serialize};
+
+use crate::event::{EventKind, EventLog, Severity};
+
+use super::{mem::Mem, save::MbcSave, Mbc};
+
+#[derive(Serialize, Deserialize)]
+pub struct Mbc5 {
+    rom_bank: u16,
+    ram_bank: u8,
+    ram_enabled: bool,
+    has_rumble: bool,
+    rumble: bool,
+}
+
+impl Default for Mbc5 {
+    fn default() -> Self {
+        Self {
+            // The upper area starts out at bank 1, like older MBCs
+            rom_bank: 1,
+            ram_bank: 0,
+            ram_enabled: false,
+            has_rumble: false,
+            rumble: false,
+        }
+    }
+}
+
+impl Mbc5 {
+    pub fn new_with_rumble() -> Self {
+        Self {
+            has_rumble: true,
+            ..Default::default()
+        }
+    }
+
+    /// Whether the rumble motor is on. Games control its strength by switching it on and off
+    /// quickly.
+    pub fn rumble(&self) -> bool {
+        self.rumble
+    }
+}
+
+impl Mbc for Mbc5 {
+    fn read_low(&self, addr: u16, mem: &Mem) -> u8 {
+        mem.rom.read(self.rom_offset(addr))
+    }
+
+    fn write_low(&mut self, addr: u16, val: u8, _mem: &mut Mem, events: &EventLog) {
+        match addr >> 12 {
+            0x0 | 0x1 => self.ram_enabled = val & 0xf == 0xa,
+            0x2 => self.rom_bank = (self.rom_bank & 0x100) | val as u16,
+            0x3 => self.rom_bank = (self.rom_bank & 0xff) | (val as u16 & 0x1) << 8,
+            // Rumble carts wire the motor to what would be the top bit of the RAM bank
+            0x4 | 0x5 if self.has_rumble => {
+                let rumble = val & 0x8 != 0;
+                if rumble != self.rumble {
+                    events.push(Severity::Debug, EventKind::Rumble(rumble));
+                }
+                self.rumble = rumble;
+                self.ram_bank = val & 0x7;
+            }
+            0x4 | 0x5 => self.ram_bank = val & 0xf,
+            _ => (),
+        }
+    }
+
+    fn read_high(&self, addr: u16, mem: &Mem) -> u8 {
+        if self.ram_enabled {
+            mem.ram.read(self.ram_offset(addr))
+        } else {
+            0xff
+        }
+    }
+
+    fn write_high(&mut self, addr: u16, val: u8, mem: &mut Mem, _events: &EventLog) {
+        if self.ram_enabled {
+            mem.ram.write(self.ram_offset(addr), val);
+        }
+    }
+
+    fn rom_offset(&self, addr: u16) -> usize {
+        let offset = (addr & 0x3fff) as usize;
+        // Unlike older MBCs, bank 0 can be mapped to the upper area too
+        if addr & 0x4000 != 0 {
+            offset | (self.rom_bank as usize) << 14
+        } else {
+            offset
+        }
+    }
+
+    fn ram_offset(&self, addr: u16) -> usize {
+        (addr & 0x1fff) as usize | (self.ram_bank as usize) << 13
+    }
+
+    fn save(&self) -> MbcSave {
+        MbcSave::None
+    }
+}
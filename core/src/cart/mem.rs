@@ -1,6 +1,9 @@
 // SPDX-License-Identifier: GPL-3.0-or-later
 // Copyright (C) 2023 Robert Hrusecky <jadedpastabowl@gmail.com>
 
+use serde::{Deserialize, Serialize};
+
+#[derive(Serialize, Deserialize)]
 pub struct Segment(Box<[u8]>);
 
 impl Segment {
@@ -34,6 +37,7 @@ impl TryFrom<Box<[u8]>> for Segment {
     }
 }
 
+#[derive(Serialize, Deserialize)]
 pub struct OptionalSegment(Option<Segment>);
 
 impl OptionalSegment {
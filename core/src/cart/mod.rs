@@ -2,47 +2,115 @@
 // Copyright (C) 2023 Robert Hrusecky <jadedpastabowl@gmail.com>
 
 use ambassador::{delegatable_trait, Delegate};
+use std::{
+    fmt::{self, Display, Formatter},
+    time::Duration,
+};
+
+use serde::{Deserialize, Serialize};
 use thiserror::Error;
 
+use crate::event::{EventKind, EventLog};
+
 use self::{
+    header::{CgbSupport, Header},
     mbc1::Mbc1,
     mbc2::Mbc2,
     mbc3::Mbc3,
+    mbc5::Mbc5,
     mem::{Mem, OptionalSegment, Segment},
+    rtc::Rtc,
     save::{CartSave, MbcSave},
     simple::Simple,
 };
 
+mod address;
+pub mod header;
 mod mbc1;
 mod mbc2;
 mod mbc3;
+mod mbc5;
 mod mem;
 mod rtc;
 pub mod save;
 mod simple;
 
+pub use address::{AddressParseError, BankedAddress};
+pub use rtc::{ClockSource, RtcClock};
+
 #[delegatable_trait]
 pub trait Mbc {
     fn read_low(&self, addr: u16, mem: &Mem) -> u8;
-    fn write_low(&mut self, addr: u16, val: u8, mem: &mut Mem);
+    fn write_low(&mut self, addr: u16, val: u8, mem: &mut Mem, events: &EventLog);
     fn read_high(&self, addr: u16, mem: &Mem) -> u8;
-    fn write_high(&mut self, addr: u16, val: u8, mem: &mut Mem);
+    fn write_high(&mut self, addr: u16, val: u8, mem: &mut Mem, events: &EventLog);
+    /// The offset into ROM that `addr` currently maps to, before wrapping to the ROM size.
+    fn rom_offset(&self, addr: u16) -> usize;
+    /// The offset into RAM that `addr` currently maps to, before wrapping to the RAM size.
+    fn ram_offset(&self, addr: u16) -> usize;
     fn save(&self) -> MbcSave;
 }
 
-#[derive(Delegate)]
+// New variants must be added at the end so that existing save states still deserialize
+#[derive(Delegate, Serialize, Deserialize)]
 #[delegate(Mbc)]
 pub enum AnyMbc {
     Simple(Simple),
     Mbc1(Mbc1),
     Mbc2(Mbc2),
     Mbc3(Mbc3),
+    Mbc5(Mbc5),
+}
+
+/// The kinds of mapper chip, which is usually detected from the cartridge type in the header.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MbcKind {
+    /// Just ROM, and maybe RAM, without banking
+    None,
+    Mbc1,
+    Mbc2,
+    Mbc3,
+    Mbc5,
+}
+
+impl MbcKind {
+    pub const ALL: [MbcKind; 5] = [Self::None, Self::Mbc1, Self::Mbc2, Self::Mbc3, Self::Mbc5];
+
+    fn from_cart_type(cart_type: u8) -> Option<Self> {
+        match cart_type {
+            0x00 | 0x08 | 0x09 => Some(Self::None),
+            0x01..=0x03 => Some(Self::Mbc1),
+            0x05 | 0x06 => Some(Self::Mbc2),
+            0x0f..=0x13 => Some(Self::Mbc3),
+            0x19..=0x1e => Some(Self::Mbc5),
+            _ => None,
+        }
+    }
 }
 
+impl Display for MbcKind {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::None => "None",
+            Self::Mbc1 => "MBC1",
+            Self::Mbc2 => "MBC2",
+            Self::Mbc3 => "MBC3",
+            Self::Mbc5 => "MBC5",
+        })
+    }
+}
+
+pub const ROM_BANK_SIZE: usize = 0x4000;
+pub const RAM_BANK_SIZE: usize = 0x2000;
+
 pub struct Cart<M = AnyMbc> {
     mem: Mem,
     mbc: M,
+    header: Header,
     battery_backed: bool,
+    warnings: Vec<EventKind>,
+    /// Whether RAM may have changed since the last [`Cart::take_dirty`]
+    dirty: bool,
 }
 
 impl<M: Mbc> Cart<M> {
@@ -50,16 +118,65 @@ impl<M: Mbc> Cart<M> {
         self.mbc.read_low(addr, &self.mem)
     }
 
-    pub fn write_low(&mut self, addr: u16, val: u8) {
-        self.mbc.write_low(addr, val, &mut self.mem);
+    pub fn write_low(&mut self, addr: u16, val: u8, events: &EventLog) {
+        self.mbc.write_low(addr, val, &mut self.mem, events);
     }
 
     pub fn read_high(&self, addr: u16) -> u8 {
         self.mbc.read_high(addr, &self.mem)
     }
 
-    pub fn write_high(&mut self, addr: u16, val: u8) {
-        self.mbc.write_high(addr, val, &mut self.mem);
+    pub fn write_high(&mut self, addr: u16, val: u8, events: &EventLog) {
+        self.mbc.write_high(addr, val, &mut self.mem, events);
+        self.dirty = true;
+    }
+
+    /// Whether RAM, or the RTC, may have changed since the last [`Self::take_dirty`], i.e. whether
+    /// there's anything new to save. Any write to them counts, even one the MBC ignores.
+    pub fn ram_dirty(&self) -> bool {
+        self.dirty
+    }
+
+    /// Returns whether RAM is dirty, and marks it clean, for when it's about to be saved.
+    pub fn take_dirty(&mut self) -> bool {
+        std::mem::take(&mut self.dirty)
+    }
+
+    pub fn rom_bank_count(&self) -> usize {
+        self.mem.rom.len() / ROM_BANK_SIZE
+    }
+
+    pub fn ram_bank_count(&self) -> usize {
+        self.mem.ram.len().div_ceil(RAM_BANK_SIZE)
+    }
+
+    /// The offset into the ROM image that the CPU address `addr` (0x0000-0x7fff) currently maps
+    /// to.
+    pub fn rom_offset(&self, addr: u16) -> usize {
+        self.mbc.rom_offset(addr) & (self.mem.rom.len() - 1)
+    }
+
+    /// The ROM bank currently mapped at the CPU address `addr` (0x0000-0x7fff).
+    pub fn rom_bank(&self, addr: u16) -> usize {
+        self.rom_offset(addr) / ROM_BANK_SIZE
+    }
+
+    /// Qualifies the CPU address `addr` with the ROM bank currently mapped there, if any.
+    pub fn banked_address(&self, addr: u16) -> BankedAddress {
+        let bank = (addr < 0x8000).then(|| self.rom_bank(addr));
+        BankedAddress::new(bank, addr)
+    }
+
+    /// Sound from the cartridge on the VIN pin, from -1 to 1. None of the mappers emulated have
+    /// any, but some do on real hardware, like HuC-3's melody generator.
+    pub fn vin(&self) -> f32 {
+        0.0
+    }
+
+    /// The RAM bank currently mapped at 0xa000-0xbfff, if the cart has RAM.
+    pub fn ram_bank(&self) -> Option<usize> {
+        let len = self.mem.ram.len();
+        (len != 0).then(|| (self.mbc.ram_offset(0) & (len - 1)) / RAM_BANK_SIZE)
     }
 }
 
@@ -71,36 +188,52 @@ pub enum RomParseError {
     UnknownRomSize(u8),
     #[error("Unknown RAM size ID: {0:#x}")]
     UnknownRamSize(u8),
-    #[error("Provided ROM is too large")]
+    #[error("Provided ROM is larger than its header says and isn't a multiple of 32 KiB")]
     LargeRom,
 }
 
 impl Cart {
-    pub fn from_rom(mut rom: Box<[u8]>) -> Result<Self, RomParseError> {
-        let cart_type = rom[0x147];
-        let rom_size = match rom[0x148] {
-            id @ 0x0..=0x8 => 1 << (id + 15),
-            id => return Err(RomParseError::UnknownRomSize(id)),
-        };
-        let mut ram_size = match rom[0x149] {
-            0x00 => 0,
-            0x02 => 0x2000,
-            0x03 => 0x8000,
-            0x04 => 0x20000,
-            0x05 => 0x10000,
-            id => return Err(RomParseError::UnknownRamSize(id)),
-        };
+    pub fn from_rom(rom: Box<[u8]>) -> Result<Self, RomParseError> {
+        Self::parse(rom, None)
+    }
+
+    /// Like [`Self::from_rom`], but with `mbc` in place of the one the header asks for, for ROMs
+    /// with a wrong or unknown cartridge type. The header still decides whether there's a battery,
+    /// an RTC, or a rumble motor.
+    pub fn from_rom_with_mbc(rom: Box<[u8]>, mbc: MbcKind) -> Result<Self, RomParseError> {
+        Self::parse(rom, Some(mbc))
+    }
+
+    fn parse(mut rom: Box<[u8]>, mbc: Option<MbcKind>) -> Result<Self, RomParseError> {
+        let header = Header::parse(&rom);
+        let cart_type = header.cart_type;
+        let mut warnings = Vec::new();
+        let header_rom_size = header
+            .rom_size()
+            .ok_or(RomParseError::UnknownRomSize(header.rom_size_id))?;
+        if matches!(header.rom_size_id, 0x52..=0x54) {
+            warnings.push(EventKind::NonstandardRomSize(header.rom_size_id));
+        }
+        let mut ram_size = header
+            .ram_size()
+            .ok_or(RomParseError::UnknownRamSize(header.ram_size_id))?;
 
-        let mbc = match cart_type {
-            0x00 | 0x08 | 0x09 => AnyMbc::Simple(Default::default()),
-            0x01..=0x03 => AnyMbc::Mbc1(Default::default()),
-            0x05 | 0x06 => {
+        let kind = mbc
+            .or_else(|| MbcKind::from_cart_type(cart_type))
+            .ok_or(RomParseError::UnknownCartType(cart_type))?;
+        let mbc = match kind {
+            MbcKind::None => AnyMbc::Simple(Default::default()),
+            MbcKind::Mbc1 => AnyMbc::Mbc1(Default::default()),
+            MbcKind::Mbc2 => {
                 ram_size = 512;
                 AnyMbc::Mbc2(Default::default())
             }
-            0x0f | 0x10 => AnyMbc::Mbc3(Mbc3::new_with_rtc()),
-            0x11..=0x13 => AnyMbc::Mbc3(Default::default()),
-            _ => return Err(RomParseError::UnknownCartType(cart_type)),
+            MbcKind::Mbc3 if matches!(cart_type, 0x0f | 0x10) => AnyMbc::Mbc3(Mbc3::new_with_rtc()),
+            MbcKind::Mbc3 => AnyMbc::Mbc3(Default::default()),
+            MbcKind::Mbc5 if matches!(cart_type, 0x1c..=0x1e) => {
+                AnyMbc::Mbc5(Mbc5::new_with_rumble())
+            }
+            MbcKind::Mbc5 => AnyMbc::Mbc5(Default::default()),
         };
 
         let battery_backed = matches!(
@@ -108,9 +241,17 @@ impl Cart {
             0x03 | 0x06 | 0x09 | 0x0d | 0x0f | 0x10 | 0x13 | 0x1b | 0x1e | 0x22 | 0xff
         );
 
-        if rom_size < rom.len() {
-            return Err(RomParseError::LargeRom);
+        if header_rom_size < rom.len() {
+            if rom.len() & 0x7fff != 0 {
+                return Err(RomParseError::LargeRom);
+            }
+            warnings.push(EventKind::OversizedRom {
+                header: header_rom_size,
+                file: rom.len(),
+            });
         }
+        // Pad out to a power of two so that bank numbers can just be masked
+        let rom_size = header_rom_size.max(rom.len()).next_power_of_two();
         if rom_size > rom.len() {
             let mut vec = Vec::from(rom);
             vec.resize(rom_size, 0);
@@ -123,26 +264,122 @@ impl Cart {
         Ok(Self {
             mem: Mem { rom, ram },
             mbc,
+            header,
             battery_backed,
+            warnings,
+            dirty: false,
         })
     }
 
+    pub fn mbc_kind(&self) -> MbcKind {
+        match self.mbc {
+            AnyMbc::Simple(_) => MbcKind::None,
+            AnyMbc::Mbc1(_) => MbcKind::Mbc1,
+            AnyMbc::Mbc2(_) => MbcKind::Mbc2,
+            AnyMbc::Mbc3(_) => MbcKind::Mbc3,
+            AnyMbc::Mbc5(_) => MbcKind::Mbc5,
+        }
+    }
+
+    /// Problems with the ROM, or its save, that were worked around while loading them.
+    pub fn warnings(&self) -> &[EventKind] {
+        &self.warnings
+    }
+
     pub fn load_from_save(&mut self, save: CartSave) {
-        if let MbcSave::Rtc(rtc) = save.mbc {
-            if let AnyMbc::Mbc3(mbc3) = &mut self.mbc {
-                if mbc3.has_rtc() {
-                    mbc3.set_rtc(rtc.into())
-                }
+        let rtc = match save.mbc {
+            MbcSave::None => None,
+            MbcSave::LegacyRtc(rtc) => Some(rtc.into()),
+            MbcSave::Rtc(rtc) => Some(rtc.into()),
+        };
+        if let (Some(rtc), AnyMbc::Mbc3(mbc3)) = (rtc, &mut self.mbc) {
+            if mbc3.has_rtc() {
+                mbc3.set_rtc(rtc)
             }
         }
 
-        self.mem.ram = save.ram.try_into().unwrap();
+        let expected = self.mem.ram.len();
+        let mut ram = Vec::from(save.ram);
+        if ram.len() != expected {
+            // Either the header or whatever wrote the save got the size wrong. Keep as much of it
+            // as fits.
+            self.warnings.push(EventKind::SaveSizeMismatch {
+                ram: expected,
+                save: ram.len(),
+            });
+            ram.resize(expected, 0);
+        }
+        self.mem.ram = ram
+            .into_boxed_slice()
+            .try_into()
+            .expect("Cartridge RAM is a power of two");
+    }
+
+    fn rtc_mut(&mut self) -> Option<&mut Rtc> {
+        match &mut self.mbc {
+            AnyMbc::Mbc3(mbc3) => mbc3.rtc_mut(),
+            _ => None,
+        }
+    }
+
+    /// Switches what the RTC keeps time by, for carts that have one. The RTC reads the same
+    /// afterwards, and carries on from there. An RTC loaded from a save first catches up on the
+    /// time since it was made, once it has a clock that keeps real time.
+    pub fn set_rtc_clock(&mut self, clock: RtcClock) {
+        if let Some(rtc) = self.rtc_mut() {
+            rtc.set_clock(clock);
+        }
+    }
+
+    /// Moves the RTC on by `elapsed` of emulated time, if it's keeping [`RtcClock::Emulated`]
+    /// time.
+    pub fn advance_rtc(&mut self, elapsed: Duration) {
+        if let Some(rtc) = self.rtc_mut() {
+            rtc.advance(elapsed);
+        }
+    }
+
+    /// Replaces cartridge RAM with `size` bytes, rounded up to a power of two, for games whose
+    /// header gets it wrong. Must be done before loading a save.
+    pub fn set_ram_size(&mut self, size: usize) {
+        let size = if size == 0 {
+            0
+        } else {
+            size.next_power_of_two()
+        };
+        self.mem.ram = OptionalSegment::new(size);
+    }
+
+    pub fn header(&self) -> &Header {
+        &self.header
+    }
+
+    /// The game's title from the header, without padding.
+    pub fn title(&self) -> String {
+        self.header.title.clone()
+    }
+
+    /// Whether the header says the game uses the CGB's features, rather than running in
+    /// compatibility mode.
+    pub fn cgb(&self) -> bool {
+        self.header.cgb != CgbSupport::None
+    }
+
+    /// Whether the cart's rumble motor is running, which is always false for carts without one.
+    /// Each switch is also pushed as [`EventKind::Rumble`](crate::event::EventKind::Rumble).
+    pub fn rumble(&self) -> bool {
+        matches!(&self.mbc, AnyMbc::Mbc5(mbc5) if mbc5.rumble())
     }
 
     pub fn battery_backed(&self) -> bool {
         self.battery_backed
     }
 
+    /// Every bank of cartridge RAM, whether or not it's battery backed.
+    pub fn ram(&self) -> Box<[u8]> {
+        self.mem.ram.raw()
+    }
+
     pub fn save(&self) -> Option<CartSave> {
         if self.battery_backed {
             Some(CartSave {
@@ -154,3 +391,192 @@ impl Cart {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn mbc1_cart() -> Cart {
+        let mut rom = vec![0; 8 * ROM_BANK_SIZE];
+        rom[0x147] = 0x03;
+        rom[0x148] = 0x02;
+        rom[0x149] = 0x03;
+        Cart::from_rom(rom.into_boxed_slice()).unwrap()
+    }
+
+    #[test]
+    fn bank_introspection() {
+        let mut cart = mbc1_cart();
+        let events = EventLog::new();
+        assert_eq!((cart.rom_bank_count(), cart.ram_bank_count()), (8, 4));
+        assert_eq!((cart.rom_bank(0x0000), cart.rom_bank(0x4000)), (0, 1));
+
+        cart.write_low(0x2000, 0x05, &events);
+        assert_eq!(cart.rom_bank(0x7fff), 5);
+        assert_eq!(cart.rom_offset(0x4f20), 5 * ROM_BANK_SIZE + 0xf20);
+
+        // Banks past the end of the ROM wrap around
+        cart.write_low(0x2000, 0x0b, &events);
+        assert_eq!(cart.rom_bank(0x4000), 3);
+
+        assert_eq!(cart.ram_bank(), Some(0));
+        cart.write_low(0x4000, 0x02, &events);
+        cart.write_low(0x6000, 0x01, &events);
+        assert_eq!(cart.ram_bank(), Some(2));
+    }
+
+    #[test]
+    fn banked_addresses() {
+        let cart = mbc1_cart();
+        assert_eq!(cart.banked_address(0x4f20).to_string(), "01:4f20");
+        assert_eq!(cart.banked_address(0xc000).to_string(), "c000");
+
+        let breakpoint: BankedAddress = "03:4F20".parse().unwrap();
+        assert_eq!(breakpoint, BankedAddress::new(Some(3), 0x4f20));
+        assert!(!breakpoint.matches(cart.banked_address(0x4f20)));
+        let breakpoint: BankedAddress = "4f20".parse().unwrap();
+        assert!(breakpoint.matches(cart.banked_address(0x4f20)));
+        assert!("03:4g20".parse::<BankedAddress>().is_err());
+    }
+
+    #[test]
+    fn unusual_rom_sizes() {
+        // 1.5 MiB according to the header
+        let mut rom = vec![0; 96 * ROM_BANK_SIZE];
+        rom[0x148] = 0x54;
+        let cart = Cart::from_rom(rom.into_boxed_slice()).unwrap();
+        assert_eq!(cart.rom_bank_count(), 128);
+        assert!(matches!(
+            cart.warnings(),
+            [EventKind::NonstandardRomSize(0x54)]
+        ));
+
+        // Larger than the header's 32 KiB
+        let rom = vec![0; 4 * ROM_BANK_SIZE];
+        let cart = Cart::from_rom(rom.into_boxed_slice()).unwrap();
+        assert_eq!(cart.rom_bank_count(), 4);
+        assert!(matches!(cart.warnings(), [EventKind::OversizedRom { .. }]));
+
+        let rom = vec![0; 2 * ROM_BANK_SIZE + 0x100];
+        assert!(matches!(
+            Cart::from_rom(rom.into_boxed_slice()),
+            Err(RomParseError::LargeRom)
+        ));
+    }
+
+    #[test]
+    fn mbc5() {
+        let mut rom = vec![0; 512 * ROM_BANK_SIZE];
+        rom[0x147] = 0x1e;
+        rom[0x148] = 0x08;
+        rom[0x149] = 0x04;
+        rom[0x1ff * ROM_BANK_SIZE] = 0x42;
+        let mut cart = Cart::from_rom(rom.into_boxed_slice()).unwrap();
+        let mut events = EventLog::new();
+        assert!(cart.battery_backed());
+        assert_eq!(cart.rom_bank(0x4000), 1);
+
+        // 9 bit ROM bank number
+        cart.write_low(0x2000, 0xff, &events);
+        cart.write_low(0x3000, 0x01, &events);
+        assert_eq!(cart.read_low(0x4000), 0x42);
+        cart.write_low(0x2000, 0x00, &events);
+        cart.write_low(0x3000, 0x00, &events);
+        assert_eq!(cart.rom_bank(0x4000), 0);
+
+        // The motor takes the top bit of the RAM bank on rumble carts
+        cart.write_low(0x4000, 0x0b, &events);
+        assert!(cart.rumble());
+        assert_eq!(cart.ram_bank(), Some(3));
+        cart.write_low(0x4000, 0x0a, &events);
+        cart.write_low(0x4000, 0x03, &events);
+        assert!(!cart.rumble());
+
+        // Only switching the motor is an event
+        let switches: Vec<_> = events
+            .drain()
+            .filter_map(|event| match event.kind {
+                EventKind::Rumble(on) => Some(on),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(switches, [true, false]);
+    }
+
+    #[test]
+    fn title() {
+        let mut rom = vec![0; 2 * ROM_BANK_SIZE];
+        rom[0x134..0x13f].copy_from_slice(b"PM_CRYSTAL ");
+        // CGB flag, which older headers used as the last character of the title
+        rom[0x143] = 0x80;
+        let cart = Cart::from_rom(rom.into_boxed_slice()).unwrap();
+        assert_eq!(cart.title(), "PM_CRYSTAL");
+    }
+
+    #[test]
+    fn save_size_mismatch() {
+        let save = |len| CartSave {
+            mbc: MbcSave::None,
+            ram: vec![0x42; len].into_boxed_slice(),
+        };
+        // 32 KiB of RAM
+        let mut cart = mbc1_cart();
+        cart.load_from_save(save(0x10000));
+        assert_eq!(cart.ram().len(), 0x8000);
+        assert!(matches!(
+            cart.warnings(),
+            [EventKind::SaveSizeMismatch {
+                ram: 0x8000,
+                save: 0x10000
+            }]
+        ));
+
+        let mut cart = mbc1_cart();
+        cart.load_from_save(save(0x2000));
+        assert_eq!(cart.ram()[..0x2000], [0x42; 0x2000]);
+        assert_eq!(cart.ram()[0x2000..], [0; 0x6000]);
+
+        let mut cart = mbc1_cart();
+        cart.set_ram_size(0x3000);
+        assert_eq!(cart.ram_bank_count(), 2);
+        cart.load_from_save(save(0x4000));
+        assert!(cart.warnings().is_empty());
+    }
+
+    #[test]
+    fn mbc_override() {
+        assert_eq!(mbc1_cart().mbc_kind(), MbcKind::Mbc1);
+
+        let mut rom = vec![0; 2 * ROM_BANK_SIZE];
+        rom[0x147] = 0xee;
+        let rom: Box<[u8]> = rom.into();
+        assert!(matches!(
+            Cart::from_rom(rom.clone()),
+            Err(RomParseError::UnknownCartType(0xee))
+        ));
+        let cart = Cart::from_rom_with_mbc(rom, MbcKind::Mbc2).unwrap();
+        assert_eq!(cart.mbc_kind(), MbcKind::Mbc2);
+        // MBC2's built in RAM
+        assert_eq!(cart.ram().len(), 512);
+
+        // The header's RTC carries over
+        let mut rom = vec![0; 2 * ROM_BANK_SIZE];
+        rom[0x147] = 0x10;
+        let cart = Cart::from_rom_with_mbc(rom.into(), MbcKind::Mbc3).unwrap();
+        assert!(matches!(&cart.mbc, AnyMbc::Mbc3(mbc3) if mbc3.has_rtc()));
+    }
+
+    #[test]
+    fn dirty() {
+        let events = EventLog::new();
+        let mut cart = mbc1_cart();
+        assert!(!cart.ram_dirty());
+        // Enable RAM, which isn't a write to it
+        cart.write_low(0x0000, 0x0a, &events);
+        assert!(!cart.take_dirty());
+        cart.write_high(0xa000, 0x42, &events);
+        assert!(cart.ram_dirty());
+        assert!(cart.take_dirty());
+        assert!(!cart.ram_dirty());
+    }
+}
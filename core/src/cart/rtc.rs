@@ -2,10 +2,15 @@
 // Copyright (C) 2023 Robert Hrusecky <jadedpastabowl@gmail.com>
 
 use bilge::prelude::*;
+use serde::{Deserialize, Serialize};
 
-use std::time::{Duration, SystemTime};
+use std::{
+    fmt,
+    sync::{Arc, OnceLock},
+    time::{Duration, Instant, SystemTime},
+};
 
-use super::save::RtcSave;
+use super::save::{LegacyRtcSave, RtcSave};
 
 const SECONDS_PER_MINUTE: u64 = 60;
 const MINUTES_PER_HOUR: u64 = 60;
@@ -13,49 +18,147 @@ const HOURS_PER_DAY: u64 = 24;
 const SECONDS_PER_HOUR: u64 = SECONDS_PER_MINUTE * MINUTES_PER_HOUR;
 const SECONDS_PER_DAY: u64 = SECONDS_PER_HOUR * HOURS_PER_DAY;
 
+/// Where an RTC can get the time from, for frontends that bring their own clock, like a browser's,
+/// or tests that need one they can move by hand.
+pub trait ClockSource: Send + Sync {
+    /// The time since some fixed point, which must never go backwards.
+    fn now(&self) -> Duration;
+
+    /// The time since the UNIX epoch, for clocks that keep real time. The RTC only catches up on
+    /// the time that passed while the emulator was closed if its clock has this.
+    fn unix_time(&self) -> Option<Duration> {
+        None
+    }
+}
+
+/// The host's clocks, from the standard library. Elapsed time is measured with the monotonic clock
+/// so that wall clock adjustments while the game is running don't disturb the RTC. The wall clock
+/// is only consulted when saving and loading.
+struct HostClock;
+
+impl ClockSource for HostClock {
+    fn now(&self) -> Duration {
+        static START: OnceLock<Instant> = OnceLock::new();
+        START.get_or_init(Instant::now).elapsed()
+    }
+
+    fn unix_time(&self) -> Option<Duration> {
+        Some(
+            SystemTime::now()
+                .duration_since(SystemTime::UNIX_EPOCH)
+                .unwrap_or_default(),
+        )
+    }
+}
+
+/// What the RTC keeps time by.
+#[derive(Clone, Default)]
+pub enum RtcClock {
+    /// The host's clock, so that the RTC keeps real time, even while the emulator is closed. The
+    /// standard library has no clock on the web, where the default is [`Self::Emulated`] instead.
+    #[cfg_attr(not(target_arch = "wasm32"), default)]
+    Host,
+    /// Only the time that passes in the emulated machine, so that runs are deterministic, like
+    /// for movies
+    #[cfg_attr(target_arch = "wasm32", default)]
+    Emulated,
+    /// A clock supplied by the frontend
+    Custom(Arc<dyn ClockSource>),
+}
+
+impl RtcClock {
+    fn source(&self) -> Option<&dyn ClockSource> {
+        match self {
+            Self::Host => Some(&HostClock),
+            Self::Emulated => None,
+            Self::Custom(source) => Some(source.as_ref()),
+        }
+    }
+
+    /// `None` for emulated time, which only moves when it's advanced.
+    fn now(&self) -> Option<Duration> {
+        self.source().map(ClockSource::now)
+    }
+
+    fn unix_time(&self) -> Option<Duration> {
+        self.source().and_then(ClockSource::unix_time)
+    }
+}
+
+impl fmt::Debug for RtcClock {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Host => f.write_str("Host"),
+            Self::Emulated => f.write_str("Emulated"),
+            Self::Custom(_) => f.write_str("Custom"),
+        }
+    }
+}
+
+/// Counts up from `value` by the time its clock says has passed since `since`, or by however much
+/// it's advanced with [`RtcClock::Emulated`].
+#[derive(Clone)]
 struct Counter {
-    base: SystemTime,
-    halted: Option<SystemTime>,
+    value: Duration,
+    since: Duration,
+    halted: bool,
+    clock: RtcClock,
 }
 
 impl Default for Counter {
     fn default() -> Self {
-        Self {
-            base: SystemTime::UNIX_EPOCH,
-            halted: None,
-        }
+        Self::new(Duration::ZERO, false, RtcClock::default())
     }
 }
 
 impl Counter {
+    fn new(value: Duration, halted: bool, clock: RtcClock) -> Self {
+        Self {
+            value,
+            since: clock.now().unwrap_or_default(),
+            halted,
+            clock,
+        }
+    }
+
+    fn set_clock(&mut self, clock: RtcClock) {
+        *self = Self::new(self.get(), self.halted, clock);
+    }
+
+    fn advance(&mut self, elapsed: Duration) {
+        if matches!(self.clock, RtcClock::Emulated) && !self.halted {
+            self.value += elapsed;
+        }
+    }
+
     fn halt(&mut self) {
-        if self.halted.is_none() {
-            self.halted = Some(SystemTime::now());
+        if !self.halted {
+            self.value = self.get();
+            self.halted = true;
         }
     }
 
     fn resume(&mut self) {
-        if let Some(halted) = self.halted {
-            self.base += halted.elapsed().unwrap_or_default();
-            self.halted = None;
+        if self.halted {
+            self.since = self.clock.now().unwrap_or_default();
+            self.halted = false;
         }
     }
 
     fn halted(&self) -> bool {
-        self.halted.is_some()
+        self.halted
     }
 
     fn set(&mut self, time: Duration) {
-        let now = SystemTime::now();
-        self.base = now - time;
-        if let Some(halted) = &mut self.halted {
-            *halted = now;
-        }
+        self.value = time;
+        self.since = self.clock.now().unwrap_or_default();
     }
 
     fn get(&self) -> Duration {
-        let end = self.halted.unwrap_or_else(SystemTime::now);
-        end.duration_since(self.base).unwrap_or_default()
+        match self.clock.now() {
+            Some(now) if !self.halted => self.value + now.saturating_sub(self.since),
+            _ => self.value,
+        }
     }
 }
 
@@ -68,12 +171,17 @@ pub struct RtcFlags {
     day_carry: bool,
 }
 
-#[derive(Default)]
+#[derive(Default, Clone, Serialize, Deserialize)]
+/// Save states go through the same format as battery saves, since a clock can't be serialized.
+#[serde(into = "RtcSave", from = "RtcSave")]
 pub struct Rtc {
     counter: Counter,
     latched: Duration,
     latch_signal: bool,
     day_carry: bool,
+    /// When the save this was restored from was made, if the time since then has yet to be caught
+    /// up on. That waits for a clock that keeps real time.
+    saved_at: Option<Duration>,
 }
 
 impl Rtc {
@@ -144,38 +252,183 @@ impl Rtc {
             .set(current + days256 * ((flags.day_msb() as u32) - current_days_msb))
     }
 
-    pub fn latch(&mut self, high: bool) {
+    /// Latches the current time on a rising edge of `high`. Returns whether the day counter
+    /// overflowed.
+    pub fn latch(&mut self, high: bool) -> bool {
+        let mut overflow = false;
         if !self.latch_signal && high {
             self.latched = self.counter.get();
             if self.days() >= 512 {
                 self.day_carry = true;
-                // Move the base forward so we have the opportunity to overflow again
-                self.counter.base += Duration::from_secs(SECONDS_PER_DAY * 512);
+                overflow = true;
+                // Wind the counter back so we have the opportunity to overflow again
+                let days512 = Duration::from_secs(SECONDS_PER_DAY * 512);
+                self.counter.set(self.counter.get() - days512);
             }
         }
         self.latch_signal = high;
+        overflow
     }
 
+    /// Switches what the RTC keeps time by. It reads the same afterwards, plus the time that passed
+    /// since the save it was restored from, if `clock` is the first to keep real time since.
+    pub fn set_clock(&mut self, clock: RtcClock) {
+        self.counter.set_clock(clock);
+        if let Some(now) = self.counter.clock.unix_time() {
+            if let Some(saved_at) = self.saved_at.take() {
+                self.counter.value += now.saturating_sub(saved_at);
+            }
+        }
+    }
+
+    /// Moves the counter on by `elapsed` of emulated time, if it's keeping emulated time.
+    pub fn advance(&mut self, elapsed: Duration) {
+        self.counter.advance(elapsed);
+    }
+
+    /// Without a clock that keeps real time, the save is marked as made at the UNIX epoch, and
+    /// won't be caught up on when it's restored.
     pub fn save(&self) -> RtcSave {
+        let saved_at = self.counter.clock.unix_time().or(self.saved_at);
         RtcSave {
-            base: self.counter.base,
+            counter: self.counter.get(),
+            saved_at: saved_at.unwrap_or_default(),
+            halted: self.counter.halted(),
             latched: self.latched,
+            latch_signal: self.latch_signal,
             day_carry: self.day_carry,
-            halted: self.counter.halted,
         }
     }
 }
 
+impl From<Rtc> for RtcSave {
+    fn from(rtc: Rtc) -> Self {
+        rtc.save()
+    }
+}
+
+/// A restored RTC is stopped, at exactly the time it was saved, until it's given a clock with
+/// [`Rtc::set_clock`], so that restoring is deterministic.
 impl From<RtcSave> for Rtc {
     fn from(save: RtcSave) -> Self {
+        let caught_up = save.halted || save.saved_at.is_zero();
         Self {
-            counter: Counter {
-                base: save.base,
-                halted: save.halted,
-            },
+            counter: Counter::new(save.counter, save.halted, RtcClock::Emulated),
+            latched: save.latched,
+            latch_signal: save.latch_signal,
+            day_carry: save.day_carry,
+            saved_at: (!caught_up).then_some(save.saved_at),
+        }
+    }
+}
+
+impl From<LegacyRtcSave> for Rtc {
+    fn from(save: LegacyRtcSave) -> Self {
+        let end = save.halted.unwrap_or_else(SystemTime::now);
+        let counter = end.duration_since(save.base).unwrap_or_default();
+        Self {
+            counter: Counter::new(counter, save.halted.is_some(), RtcClock::Emulated),
             latched: save.latched,
             latch_signal: false,
             day_carry: save.day_carry,
+            saved_at: None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicU64, Ordering};
+
+    use super::*;
+
+    /// Keeps real time that only moves when told to.
+    #[derive(Default)]
+    struct MockClock(AtomicU64);
+
+    impl MockClock {
+        fn wait(&self, seconds: u64) {
+            self.0.fetch_add(seconds, Ordering::Relaxed);
+        }
+    }
+
+    impl ClockSource for MockClock {
+        fn now(&self) -> Duration {
+            Duration::from_secs(self.0.load(Ordering::Relaxed))
         }
+
+        fn unix_time(&self) -> Option<Duration> {
+            Some(self.now() + Duration::from_secs(1_700_000_000))
+        }
+    }
+
+    #[test]
+    fn halted_save_round_trip() {
+        let mut rtc = Rtc::default();
+        rtc.set_flags(RtcFlags::new(false, u5::new(0), true, false));
+        rtc.set_hours(5);
+        rtc.set_minutes(42);
+        let mut save = rtc.save();
+        // A halted RTC should not advance no matter how long ago it was saved
+        save.saved_at = Duration::ZERO;
+        let mut rtc = Rtc::from(save);
+        rtc.set_clock(RtcClock::Host);
+        rtc.latch(true);
+        assert_eq!((rtc.days(), rtc.hours(), rtc.minutes()), (0, 5, 42));
+        assert!(rtc.flags().halt());
+    }
+
+    #[test]
+    fn running_save_catches_up() {
+        let mut save = Rtc::default().save();
+        save.saved_at -= Duration::from_secs(SECONDS_PER_DAY * 3);
+        let mut rtc = Rtc::from(save);
+        // Not until it has a clock that keeps real time
+        rtc.set_clock(RtcClock::Emulated);
+        rtc.latch(true);
+        assert_eq!(rtc.days(), 0);
+        rtc.set_clock(RtcClock::Host);
+        rtc.latch(false);
+        rtc.latch(true);
+        assert_eq!(rtc.days(), 3);
+    }
+
+    #[test]
+    fn emulated_clock() {
+        let mut rtc = Rtc::default();
+        rtc.set_clock(RtcClock::Emulated);
+        rtc.advance(Duration::from_secs(90));
+        let save = rtc.save();
+        // Unlike with the host's clock, restoring doesn't catch up, whatever keeps time afterwards
+        assert!(save.saved_at.is_zero());
+        let mut rtc = Rtc::from(save);
+        rtc.set_clock(RtcClock::Emulated);
+        rtc.latch(true);
+        assert_eq!((rtc.days(), rtc.minutes(), rtc.seconds()), (0, 1, 30));
+
+        // Nor does a halted RTC advance
+        rtc.set_flags(RtcFlags::new(false, u5::new(0), true, false));
+        rtc.advance(Duration::from_secs(60));
+        rtc.latch(false);
+        rtc.latch(true);
+        assert_eq!((rtc.minutes(), rtc.seconds()), (1, 30));
+    }
+
+    #[test]
+    fn custom_clock() {
+        let clock = Arc::new(MockClock::default());
+        let mut rtc = Rtc::default();
+        rtc.set_clock(RtcClock::Custom(clock.clone()));
+        clock.wait(2 * SECONDS_PER_HOUR + 5);
+        rtc.latch(true);
+        assert_eq!((rtc.hours(), rtc.seconds()), (2, 5));
+
+        let save = rtc.save();
+        clock.wait(SECONDS_PER_DAY);
+        let mut rtc = Rtc::from(save);
+        rtc.set_clock(RtcClock::Custom(clock));
+        rtc.latch(false);
+        rtc.latch(true);
+        assert_eq!((rtc.days(), rtc.hours(), rtc.seconds()), (1, 2, 5));
     }
 }
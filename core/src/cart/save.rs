@@ -1,23 +1,122 @@
 // SPDX-License-Identifier: GPL-3.0-or-later
 // Copyright (C) 2023 Robert Hrusecky <jadedpastabowl@gmail.com>
 
-use std::time::{Duration, SystemTime};
+use std::{
+    array, mem,
+    time::{Duration, SystemTime},
+};
 
+use bincode::Options as _;
 use serde::{Deserialize, Serialize};
 
-use super::{Cart, Mbc};
+use super::{mem::OptionalSegment, AnyMbc, Cart, Mbc};
 
+/// The original RTC save format, which stored the wall clock time the counter started at. Only
+/// kept around to load old saves.
 #[derive(Serialize, Deserialize)]
-pub struct RtcSave {
+pub struct LegacyRtcSave {
     pub base: SystemTime,
     pub latched: Duration,
     pub day_carry: bool,
     pub halted: Option<SystemTime>,
 }
 
+/// The size of the RTC footer that other emulators, like BGB and VBA, append to the RAM in a
+/// `.sav` file. Some write a 32 bit timestamp instead, making it 4 bytes shorter.
+const SAV_FOOTER_LEN: usize = 48;
+const SHORT_SAV_FOOTER_LEN: usize = 44;
+/// Cartridge RAM always comes in multiples of this, so anything left over is a footer.
+const SAV_RAM_UNIT: usize = 0x200;
+
+const SECONDS_PER_DAY: u64 = 24 * 60 * 60;
+
+#[derive(Serialize, Deserialize)]
+pub struct RtcSave {
+    /// Value of the counter at the time of the save
+    pub counter: Duration,
+    /// UNIX time of the save, used to advance the counter when it is loaded
+    pub saved_at: Duration,
+    pub halted: bool,
+    pub latched: Duration,
+    pub latch_signal: bool,
+    pub day_carry: bool,
+}
+
+impl RtcSave {
+    /// The RTC registers as a game would read them from `time`: seconds, minutes, hours, the low
+    /// byte of the day, and the flags.
+    fn registers(&self, time: Duration) -> [u8; 5] {
+        let secs = time.as_secs();
+        let days = secs / SECONDS_PER_DAY;
+        // The counter only wraps when it's latched, so it may have gone past the carry since
+        let carry = self.day_carry || days >= 512;
+        let flags = ((days >> 8) & 0x1) as u8 | (self.halted as u8) << 6 | (carry as u8) << 7;
+        [
+            (secs % 60) as u8,
+            (secs / 60 % 60) as u8,
+            (secs / 3600 % 24) as u8,
+            days as u8,
+            flags,
+        ]
+    }
+
+    /// The time the RTC registers in `regs` add up to, and whether they have the carry set.
+    fn time(regs: [u32; 5]) -> (Duration, bool) {
+        let [secs, mins, hours, days_low, flags] = regs.map(u64::from);
+        let days = (days_low & 0xff) | (flags & 0x1) << 8;
+        let secs = secs % 60 + mins % 60 * 60 + hours % 24 * 3600 + days * SECONDS_PER_DAY;
+        (Duration::from_secs(secs), flags & 0x80 != 0)
+    }
+
+    /// The footer other emulators put after cartridge RAM: the live and latched registers, each
+    /// as a little endian 32 bit number, then the UNIX time of the save in seconds. The fraction
+    /// of a second the counter was into is lost.
+    pub fn to_sav_footer(&self) -> [u8; SAV_FOOTER_LEN] {
+        let mut footer = [0; SAV_FOOTER_LEN];
+        let regs = self
+            .registers(self.counter)
+            .into_iter()
+            .chain(self.registers(self.latched));
+        for (chunk, reg) in footer.chunks_exact_mut(4).zip(regs) {
+            chunk.copy_from_slice(&u32::from(reg).to_le_bytes());
+        }
+        footer[40..].copy_from_slice(&self.saved_at.as_secs().to_le_bytes());
+        footer
+    }
+
+    /// Reads a footer from [`Self::to_sav_footer`], or one with a 32 bit timestamp. `None` if
+    /// it's neither length.
+    pub fn from_sav_footer(footer: &[u8]) -> Option<Self> {
+        if !matches!(footer.len(), SAV_FOOTER_LEN | SHORT_SAV_FOOTER_LEN) {
+            return None;
+        }
+        let (regs, timestamp) = footer.split_at(40);
+        let mut words = regs
+            .chunks_exact(4)
+            .map(|chunk| u32::from_le_bytes(chunk.try_into().unwrap()));
+        let live: [u32; 5] = array::from_fn(|_| words.next().unwrap());
+        let latched: [u32; 5] = array::from_fn(|_| words.next().unwrap());
+        let timestamp = match timestamp.try_into() {
+            Ok(bytes) => u64::from_le_bytes(bytes),
+            Err(_) => u32::from_le_bytes(timestamp.try_into().ok()?).into(),
+        };
+        let (counter, day_carry) = Self::time(live);
+        Some(Self {
+            counter,
+            saved_at: Duration::from_secs(timestamp),
+            halted: live[4] & 0x40 != 0,
+            latched: Self::time(latched).0,
+            latch_signal: false,
+            day_carry,
+        })
+    }
+}
+
+// New variants must be added at the end so that existing saves still deserialize
 #[derive(Serialize, Deserialize)]
 pub enum MbcSave {
     None,
+    LegacyRtc(LegacyRtcSave),
     Rtc(RtcSave),
 }
 
@@ -27,6 +126,51 @@ pub struct CartSave {
     pub ram: Box<[u8]>,
 }
 
+impl CartSave {
+    /// The `.sav` format most other emulators use: cartridge RAM as is, followed by the footer
+    /// from [`RtcSave::to_sav_footer`] for carts with an RTC.
+    pub fn to_sav(&self) -> Vec<u8> {
+        let mut data = self.ram.to_vec();
+        if let MbcSave::Rtc(rtc) = &self.mbc {
+            data.extend_from_slice(&rtc.to_sav_footer());
+        }
+        data
+    }
+
+    /// Reads a `.sav` file, like from [`Self::to_sav`]. Whatever follows the last whole multiple
+    /// of 512 bytes is the RTC footer, if it's the right length. Otherwise it's kept as RAM, for
+    /// the cart to complain about.
+    pub fn from_sav(data: &[u8]) -> Self {
+        let (ram, footer) = data.split_at(data.len() - data.len() % SAV_RAM_UNIT);
+        match RtcSave::from_sav_footer(footer) {
+            Some(rtc) => Self {
+                mbc: MbcSave::Rtc(rtc),
+                ram: ram.into(),
+            },
+            None => Self {
+                mbc: MbcSave::None,
+                ram: data.into(),
+            },
+        }
+    }
+
+    /// Reads a `.cart` save, serialized with bincode. Fails if it's corrupt or truncated, or has
+    /// anything left over.
+    pub fn from_cart(data: &[u8]) -> bincode::Result<Self> {
+        bincode::DefaultOptions::new()
+            .with_fixint_encoding()
+            .reject_trailing_bytes()
+            .deserialize(data)
+    }
+
+    /// Reads a save in either format: a `.cart` save, or a `.sav` file. Anything that isn't
+    /// exactly a `.cart` save is taken for a `.sav`, so this is only for imports, where either
+    /// could turn up. A corrupt `.cart` save would be loaded as garbage RAM.
+    pub fn detect(data: &[u8]) -> Self {
+        Self::from_cart(data).unwrap_or_else(|_| Self::from_sav(data))
+    }
+}
+
 impl From<Cart> for CartSave {
     fn from(cart: Cart) -> Self {
         Self {
@@ -35,3 +179,134 @@ impl From<Cart> for CartSave {
         }
     }
 }
+
+/// Everything about the cart that changes as a game runs, for save states. Unlike [`CartSave`],
+/// this includes the MBC's bank registers.
+#[derive(Serialize)]
+pub(crate) struct CartStateRef<'a> {
+    mbc: &'a AnyMbc,
+    ram: &'a OptionalSegment,
+}
+
+#[derive(Deserialize)]
+pub(crate) struct CartState {
+    mbc: AnyMbc,
+    ram: OptionalSegment,
+}
+
+impl Cart {
+    pub(crate) fn state(&self) -> CartStateRef<'_> {
+        CartStateRef {
+            mbc: &self.mbc,
+            ram: &self.mem.ram,
+        }
+    }
+
+    /// Returns false, leaving the cart untouched, if the state is from a different kind of cart.
+    pub(crate) fn load_state(&mut self, state: CartState) -> bool {
+        if mem::discriminant(&state.mbc) != mem::discriminant(&self.mbc)
+            || state.ram.len() != self.mem.ram.len()
+        {
+            return false;
+        }
+        self.mbc = state.mbc;
+        self.mem.ram = state.ram;
+        self.dirty = true;
+        true
+    }
+
+    /// The header and global checksums, which tell ROMs apart well enough to catch loading a
+    /// save state into the wrong game.
+    pub fn checksums(&self) -> [u8; 3] {
+        let [high, low] = self.header.global_checksum.to_be_bytes();
+        [self.header.header_checksum, high, low]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rtc_save(counter: Duration) -> RtcSave {
+        RtcSave {
+            counter,
+            saved_at: Duration::from_secs(1_700_000_000),
+            halted: true,
+            latched: Duration::from_secs(3 * SECONDS_PER_DAY + 61),
+            latch_signal: true,
+            day_carry: false,
+        }
+    }
+
+    #[test]
+    fn sav_round_trip() {
+        let counter = Duration::from_secs(300 * SECONDS_PER_DAY + 5 * 3600 + 6 * 60 + 7);
+        let save = CartSave {
+            mbc: MbcSave::Rtc(rtc_save(counter + Duration::from_millis(500))),
+            ram: vec![0x42; 0x2000].into(),
+        };
+        let data = save.to_sav();
+        assert_eq!(data.len(), 0x2000 + SAV_FOOTER_LEN);
+        // Seconds, minutes, hours, and days as BGB writes them, with the day's high bit and halt
+        assert_eq!(
+            &data[0x2000..0x2014],
+            [7, 0, 0, 0, 6, 0, 0, 0, 5, 0, 0, 0, 44, 0, 0, 0, 0x41, 0, 0, 0]
+        );
+
+        let loaded = CartSave::from_sav(&data);
+        assert_eq!(loaded.ram, save.ram);
+        let MbcSave::Rtc(rtc) = loaded.mbc else {
+            panic!("The RTC footer was missed");
+        };
+        assert_eq!(rtc.counter, counter);
+        assert_eq!(rtc.saved_at, Duration::from_secs(1_700_000_000));
+        assert_eq!(rtc.latched, Duration::from_secs(3 * SECONDS_PER_DAY + 61));
+        assert!(rtc.halted && !rtc.day_carry && !rtc.latch_signal);
+
+        // Some emulators write the timestamp in 32 bits
+        let short = &data[..data.len() - 4];
+        let MbcSave::Rtc(rtc) = CartSave::from_sav(short).mbc else {
+            panic!("The short RTC footer was missed");
+        };
+        assert_eq!(rtc.saved_at, Duration::from_secs(1_700_000_000));
+    }
+
+    #[test]
+    fn sav_footer_carries() {
+        let footer = rtc_save(Duration::from_secs(515 * SECONDS_PER_DAY)).to_sav_footer();
+        let rtc = RtcSave::from_sav_footer(&footer).unwrap();
+        assert_eq!(rtc.counter, Duration::from_secs(3 * SECONDS_PER_DAY));
+        assert!(rtc.day_carry);
+    }
+
+    #[test]
+    fn detect_format() {
+        let save = CartSave {
+            mbc: MbcSave::Rtc(rtc_save(Duration::from_secs(61))),
+            ram: vec![0; 0x2000].into(),
+        };
+        let cart = bincode::serialize(&save).unwrap();
+        let MbcSave::Rtc(rtc) = CartSave::detect(&cart).mbc else {
+            panic!("The .cart save was misread");
+        };
+        assert_eq!(rtc.counter, Duration::from_secs(61));
+        assert!(CartSave::from_cart(&cart[..cart.len() - 1]).is_err());
+
+        // All zeros starts out like a .cart save without an RTC or any RAM
+        let sav = CartSave::detect(&[0; 0x2000]);
+        assert!(matches!(sav.mbc, MbcSave::None));
+        assert_eq!(sav.ram.len(), 0x2000);
+    }
+
+    #[test]
+    fn sav_without_rtc() {
+        let save = CartSave::from_sav(&[0x42; 0x2000]);
+        assert!(matches!(save.mbc, MbcSave::None));
+        assert_eq!(save.ram.len(), 0x2000);
+
+        // Leftovers that aren't a footer are left for the cart to notice
+        let save = CartSave::from_sav(&[0x42; 0x2010]);
+        assert!(matches!(save.mbc, MbcSave::None));
+        assert_eq!(save.ram.len(), 0x2010);
+    }
+}
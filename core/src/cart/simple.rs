@@ -1,9 +1,13 @@
 // SPDX-License-Identifier: GPL-3.0-or-later
 // Copyright (C) 2023 Robert Hrusecky <jadedpastabowl@gmail.com>
 
+use serde::{Deserialize, Serialize};
+
+use crate::event::{EventKind, EventLog, Severity};
+
 use super::{mem::Mem, save::MbcSave, Mbc};
 
-#[derive(Default)]
+#[derive(Default, Serialize, Deserialize)]
 pub struct Simple;
 
 impl Mbc for Simple {
@@ -11,16 +15,27 @@ impl Mbc for Simple {
         mem.rom.read(addr as usize)
     }
 
-    fn write_low(&mut self, _addr: u16, _val: u8, _mem: &mut Mem) {}
+    fn write_low(&mut self, addr: u16, val: u8, _mem: &mut Mem, events: &EventLog) {
+        // Plenty of games without an MBC write here anyway, so this is only worth a debug note
+        events.push(Severity::Debug, EventKind::SuspiciousMbcWrite(addr, val));
+    }
 
     fn read_high(&self, addr: u16, mem: &Mem) -> u8 {
         mem.ram.read(addr as usize)
     }
 
-    fn write_high(&mut self, addr: u16, val: u8, mem: &mut Mem) {
+    fn write_high(&mut self, addr: u16, val: u8, mem: &mut Mem, _events: &EventLog) {
         mem.ram.write(addr as usize, val)
     }
 
+    fn rom_offset(&self, addr: u16) -> usize {
+        addr as usize
+    }
+
+    fn ram_offset(&self, addr: u16) -> usize {
+        addr as usize
+    }
+
     fn save(&self) -> MbcSave {
         MbcSave::None
     }
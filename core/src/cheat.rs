@@ -0,0 +1,193 @@
+// SPDX-License-Identifier: GPL-3.0-or-later
+// Copyright (C) 2023 Robert Hrusecky <jadedpastabowl@gmail.com>
+
+//! Cheat codes in the formats of the two common cheat devices. Game Genie codes patch bytes of ROM
+//! as they are read, and GameShark codes poke a byte of RAM once every frame.
+
+use std::str::FromStr;
+
+use thiserror::Error;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Cheat {
+    /// Written `ABC-DEF` or `ABC-DEF-GHI`. Reads of ROM at `addr` return `value` instead, but only
+    /// when the original byte is `compare`, if there is one.
+    GameGenie {
+        addr: u16,
+        value: u8,
+        compare: Option<u8>,
+    },
+    /// Written `BBVVLLHH`, with the address little endian. The bank is ignored, and the value goes
+    /// to whatever bank is mapped at `addr`, which is what codes with the usual `01` bank expect.
+    GameShark { bank: u8, value: u8, addr: u16 },
+}
+
+#[derive(Error, Debug, PartialEq, Eq)]
+pub enum CheatParseError {
+    #[error(
+        "Invalid cheat {0:?}, expected a Game Genie (ABC-DEF-GHI) or GameShark (01VVLLHH) code"
+    )]
+    Format(String),
+    #[error("Game Genie code {0:?} patches {1:#06x}, which is outside of ROM")]
+    NotRom(String, u16),
+}
+
+impl Cheat {
+    /// The byte read from ROM at `addr` instead of `original`, if this cheat patches it.
+    fn patch_rom(&self, addr: u16, original: u8) -> Option<u8> {
+        match *self {
+            Cheat::GameGenie {
+                addr: patched,
+                value,
+                compare,
+            } if patched == addr && compare.is_none_or(|compare| compare == original) => {
+                Some(value)
+            }
+            _ => None,
+        }
+    }
+}
+
+impl FromStr for Cheat {
+    type Err = CheatParseError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let error = || CheatParseError::Format(s.to_owned());
+        let digits = s
+            .trim()
+            .chars()
+            .filter(|&c| c != '-')
+            .map(|c| c.to_digit(16).map(|d| d as u8))
+            .collect::<Option<Vec<_>>>()
+            .ok_or_else(error)?;
+        let byte = |i: usize| digits[i] << 4 | digits[i + 1];
+        match digits.len() {
+            6 | 9 => {
+                // The address nibbles are scrambled, and the top one inverted
+                let addr = ((digits[5] ^ 0xf) as u16) << 12
+                    | (digits[2] as u16) << 8
+                    | (digits[3] as u16) << 4
+                    | digits[4] as u16;
+                if addr >= 0x8000 {
+                    return Err(CheatParseError::NotRom(s.to_owned(), addr));
+                }
+                // The compare digits are G and I. H is thought to be a checksum, and is ignored.
+                let compare = (digits.len() == 9)
+                    .then(|| (digits[6] << 4 | digits[8]).rotate_right(2) ^ 0xba);
+                Ok(Cheat::GameGenie {
+                    addr,
+                    value: byte(0),
+                    compare,
+                })
+            }
+            8 => Ok(Cheat::GameShark {
+                bank: byte(0),
+                value: byte(2),
+                addr: u16::from_le_bytes([byte(4), byte(6)]),
+            }),
+            _ => Err(error()),
+        }
+    }
+}
+
+/// The cheats that are in effect. Game Genie codes are looked up on every ROM read, so the common
+/// case of no cheats is kept cheap.
+#[derive(Debug, Default)]
+pub struct Cheats {
+    game_genie: Vec<Cheat>,
+    game_shark: Vec<Cheat>,
+}
+
+impl Cheats {
+    pub fn set(&mut self, cheats: impl IntoIterator<Item = Cheat>) {
+        self.game_genie.clear();
+        self.game_shark.clear();
+        for cheat in cheats {
+            match cheat {
+                Cheat::GameGenie { .. } => self.game_genie.push(cheat),
+                Cheat::GameShark { .. } => self.game_shark.push(cheat),
+            }
+        }
+    }
+
+    pub fn patch_rom(&self, addr: u16, original: u8) -> u8 {
+        self.game_genie
+            .iter()
+            .find_map(|cheat| cheat.patch_rom(addr, original))
+            .unwrap_or(original)
+    }
+
+    /// The `(addr, value)` pairs to write at the end of each frame.
+    pub fn ram_writes(&self) -> impl Iterator<Item = (u16, u8)> + '_ {
+        self.game_shark.iter().filter_map(|cheat| match *cheat {
+            Cheat::GameShark { addr, value, .. } => Some((addr, value)),
+            Cheat::GameGenie { .. } => None,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn game_genie() {
+        let cheat: Cheat = "00A-17B-C49".parse().unwrap();
+        assert_eq!(
+            cheat,
+            Cheat::GameGenie {
+                addr: 0x4a17,
+                value: 0x00,
+                compare: Some(0xc8),
+            }
+        );
+
+        let cheat: Cheat = "3EA-FFF".parse().unwrap();
+        assert_eq!(
+            cheat,
+            Cheat::GameGenie {
+                addr: 0x0aff,
+                value: 0x3e,
+                compare: None,
+            }
+        );
+    }
+
+    #[test]
+    fn game_genie_outside_rom() {
+        assert!(matches!(
+            "00A-170-C49".parse::<Cheat>(),
+            Err(CheatParseError::NotRom(_, 0xfa17))
+        ));
+    }
+
+    #[test]
+    fn game_shark() {
+        let cheat: Cheat = "010F34D3".parse().unwrap();
+        assert_eq!(
+            cheat,
+            Cheat::GameShark {
+                bank: 0x01,
+                value: 0x0f,
+                addr: 0xd334,
+            }
+        );
+    }
+
+    #[test]
+    fn invalid() {
+        for code in ["", "0", "010F34D", "010F34DG", "00A-17B-C"] {
+            assert!(code.parse::<Cheat>().is_err(), "{code:?}");
+        }
+    }
+
+    #[test]
+    fn cheats() {
+        let mut cheats = Cheats::default();
+        cheats.set(["00A-17B-C49".parse().unwrap(), "010F34D3".parse().unwrap()]);
+        assert_eq!(cheats.patch_rom(0x4a17, 0xc8), 0x00);
+        assert_eq!(cheats.patch_rom(0x4a17, 0xc9), 0xc9);
+        assert_eq!(cheats.patch_rom(0x4a18, 0xc8), 0xc8);
+        assert_eq!(cheats.ram_writes().collect::<Vec<_>>(), [(0xd334, 0x0f)]);
+    }
+}
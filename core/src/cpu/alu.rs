@@ -317,6 +317,8 @@ impl Cpu {
 
 #[cfg(test)]
 mod tests {
+    use crate::cart::BankedAddress;
+
     use super::*;
 
     struct Memory([u8; 0x10000]);
@@ -342,6 +344,10 @@ mod tests {
             unimplemented!();
         }
 
+        fn banked_address(&self, addr: u16) -> BankedAddress {
+            addr.into()
+        }
+
         fn pop_interrupt(&mut self) -> Option<u8> {
             unimplemented!();
         }
@@ -349,6 +355,10 @@ mod tests {
         fn interrupt_pending(&mut self) -> bool {
             unimplemented!();
         }
+
+        fn switch_speed(&mut self) -> bool {
+            unimplemented!();
+        }
     }
 
     #[test]
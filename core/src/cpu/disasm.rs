@@ -0,0 +1,332 @@
+// SPDX-License-Identifier: GPL-3.0-or-later
+// Copyright (C) 2023 Robert Hrusecky <jadedpastabowl@gmail.com>
+
+//! Turns machine code back into assembly, for debuggers. Mnemonics are lowercase, with
+//! parentheses around memory operands and immediates in hex, like `ld a, (0xc000)`.
+
+use std::{collections::VecDeque, fmt::Write};
+
+use crate::cart::BankedAddress;
+
+use super::{
+    instruction_set::{self, HlIncDec, Instruction, Operand8, Test, Var8},
+    Reg16, Reg8,
+};
+
+/// The longest instruction, with its opcode and a 16 bit immediate
+pub const MAX_LEN: usize = 3;
+/// How many instructions a trace keeps
+pub const TRACE_LEN: usize = 1024;
+
+/// An instruction, decoded from the bytes at `addr`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Line {
+    pub addr: u16,
+    pub bytes: Vec<u8>,
+    pub text: String,
+}
+
+fn reg8(reg: Reg8) -> String {
+    format!("{reg:?}").to_lowercase()
+}
+
+fn reg16(reg: Reg16) -> String {
+    format!("{reg:?}").to_lowercase()
+}
+
+fn var8(var: Var8) -> String {
+    match var {
+        Var8::Reg(reg) => reg8(reg),
+        Var8::MemHl => "(hl)".into(),
+    }
+}
+
+fn test(test: Test) -> &'static str {
+    match test {
+        Test::C => "c",
+        Test::Z => "z",
+        Test::Nc => "nc",
+        Test::Nz => "nz",
+    }
+}
+
+/// `mnemonic`, with a condition if there is one, then `target`.
+fn branch(mnemonic: &str, condition: Option<Test>, target: &str) -> String {
+    match condition {
+        Some(condition) => format!("{mnemonic} {}, {target}", test(condition)),
+        None => format!("{mnemonic} {target}"),
+    }
+}
+
+fn signed(offset: i8) -> String {
+    let sign = if offset < 0 { '-' } else { '+' };
+    format!("{sign}0x{:02x}", offset.unsigned_abs())
+}
+
+/// How many bytes of immediate data follow the opcode.
+fn immediate_len(instruction: &Instruction) -> usize {
+    use Instruction::*;
+    match instruction {
+        Ld(_, Operand8::Imm)
+        | Adc(Operand8::Imm)
+        | Add(Operand8::Imm)
+        | And(Operand8::Imm)
+        | Cp(Operand8::Imm)
+        | Or(Operand8::Imm)
+        | Sbc(Operand8::Imm)
+        | Sub(Operand8::Imm)
+        | Xor(Operand8::Imm)
+        | LdhMemA
+        | LdhAMem
+        | LdHlSpInc
+        | AddSp
+        | Jr(_)
+        | Stop => 1,
+        LdMem16A | LdAMem16 | Ld16(_) | LdMemSp | Call(_) | Jp(_) => 2,
+        _ => 0,
+    }
+}
+
+/// Decodes the instruction at the start of `bytes`, which came from `addr`, and returns it along
+/// with its length. `bytes` should hold [`MAX_LEN`] bytes, or as many as there are before the
+/// end of memory; any that are missing read as 0.
+pub fn decode(addr: u16, bytes: &[u8]) -> (String, usize) {
+    use Instruction::*;
+
+    let byte = |i: usize| bytes.get(i).copied().unwrap_or(0);
+    let (entry, opcode_len);
+    let prefixed;
+    if byte(0) == instruction_set::PREFIX_OPCODE {
+        prefixed = instruction_set::entry_for_prefix_opcode(byte(1));
+        entry = &prefixed;
+        opcode_len = 2;
+    } else {
+        entry = instruction_set::entry_for_opcode(byte(0));
+        opcode_len = 1;
+    }
+    let instruction = entry.instruction;
+    let len = opcode_len + immediate_len(&instruction);
+    let imm8 = byte(opcode_len);
+    let imm16 = u16::from_le_bytes([imm8, byte(opcode_len + 1)]);
+    let operand = |operand: Operand8| match operand {
+        Operand8::Imm => format!("0x{imm8:02x}"),
+        Operand8::Var(var) => var8(var),
+    };
+    let inc_dec = |inc_dec: HlIncDec| match inc_dec {
+        HlIncDec::Inc => "(hl+)",
+        HlIncDec::Dec => "(hl-)",
+    };
+
+    let text = match instruction {
+        Nop => "nop".into(),
+        Ld(dest, src) => format!("ld {}, {}", var8(dest), operand(src)),
+        LdMemRegA(reg) => format!("ld ({}), a", reg16(reg)),
+        LdAMemReg(reg) => format!("ld a, ({})", reg16(reg)),
+        LdMem16A => format!("ld (0x{imm16:04x}), a"),
+        LdAMem16 => format!("ld a, (0x{imm16:04x})"),
+        LdhMemA => format!("ldh (0xff{imm8:02x}), a"),
+        LdhAMem => format!("ldh a, (0xff{imm8:02x})"),
+        LdhMemCA => "ld (c), a".into(),
+        LdhAMemC => "ld a, (c)".into(),
+        LdIncDecA(inc_dec_hl) => format!("ld {}, a", inc_dec(inc_dec_hl)),
+        LdAIncDec(inc_dec_hl) => format!("ld a, {}", inc_dec(inc_dec_hl)),
+
+        Ld16(reg) => format!("ld {}, 0x{imm16:04x}", reg16(reg)),
+        LdMemSp => format!("ld (0x{imm16:04x}), sp"),
+        LdHlSpInc => format!("ld hl, sp{}", signed(imm8 as i8)),
+        LdSpHl => "ld sp, hl".into(),
+        Pop(reg) => format!("pop {}", reg16(reg)),
+        Push(reg) => format!("push {}", reg16(reg)),
+
+        Bit(bit, var) => format!("bit {bit}, {}", var8(var)),
+        Res(bit, var) => format!("res {bit}, {}", var8(var)),
+        Set(bit, var) => format!("set {bit}, {}", var8(var)),
+        Dec(var) => format!("dec {}", var8(var)),
+        Inc(var) => format!("inc {}", var8(var)),
+        Rla => "rla".into(),
+        Rl(var) => format!("rl {}", var8(var)),
+        Rlca => "rlca".into(),
+        Rlc(var) => format!("rlc {}", var8(var)),
+        Rra => "rra".into(),
+        Rr(var) => format!("rr {}", var8(var)),
+        Rrca => "rrca".into(),
+        Rrc(var) => format!("rrc {}", var8(var)),
+        Sla(var) => format!("sla {}", var8(var)),
+        Sra(var) => format!("sra {}", var8(var)),
+        Srl(var) => format!("srl {}", var8(var)),
+        Swap(var) => format!("swap {}", var8(var)),
+        Adc(src) => format!("adc a, {}", operand(src)),
+        Add(src) => format!("add a, {}", operand(src)),
+        And(src) => format!("and {}", operand(src)),
+        Cp(src) => format!("cp {}", operand(src)),
+        Or(src) => format!("or {}", operand(src)),
+        Sbc(src) => format!("sbc a, {}", operand(src)),
+        Sub(src) => format!("sub {}", operand(src)),
+        Xor(src) => format!("xor {}", operand(src)),
+        Cpl => "cpl".into(),
+        Daa => "daa".into(),
+
+        AddHl(reg) => format!("add hl, {}", reg16(reg)),
+        AddSp => format!("add sp, {}", signed(imm8 as i8)),
+        Dec16(reg) => format!("dec {}", reg16(reg)),
+        Inc16(reg) => format!("inc {}", reg16(reg)),
+
+        Ccf => "ccf".into(),
+        Scf => "scf".into(),
+
+        Call(condition) => branch("call", condition, &format!("0x{imm16:04x}")),
+        Jp(condition) => branch("jp", condition, &format!("0x{imm16:04x}")),
+        JpHl => "jp hl".into(),
+        Jr(condition) => {
+            // Relative to the end of the instruction
+            let target = addr
+                .wrapping_add(len as u16)
+                .wrapping_add(imm8 as i8 as u16);
+            branch("jr", condition, &format!("0x{target:04x}"))
+        }
+        Rst(vector) => format!("rst 0x{vector:02x}"),
+
+        Ret(condition) => match condition {
+            Some(condition) => format!("ret {}", test(condition)),
+            None => "ret".into(),
+        },
+        Reti => "reti".into(),
+
+        Di => "di".into(),
+        Ei => "ei".into(),
+        Halt => "halt".into(),
+        Stop => "stop".into(),
+
+        Illegal => format!("db 0x{:02x}", byte(0)),
+    };
+    (text, len)
+}
+
+/// Decodes instructions one after another, from `start` until one starts at or after `end`,
+/// reading memory with `read`.
+pub fn disassemble(start: u16, end: u16, mut read: impl FnMut(u16) -> u8) -> Vec<Line> {
+    let mut lines = Vec::new();
+    let mut addr = start;
+    while addr < end {
+        let bytes: Vec<u8> = (0..MAX_LEN as u16)
+            .map_while(|i| addr.checked_add(i))
+            .map(&mut read)
+            .collect();
+        let (text, len) = decode(addr, &bytes);
+        lines.push(Line {
+            addr,
+            bytes: bytes[..len.min(bytes.len())].to_vec(),
+            text,
+        });
+        match addr.checked_add(len as u16) {
+            Some(next) => addr = next,
+            None => break,
+        }
+    }
+    lines
+}
+
+/// An instruction the CPU executed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TraceEntry {
+    /// With the ROM bank it was in
+    pub addr: BankedAddress,
+    pub bytes: [u8; MAX_LEN],
+}
+
+impl TraceEntry {
+    pub fn text(&self) -> String {
+        decode(self.addr.addr, &self.bytes).0
+    }
+
+    /// Like a line of a disassembly, e.g. `00:0150  3e 42     ld a, 0x42`
+    pub fn to_line(&self) -> String {
+        let (text, len) = decode(self.addr.addr, &self.bytes);
+        let mut bytes = String::new();
+        for byte in &self.bytes[..len] {
+            let _ = write!(bytes, "{byte:02x} ");
+        }
+        format!("{}  {bytes:<9} {text}", self.addr)
+    }
+}
+
+/// The last [`TRACE_LEN`] instructions executed, oldest first.
+#[derive(Debug, Default)]
+pub struct Trace {
+    entries: VecDeque<TraceEntry>,
+}
+
+impl Trace {
+    pub fn push(&mut self, entry: TraceEntry) {
+        if self.entries.len() == TRACE_LEN {
+            self.entries.pop_front();
+        }
+        self.entries.push_back(entry);
+    }
+
+    pub fn entries(&self) -> impl Iterator<Item = &TraceEntry> {
+        self.entries.iter()
+    }
+
+    pub fn clear(&mut self) {
+        self.entries.clear();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn text(bytes: &[u8]) -> String {
+        decode(0x150, bytes).0
+    }
+
+    #[test]
+    fn instructions() {
+        assert_eq!(decode(0x150, &[0x00]), ("nop".into(), 1));
+        assert_eq!(text(&[0x3e, 0x42]), "ld a, 0x42");
+        assert_eq!(text(&[0x21, 0x00, 0x02]), "ld hl, 0x0200");
+        assert_eq!(text(&[0x2a]), "ld a, (hl+)");
+        assert_eq!(text(&[0xe0, 0x01]), "ldh (0xff01), a");
+        assert_eq!(text(&[0xea, 0x00, 0xc0]), "ld (0xc000), a");
+        assert_eq!(text(&[0xf8, 0xfe]), "ld hl, sp-0x02");
+        assert_eq!(text(&[0xc4, 0x34, 0x12]), "call nz, 0x1234");
+        assert_eq!(text(&[0xc9]), "ret");
+        assert_eq!(text(&[0xd8]), "ret c");
+        assert_eq!(text(&[0xff]), "rst 0x38");
+        assert_eq!(text(&[0xd3]), "db 0xd3");
+        assert_eq!(decode(0x150, &[0xcb, 0x7e]), ("bit 7, (hl)".into(), 2));
+        assert_eq!(text(&[0xcb, 0x37]), "swap a");
+        // Relative to the next instruction
+        assert_eq!(text(&[0x18, 0xfe]), "jr 0x0150");
+        assert_eq!(text(&[0x28, 0x08]), "jr z, 0x015a");
+    }
+
+    #[test]
+    fn range() {
+        let rom = [0x3e, 0x42, 0xcb, 0x37, 0xc3, 0x50, 0x01];
+        let lines = disassemble(0x150, 0x157, |addr| rom[addr as usize - 0x150]);
+        let texts: Vec<_> = lines.iter().map(|line| line.text.as_str()).collect();
+        assert_eq!(texts, ["ld a, 0x42", "swap a", "jp 0x0150"]);
+        assert_eq!(lines[2].addr, 0x154);
+        assert_eq!(lines[2].bytes, [0xc3, 0x50, 0x01]);
+
+        // Stops at the end of memory
+        let lines = disassemble(0xfffe, 0xffff, |_| 0x00);
+        assert_eq!(lines.len(), 1);
+    }
+
+    #[test]
+    fn trace() {
+        let mut trace = Trace::default();
+        for i in 0..TRACE_LEN + 1 {
+            trace.push(TraceEntry {
+                addr: BankedAddress::new(Some(0), i as u16),
+                bytes: [0; MAX_LEN],
+            });
+        }
+        let first = trace.entries().next().unwrap();
+        assert_eq!(first.addr.addr, 1);
+        assert_eq!(first.to_line(), "00:0001  00        nop");
+    }
+}
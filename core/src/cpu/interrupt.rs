@@ -1,7 +1,13 @@
 // SPDX-License-Identifier: GPL-3.0-or-later
 // Copyright (C) 2023 Robert Hrusecky <jadedpastabowl@gmail.com>
+use log::debug;
+
+use crate::logging;
+
 use super::{Cpu, CpuBus};
 
+const NAMES: [&str; 5] = ["VBlank", "LCD STAT", "Timer", "Serial", "Joypad"];
+
 impl Cpu {
     pub(super) fn ei(&mut self) {
         self.enable_interrupts_timer = 2;
@@ -26,9 +32,18 @@ impl Cpu {
         }
     }
 
-    pub(super) fn halt(&mut self) {
-        // TODO: Halt bug
-        self.halted = true;
+    pub(super) fn halt(&mut self, bus: &mut impl CpuBus) {
+        if self.interrupts_enabled || !bus.interrupt_pending() {
+            self.halted = true;
+        } else if self.enable_interrupts_timer > 0 {
+            // HALT right after EI with an interrupt pending. The interrupt is dispatched with the
+            // HALT as its return address, so that the HALT runs again once the handler returns.
+            self.pc = self.pc.wrapping_sub(1);
+        } else {
+            // The halt bug: HALT doesn't halt, and PC fails to increment past the next opcode, so
+            // its byte is read twice.
+            self.halt_bug = true;
+        }
     }
 
     pub(super) fn handle_interrupts(&mut self, bus: &mut impl CpuBus) -> bool {
@@ -54,6 +69,12 @@ impl Cpu {
         // Bit 3: Serial   Interrupt Request (INT $58)
         // Bit 4: Joypad   Interrupt Request (INT $60)
         let addr = 0x40 + bit as u16 * 0x8;
+        debug!(
+            target: logging::INTERRUPT,
+            "{} interrupt at {:#06x}, calling {addr:#06x}",
+            NAMES[bit as usize],
+            self.pc
+        );
 
         self.call_addr(addr, bus);
 
@@ -61,3 +82,111 @@ impl Cpu {
         true
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use crate::cart::BankedAddress;
+
+    use super::*;
+
+    struct Bus {
+        mem: Box<[u8; 0x10000]>,
+        interrupt: bool,
+    }
+
+    impl Bus {
+        fn new(program: &[u8]) -> Self {
+            let mut mem = Box::new([0; 0x10000]);
+            mem[..program.len()].copy_from_slice(program);
+            Self {
+                mem,
+                interrupt: true,
+            }
+        }
+    }
+
+    impl CpuBus for Bus {
+        fn read_8(&self, addr: u16) -> u8 {
+            self.mem[addr as usize]
+        }
+
+        fn write_8(&mut self, addr: u16, val: u8) {
+            self.mem[addr as usize] = val;
+        }
+
+        fn cpu_dma_paused(&self) -> bool {
+            false
+        }
+
+        fn banked_address(&self, addr: u16) -> BankedAddress {
+            addr.into()
+        }
+
+        fn interrupt_pending(&mut self) -> bool {
+            self.interrupt
+        }
+
+        fn pop_interrupt(&mut self) -> Option<u8> {
+            self.interrupt.then(|| {
+                self.interrupt = false;
+                0
+            })
+        }
+
+        fn switch_speed(&mut self) -> bool {
+            false
+        }
+    }
+
+    fn run_instructions(cpu: &mut Cpu, bus: &mut Bus, count: u64) {
+        while cpu.instructions() < count || !cpu.at_instruction_boundary() {
+            cpu.execute(bus);
+        }
+    }
+
+    #[test]
+    fn halt_bug() {
+        // HALT; INC A
+        let mut bus = Bus::new(&[0x76, 0x3c]);
+        let mut cpu = Cpu::default();
+        run_instructions(&mut cpu, &mut bus, 3);
+
+        // INC A runs twice, and the CPU never halts
+        assert_eq!(cpu.registers().af >> 8, 2);
+        assert_eq!(cpu.pc(), 2);
+        assert!(!cpu.halted);
+    }
+
+    #[test]
+    fn halt_after_ei() {
+        // EI; HALT
+        let mut bus = Bus::new(&[0xfb, 0x76]);
+        let mut cpu = Cpu::default();
+        run_instructions(&mut cpu, &mut bus, 2);
+        cpu.execute(&mut bus);
+
+        // The interrupt returns to the HALT
+        assert_eq!(cpu.pc(), 0x40);
+        assert_eq!(bus.read_16(cpu.registers().sp), 1);
+    }
+
+    #[test]
+    fn halt_until_interrupt() {
+        // HALT; INC A
+        let mut bus = Bus::new(&[0x76, 0x3c]);
+        bus.interrupt = false;
+        let mut cpu = Cpu::default();
+        run_instructions(&mut cpu, &mut bus, 1);
+        for _ in 0..10 {
+            cpu.execute(&mut bus);
+        }
+        assert!(cpu.idle());
+
+        // Without IME, the interrupt wakes the CPU but isn't dispatched
+        bus.interrupt = true;
+        run_instructions(&mut cpu, &mut bus, 2);
+        assert_eq!(cpu.registers().af >> 8, 1);
+        assert_eq!(cpu.pc(), 2);
+        assert!(bus.interrupt);
+    }
+}
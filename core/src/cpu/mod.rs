@@ -2,15 +2,25 @@
 // Copyright (C) 2023 Robert Hrusecky <jadedpastabowl@gmail.com>
 use core::fmt;
 use std::{
+    array,
     fmt::{Debug, Formatter},
     marker::PhantomData,
     ops::{Index, IndexMut},
 };
 
-use self::instruction_set::{Instruction, InstructionEntry, Operand8, Var8};
+use log::trace;
+use serde::{Deserialize, Serialize};
+
+use crate::{cart::BankedAddress, logging};
+
+use self::{
+    disasm::{Trace, TraceEntry},
+    instruction_set::{Instruction, InstructionEntry, Operand8, Var8},
+};
 
 mod alu;
 mod control;
+pub mod disasm;
 mod instruction_set;
 mod interrupt;
 mod load;
@@ -112,7 +122,7 @@ impl Flag {
     }
 }
 
-#[derive(Debug, Default)]
+#[derive(Debug, Default, Serialize, Deserialize)]
 struct RegisterSet {
     regs: [u16; 5],
     // bc: u16,
@@ -172,6 +182,11 @@ pub trait CpuBus {
     fn read_16(&self, addr: u16) -> u16 {
         u16::from_le_bytes([self.read_8(addr), self.read_8(addr.wrapping_add(1))])
     }
+    /// Reads `addr` on behalf of a debugger rather than the running program, which mustn't trip
+    /// watchpoints.
+    fn peek_8(&self, addr: u16) -> u8 {
+        self.read_8(addr)
+    }
 
     fn write_8(&mut self, addr: u16, val: u8);
     fn write_16(&mut self, addr: u16, val: u16) {
@@ -179,20 +194,93 @@ pub trait CpuBus {
         self.write_8(addr, low);
         self.write_8(addr.wrapping_add(1), high);
     }
+    /// Writes `addr` on behalf of something other than the running program, like a cheat, which
+    /// mustn't trip watchpoints.
+    fn poke_8(&mut self, addr: u16, val: u8) {
+        self.write_8(addr, val);
+    }
 
     fn cpu_dma_paused(&self) -> bool;
+    /// Qualifies `addr` with the ROM bank mapped there, for tracing.
+    fn banked_address(&self, addr: u16) -> BankedAddress;
     fn interrupt_pending(&mut self) -> bool;
     fn pop_interrupt(&mut self) -> Option<u8>;
+    /// Performs the CGB speed switch armed through KEY1, if any. Returns whether it did.
+    fn switch_speed(&mut self) -> bool;
+}
+
+/// Logs each access to the bus it wraps.
+struct LoggedBus<'a, B>(&'a mut B);
+
+impl<B: CpuBus> CpuBus for LoggedBus<'_, B> {
+    fn read_8(&self, addr: u16) -> u8 {
+        let val = self.0.read_8(addr);
+        trace!(target: logging::MEMORY, "Read {val:#04x} from {addr:#06x}");
+        val
+    }
+
+    fn write_8(&mut self, addr: u16, val: u8) {
+        trace!(target: logging::MEMORY, "Write {val:#04x} to {addr:#06x}");
+        self.0.write_8(addr, val);
+    }
+
+    fn peek_8(&self, addr: u16) -> u8 {
+        self.0.peek_8(addr)
+    }
+
+    fn poke_8(&mut self, addr: u16, val: u8) {
+        self.0.poke_8(addr, val);
+    }
+
+    fn cpu_dma_paused(&self) -> bool {
+        self.0.cpu_dma_paused()
+    }
+
+    fn banked_address(&self, addr: u16) -> BankedAddress {
+        self.0.banked_address(addr)
+    }
+
+    fn interrupt_pending(&mut self) -> bool {
+        self.0.interrupt_pending()
+    }
+
+    fn pop_interrupt(&mut self) -> Option<u8> {
+        self.0.pop_interrupt()
+    }
+
+    fn switch_speed(&mut self) -> bool {
+        self.0.switch_speed()
+    }
+}
+
+/// A copy of the CPU's registers, for debuggers.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Registers {
+    pub af: u16,
+    pub bc: u16,
+    pub de: u16,
+    pub hl: u16,
+    pub sp: u16,
+    pub pc: u16,
+    /// The interrupt master enable flag
+    pub ime: bool,
+    pub halted: bool,
 }
 
-#[derive(Debug, Default)]
+#[derive(Debug, Default, Serialize, Deserialize)]
 pub struct Cpu {
     regs: RegisterSet,
     cycles_remaining: usize,
     pc: u16,
     interrupts_enabled: bool,
     halted: bool,
+    /// Set by the halt bug, so that the next opcode fetch doesn't increment PC
+    halt_bug: bool,
     enable_interrupts_timer: usize,
+    instructions: u64,
+    /// Only kept while a debugger asks for it
+    #[serde(skip)]
+    trace: Option<Trace>,
 }
 
 impl Cpu {
@@ -298,12 +386,76 @@ impl Cpu {
             Reti => self.reti(bus),
             Di => self.di(),
             Ei => self.ei(),
-            Halt => self.halt(),
+            Halt => self.halt(bus),
             Stop => self.stop(bus),
             Illegal => panic!("Tried to execute illegal instruction"),
         }
     }
 
+    pub fn registers(&self) -> Registers {
+        Registers {
+            af: self.regs[Reg16::AF],
+            bc: self.regs[Reg16::BC],
+            de: self.regs[Reg16::DE],
+            hl: self.regs[Reg16::HL],
+            sp: self.regs[Reg16::SP],
+            pc: self.pc,
+            ime: self.interrupts_enabled,
+            halted: self.halted,
+        }
+    }
+
+    /// Overwrites every register, like the boot ROM does by the time it jumps to the cartridge.
+    pub fn set_registers(&mut self, regs: Registers) {
+        self.regs[Reg16::AF] = regs.af;
+        self.regs[Reg16::BC] = regs.bc;
+        self.regs[Reg16::DE] = regs.de;
+        self.regs[Reg16::HL] = regs.hl;
+        self.regs[Reg16::SP] = regs.sp;
+        self.pc = regs.pc;
+        self.interrupts_enabled = regs.ime;
+        self.halted = regs.halted;
+    }
+
+    pub fn pc(&self) -> u16 {
+        self.pc
+    }
+
+    /// Starts or stops keeping a trace of executed instructions. Stopping discards it.
+    pub fn set_tracing(&mut self, tracing: bool) {
+        if tracing != self.trace.is_some() {
+            self.trace = tracing.then(Trace::default);
+        }
+    }
+
+    pub(crate) fn take_trace(&mut self) -> Option<Trace> {
+        self.trace.take()
+    }
+
+    pub(crate) fn set_trace(&mut self, trace: Option<Trace>) {
+        self.trace = trace;
+    }
+
+    pub fn trace(&self) -> Option<&Trace> {
+        self.trace.as_ref()
+    }
+
+    /// Whether the previous instruction has used up all of its cycles, so the next call to
+    /// [`Self::execute`] either dispatches an interrupt or starts the instruction at PC.
+    pub fn at_instruction_boundary(&self) -> bool {
+        self.cycles_remaining == 0
+    }
+
+    /// The number of instructions started since power on, not counting interrupt dispatches.
+    pub fn instructions(&self) -> u64 {
+        self.instructions
+    }
+
+    /// Whether the CPU is halted and will do nothing until an interrupt is requested.
+    pub fn idle(&self) -> bool {
+        self.halted && self.cycles_remaining == 0
+    }
+
     pub fn execute(&mut self, bus: &mut impl CpuBus) {
         if bus.cpu_dma_paused() {
             return;
@@ -314,28 +466,38 @@ impl Cpu {
                 return;
             }
 
-            #[cfg(feature = "cpu-debug")]
             let start_pc = self.pc;
             let opcode = self.read_immedate_8(bus);
-
-            #[cfg(feature = "cpu-debug")]
-            print!("Executing({:04x}): {opcode:#02x} ", start_pc);
+            if self.halt_bug {
+                self.halt_bug = false;
+                self.pc = self.pc.wrapping_sub(1);
+            }
 
             let entry_data;
             let entry = if opcode == instruction_set::PREFIX_OPCODE {
                 let opcode = self.read_immedate_8(bus);
-                #[cfg(feature = "cpu-debug")]
-                print!("{opcode:#02x} ");
                 entry_data = instruction_set::entry_for_prefix_opcode(opcode);
                 &entry_data
             } else {
                 instruction_set::entry_for_opcode(opcode)
             };
+            if self.trace.is_some() || logging::tracing!(logging::CPU) {
+                let entry = TraceEntry {
+                    addr: bus.banked_address(start_pc),
+                    bytes: array::from_fn(|i| bus.peek_8(start_pc.wrapping_add(i as u16))),
+                };
+                trace!(target: logging::CPU, "Executing {}", entry.to_line());
+                if let Some(trace) = &mut self.trace {
+                    trace.push(entry);
+                }
+            }
 
-            #[cfg(feature = "cpu-debug")]
-            println!("{:?}", entry.instruction);
-
-            self.execute_instruction(bus, entry);
+            if logging::tracing!(logging::MEMORY) {
+                self.execute_instruction(&mut LoggedBus(bus), entry);
+            } else {
+                self.execute_instruction(bus, entry);
+            }
+            self.instructions += 1;
         }
         self.update_interrupt_timer();
         self.cycles_remaining -= 1;
@@ -0,0 +1,111 @@
+// SPDX-License-Identifier: GPL-3.0-or-later
+// Copyright (C) 2023 Robert Hrusecky <jadedpastabowl@gmail.com>
+
+//! Delta compression for save states taken close together, which are mostly the same. A delta is
+//! the XOR of the two states, with the runs of zeros left out:
+//!
+//! ```text
+//! target length: u32
+//! repeated: skip: u16, len: u16, len XORed bytes
+//! ```
+//!
+//! All integers are little endian.
+
+/// A run of zeros shorter than this doesn't pay for the header of a new run.
+const MIN_SKIP: usize = 4;
+
+/// Encodes `target` relative to `base`. They don't have to be the same length.
+pub fn encode(base: &[u8], target: &[u8]) -> Vec<u8> {
+    let xor = |i: usize| target[i] ^ base.get(i).copied().unwrap_or(0);
+    let mut delta = (target.len() as u32).to_le_bytes().to_vec();
+    let mut i = 0;
+    while i < target.len() {
+        let start = i;
+        while i < target.len() && i - start < u16::MAX as usize && xor(i) == 0 {
+            i += 1;
+        }
+        if i == target.len() {
+            break;
+        }
+        let skip = i - start;
+
+        let start = i;
+        let mut zeros = 0;
+        while i < target.len() && i - start < u16::MAX as usize && zeros < MIN_SKIP {
+            zeros = if xor(i) == 0 { zeros + 1 } else { 0 };
+            i += 1;
+        }
+        i -= zeros;
+
+        delta.extend_from_slice(&(skip as u16).to_le_bytes());
+        delta.extend_from_slice(&((i - start) as u16).to_le_bytes());
+        delta.extend((start..i).map(xor));
+    }
+    delta
+}
+
+/// Recovers the target from a delta made by [`encode`] with the same `base`. Returns `None` if
+/// the delta is malformed.
+pub fn decode(base: &[u8], delta: &[u8]) -> Option<Vec<u8>> {
+    let (len, mut delta) = delta.split_first_chunk()?;
+    let len = u32::from_le_bytes(*len) as usize;
+    let mut target = base.to_vec();
+    target.resize(len, 0);
+    let mut i = 0;
+    while !delta.is_empty() {
+        let (header, rest) = delta.split_first_chunk::<4>()?;
+        let skip = u16::from_le_bytes([header[0], header[1]]) as usize;
+        let run = u16::from_le_bytes([header[2], header[3]]) as usize;
+        let (bytes, rest) = rest.split_at_checked(run)?;
+        i += skip;
+        for (byte, xor) in target.get_mut(i..i + run)?.iter_mut().zip(bytes) {
+            *byte ^= xor;
+        }
+        i += run;
+        delta = rest;
+    }
+    Some(target)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn round_trip(base: &[u8], target: &[u8]) -> Vec<u8> {
+        let delta = encode(base, target);
+        assert_eq!(decode(base, &delta).as_deref(), Some(target));
+        delta
+    }
+
+    #[test]
+    fn same() {
+        let state = [1, 2, 3, 4, 5];
+        assert_eq!(round_trip(&state, &state), 5u32.to_le_bytes());
+    }
+
+    #[test]
+    fn sparse_changes() {
+        let base = vec![0xaa; 0x30000];
+        let mut target = base.clone();
+        target[0] = 0;
+        target[0x100] = 1;
+        target[0x102] = 2;
+        target[0x2ffff] = 3;
+        let delta = round_trip(&base, &target);
+        assert!(delta.len() < 48, "{} bytes", delta.len());
+    }
+
+    #[test]
+    fn different_lengths() {
+        round_trip(&[1, 2, 3], &[1, 2, 3, 4, 5]);
+        round_trip(&[1, 2, 3, 4, 5], &[1, 2]);
+        round_trip(&[], &[0, 0, 7]);
+    }
+
+    #[test]
+    fn malformed() {
+        let base = [0; 8];
+        assert_eq!(decode(&base, &[8, 0]), None);
+        assert_eq!(decode(&base, &[8, 0, 0, 0, 6, 0, 4, 0, 1]), None);
+    }
+}
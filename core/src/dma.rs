@@ -1,12 +1,17 @@
 // SPDX-License-Identifier: GPL-3.0-or-later
 // Copyright (C) 2023 Robert Hrusecky <jadedpastabowl@gmail.com>
-use crate::memory::OamBytes;
+use log::debug;
+use serde::{Deserialize, Serialize};
 
+use crate::logging;
+
+#[derive(Serialize, Deserialize)]
 pub enum DmaType {
     Oam,
     General,
 }
 
+#[derive(Serialize, Deserialize)]
 struct DmaState {
     pub ty: DmaType,
     pub len: u16,
@@ -14,14 +19,41 @@ struct DmaState {
     pub oam_src: u16,
 }
 
+/// The buses that the CPU can find an OAM DMA using.
+#[derive(PartialEq, Eq)]
+enum MemoryBus {
+    /// The cartridge's, which WRAM shares on the DMG
+    External,
+    /// WRAM's own, on the CGB
+    Wram,
+    Video,
+}
+
+/// The bus that `addr` is on, or `None` for OAM, I/O, and HRAM, which the CPU always has to itself.
+fn bus(addr: u16, cgb: bool) -> Option<MemoryBus> {
+    match (addr >> 8) as u8 {
+        0x00..=0x7f | 0xa0..=0xbf => Some(MemoryBus::External),
+        0x80..=0x9f => Some(MemoryBus::Video),
+        0xc0..=0xfd if cgb => Some(MemoryBus::Wram),
+        0xc0..=0xfd => Some(MemoryBus::External),
+        0xfe..=0xff => None,
+    }
+}
+
 pub trait DmaBus {
     fn write_vram(&mut self, addr: u16, val: u8);
-    fn oam_mut(&mut self) -> &mut OamBytes;
+    fn write_oam(&mut self, offset: u8, val: u8);
     fn read_8(&self, addr: u16) -> u8;
 }
 
+#[derive(Serialize, Deserialize)]
 pub struct Dma {
     state: Option<DmaState>,
+    /// The source of an OAM DMA that was just started. It takes over from any ongoing transfer
+    /// after a one cycle startup delay.
+    oam_starting: Option<u16>,
+    /// The byte the OAM DMA copied last, which is what's on its bus
+    oam_byte: u8,
     cpu_paused: bool,
     dma: u8,
     pub hdma1: u8,
@@ -34,6 +66,8 @@ impl Dma {
     pub fn new() -> Self {
         Self {
             state: None,
+            oam_starting: None,
+            oam_byte: 0,
             cpu_paused: false,
             dma: 0,
             hdma1: 0,
@@ -49,6 +83,12 @@ impl Dma {
 
     fn start_general(&mut self, len: u16) {
         // TODO: Do some kind of cancel of an ongoing OAM DMA for simplicity
+        debug!(
+            target: logging::DMA,
+            "General DMA of {len:#x} bytes from {:#06x} to {:#06x}",
+            self.general_src_addr(),
+            self.general_dst_addr()
+        );
         self.state = Some(DmaState {
             ty: DmaType::General,
             len,
@@ -72,12 +112,47 @@ impl Dma {
 
     fn start_oam(&mut self, oam_src: u16) {
         // TODO: Do some kind of cancel of an ongoing HDMA for simplicity
-        self.state = Some(DmaState {
+        debug!(target: logging::DMA, "OAM DMA from {oam_src:#06x}");
+        self.oam_starting = Some(oam_src);
+    }
+
+    /// Whether an OAM DMA is copying, which blocks the CPU from OAM. The startup delay of a
+    /// restarted transfer counts, since the old transfer is still running.
+    pub fn oam_blocked(&self) -> bool {
+        matches!(
+            self.state,
+            Some(DmaState {
+                ty: DmaType::Oam,
+                ..
+            })
+        )
+    }
+
+    /// What the CPU reads from `addr` while an OAM DMA is copying over the same bus: the byte
+    /// being copied, rather than what's there. Its writes to that bus are lost. `None` means the
+    /// CPU has the bus to itself. `cgb` is whether the hardware is a CGB, which gives WRAM a bus of
+    /// its own, so that games can run code from ROM during a transfer from WRAM.
+    pub fn oam_conflict(&self, addr: u16, cgb: bool) -> Option<u8> {
+        let Some(DmaState {
             ty: DmaType::Oam,
-            len: 0xa0,
-            count: 0,
+            count,
             oam_src,
-        });
+            ..
+        }) = self.state
+        else {
+            return None;
+        };
+        if count == 0 {
+            // Nothing has been read yet
+            return None;
+        }
+        // Sources past WRAM read from its echo
+        let src = if oam_src >= 0xe000 {
+            oam_src - 0x2000
+        } else {
+            oam_src
+        };
+        (bus(src, cgb) == Some(bus(addr, cgb)?)).then_some(self.oam_byte)
     }
 
     pub fn dma(&self) -> u8 {
@@ -89,6 +164,19 @@ impl Dma {
         self.start_oam((dma as u16) << 8);
     }
 
+    /// The number of machine cycles until the current transfer completes, if there is one.
+    pub fn cycles_until_done(&self) -> Option<usize> {
+        if self.oam_starting.is_some() {
+            return Some(1 + 0xa0);
+        }
+        let state = self.state.as_ref()?;
+        let per_cycle = match state.ty {
+            DmaType::General => 2,
+            DmaType::Oam => 1,
+        };
+        Some(((state.len - state.count) / per_cycle) as usize)
+    }
+
     fn general_src_addr(&self) -> u16 {
         u16::from_be_bytes([self.hdma1, self.hdma2]) & 0xfff0
     }
@@ -98,6 +186,18 @@ impl Dma {
     }
 
     pub fn execute(&mut self, bus: &mut impl DmaBus) {
+        self.execute_transfer(bus);
+        if let Some(oam_src) = self.oam_starting.take() {
+            self.state = Some(DmaState {
+                ty: DmaType::Oam,
+                len: 0xa0,
+                count: 0,
+                oam_src,
+            });
+        }
+    }
+
+    fn execute_transfer(&mut self, bus: &mut impl DmaBus) {
         let Some(state) = &self.state else {
             return;
         };
@@ -117,7 +217,8 @@ impl Dma {
             DmaType::Oam => {
                 let src_addr = state.oam_src.wrapping_add(state.count);
                 let dst_addr = state.count;
-                bus.oam_mut()[dst_addr as usize] = bus.read_8(src_addr);
+                self.oam_byte = bus.read_8(src_addr);
+                bus.write_oam(dst_addr as u8, self.oam_byte);
             }
         }
 
@@ -136,3 +237,92 @@ impl Dma {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use crate::memory::OamBytes;
+
+    use super::*;
+
+    struct Bus {
+        oam: OamBytes,
+    }
+
+    impl DmaBus for Bus {
+        fn write_vram(&mut self, _addr: u16, _val: u8) {
+            unimplemented!();
+        }
+
+        fn write_oam(&mut self, offset: u8, val: u8) {
+            self.oam[offset as usize] = val;
+        }
+
+        fn read_8(&self, addr: u16) -> u8 {
+            (addr >> 8) as u8
+        }
+    }
+
+    #[test]
+    fn oam_startup_delay() {
+        let mut bus = Bus { oam: [0; 0xa0] };
+        let mut dma = Dma::new();
+        dma.set_dma(0xc0);
+        assert_eq!(dma.cycles_until_done(), Some(0xa1));
+
+        dma.execute(&mut bus);
+        assert_eq!(bus.oam, [0; 0xa0]);
+        assert!(dma.oam_blocked());
+
+        for _ in 0..0xa0 {
+            dma.execute(&mut bus);
+        }
+        assert_eq!(bus.oam, [0xc0; 0xa0]);
+        assert!(!dma.oam_blocked());
+        assert_eq!(dma.cycles_until_done(), None);
+    }
+
+    #[test]
+    fn oam_restart() {
+        let mut bus = Bus { oam: [0; 0xa0] };
+        let mut dma = Dma::new();
+        dma.set_dma(0xc0);
+        for _ in 0..11 {
+            dma.execute(&mut bus);
+        }
+        assert_eq!(bus.oam[..10], [0xc0; 10]);
+
+        // The old transfer copies one more byte during the new one's startup delay
+        dma.set_dma(0xd0);
+        dma.execute(&mut bus);
+        assert_eq!(bus.oam[10], 0xc0);
+        assert_eq!(bus.oam[0], 0xc0);
+
+        dma.execute(&mut bus);
+        assert_eq!(bus.oam[0], 0xd0);
+        assert_eq!(bus.oam[11], 0);
+    }
+
+    #[test]
+    fn bus_conflicts() {
+        let mut bus = Bus { oam: [0; 0xa0] };
+        let mut dma = Dma::new();
+        dma.set_dma(0xc1);
+        dma.execute(&mut bus);
+        // Nothing is on the bus during the startup delay
+        assert_eq!(dma.oam_conflict(0xd000, true), None);
+
+        dma.execute(&mut bus);
+        assert_eq!(dma.oam_conflict(0xd000, true), Some(0xc1));
+        assert_eq!(dma.oam_conflict(0xe123, true), Some(0xc1));
+        // WRAM only shares the cartridge's bus on the DMG
+        assert_eq!(dma.oam_conflict(0x4000, true), None);
+        assert_eq!(dma.oam_conflict(0x4000, false), Some(0xc1));
+        assert_eq!(dma.oam_conflict(0x8000, false), None);
+        assert_eq!(dma.oam_conflict(0xff80, false), None);
+
+        for _ in 0..0x9f {
+            dma.execute(&mut bus);
+        }
+        assert_eq!(dma.oam_conflict(0xd000, true), None);
+    }
+}
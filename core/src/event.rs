@@ -0,0 +1,110 @@
+// SPDX-License-Identifier: GPL-3.0-or-later
+// Copyright (C) 2023 Robert Hrusecky <jadedpastabowl@gmail.com>
+
+use std::{
+    cell::RefCell,
+    collections::VecDeque,
+    fmt::{self, Display, Formatter},
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Severity {
+    Debug,
+    Info,
+    Warning,
+    Error,
+}
+
+impl Display for Severity {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::Debug => "Debug",
+            Self::Info => "Info",
+            Self::Warning => "Warning",
+            Self::Error => "Error",
+        })
+    }
+}
+
+#[derive(Debug, Clone)]
+pub enum EventKind {
+    UnimplementedRead(u16),
+    UnimplementedWrite(u16, u8),
+    SuspiciousMbcWrite(u16, u8),
+    RtcOverflow,
+    SerialTransfer(u8),
+    NonstandardRomSize(u8),
+    OversizedRom { header: usize, file: usize },
+    SaveSizeMismatch { ram: usize, save: usize },
+    Rumble(bool),
+}
+
+impl Display for EventKind {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::UnimplementedRead(addr) => {
+                write!(f, "Read from unimplemented address {addr:#06x}")
+            }
+            Self::UnimplementedWrite(addr, val) => {
+                write!(f, "Wrote {val:#04x} to unimplemented address {addr:#06x}")
+            }
+            Self::SuspiciousMbcWrite(addr, val) => {
+                write!(f, "Suspicious MBC write of {val:#04x} to {addr:#06x}")
+            }
+            Self::RtcOverflow => write!(f, "RTC day counter overflowed"),
+            Self::SerialTransfer(val) => write!(f, "Serial transfer started with {val:#04x}"),
+            Self::NonstandardRomSize(id) => write!(f, "Nonstandard ROM size ID {id:#x}"),
+            Self::OversizedRom { header, file } => write!(
+                f,
+                "ROM file is {file:#x} bytes, but its header says {header:#x}; using the whole file"
+            ),
+            Self::SaveSizeMismatch { ram, save } => write!(
+                f,
+                "Save file is {save:#x} bytes, but the cartridge has {ram:#x} bytes of RAM; \
+                 truncated or padded it to fit"
+            ),
+            Self::Rumble(on) => write!(f, "Rumble motor {}", if *on { "on" } else { "off" }),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Event {
+    pub severity: Severity,
+    pub kind: EventKind,
+}
+
+impl Display for Event {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "[{}] {}", self.severity, self.kind)
+    }
+}
+
+/// A bounded queue of events raised by the core for the frontend to display. Bus reads only have
+/// shared access to the system, so events are pushed through a shared reference.
+pub struct EventLog {
+    events: RefCell<VecDeque<Event>>,
+}
+
+impl EventLog {
+    // Keep the oldest events from piling up if the frontend never drains the log
+    const CAPACITY: usize = 1024;
+
+    pub fn new() -> Self {
+        Self {
+            events: RefCell::new(VecDeque::new()),
+        }
+    }
+
+    pub fn push(&self, severity: Severity, kind: EventKind) {
+        let mut events = self.events.borrow_mut();
+        if events.len() == Self::CAPACITY {
+            events.pop_front();
+        }
+        events.push_back(Event { severity, kind });
+    }
+
+    pub fn drain(&mut self) -> impl Iterator<Item = Event> + '_ {
+        self.events.get_mut().drain(..)
+    }
+}
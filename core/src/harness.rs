@@ -0,0 +1,141 @@
+// SPDX-License-Identifier: GPL-3.0-or-later
+// Copyright (C) 2023 Robert Hrusecky <jadedpastabowl@gmail.com>
+
+//! Runs a game headlessly for automated tests, like checking that a test ROM ends on a known
+//! screen. Input can be scripted frame by frame, and frames are compared by hash.
+
+use crate::{
+    cart::Cart,
+    system::{CgbSystem, FrameBuffer, SCREEN_HEIGHT, SCREEN_WIDTH},
+};
+
+pub use crate::joypad::Input;
+
+/// Hashes a frame with 64 bit FNV-1a, which is stable across platforms and releases, so hashes
+/// can be written into tests.
+pub fn frame_hash(frame_buff: &FrameBuffer) -> u64 {
+    frame_buff
+        .as_flattened()
+        .as_flattened()
+        .iter()
+        .fold(0xcbf2_9ce4_8422_2325, |hash, &byte| {
+            (hash ^ byte as u64).wrapping_mul(0x100_0000_01b3)
+        })
+}
+
+pub struct Harness {
+    system: Box<CgbSystem>,
+    frame_buff: Box<FrameBuffer>,
+    frames: u64,
+}
+
+impl Harness {
+    pub fn new(cart: Cart) -> Self {
+        Self::with_system(CgbSystem::new(cart))
+    }
+
+    /// Runs a system that has already been set up, like with a different boot ROM.
+    pub fn with_system(system: CgbSystem) -> Self {
+        Self {
+            system: Box::new(system),
+            frame_buff: Box::new([[[0; 4]; SCREEN_WIDTH]; SCREEN_HEIGHT]),
+            frames: 0,
+        }
+    }
+
+    pub fn system(&self) -> &CgbSystem {
+        &self.system
+    }
+
+    pub fn system_mut(&mut self) -> &mut CgbSystem {
+        &mut self.system
+    }
+
+    /// The last frame drawn.
+    pub fn frame_buffer(&self) -> &FrameBuffer {
+        &self.frame_buff
+    }
+
+    pub fn frame_hash(&self) -> u64 {
+        frame_hash(&self.frame_buff)
+    }
+
+    /// The number of frames run so far.
+    pub fn frames(&self) -> u64 {
+        self.frames
+    }
+
+    /// Runs a frame with `input` held down the whole time. Audio is thrown away.
+    pub fn run_frame(&mut self, input: Input) {
+        self.system.set_input(input);
+        self.system.execute(&mut self.frame_buff, |_| ());
+        self.frames += 1;
+    }
+
+    /// Runs `frames` frames with nothing held, and returns the hash of the last one.
+    pub fn run_frames(&mut self, frames: usize) -> u64 {
+        self.run_script(std::iter::repeat_n(Input::NONE, frames))
+    }
+
+    /// Runs frames, keeping the same buttons held, until `done` returns true after one or
+    /// `max_frames` have gone by. Returns whether `done` did.
+    pub fn run_until(
+        &mut self,
+        max_frames: usize,
+        mut done: impl FnMut(&mut CgbSystem) -> bool,
+    ) -> bool {
+        for _ in 0..max_frames {
+            self.run_frame(self.system.input());
+            if done(&mut self.system) {
+                return true;
+            }
+        }
+        false
+    }
+
+    /// Runs a frame for each input in `script`, and returns the hash of the last one.
+    pub fn run_script(&mut self, script: impl IntoIterator<Item = Input>) -> u64 {
+        for input in script {
+            self.run_frame(input);
+        }
+        self.frame_hash()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{joypad::Button, system::BootRom};
+
+    use super::*;
+
+    fn harness() -> Harness {
+        Harness::new(Cart::from_rom(vec![0; 0x8000].into_boxed_slice()).unwrap())
+    }
+
+    #[test]
+    fn deterministic() {
+        let (mut a, mut b) = (harness(), harness());
+        assert_eq!(a.run_frames(10), b.run_frames(10));
+        assert_eq!(a.frames(), 10);
+        // The boot ROM's logo is still scrolling in
+        #[cfg(feature = "sameboy-boot-rom")]
+        assert_ne!(a.run_frames(1), b.run_frames(20));
+    }
+
+    #[test]
+    fn scripted_input() {
+        let mut rom = vec![0; 0x8000];
+        // Select the directions in P1 and idle
+        rom[0x100..0x106].copy_from_slice(&[0x3e, 0x20, 0xe0, 0x00, 0x18, 0xfe]);
+        let cart = Cart::from_rom(rom.into_boxed_slice()).unwrap();
+        let mut harness = Harness::with_system(CgbSystem::with_boot_rom(cart, BootRom::skip()));
+        let right = |harness: &mut Harness| harness.system.peek(0xff00) & 0x01 == 0;
+        harness.run_script([
+            Input::NONE,
+            [Button::Right, Button::A].into_iter().collect(),
+        ]);
+        assert!(right(&mut harness));
+        harness.run_frame(Input::NONE.with(Button::A));
+        assert!(!right(&mut harness));
+    }
+}
@@ -1,15 +1,17 @@
 // SPDX-License-Identifier: GPL-3.0-or-later
 // Copyright (C) 2023 Robert Hrusecky <jadedpastabowl@gmail.com>
+use serde::{Deserialize, Serialize};
+
 #[derive(Debug, Clone, Copy)]
 pub enum Interrupt {
     VBlank = 0,
     Stat,
     Timer,
-    #[allow(unused)]
     Serial,
     Joypad,
 }
 
+#[derive(Serialize, Deserialize)]
 pub struct InterruptState {
     pub enable: u8,
     pub flags: u8,
@@ -1,6 +1,14 @@
 // SPDX-License-Identifier: GPL-3.0-or-later
 // Copyright (C) 2023 Robert Hrusecky <jadedpastabowl@gmail.com>
 
+use std::{
+    f32::consts::FRAC_PI_4,
+    fmt::{self, Display, Formatter},
+};
+
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum Button {
     Right = 0,
     Left,
@@ -12,35 +20,227 @@ pub enum Button {
     Start,
 }
 
+impl Button {
+    pub const ALL: [Button; 8] = [
+        Button::Right,
+        Button::Left,
+        Button::Up,
+        Button::Down,
+        Button::A,
+        Button::B,
+        Button::Select,
+        Button::Start,
+    ];
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum ButtonState {
     Pressed,
     Released,
 }
 
+/// How to resolve opposing directions held at once, like left and right, which can't both be
+/// pressed on a real d-pad. Some games glitch if they see both.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Socd {
+    /// Neither direction
+    Neutral,
+    /// Whichever was pressed last
+    #[default]
+    LastWins,
+}
+
+impl Socd {
+    pub const ALL: [Socd; 2] = [Self::Neutral, Self::LastWins];
+}
+
+impl Display for Socd {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::Neutral => "Neutral",
+            Self::LastWins => "Last wins",
+        })
+    }
+}
+
+/// The buttons held down, like for a frame of input.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Input(u8);
+
+impl Input {
+    pub const NONE: Input = Input(0);
+
+    /// This input with `button` held down too.
+    pub fn with(self, button: Button) -> Self {
+        Self(self.0 | 1 << button as u8)
+    }
+
+    pub fn held(self, button: Button) -> bool {
+        self.0 & 1 << button as u8 != 0
+    }
+}
+
+impl FromIterator<Button> for Input {
+    fn from_iter<T: IntoIterator<Item = Button>>(buttons: T) -> Self {
+        buttons.into_iter().fold(Self::NONE, Self::with)
+    }
+}
+
+/// The buttons held down, along with which opposing directions were pressed last. Together they
+/// decide what the game sees when the input next changes. They aren't part of save states, so
+/// movies keep them alongside their starting state.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct HeldInput {
+    input: Input,
+    last: u8,
+}
+
+/// Right and left, then up and down
+const AXES: [u8; 2] = [0b0011, 0b1100];
+
+/// The directions a stick points in for each eighth of a turn, counterclockwise from the right.
+const STICK_DIRECTIONS: [u8; 8] = [
+    1 << Button::Right as u8,
+    1 << Button::Right as u8 | 1 << Button::Up as u8,
+    1 << Button::Up as u8,
+    1 << Button::Up as u8 | 1 << Button::Left as u8,
+    1 << Button::Left as u8,
+    1 << Button::Left as u8 | 1 << Button::Down as u8,
+    1 << Button::Down as u8,
+    1 << Button::Down as u8 | 1 << Button::Right as u8,
+];
+
 pub trait JoypadBus {
     fn request_joypad_interrupt(&mut self);
 }
 
+/// Input from the frontend isn't part of save states, so that loading one doesn't leave buttons
+/// held that the user has since let go of.
+#[derive(Serialize, Deserialize)]
 pub struct Joypad {
+    /// The buttons as the game sees them
     state: u8,
     p1: u8,
+    /// The buttons the frontend is holding down
+    #[serde(skip)]
+    held: u8,
+    /// The directions an analog stick is holding down, on top of `held`
+    #[serde(skip)]
+    stick: u8,
+    /// The direction on each axis that was pressed last
+    #[serde(skip)]
+    last: u8,
+    #[serde(skip)]
+    socd: Socd,
 }
 
 impl Joypad {
     pub fn new() -> Self {
-        // Upper 2 bits of P1 are locked on
-        Self { state: 0, p1: 0xc0 }
+        Self {
+            state: 0,
+            // Upper 2 bits of P1 are locked on
+            p1: 0xc0,
+            held: 0,
+            stick: 0,
+            last: 0,
+            socd: Socd::default(),
+        }
     }
 
     pub fn handle(&mut self, button: Button, state: ButtonState, bus: &mut impl JoypadBus) {
         let button = 1 << button as u8;
-        match state {
-            ButtonState::Pressed => {
-                self.state |= button;
-                bus.request_joypad_interrupt();
+        let held = match state {
+            ButtonState::Pressed => self.held | button,
+            ButtonState::Released => self.held & !button,
+        };
+        self.update(held, self.stick, bus);
+    }
+
+    /// Holds down the d-pad direction an analog stick points in, out of eight, once it's pushed
+    /// at least `deadzone` away from the center. `x` is positive to the right and `y` up, both
+    /// from -1 to 1.
+    pub fn handle_stick(&mut self, x: f32, y: f32, deadzone: f32, bus: &mut impl JoypadBus) {
+        let stick = if x.hypot(y) < deadzone {
+            0
+        } else {
+            let eighth = (y.atan2(x) / FRAC_PI_4).round() as i32;
+            STICK_DIRECTIONS[eighth.rem_euclid(8) as usize]
+        };
+        self.update(self.held, stick, bus);
+    }
+
+    /// The buttons held down, on the d-pad or the stick.
+    pub fn input(&self) -> Input {
+        Input(self.held | self.stick)
+    }
+
+    /// Holds down exactly the buttons in `input`, letting go of the stick.
+    pub fn set_input(&mut self, input: Input, bus: &mut impl JoypadBus) {
+        self.update(input.0, 0, bus);
+    }
+
+    pub fn held_input(&self) -> HeldInput {
+        HeldInput {
+            input: self.input(),
+            last: self.last,
+        }
+    }
+
+    /// Holds down the buttons in `held` as if they had been held all along, so no interrupt is
+    /// requested. Lets go of the stick.
+    pub fn set_held_input(&mut self, held: HeldInput) {
+        self.held = held.input.0;
+        self.stick = 0;
+        self.last = held.last;
+        self.state = self.resolve();
+    }
+
+    pub fn socd(&self) -> Socd {
+        self.socd
+    }
+
+    pub fn set_socd(&mut self, socd: Socd) {
+        self.socd = socd;
+        self.state = self.resolve();
+    }
+
+    /// Takes the hardware state from `saved`, keeping the input and settings from the frontend.
+    pub(crate) fn restore(&mut self, saved: Joypad) {
+        self.p1 = saved.p1;
+        self.state = self.resolve();
+    }
+
+    fn update(&mut self, held: u8, stick: u8, bus: &mut impl JoypadBus) {
+        let pressed = (held | stick) & !(self.held | self.stick);
+        for axis in AXES {
+            match pressed & axis {
+                0 => (),
+                // Both at once, which is as good as neither having been pressed last
+                new if new == axis => self.last &= !axis,
+                new => self.last = self.last & !axis | new,
             }
-            ButtonState::Released => self.state &= !button,
         }
+        self.held = held;
+        self.stick = stick;
+        let state = self.resolve();
+        if state & !self.state != 0 {
+            bus.request_joypad_interrupt();
+        }
+        self.state = state;
+    }
+
+    /// The buttons the game should see, with opposing directions resolved.
+    fn resolve(&self) -> u8 {
+        let held = self.held | self.stick;
+        AXES.into_iter().fold(held, |state, axis| {
+            if held & axis != axis {
+                return state;
+            }
+            match self.socd {
+                Socd::Neutral => state & !axis,
+                Socd::LastWins => state & !axis | self.last & axis,
+            }
+        })
     }
 
     fn direction_bits(&self) -> u8 {
@@ -68,3 +268,65 @@ impl Joypad {
         self.p1 |= p1 & 0x30;
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Default)]
+    struct Interrupts(usize);
+
+    impl JoypadBus for Interrupts {
+        fn request_joypad_interrupt(&mut self) {
+            self.0 += 1;
+        }
+    }
+
+    /// The directions the game sees, active high.
+    fn directions(joypad: &mut Joypad) -> u8 {
+        joypad.set_p1(0x20);
+        !joypad.p1() & 0x0f
+    }
+
+    const RIGHT: u8 = 1 << Button::Right as u8;
+    const LEFT: u8 = 1 << Button::Left as u8;
+    const UP: u8 = 1 << Button::Up as u8;
+
+    #[test]
+    fn socd() {
+        let mut bus = Interrupts::default();
+        let mut joypad = Joypad::new();
+        joypad.handle(Button::Left, ButtonState::Pressed, &mut bus);
+        joypad.handle(Button::Right, ButtonState::Pressed, &mut bus);
+        assert_eq!(directions(&mut joypad), RIGHT);
+        joypad.handle(Button::Right, ButtonState::Released, &mut bus);
+        assert_eq!(directions(&mut joypad), LEFT);
+        // Letting go of right gave left back, which is another press
+        assert_eq!(bus.0, 3);
+
+        joypad.handle(Button::Right, ButtonState::Pressed, &mut bus);
+        joypad.set_socd(Socd::Neutral);
+        assert_eq!(directions(&mut joypad), 0);
+        joypad.handle(Button::Up, ButtonState::Pressed, &mut bus);
+        assert_eq!(directions(&mut joypad), UP);
+    }
+
+    #[test]
+    fn stick() {
+        let mut bus = Interrupts::default();
+        let mut joypad = Joypad::new();
+        joypad.handle_stick(0.3, 0.1, 0.5, &mut bus);
+        assert_eq!(directions(&mut joypad), 0);
+        joypad.handle_stick(0.9, 0.1, 0.5, &mut bus);
+        assert_eq!(directions(&mut joypad), RIGHT);
+        joypad.handle_stick(0.6, 0.6, 0.5, &mut bus);
+        assert_eq!(directions(&mut joypad), RIGHT | UP);
+        joypad.handle_stick(-0.1, -0.9, 0.5, &mut bus);
+        assert_eq!(directions(&mut joypad), 1 << Button::Down as u8);
+
+        // The stick and the d-pad together are resolved like any other opposing directions
+        joypad.handle_stick(-1.0, 0.0, 0.5, &mut bus);
+        joypad.handle(Button::Right, ButtonState::Pressed, &mut bus);
+        assert_eq!(directions(&mut joypad), RIGHT);
+    }
+}
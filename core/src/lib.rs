@@ -4,14 +4,29 @@
 #![allow(clippy::new_without_default)]
 
 mod apu;
+mod byte_array;
 mod cpu;
 mod dma;
 mod interrupt;
+mod logging;
 mod memory;
+mod open_bus;
 mod ppu;
 mod reg;
+mod serial;
 mod timer;
 
 pub mod cart;
+pub mod cheat;
+pub mod delta;
+pub mod event;
+pub mod harness;
 pub mod joypad;
+pub mod movie;
+pub mod snapshot;
+pub mod symbols;
 pub mod system;
+#[cfg(feature = "test-roms")]
+pub mod test_roms;
+#[cfg(any(test, feature = "test-support"))]
+pub mod test_support;
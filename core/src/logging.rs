@@ -0,0 +1,25 @@
+// SPDX-License-Identifier: GPL-3.0-or-later
+// Copyright (C) 2023 Robert Hrusecky <jadedpastabowl@gmail.com>
+
+//! Log targets for each subsystem, so they can be turned on separately, like with
+//! `RUST_LOG=iron_boy::interrupt=debug,iron_boy::dma=debug`.
+//!
+//! Events that happen a few times a frame, like interrupts and DMA, are logged at the debug level.
+//! Every instruction and memory access is logged at the trace level, but only with the `trace`
+//! feature, since checking whether that's enabled so often would slow down emulation otherwise.
+
+pub const CPU: &str = "iron_boy::cpu";
+/// Memory accessed by instructions. Opcode fetches are left to the [`CPU`] target.
+pub const MEMORY: &str = "iron_boy::memory";
+pub const INTERRUPT: &str = "iron_boy::interrupt";
+pub const DMA: &str = "iron_boy::dma";
+pub const APU: &str = "iron_boy::apu";
+
+/// Whether trace level logging is enabled for `target`, which it never is without the `trace`
+/// feature.
+macro_rules! tracing {
+    ($target:expr) => {
+        cfg!(feature = "trace") && log::log_enabled!(target: $target, log::Level::Trace)
+    };
+}
+pub(crate) use tracing;
@@ -1,14 +1,27 @@
 // SPDX-License-Identifier: GPL-3.0-or-later
 // Copyright (C) 2023 Robert Hrusecky <jadedpastabowl@gmail.com>
-use std::mem::{self, MaybeUninit};
+use std::mem;
 
+use serde::{Deserialize, Serialize};
+
+#[derive(Serialize, Deserialize)]
 pub struct WorkRam {
-    low: [u8; 0x1000],
-    high: [[u8; 0x1000]; 7],
+    #[serde(with = "crate::byte_array")]
+    low: Box<[u8; 0x1000]>,
+    #[serde(with = "crate::byte_array")]
+    high: Box<[[u8; 0x1000]; 7]>,
     pub svbk: u8,
 }
 
 impl WorkRam {
+    fn new() -> Self {
+        Self {
+            low: Box::new([0; 0x1000]),
+            high: Box::new([[0; 0x1000]; 7]),
+            svbk: 0,
+        }
+    }
+
     fn bank(&self, cgb_mode: bool) -> usize {
         if !cgb_mode || self.svbk == 0 {
             0
@@ -32,16 +45,30 @@ impl WorkRam {
     pub fn write_high(&mut self, addr: u16, val: u8, cgb_mode: bool) {
         self.high[self.bank(cgb_mode)][addr as usize & 0xfff] = val;
     }
+
+    /// Every bank, in order.
+    pub fn bytes(&self) -> Vec<u8> {
+        [&self.low[..], self.high.as_flattened()].concat()
+    }
 }
 
 pub type VRamBytes = [[u8; 0x2000]; 2];
 
+#[derive(Serialize, Deserialize)]
 pub struct VideoRam {
-    vram: VRamBytes,
+    #[serde(with = "crate::byte_array")]
+    vram: Box<VRamBytes>,
     pub vbk: u8,
 }
 
 impl VideoRam {
+    fn new() -> Self {
+        Self {
+            vram: Box::new([[0; 0x2000]; 2]),
+            vbk: 0,
+        }
+    }
+
     pub fn bank(&self, cgb_mode: bool) -> usize {
         if cgb_mode {
             self.vbk as usize & 0x1
@@ -61,18 +88,31 @@ impl VideoRam {
     pub fn bytes(&self) -> &VRamBytes {
         &self.vram
     }
+
+    pub fn load(&mut self, bank: usize, offset: usize, data: &[u8]) {
+        self.vram[bank][offset..offset + data.len()].copy_from_slice(data);
+    }
 }
 
 pub type Color = [u8; 2];
 pub type Palette = [Color; 4];
 pub type Palettes = [Palette; 8];
 
+#[derive(Serialize, Deserialize)]
 pub struct PaletteRam {
+    #[serde(with = "crate::byte_array")]
     ram: [u8; 64],
     pub select: u8,
 }
 
 impl PaletteRam {
+    fn new() -> Self {
+        Self {
+            ram: [0; 64],
+            select: 0,
+        }
+    }
+
     fn index(&self) -> usize {
         (self.select & 0x3f) as usize
     }
@@ -89,16 +129,23 @@ impl PaletteRam {
     pub fn palettes(&self) -> &Palettes {
         unsafe { mem::transmute(&self.ram) }
     }
+
+    pub fn load(&mut self, data: &[u8]) {
+        self.ram[..data.len()].copy_from_slice(data);
+    }
 }
 
 pub type OamBytes = [u8; 0xa0];
 
+#[derive(Serialize, Deserialize)]
 pub struct MemoryData {
     pub vram: VideoRam,
     pub wram: WorkRam,
     // echo_ram: mirror of 0xc000~0xddff
+    #[serde(with = "crate::byte_array")]
     pub oam: OamBytes,
     // prohibited_area: 0xfea0~0xfeff
+    #[serde(with = "crate::byte_array")]
     pub hram: [u8; 0x7f],
     pub bg_palette: PaletteRam,
     pub obj_palette: PaletteRam,
@@ -106,7 +153,14 @@ pub struct MemoryData {
 
 impl MemoryData {
     pub fn new() -> Self {
-        // SAFTEY: All zeros is valid for MemoryData, which is just a bunch of nested arrays of u8
-        unsafe { MaybeUninit::<MemoryData>::zeroed().assume_init() }
+        // The larger memories are boxed to keep the system, and save states of it, off the stack
+        Self {
+            vram: VideoRam::new(),
+            wram: WorkRam::new(),
+            oam: [0; 0xa0],
+            hram: [0; 0x7f],
+            bg_palette: PaletteRam::new(),
+            obj_palette: PaletteRam::new(),
+        }
     }
 }
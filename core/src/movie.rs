@@ -0,0 +1,216 @@
+// SPDX-License-Identifier: GPL-3.0-or-later
+// Copyright (C) 2023 Robert Hrusecky <jadedpastabowl@gmail.com>
+
+//! Movies, which record the buttons held on each frame so that a run can be played back exactly,
+//! like for tool-assisted runs or reproducing a bug. A movie starts from a save state taken when
+//! recording started, which may be right after power on.
+//!
+//! Playback only matches the recording if the system is deterministic, so recording switches the
+//! RTC over to [`RtcClock::Emulated`]. Settings that aren't part of save states, like cheats and
+//! overclocking, aren't recorded and have to match by hand.
+
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+use crate::{
+    cart::RtcClock,
+    joypad::{HeldInput, Input, Socd},
+    system::{CgbSystem, StateError},
+};
+
+const MAGIC: &[u8; 4] = b"IBMV";
+/// Must be bumped whenever the layout of [`Movie`] changes.
+const VERSION: u32 = 2;
+
+#[derive(Error, Debug)]
+pub enum MovieError {
+    #[error("Not a movie")]
+    NotAMovie,
+    #[error("Movie version {0} is not supported, expected version {VERSION}")]
+    UnsupportedVersion(u32),
+    #[error("Movie is for a different ROM")]
+    WrongRom,
+    #[error("Movie is corrupt")]
+    Corrupt(#[from] bincode::Error),
+    #[error("The movie's starting state failed to load")]
+    State(#[from] StateError),
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct Movie {
+    rom_checksums: [u8; 3],
+    /// Which decides what the game sees when opposing directions are held
+    socd: Socd,
+    /// A save state from when recording started
+    start: Vec<u8>,
+    /// What was held when recording started, which isn't part of the state
+    held: HeldInput,
+    frames: u64,
+    /// The frames where the input changed, in order, and what it changed to
+    changes: Vec<(u64, Input)>,
+}
+
+impl Movie {
+    /// Starts recording from where `system` is now, switching its RTC to emulated time.
+    pub fn record(system: &mut CgbSystem) -> Self {
+        system.set_rtc_clock(RtcClock::Emulated);
+        Self {
+            rom_checksums: system.cart().checksums(),
+            socd: system.socd(),
+            start: system.save_state(),
+            held: system.held_input(),
+            frames: 0,
+            changes: Vec::new(),
+        }
+    }
+
+    /// Records `input` as held for the next frame.
+    pub fn push(&mut self, input: Input) {
+        if self.changes.last().map(|&(_, last)| last) != Some(input) {
+            self.changes.push((self.frames, input));
+        }
+        self.frames += 1;
+    }
+
+    /// The number of frames recorded.
+    pub fn frames(&self) -> u64 {
+        self.frames
+    }
+
+    pub fn socd(&self) -> Socd {
+        self.socd
+    }
+
+    /// The input held for `frame`, counting from 0, or `None` past the end of the movie.
+    pub fn input(&self, frame: u64) -> Option<Input> {
+        if frame >= self.frames {
+            return None;
+        }
+        let i = self.changes.partition_point(|&(start, _)| start <= frame);
+        Some(i.checked_sub(1).map_or(Input::NONE, |i| self.changes[i].1))
+    }
+
+    /// Puts `system` back where recording started, ready to play the first frame, and switches its
+    /// RTC to emulated time.
+    pub fn rewind(&self, system: &mut CgbSystem) -> Result<(), MovieError> {
+        if self.rom_checksums != system.cart().checksums() {
+            return Err(MovieError::WrongRom);
+        }
+        system.set_rtc_clock(RtcClock::Emulated);
+        system.load_state(&self.start)?;
+        system.set_socd(self.socd);
+        system.set_held_input(self.held);
+        Ok(())
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut data = Vec::new();
+        data.extend_from_slice(MAGIC);
+        data.extend_from_slice(&VERSION.to_le_bytes());
+        bincode::serialize_into(&mut data, self).expect("Serializing into a Vec can't fail");
+        data
+    }
+
+    pub fn from_bytes(data: &[u8]) -> Result<Self, MovieError> {
+        let data = data.strip_prefix(MAGIC).ok_or(MovieError::NotAMovie)?;
+        let (version, data) = data.split_first_chunk().ok_or(MovieError::NotAMovie)?;
+        let version = u32::from_le_bytes(*version);
+        if version != VERSION {
+            return Err(MovieError::UnsupportedVersion(version));
+        }
+        Ok(bincode::deserialize(data)?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        cart::Cart,
+        harness::Harness,
+        joypad::Button,
+        system::{BootRom, CgbSystem},
+    };
+
+    use super::*;
+
+    fn harness(header_checksum: u8) -> Harness {
+        let mut rom = vec![0; 0x8000];
+        // Select the directions in P1, then copy it around 0xc000-0xc0ff forever
+        rom[0x100..0x10d].copy_from_slice(&[
+            0x3e, 0x20, 0xe0, 0x00, 0x21, 0x00, 0xc0, 0xf0, 0x00, 0x77, 0x2c, 0x18, 0xfa,
+        ]);
+        rom[0x14d] = header_checksum;
+        let cart = Cart::from_rom(rom.into_boxed_slice()).unwrap();
+        Harness::with_system(CgbSystem::with_boot_rom(cart, BootRom::skip()))
+    }
+
+    #[test]
+    fn playback_matches_recording() {
+        let mut recording = harness(0);
+        recording.run_frames(3);
+        let mut movie = Movie::record(recording.system_mut());
+        let right = Input::NONE.with(Button::Right);
+        let inputs = [
+            Input::NONE,
+            right,
+            right,
+            right.with(Button::Down),
+            Input::NONE,
+        ];
+        for input in inputs {
+            movie.push(input);
+            recording.run_frame(input);
+        }
+        let expected = recording.system().save_state();
+
+        let movie = Movie::from_bytes(&movie.to_bytes()).unwrap();
+        assert_eq!(movie.frames(), inputs.len() as u64);
+        assert_eq!(movie.input(2), Some(right));
+        assert_eq!(movie.input(movie.frames()), None);
+
+        let mut playback = harness(0);
+        movie.rewind(playback.system_mut()).unwrap();
+        for frame in 0..movie.frames() {
+            playback.run_frame(movie.input(frame).unwrap());
+        }
+        assert_eq!(playback.system().save_state(), expected);
+    }
+
+    #[test]
+    fn starts_with_recorded_input() {
+        let right = Input::NONE.with(Button::Right);
+        let mut recording = harness(0);
+        recording.run_frame(right);
+        // Clear the interrupt from pressing it
+        recording.system_mut().poke(0xff0f, 0x00);
+        let mut movie = Movie::record(recording.system_mut());
+        for _ in 0..3 {
+            movie.push(right);
+            recording.run_frame(right);
+        }
+        let expected = recording.system().save_state();
+
+        // Holding something else when playback starts makes no difference, not even an interrupt
+        let mut playback = harness(0);
+        playback.run_frame(Input::NONE.with(Button::Left));
+        movie.rewind(playback.system_mut()).unwrap();
+        for frame in 0..movie.frames() {
+            playback.run_frame(movie.input(frame).unwrap());
+        }
+        assert_eq!(playback.system().save_state(), expected);
+    }
+
+    #[test]
+    fn rejects_other_roms() {
+        let mut recording = harness(0);
+        let movie = Movie::record(recording.system_mut());
+        assert!(matches!(
+            movie.rewind(harness(1).system_mut()),
+            Err(MovieError::WrongRom)
+        ));
+        assert!(matches!(
+            Movie::from_bytes(b"not a movie"),
+            Err(MovieError::NotAMovie)
+        ));
+    }
+}
@@ -0,0 +1,45 @@
+// SPDX-License-Identifier: GPL-3.0-or-later
+// Copyright (C) 2023 Robert Hrusecky <jadedpastabowl@gmail.com>
+use std::cell::Cell;
+
+use crate::event::{EventKind, EventLog, Severity};
+
+/// Handles accesses to IO registers that aren't implemented.
+pub struct OpenBus {
+    pub value: u8,
+    pub log_first_access: bool,
+    // One bit per register in 0xff00~0xff7f
+    seen: Cell<u128>,
+}
+
+impl OpenBus {
+    pub fn new() -> Self {
+        Self {
+            // Unconnected lines are pulled high on hardware
+            value: 0xff,
+            log_first_access: true,
+            seen: Cell::new(0),
+        }
+    }
+
+    fn note(&self, addr: u16, kind: EventKind, events: &EventLog) {
+        if !self.log_first_access {
+            return;
+        }
+        let bit = 1 << (addr & 0x7f);
+        let seen = self.seen.get();
+        if seen & bit == 0 {
+            self.seen.set(seen | bit);
+            events.push(Severity::Warning, kind);
+        }
+    }
+
+    pub fn read(&self, addr: u16, events: &EventLog) -> u8 {
+        self.note(addr, EventKind::UnimplementedRead(addr), events);
+        self.value
+    }
+
+    pub fn write(&self, addr: u16, val: u8, events: &EventLog) {
+        self.note(addr, EventKind::UnimplementedWrite(addr, val), events);
+    }
+}
@@ -1,10 +1,16 @@
 // SPDX-License-Identifier: GPL-3.0-or-later
 // Copyright (C) 2023 Robert Hrusecky <jadedpastabowl@gmail.com>
+use std::{
+    fmt::{self, Display, Formatter},
+    sync::OnceLock,
+};
+
 use bilge::prelude::*;
+use serde::{Deserialize, Deserializer, Serialize};
 
 use crate::{
     memory::{OamBytes, Palettes, VRamBytes},
-    system::{self, FrameBuffer},
+    system::{self, FrameBuffer, Model},
 };
 
 #[bitsize(2)]
@@ -38,7 +44,8 @@ struct StatInterruptSources {
 }
 
 #[bitsize(8)]
-#[derive(FromBits, DebugBits, DefaultBits, Clone, Copy)]
+#[derive(FromBits, DebugBits, DefaultBits, Clone, Copy, Serialize, Deserialize)]
+#[serde(from = "u8", into = "u8")]
 struct Stat {
     mode: Mode,
     lyc_equal: bool,
@@ -47,7 +54,8 @@ struct Stat {
 }
 
 #[bitsize(8)]
-#[derive(FromBits, DebugBits, Clone, Copy)]
+#[derive(FromBits, DebugBits, Clone, Copy, Serialize, Deserialize)]
+#[serde(from = "u8", into = "u8")]
 struct Lcdc {
     bg_window_enable_priority: bool,
     obj_enabled: bool,
@@ -92,6 +100,7 @@ pub trait PpuBus {
     fn oam(&self) -> &OamBytes;
 
     fn cgb_mode(&self) -> bool;
+    fn model(&self) -> Model;
 }
 
 // Use a separate extension trait so that Obj can be private
@@ -103,7 +112,166 @@ trait ObjView: PpuBus {
 }
 impl<T: PpuBus> ObjView for T {}
 
-#[derive(Debug)]
+/// Display overrides that trade accuracy for looks or debugging. The defaults match hardware.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct PpuConfig {
+    /// Maximum number of OBJs drawn per scanline. Hardware draws at most 10; raising this to 40
+    /// removes sprite flicker in games that multiplex sprites.
+    pub sprite_limit: usize,
+    pub show_bg: bool,
+    pub show_window: bool,
+    pub show_objs: bool,
+    /// Average each frame with the previous one, emulating the slow response of the LCD. Some
+    /// games rely on this for transparency effects.
+    pub frame_blend: bool,
+    /// How colors are converted from RGB555 for display.
+    #[serde(
+        alias = "color_correction",
+        deserialize_with = "deserialize_color_transform"
+    )]
+    pub color_transform: ColorTransform,
+}
+
+impl Default for PpuConfig {
+    fn default() -> Self {
+        Self {
+            sprite_limit: 10,
+            show_bg: true,
+            show_window: true,
+            show_objs: true,
+            frame_blend: false,
+            color_transform: ColorTransform::Raw,
+        }
+    }
+}
+
+/// How the CGB's RGB555 colors become the RGB888 colors of the frame buffer. Games were colored to
+/// look right on the CGB's LCD, which mixes the channels together and is darker than a monitor, so
+/// their raw colors look oversaturated.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ColorTransform {
+    /// Each channel scaled up as is
+    #[default]
+    Raw,
+    /// A cheap integer approximation of the LCD, like Gambatte's
+    Fast,
+    /// The LCD's color matrix, applied in linear light
+    Accurate,
+}
+
+impl ColorTransform {
+    pub const ALL: [ColorTransform; 3] = [Self::Raw, Self::Fast, Self::Accurate];
+
+    fn to_rgb(self, color: u16) -> [u8; 3] {
+        let [r, g, b] = [color, color >> 5, color >> 10].map(|c| (c & 0x1f) as u32);
+        match self {
+            Self::Raw => [r, g, b].map(|c| (c * 0xff / 0x1f) as u8),
+            Self::Fast => [
+                r * 26 + g * 4 + b * 2,
+                g * 24 + b * 8,
+                r * 6 + g * 4 + b * 22,
+            ]
+            .map(|c| (c.min(960) >> 2) as u8),
+            Self::Accurate => {
+                static TABLE: OnceLock<Box<[[u8; 3]]>> = OnceLock::new();
+                TABLE.get_or_init(|| (0..0x8000).map(lcd_color).collect())[color as usize & 0x7fff]
+            }
+        }
+    }
+}
+
+/// Also accepts the `color_correction` flag that the transform replaced, which turned on the fast
+/// one.
+fn deserialize_color_transform<'de, D: Deserializer<'de>>(
+    deserializer: D,
+) -> Result<ColorTransform, D::Error> {
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum Setting {
+        Transform(ColorTransform),
+        Correction(bool),
+    }
+
+    Ok(match Setting::deserialize(deserializer)? {
+        Setting::Transform(transform) => transform,
+        Setting::Correction(true) => ColorTransform::Fast,
+        Setting::Correction(false) => ColorTransform::Raw,
+    })
+}
+
+impl Display for ColorTransform {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::Raw => "Raw",
+            Self::Fast => "Fast",
+            Self::Accurate => "Accurate",
+        })
+    }
+}
+
+/// The gamma of both the LCD and the monitor showing it
+const GAMMA: f32 = 2.2;
+/// How bright the LCD's white is
+const LCD_LUMINANCE: f32 = 0.94;
+/// How much of each input channel (columns) ends up in each output channel (rows). From
+/// Pokefan531's measurements of the CGB's LCD.
+const LCD_MATRIX: [[f32; 3]; 3] = [
+    [0.82, 0.24, -0.06],
+    [0.125, 0.665, 0.21],
+    [0.195, 0.075, 0.73],
+];
+
+fn lcd_color(color: u16) -> [u8; 3] {
+    let linear = [color, color >> 5, color >> 10].map(|c| ((c & 0x1f) as f32 / 31.0).powf(GAMMA));
+    LCD_MATRIX.map(|row| {
+        let mixed = row
+            .iter()
+            .zip(linear)
+            .map(|(weight, c)| weight * c)
+            .sum::<f32>();
+        let c = (mixed * LCD_LUMINANCE).clamp(0.0, 1.0).powf(GAMMA.recip());
+        (c * 255.0).round() as u8
+    })
+}
+
+/// The shades a DMG shows, since it has no palette RAM to choose colors from.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum DmgPalette {
+    #[default]
+    Gray,
+    /// The yellowish green of the original LCD
+    Green,
+}
+
+impl DmgPalette {
+    pub const ALL: [DmgPalette; 2] = [Self::Gray, Self::Green];
+
+    /// Lightest first, as RGB555
+    pub(crate) fn shades(self) -> [u16; 4] {
+        match self {
+            Self::Gray => [0x7fff, 0x56b5, 0x294a, 0x0000],
+            Self::Green => [0x06f3, 0x06b1, 0x1986, 0x04e1],
+        }
+    }
+}
+
+impl Display for DmgPalette {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::Gray => "Gray",
+            Self::Green => "Green",
+        })
+    }
+}
+
+impl PpuConfig {
+    pub(crate) fn to_rgb(&self, color: u16) -> [u8; 3] {
+        self.color_transform.to_rgb(color)
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
 pub struct Ppu {
     mode_cycles_remaining: usize,
     pub bgp: u8,
@@ -118,7 +286,16 @@ pub struct Ppu {
     pub wy: u8,
     stat: Stat,
     below_window: bool,
+    /// The line of the window to draw next. It only advances on lines that show the window, so
+    /// hiding the window partway down the screen makes it pick up where it left off.
+    window_line: u8,
     interrupt_line: bool,
+    /// Belongs to the user rather than the emulated hardware, so it isn't part of save states
+    #[serde(skip)]
+    pub config: PpuConfig,
+    /// Only used by a DMG. Belongs to the user like `config`.
+    #[serde(skip)]
+    pub dmg_palette: DmgPalette,
 }
 
 struct ObjPixel {
@@ -150,18 +327,45 @@ impl Ppu {
             wy: 0,
             stat,
             below_window: false,
+            window_line: 0,
             interrupt_line: false,
+            config: PpuConfig::default(),
+            dmg_palette: DmgPalette::default(),
+        }
+    }
+
+    /// What the screen shows with the LCD off.
+    pub fn blank_pixel(&self, model: Model) -> [u8; 4] {
+        match model {
+            Model::Cgb => [0xff; 4],
+            Model::Dmg => {
+                let [r, g, b] = self.config.to_rgb(self.dmg_palette.shades()[0]);
+                [r, g, b, 0xff]
+            }
         }
     }
 
+    /// Whether the window shows on the current line. It starts at WX - 7, so it's off screen for
+    /// WX past 166, and shifted left for WX below 7.
+    fn window_on_line(&self) -> bool {
+        self.lcdc.window_enabled() && self.below_window && self.wx <= 166
+    }
+
     fn fetch_bg_pixel(&self, lx: u8, bus: &impl PpuBus) -> BgPixel {
         let vram = bus.vram();
 
         let window_x = lx + 7;
-        let render_window = self.lcdc.window_enabled() && self.below_window && window_x >= self.wx;
+        let render_window = self.window_on_line() && self.config.show_window && window_x >= self.wx;
+        if !render_window && !self.config.show_bg {
+            return BgPixel {
+                color: 0,
+                palette: 0,
+                bg_over_obj: false,
+            };
+        }
 
         let pixel_y = if render_window {
-            self.ly - self.wy
+            self.window_line
         } else {
             self.ly.wrapping_add(self.scy)
         };
@@ -219,7 +423,7 @@ impl Ppu {
         selected_objs: &[usize],
         bus: &impl PpuBus,
     ) -> Option<ObjPixel> {
-        if !self.lcdc.obj_enabled() {
+        if !self.lcdc.obj_enabled() || !self.config.show_objs {
             return None;
         }
 
@@ -319,6 +523,9 @@ impl Ppu {
                     };
                     ((obp >> (obj_pixel.color * 2)) & 0x3, obj_pixel.palette)
                 };
+                if bus.model() == Model::Dmg {
+                    return self.dmg_palette.shades()[color as usize];
+                }
 
                 let palette = obj_palettes[palette as usize];
                 return u16::from_le_bytes(palette[color as usize]);
@@ -327,7 +534,10 @@ impl Ppu {
 
         if !bus.cgb_mode() && !bg_enable_pri {
             // BG disabled; display as white
-            return 0x7fff;
+            return match bus.model() {
+                Model::Cgb => 0x7fff,
+                Model::Dmg => self.dmg_palette.shades()[0],
+            };
         }
 
         let color = if bus.cgb_mode() {
@@ -335,6 +545,9 @@ impl Ppu {
         } else {
             (self.bgp >> (bg_pixel.color * 2)) & 0x3
         };
+        if bus.model() == Model::Dmg {
+            return self.dmg_palette.shades()[color as usize];
+        }
 
         let palette = bg_palettes[bg_pixel.palette as usize];
         u16::from_le_bytes(palette[color as usize])
@@ -353,7 +566,7 @@ impl Ppu {
             .enumerate()
             .filter(|(_, obj)| obj.y <= obj_target_y && obj_target_y < obj.y + height)
             .map(|(i, _)| i)
-            .take(10)
+            .take(self.config.sprite_limit)
             .collect();
 
         if !bus.cgb_mode() {
@@ -369,11 +582,20 @@ impl Ppu {
 
             let color = self.mix_pixels(bg_pixel, obj_pixel, bus);
 
-            let mask_rescale = |c| ((c & 0x1f) * 0xff / 0x1f) as u8;
-            let red = mask_rescale(color);
-            let green = mask_rescale(color >> 5);
-            let blue = mask_rescale(color >> 10);
-            frame_buff[self.ly as usize][lx as usize] = [red, green, blue, 0xff];
+            let [red, green, blue] = self.config.to_rgb(color);
+            let pixel = &mut frame_buff[self.ly as usize][lx as usize];
+            *pixel = if self.config.frame_blend {
+                // The frame buffer still holds the previous frame
+                let blend = |old: u8, new: u8| ((old as u16 + new as u16) / 2) as u8;
+                [
+                    blend(pixel[0], red),
+                    blend(pixel[1], green),
+                    blend(pixel[2], blue),
+                    0xff,
+                ]
+            } else {
+                [red, green, blue, 0xff]
+            };
         }
     }
 
@@ -403,6 +625,26 @@ impl Ppu {
         self.lcdc.into()
     }
 
+    /// The number of machine cycles until the current mode ends, if the LCD is on. Nothing
+    /// observable changes in between.
+    pub fn cycles_until_event(&self) -> Option<usize> {
+        self.lcd_enabled()
+            .then_some(self.mode_cycles_remaining.max(1))
+    }
+
+    /// Equivalent to executing `cycles` machine cycles, all of which must fall before the current
+    /// mode ends.
+    pub fn skip(&mut self, cycles: usize) {
+        if !self.lcd_enabled() || cycles == 0 {
+            return;
+        }
+        debug_assert!(cycles < self.mode_cycles_remaining);
+        if self.stat.mode().cycles() == self.mode_cycles_remaining {
+            self.start_of_mode();
+        }
+        self.mode_cycles_remaining -= cycles;
+    }
+
     pub fn lcd_enabled(&self) -> bool {
         self.lcdc.lcd_enabled()
     }
@@ -414,6 +656,7 @@ impl Ppu {
             self.ly = 0;
             self.switch_mode(Mode::OamSearch);
             self.below_window = false;
+            self.window_line = 0;
             self.interrupt_line = false;
         }
     }
@@ -429,6 +672,9 @@ impl Ppu {
             Mode::OamSearch => self.switch_mode(Mode::Transfer),
             Mode::Transfer => {
                 self.draw_scanline(frame_buff, bus);
+                if self.window_on_line() {
+                    self.window_line += 1;
+                }
                 self.switch_mode(Mode::HBlank);
             }
             Mode::HBlank => {
@@ -445,6 +691,7 @@ impl Ppu {
                 if self.ly == system::FRAME_LINES as u8 {
                     self.ly = 0;
                     self.below_window = false;
+                    self.window_line = 0;
                     self.switch_mode(Mode::OamSearch);
                 } else {
                     self.mode_cycles_remaining = Mode::VBlank.cycles();
@@ -498,66 +745,21 @@ impl Ppu {
 
 #[cfg(test)]
 mod tests {
-    use std::{iter::repeat, mem::MaybeUninit};
+    use std::iter::repeat;
 
-    use crate::{memory::VRamBytes, system::MachineCycle};
+    use crate::{memory::VRamBytes, system::MachineCycle, test_support::MockPpuBus};
 
     use super::*;
 
-    struct Bus {
-        vram: VRamBytes,
-        bg_palette_ram: Palettes,
-        obj_palette_ram: Palettes,
-        oam: OamBytes,
-        cgb_mode: bool,
-    }
-
-    impl Bus {
-        fn new() -> Box<Self> {
-            Box::new(Self {
-                vram: unsafe { MaybeUninit::zeroed().assume_init() },
-                bg_palette_ram: unsafe { MaybeUninit::zeroed().assume_init() },
-                obj_palette_ram: unsafe { MaybeUninit::zeroed().assume_init() },
-                oam: unsafe { MaybeUninit::zeroed().assume_init() },
-                cgb_mode: true,
-            })
-        }
-    }
-
-    impl PpuBus for Bus {
-        fn request_vblank_interrupt(&mut self) {}
-        fn request_stat_interrupt(&mut self) {}
-
-        fn vram(&self) -> &VRamBytes {
-            &self.vram
-        }
-
-        fn bg_palette_ram(&self) -> &Palettes {
-            &self.bg_palette_ram
-        }
-
-        fn obj_palette_ram(&self) -> &Palettes {
-            &self.obj_palette_ram
-        }
-
-        fn oam(&self) -> &OamBytes {
-            &self.oam
-        }
-
-        fn cgb_mode(&self) -> bool {
-            self.cgb_mode
-        }
-    }
-
     struct Context {
         ppu: Ppu,
-        bus: Box<Bus>,
+        bus: Box<MockPpuBus>,
         frame_buff: FrameBuffer,
     }
 
     impl Context {
         fn new(vram_init: impl FnOnce(&mut VRamBytes)) -> Self {
-            let mut bus = Bus::new();
+            let mut bus = Box::new(MockPpuBus::new());
             vram_init(&mut bus.vram);
             let palette: Vec<[u8; 2]> = [0xffff, 0x1f << 10, 0x1f << 5, 0x1f]
                 .into_iter()
@@ -570,7 +772,7 @@ mod tests {
             Self {
                 ppu,
                 bus,
-                frame_buff: unsafe { MaybeUninit::zeroed().assume_init() },
+                frame_buff: [[[0; 4]; system::SCREEN_WIDTH]; system::SCREEN_HEIGHT],
             }
         }
 
@@ -580,7 +782,11 @@ mod tests {
                 mode as u8 == Mode::OamSearch as u8,
                 "Started frame in {mode:?}"
             );
-            for _ in 0..MachineCycle::PER_FRAME {
+            self.draw_lines(system::FRAME_LINES);
+        }
+
+        fn draw_lines(&mut self, lines: usize) {
+            for _ in 0..lines * MachineCycle::PER_LINE {
                 self.ppu.execute(&mut self.frame_buff, &mut *self.bus);
             }
         }
@@ -608,6 +814,75 @@ mod tests {
         }
     }
 
+    const RED: [u8; 3] = [0xff, 0x00, 0x00];
+    const WHITE: [u8; 3] = [0xff, 0xff, 0xff];
+
+    fn checkerboard(x: u8, y: u8) -> [u8; 3] {
+        if (x / 8) & 0x1 == (y / 8) & 0x1 {
+            RED
+        } else {
+            WHITE
+        }
+    }
+
+    fn window_row(window_y: u8) -> [u8; 3] {
+        if (window_y / 8) & 0x1 == 0 {
+            RED
+        } else {
+            WHITE
+        }
+    }
+
+    /// The checkerboard behind a window map where every other row of tiles is red.
+    fn window_vram_init(vram: &mut VRamBytes) {
+        checkerboard_vram_init(vram);
+        for (y, x) in (0..32).flat_map(|y| repeat(y).zip(0..32)) {
+            vram[0][0x1c00 + 32 * y + x] = y as u8 & 0x1;
+        }
+    }
+
+    fn window_context() -> Context {
+        let mut ctx = Context::new(window_vram_init);
+        ctx.ppu.lcdc.set_window_enabled(true);
+        ctx.ppu.lcdc.set_window_map_bit(true.into());
+        ctx
+    }
+
+    #[test]
+    fn window_position() {
+        let mut ctx = window_context();
+        ctx.ppu.wy = 40;
+        for wx in [0, 3, 7, 27, 100, 166, 167, 255] {
+            ctx.ppu.wx = wx;
+            ctx.draw_frame();
+            ctx.assert_frame(|x, y| {
+                if wx <= 166 && y >= 40 && x + 7 >= wx {
+                    window_row(y - 40)
+                } else {
+                    checkerboard(x, y)
+                }
+            });
+        }
+    }
+
+    #[test]
+    fn window_line_counter() {
+        let mut ctx = window_context();
+        ctx.ppu.wx = 7;
+        // Hide the window for the second row of tiles
+        ctx.draw_lines(8);
+        ctx.ppu.lcdc.set_window_enabled(false);
+        ctx.draw_lines(8);
+        ctx.ppu.lcdc.set_window_enabled(true);
+        ctx.draw_lines(system::FRAME_LINES - 16);
+        ctx.assert_frame(|x, y| match y {
+            0..=7 => window_row(y),
+            8..=15 => checkerboard(x, y),
+            // Then it carries on from its second row
+            _ => window_row(y - 8),
+        });
+    }
+
     #[test]
     fn scroll_x() {
         let mut ctx = Context::new(checkerboard_vram_init);
@@ -626,6 +901,14 @@ mod tests {
         }
     }
 
+    #[test]
+    fn hide_bg() {
+        let mut ctx = Context::new(checkerboard_vram_init);
+        ctx.ppu.config.show_bg = false;
+        ctx.draw_frame();
+        ctx.assert_frame(|_, _| [0xff, 0xff, 0xff]);
+    }
+
     #[test]
     fn scroll_y() {
         let mut ctx = Context::new(checkerboard_vram_init);
@@ -643,4 +926,65 @@ mod tests {
             });
         }
     }
+
+    #[test]
+    fn color_transforms() {
+        let red = 0x001f;
+        assert_eq!(ColorTransform::Raw.to_rgb(red), [0xff, 0x00, 0x00]);
+        for transform in [ColorTransform::Fast, ColorTransform::Accurate] {
+            // The LCD's red is duller, and bleeds into blue
+            let [r, g, b] = transform.to_rgb(red);
+            assert!(r < 0xff && b > 0, "{transform}: {:?}", [r, g, b]);
+        }
+        for transform in ColorTransform::ALL {
+            assert_eq!(transform.to_rgb(0x0000), [0x00; 3], "{transform}");
+            let [r, g, b] = transform.to_rgb(0x7fff);
+            assert!(r == g && g == b && r > 0xe0, "{transform}: {:?}", [r, g, b]);
+        }
+    }
+
+    #[test]
+    fn color_correction_setting() {
+        use serde::de::{
+            value::{Error, MapDeserializer},
+            IntoDeserializer,
+        };
+
+        fn config<V: IntoDeserializer<'static, Error>>(key: &'static str, value: V) -> PpuConfig {
+            PpuConfig::deserialize(MapDeserializer::<_, Error>::new([(key, value)].into_iter()))
+                .unwrap()
+        }
+
+        assert_eq!(
+            config("color_transform", "Accurate").color_transform,
+            ColorTransform::Accurate
+        );
+        assert_eq!(
+            config("color_correction", true).color_transform,
+            ColorTransform::Fast
+        );
+        assert_eq!(
+            config("color_correction", false).color_transform,
+            ColorTransform::Raw
+        );
+    }
+
+    #[test]
+    fn dmg_palette() {
+        let mut ctx = Context::new(checkerboard_vram_init);
+        ctx.bus.cgb_mode = false;
+        ctx.bus.model = Model::Dmg;
+        ctx.ppu.lcdc.set_bg_window_enable_priority(true);
+        ctx.ppu.bgp = 0b11_10_01_00;
+        ctx.ppu.dmg_palette = DmgPalette::Green;
+        let [lightest, .., darkest] = DmgPalette::Green.shades().map(|c| ctx.ppu.config.to_rgb(c));
+        ctx.draw_frame();
+        ctx.assert_frame(|x, y| {
+            if (x / 8) & 0x1 == (y / 8) & 0x1 {
+                darkest
+            } else {
+                lightest
+            }
+        });
+    }
 }
@@ -68,6 +68,10 @@ u8_consts! {
     OCPD = 0x6b,  // OBJ color palette data                    | R/W   | CGB
     OPRI = 0x6c,  // Object priority mode                      | R/W   | CGB
     SVBK = 0x70,  // WRAM bank                                 | R/W   | CGB
+    FF72 = 0x72,  // Undocumented                              | R/W   | CGB
+    FF73 = 0x73,  // Undocumented                              | R/W   | CGB
+    FF74 = 0x74,  // Undocumented, locked in compat mode       | R/W   | CGB
+    FF75 = 0x75,  // Undocumented, only bits 4-6               | Mixed | CGB
     PCM12 = 0x76, // Audio digital outputs 1 & 2               | R     | CGB
     PCM34 = 0x77, // Audio digital outputs 3 & 4               | R     | CGB
     IE = 0xff,    // Interrupt enable                          | R/W   | All
@@ -0,0 +1,120 @@
+// SPDX-License-Identifier: GPL-3.0-or-later
+// Copyright (C) 2023 Robert Hrusecky <jadedpastabowl@gmail.com>
+
+//! The serial port. Nothing is ever on the other end of the link cable, so a transfer on the
+//! internal clock shifts in 1s and finishes on its own, while one on the external clock never
+//! finishes.
+
+use serde::{Deserialize, Serialize};
+
+const SC_TRANSFER: u8 = 0x80;
+const SC_FAST_CLOCK: u8 = 0x02;
+const SC_INTERNAL_CLOCK: u8 = 0x01;
+
+/// CPU cycles to shift one bit at 8192 Hz, or at 262144 Hz on the CGB's fast clock
+const BIT_CYCLES: u16 = 128;
+const FAST_BIT_CYCLES: u16 = 4;
+
+pub trait SerialBus {
+    fn request_serial_interrupt(&mut self);
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct Serial {
+    pub sb: u8,
+    sc: u8,
+    /// CPU cycles until the transfer on the internal clock finishes
+    cycles_left: Option<u16>,
+}
+
+impl Serial {
+    pub fn new() -> Self {
+        Self {
+            sb: 0,
+            sc: 0,
+            cycles_left: None,
+        }
+    }
+
+    /// The clock speed bit only exists in CGB mode.
+    pub fn sc(&self, cgb_mode: bool) -> u8 {
+        let used = SC_TRANSFER | SC_INTERNAL_CLOCK | if cgb_mode { SC_FAST_CLOCK } else { 0 };
+        !used | self.sc
+    }
+
+    pub fn set_sc(&mut self, sc: u8, cgb_mode: bool) {
+        self.sc = sc;
+        self.cycles_left = (sc & SC_TRANSFER != 0 && sc & SC_INTERNAL_CLOCK != 0).then(|| {
+            let fast = cgb_mode && sc & SC_FAST_CLOCK != 0;
+            8 * if fast { FAST_BIT_CYCLES } else { BIT_CYCLES }
+        });
+    }
+
+    pub fn cycles_until_done(&self) -> Option<usize> {
+        self.cycles_left.map(usize::from)
+    }
+
+    /// Runs for one CPU cycle.
+    pub fn execute(&mut self, bus: &mut impl SerialBus) {
+        let Some(cycles_left) = &mut self.cycles_left else {
+            return;
+        };
+        *cycles_left -= 1;
+        if *cycles_left == 0 {
+            self.cycles_left = None;
+            self.sb = 0xff;
+            self.sc &= !SC_TRANSFER;
+            bus.request_serial_interrupt();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct Bus(bool);
+
+    impl SerialBus for Bus {
+        fn request_serial_interrupt(&mut self) {
+            self.0 = true;
+        }
+    }
+
+    fn transfer_cycles(serial: &mut Serial) -> usize {
+        let mut bus = Bus(false);
+        let mut cycles = 0;
+        while !bus.0 {
+            serial.execute(&mut bus);
+            cycles += 1;
+        }
+        cycles
+    }
+
+    #[test]
+    fn internal_clock() {
+        let mut serial = Serial::new();
+        serial.sb = 0x42;
+        serial.set_sc(0x81, false);
+        assert_eq!(serial.sc(false), 0xff);
+        assert_eq!(transfer_cycles(&mut serial), 1024);
+        assert_eq!(serial.sc(false), 0x7f);
+        assert_eq!(serial.sb, 0xff);
+
+        // The fast clock only works in CGB mode
+        serial.set_sc(0x83, true);
+        assert_eq!(serial.sc(true), 0xff);
+        assert_eq!(transfer_cycles(&mut serial), 32);
+        assert_eq!(serial.sc(true), 0x7f);
+        serial.set_sc(0x83, false);
+        assert_eq!(serial.cycles_until_done(), Some(1024));
+    }
+
+    #[test]
+    fn external_clock() {
+        let mut serial = Serial::new();
+        serial.set_sc(0x80, false);
+        assert_eq!(serial.cycles_until_done(), None);
+        assert_eq!(serial.sc(false), 0xfe);
+    }
+}
@@ -0,0 +1,186 @@
+// SPDX-License-Identifier: GPL-3.0-or-later
+// Copyright (C) 2023 Robert Hrusecky <jadedpastabowl@gmail.com>
+
+//! Saving and restoring one subsystem at a time. Save states are made of a snapshot of each
+//! subsystem, and tests can use them to put a single subsystem back the way it was.
+
+use std::mem;
+
+use serde::{de::DeserializeOwned, Serialize};
+
+use crate::{
+    apu::Apu,
+    cart::{save::CartState, Cart},
+    cpu::Cpu,
+    dma::Dma,
+    memory::MemoryData,
+    ppu::Ppu,
+    system::StateError,
+    timer::Timer,
+};
+
+pub trait Snapshot {
+    /// Appends the state of the subsystem to `data`.
+    fn snapshot_into(&self, data: &mut Vec<u8>);
+
+    /// Restores a snapshot from [`Self::snapshot_into`]. Settings that belong to the user rather
+    /// than the emulated hardware are kept. On error, the subsystem is left untouched.
+    fn restore(&mut self, data: &[u8]) -> Result<(), StateError>;
+
+    fn snapshot(&self) -> Vec<u8> {
+        let mut data = Vec::new();
+        self.snapshot_into(&mut data);
+        data
+    }
+}
+
+fn serialize_into(data: &mut Vec<u8>, value: &impl Serialize) {
+    bincode::serialize_into(data, value).expect("Serializing into a Vec can't fail");
+}
+
+fn deserialize<T: DeserializeOwned>(data: &[u8]) -> Result<T, StateError> {
+    Ok(bincode::deserialize(data)?)
+}
+
+/// For subsystems that are nothing but hardware state.
+macro_rules! impl_snapshot {
+    ($($subsystem:ty),*) => {
+        $(
+            impl Snapshot for $subsystem {
+                fn snapshot_into(&self, data: &mut Vec<u8>) {
+                    serialize_into(data, self);
+                }
+
+                fn restore(&mut self, data: &[u8]) -> Result<(), StateError> {
+                    *self = deserialize(data)?;
+                    Ok(())
+                }
+            }
+        )*
+    };
+}
+
+impl_snapshot!(Timer, Dma, MemoryData);
+
+impl Snapshot for Cpu {
+    fn snapshot_into(&self, data: &mut Vec<u8>) {
+        serialize_into(data, self);
+    }
+
+    /// Keeps tracing on or off, along with the instructions traced so far.
+    fn restore(&mut self, data: &[u8]) -> Result<(), StateError> {
+        let cpu: Cpu = deserialize(data)?;
+        let trace = self.take_trace();
+        *self = cpu;
+        self.set_trace(trace);
+        Ok(())
+    }
+}
+
+impl Snapshot for Ppu {
+    fn snapshot_into(&self, data: &mut Vec<u8>) {
+        serialize_into(data, self);
+    }
+
+    fn restore(&mut self, data: &[u8]) -> Result<(), StateError> {
+        let ppu: Ppu = deserialize(data)?;
+        let config = mem::take(&mut self.config);
+        let dmg_palette = self.dmg_palette;
+        *self = ppu;
+        self.config = config;
+        self.dmg_palette = dmg_palette;
+        Ok(())
+    }
+}
+
+impl Snapshot for Apu {
+    fn snapshot_into(&self, data: &mut Vec<u8>) {
+        serialize_into(data, self);
+    }
+
+    fn restore(&mut self, data: &[u8]) -> Result<(), StateError> {
+        let apu: Apu = deserialize(data)?;
+        let muted = self.muted_channels();
+        *self = apu;
+        self.set_muted_channels(muted);
+        Ok(())
+    }
+}
+
+impl Snapshot for Cart {
+    /// The MBC and cartridge RAM. The ROM isn't included.
+    fn snapshot_into(&self, data: &mut Vec<u8>) {
+        serialize_into(data, &self.state());
+    }
+
+    fn restore(&mut self, data: &[u8]) -> Result<(), StateError> {
+        let state: CartState = deserialize(data)?;
+        if !self.load_state(state) {
+            return Err(StateError::WrongRom);
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn round_trip() {
+        let mut timer = Timer::new();
+        timer.set_tma(0x42);
+        let snapshot = timer.snapshot();
+        timer.set_tma(0x00);
+        timer.restore(&snapshot).unwrap();
+        assert_eq!(timer.tma(), 0x42);
+    }
+
+    #[test]
+    fn keeps_settings() {
+        let mut ppu = Ppu::new();
+        ppu.scx = 0x12;
+        let snapshot = ppu.snapshot();
+        ppu.scx = 0x00;
+        ppu.config.show_bg = false;
+        ppu.restore(&snapshot).unwrap();
+        assert_eq!(ppu.scx, 0x12);
+        assert!(!ppu.config.show_bg);
+
+        let mut apu = Apu::default();
+        apu.set_nr52(0x80);
+        apu.set_nr50(0x35);
+        let snapshot = apu.snapshot();
+        apu.set_nr50(0x00);
+        apu.set_muted_channels([true, false, true, false]);
+        apu.restore(&snapshot).unwrap();
+        assert_eq!(apu.nr50(), 0x35);
+        assert_eq!(apu.muted_channels(), [true, false, true, false]);
+
+        let mut cpu = Cpu::default();
+        let snapshot = cpu.snapshot();
+        cpu.set_tracing(true);
+        cpu.restore(&snapshot).unwrap();
+        assert!(cpu.trace().is_some());
+    }
+
+    #[test]
+    fn rejects_bad_snapshots() {
+        let mut timer = Timer::new();
+        timer.set_tma(0x42);
+        assert!(matches!(timer.restore(&[]), Err(StateError::Corrupt(_))));
+        assert_eq!(timer.tma(), 0x42);
+
+        let cart = |mbc| {
+            let mut rom = vec![0; 0x8000];
+            rom[0x147] = mbc;
+            Cart::from_rom(rom.into_boxed_slice()).unwrap()
+        };
+        // No MBC, then MBC1
+        let snapshot = cart(0x00).snapshot();
+        assert!(matches!(
+            cart(0x01).restore(&snapshot),
+            Err(StateError::WrongRom)
+        ));
+    }
+}
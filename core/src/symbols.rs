@@ -0,0 +1,99 @@
+// SPDX-License-Identifier: GPL-3.0-or-later
+// Copyright (C) 2023 Robert Hrusecky <jadedpastabowl@gmail.com>
+
+//! Symbol files in the format RGBDS writes, and most disassemblies ship, with a `bank:addr label`
+//! line per symbol. The bank is the ROM bank for code, and the RAM bank for variables.
+
+use crate::cart::BankedAddress;
+
+#[derive(Debug, Clone, Default)]
+pub struct Symbols {
+    /// Sorted by address
+    symbols: Vec<(BankedAddress, String)>,
+}
+
+impl Symbols {
+    /// Reads a symbol file, skipping comments and any lines that aren't symbols.
+    pub fn parse(text: &str) -> Self {
+        let mut symbols: Vec<(BankedAddress, _)> = text
+            .lines()
+            .filter_map(|line| {
+                let line = line.split(';').next().unwrap();
+                let (addr, label) = line.trim().split_once(char::is_whitespace)?;
+                Some((addr.parse().ok()?, label.trim().to_owned()))
+            })
+            .collect();
+        symbols.sort_by_key(|&(addr, _)| (addr.addr, addr.bank));
+        Self { symbols }
+    }
+
+    pub fn len(&self) -> usize {
+        self.symbols.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.symbols.is_empty()
+    }
+
+    /// Names `addr` after the closest symbol at or before it, in the same bank and 8 KiB area of
+    /// memory, like `wPartyMons+12`. Addresses past the end of a symbol's data still get named
+    /// after it, since symbol files don't record sizes.
+    pub fn annotate(&self, addr: BankedAddress) -> Option<String> {
+        let end = self.symbols.partition_point(|(a, _)| a.addr <= addr.addr);
+        let (symbol, label) = self.symbols[..end]
+            .iter()
+            .rev()
+            .take_while(|(a, _)| a.addr >> 13 == addr.addr >> 13)
+            .find(|(a, _)| a.bank.is_none() || a.bank == addr.bank)?;
+        Some(match addr.addr - symbol.addr {
+            0 => label.clone(),
+            offset => format!("{label}+{offset}"),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SYM: &str = "\
+; File generated by rgblink
+00:0150 Start
+00:c000 wPlayerX
+00:c001 wPlayerY
+01:d000 wInventory
+02:d000 wBoxes
+00:a000 sSaveFile
+";
+
+    fn annotate(symbols: &Symbols, bank: usize, addr: u16) -> Option<String> {
+        symbols.annotate(BankedAddress::new(Some(bank), addr))
+    }
+
+    #[test]
+    fn parse() {
+        let symbols = Symbols::parse(&format!("{SYM}\nnot a symbol\n00:c002 wScore ; comment"));
+        assert_eq!(symbols.len(), 7);
+        assert_eq!(annotate(&symbols, 0, 0xc002).as_deref(), Some("wScore"));
+    }
+
+    #[test]
+    fn annotate_nearest() {
+        let symbols = Symbols::parse(SYM);
+        assert_eq!(annotate(&symbols, 0, 0xc000).as_deref(), Some("wPlayerX"));
+        assert_eq!(
+            annotate(&symbols, 0, 0xc010).as_deref(),
+            Some("wPlayerY+15")
+        );
+        // Banks are told apart
+        assert_eq!(annotate(&symbols, 2, 0xd004).as_deref(), Some("wBoxes+4"));
+        assert_eq!(annotate(&symbols, 3, 0xd004), None);
+        // VRAM isn't named after the end of ROM, nor WRAM after SRAM
+        assert_eq!(annotate(&symbols, 0, 0x8000), None);
+        assert_eq!(
+            annotate(&symbols, 0, 0xbfff).as_deref(),
+            Some("sSaveFile+8191")
+        );
+        assert_eq!(annotate(&symbols, 0, 0x0100), None);
+    }
+}
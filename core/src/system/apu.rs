@@ -7,6 +7,98 @@ use partial_borrow::prelude::*;
 
 impl ApuBus for partial!(CgbSystem ! apu) {
     fn div(&self) -> u8 {
-        self.timer.div()
+        // DIV ticks twice as fast in double speed mode, so the frame sequencer watches the next
+        // bit up to keep the same pace
+        if *self.double_speed {
+            self.timer.div() >> 1
+        } else {
+            self.timer.div()
+        }
+    }
+
+    fn vin(&self) -> f32 {
+        self.cart.vin()
+            + self
+                .peripherals
+                .iter()
+                .map(|peripheral| peripheral.vin())
+                .sum::<f32>()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        cart::Cart,
+        cpu::CpuBus,
+        reg,
+        system::{BootRom, SCREEN_HEIGHT, SCREEN_WIDTH},
+    };
+
+    use super::*;
+
+    fn write(system: &mut CgbSystem, reg: u8, val: u8) {
+        let (_, bus) = system.split_cpu();
+        bus.write_8(0xff00 | reg as u16, val);
+    }
+
+    /// Runs for `cycles` machine cycles, writing DIV every `div_period` of them. Returns whether
+    /// channel 2 is still on.
+    fn run(system: &mut CgbSystem, cycles: usize, div_period: Option<usize>) -> bool {
+        let mut frame_buff = Box::new([[[0; 4]; SCREEN_WIDTH]; SCREEN_HEIGHT]);
+        for i in 0..cycles {
+            if div_period.is_some_and(|period| i % period == 0) {
+                write(system, reg::DIV, 0);
+            }
+            system.execute_machine_cycle(&mut frame_buff, &mut |_| ());
+        }
+        system.peek(0xff00 | reg::NR52 as u16) & 0x02 != 0
+    }
+
+    /// A system with channel 2 playing, set to stop after two length clocks.
+    fn system(double_speed: bool) -> CgbSystem {
+        let mut rom = vec![0; 0x8000];
+        // jr -2
+        rom[0x100..0x102].copy_from_slice(&[0x18, 0xfe]);
+        let cart = Cart::from_rom(rom.into_boxed_slice()).unwrap();
+        let mut system = CgbSystem::with_boot_rom(cart, BootRom::skip());
+        system.double_speed = double_speed;
+        write(&mut system, reg::NR21, 0x3f);
+        write(&mut system, reg::NR22, 0xf0);
+        write(&mut system, reg::NR24, 0xc0);
+        system
+    }
+
+    #[test]
+    fn div_writes_hold_back_frame_sequencer() {
+        // Often enough that DIV bit 4 never gets set at normal speed, and bit 5 never gets set in
+        // double speed
+        for double_speed in [false, true] {
+            let mut system = system(double_speed);
+            assert!(run(&mut system, 20000, Some(600)));
+            assert!(!run(&mut system, 20000, None));
+        }
+    }
+
+    #[test]
+    fn div_write_clocks_frame_sequencer() {
+        let mut system = system(false);
+        // Once bit 4 is set, each write is a falling edge. The first one clocks the length timer,
+        // and the third one clocks it again.
+        assert!(run(&mut system, 2000, Some(1500)));
+        assert!(run(&mut system, 2000, Some(1050)));
+        assert!(!run(&mut system, 1200, Some(1050)));
+    }
+
+    #[test]
+    fn power_off_clears_registers() {
+        let mut system = system(false);
+        write(&mut system, reg::NR50, 0x77);
+        write(&mut system, 0x30, 0xab);
+        write(&mut system, reg::NR52, 0x00);
+        write(&mut system, reg::NR52, 0x80);
+        assert!(!run(&mut system, 1, None));
+        assert_eq!(system.peek(0xff00 | reg::NR50 as u16), 0x00);
+        assert_eq!(system.peek(0xff30), 0xab);
     }
 }
@@ -0,0 +1,205 @@
+// SPDX-License-Identifier: GPL-3.0-or-later
+// Copyright (C) 2023 Robert Hrusecky <jadedpastabowl@gmail.com>
+
+//! The boot ROM, which the CGB runs at power on to show the logo and set up the hardware before
+//! jumping to the cartridge at 0x100. Boot ROMs dumped from hardware can't be redistributed, so
+//! SameBoy's open source one is built in by default, and games can also start without one. A DMG
+//! boot ROM makes the system a DMG.
+
+use std::borrow::Cow;
+
+use thiserror::Error;
+
+use crate::{
+    cpu::{CpuBus, Registers},
+    reg,
+};
+
+use super::{CgbSystem, Model};
+
+#[cfg(feature = "sameboy-boot-rom")]
+const SAMEBOY: &[u8] = include_bytes!("../../sameboy_boot.bin");
+
+/// Grayscale, lightest first, as little endian RGB555
+const DMG_PALETTE: [u8; 8] = [0xff, 0x7f, 0xb5, 0x56, 0x4a, 0x29, 0x00, 0x00];
+
+#[derive(Error, Debug)]
+#[error(
+    "Boot ROM is {0} bytes, expected {cgb} for a CGB or {dmg} for a DMG",
+    cgb = BootRom::SIZE,
+    dmg = BootRom::DMG_SIZE
+)]
+pub struct BootRomSizeError(usize);
+
+#[derive(Debug, Clone)]
+pub struct BootRom {
+    image: Option<Cow<'static, [u8]>>,
+    model: Model,
+}
+
+impl BootRom {
+    /// Mapped over 0x0000-0x00ff and 0x0200-0x08ff, with the cartridge header showing through in
+    /// between
+    pub const SIZE: usize = 0x900;
+    /// Mapped over 0x0000-0x00ff
+    pub const DMG_SIZE: usize = 0x100;
+
+    /// A boot ROM image, like a dump from hardware. Its size tells which model it's from.
+    pub fn new(image: Vec<u8>) -> Result<Self, BootRomSizeError> {
+        let model = match image.len() {
+            Self::SIZE => Model::Cgb,
+            Self::DMG_SIZE => Model::Dmg,
+            len => return Err(BootRomSizeError(len)),
+        };
+        Ok(Self {
+            image: Some(image.into()),
+            model,
+        })
+    }
+
+    #[cfg(feature = "sameboy-boot-rom")]
+    pub fn sameboy() -> Self {
+        Self {
+            image: Some(SAMEBOY.into()),
+            model: Model::Cgb,
+        }
+    }
+
+    /// Starts the cartridge right away, with the hardware set up roughly the way a boot ROM would
+    /// leave it. There's no logo or chime, and games for the original Game Boy are in grayscale.
+    pub fn skip() -> Self {
+        Self::skip_as(Model::Cgb)
+    }
+
+    /// Like [`Self::skip`], but for `model`. There's no built in boot ROM for a DMG, so this is
+    /// how to run one without a dump.
+    pub fn skip_as(model: Model) -> Self {
+        Self { image: None, model }
+    }
+
+    pub fn model(&self) -> Model {
+        self.model
+    }
+
+    pub(super) fn into_image(self) -> Option<Cow<'static, [u8]>> {
+        self.image
+    }
+}
+
+#[cfg(feature = "sameboy-boot-rom")]
+impl Default for BootRom {
+    fn default() -> Self {
+        Self::sameboy()
+    }
+}
+
+#[cfg(not(feature = "sameboy-boot-rom"))]
+impl Default for BootRom {
+    fn default() -> Self {
+        Self::skip()
+    }
+}
+
+impl CgbSystem {
+    /// Puts the system in the state a boot ROM leaves it in, based on the values listed in Pan
+    /// Docs for each model.
+    pub(super) fn skip_boot(&mut self) {
+        let dmg = self.model == Model::Dmg;
+        let cgb_game = self.cart.cgb();
+        if !cgb_game && !dmg {
+            self.load_palettes(&DMG_PALETTE, &[DMG_PALETTE, DMG_PALETTE].concat());
+        }
+        let (_, bus) = self.split_cpu();
+        for (reg, val) in [
+            (reg::NR52, 0x80),
+            (reg::NR50, 0x77),
+            (reg::NR51, 0xf3),
+            (reg::BGP, 0xfc),
+            (reg::LCDC, 0x91),
+            (reg::KEY0, if cgb_game { 0x80 } else { 0x04 }),
+            (reg::BANK, 0x01),
+        ] {
+            // A DMG has no KEY0
+            if !(dmg && reg == reg::KEY0) {
+                bus.poke_8(0xff00 | reg as u16, val);
+            }
+        }
+
+        let (af, bc, de, hl) = match (dmg, cgb_game) {
+            (true, _) => (0x01b0, 0x0013, 0x00d8, 0x014d),
+            (false, true) => (0x1180, 0x0000, 0xff56, 0x000d),
+            (false, false) => (0x1180, 0x0000, 0x0008, 0x007c),
+        };
+        self.cpu.set_registers(Registers {
+            af,
+            bc,
+            de,
+            hl,
+            sp: 0xfffe,
+            pc: 0x0100,
+            ime: false,
+            halted: false,
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        cart::Cart,
+        system::{SCREEN_HEIGHT, SCREEN_WIDTH},
+    };
+
+    use super::*;
+
+    fn cart(cgb_flag: u8) -> Cart {
+        let mut rom = vec![0; 0x8000];
+        rom[0x143] = cgb_flag;
+        Cart::from_rom(rom.into_boxed_slice()).unwrap()
+    }
+
+    #[test]
+    fn image_size() {
+        assert!(BootRom::new(vec![0; 0x200]).is_err());
+        assert_eq!(
+            BootRom::new(vec![0; BootRom::SIZE]).unwrap().model(),
+            Model::Cgb
+        );
+        assert_eq!(
+            BootRom::new(vec![0; BootRom::DMG_SIZE]).unwrap().model(),
+            Model::Dmg
+        );
+    }
+
+    #[test]
+    fn skip() {
+        for (cgb_flag, cgb_mode) in [(0x80, true), (0x00, false)] {
+            let mut system = CgbSystem::with_boot_rom(cart(cgb_flag), BootRom::skip());
+            let regs = system.registers();
+            assert_eq!((regs.pc, regs.sp, regs.af), (0x0100, 0xfffe, 0x1180));
+            assert!(!system.boot_rom_mapped);
+            assert_eq!(system.cgb_mode, cgb_mode);
+            assert_eq!(system.peek(0xff00 | reg::LCDC as u16), 0x91);
+            assert_eq!(system.peek(0x0000), 0x00);
+
+            // The cartridge's NOPs run straight away
+            let mut frame_buff = Box::new([[[0; 4]; SCREEN_WIDTH]; SCREEN_HEIGHT]);
+            system.execute(&mut frame_buff, |_| ());
+            assert!(system.registers().pc > 0x0100);
+        }
+    }
+
+    #[test]
+    fn skip_dmg() {
+        let mut system = CgbSystem::with_boot_rom(cart(0x80), BootRom::skip_as(Model::Dmg));
+        let regs = system.registers();
+        assert_eq!((regs.af, regs.bc, regs.hl), (0x01b0, 0x0013, 0x014d));
+        assert_eq!(system.model(), Model::Dmg);
+        // CGB games run in compatibility mode, without VRAM banks
+        assert!(!system.cgb_mode);
+        assert_eq!(system.peek(0xff4f), 0xff);
+        // The undocumented registers are missing too
+        system.poke(0xff72, 0x12);
+        assert_eq!(system.peek(0xff72), 0xff);
+    }
+}
@@ -2,22 +2,56 @@
 // Copyright (C) 2023 Robert Hrusecky <jadedpastabowl@gmail.com>
 use partial_borrow::prelude::*;
 
-use crate::{cpu::CpuBus, reg};
+use crate::{
+    cart::BankedAddress,
+    cpu::CpuBus,
+    event::{EventKind, Severity},
+    reg,
+};
 
-use super::{CgbSystem, BOOT_ROM};
+use super::{
+    debug::{Access, Accessor},
+    CgbSystem, Model,
+};
 
 const NON_CGB_KEY0_VAL: u8 = 0x04;
+const FF75_MASK: u8 = 0x70;
 
 impl CpuBus for partial!(CgbSystem ! cpu, mut *) {
     fn read_8(&self, addr: u16) -> u8 {
+        let val = match self.dma.oam_conflict(addr, *self.model == Model::Cgb) {
+            Some(val) => val,
+            None => self.peek_8(addr),
+        };
+        self.debugger.watch(addr, val, Access::Read, Accessor::Cpu);
+        val
+    }
+
+    fn write_8(&mut self, addr: u16, val: u8) {
+        self.debugger.watch(addr, val, Access::Write, Accessor::Cpu);
+        if self
+            .dma
+            .oam_conflict(addr, *self.model == Model::Cgb)
+            .is_none()
+        {
+            self.poke_8(addr, val);
+        }
+    }
+
+    fn peek_8(&self, addr: u16) -> u8 {
         match (addr >> 8) as u8 {
-            0x00..=0x00 | 0x02..=0x08 if *self.boot_rom_mapped => BOOT_ROM[addr as usize],
-            0x00..=0x7f => self.cart.read_low(addr),
+            0x00..=0x00 | 0x02..=0x08
+                if *self.boot_rom_mapped && (addr as usize) < self.boot_rom.len() =>
+            {
+                self.boot_rom[addr as usize]
+            }
+            0x00..=0x7f => self.cheats.patch_rom(addr, self.cart.read_low(addr)),
             0x80..=0x9f => self.mem.vram.read(addr, *self.cgb_mode),
             0xa0..=0xbf => self.cart.read_high(addr),
             0xc0..=0xcf | 0xe0..=0xef => self.mem.wram.read_low(addr),
             0xd0..=0xdf | 0xf0..=0xfd => self.mem.wram.read_high(addr, *self.cgb_mode),
             0xfe => match addr as u8 {
+                0x00..=0x9f if self.dma.oam_blocked() => 0xff,
                 low @ 0x00..=0x9f => self.mem.oam[low as usize],
                 low @ 0xa0..=0xff => {
                     // CGB-E prohibited area reads, according to pandocs
@@ -32,17 +66,21 @@ impl CpuBus for partial!(CgbSystem ! cpu, mut *) {
                 reg::BCPS if *self.cgb_mode => self.mem.bg_palette.select,
                 reg::OCPS if *self.cgb_mode => self.mem.obj_palette.select,
                 reg::HDMA5 if *self.cgb_mode => self.dma.hdma5(),
-                reg::HDMA1 => self.dma.hdma1,
-                reg::HDMA2 => self.dma.hdma2,
-                reg::HDMA3 => self.dma.hdma3,
-                reg::HDMA4 => self.dma.hdma4,
+                reg::KEY1 if *self.cgb_mode => {
+                    (*self.double_speed as u8) << 7 | 0x7e | *self.speed_switch_armed as u8
+                }
+                // A DMG doesn't have the CGB's registers at all, unlike compatibility mode
+                reg::HDMA1 if *self.model == Model::Cgb => self.dma.hdma1,
+                reg::HDMA2 if *self.model == Model::Cgb => self.dma.hdma2,
+                reg::HDMA3 if *self.model == Model::Cgb => self.dma.hdma3,
+                reg::HDMA4 if *self.model == Model::Cgb => self.dma.hdma4,
                 reg::P1 => self.joypad.p1(),
                 reg::DIV => self.timer.div(),
                 reg::TIMA => self.timer.tima(),
                 reg::TMA => self.timer.tma(),
                 reg::TAC => self.timer.tac(),
-                reg::SVBK => self.mem.wram.svbk,
-                reg::VBK => self.mem.vram.vbk,
+                reg::SVBK if *self.model == Model::Cgb => self.mem.wram.svbk,
+                reg::VBK if *self.model == Model::Cgb => self.mem.vram.vbk,
                 reg::IF => self.interrupt.flags,
                 reg::IE => self.interrupt.enable,
                 reg::DMA => self.dma.dma(),
@@ -71,26 +109,43 @@ impl CpuBus for partial!(CgbSystem ! cpu, mut *) {
                 reg::NR32 => self.apu.nr32(),
                 reg::NR33 => self.apu.nr33(),
                 reg::NR34 => self.apu.nr34(),
+                reg::NR41 => self.apu.nr41(),
                 reg::NR42 => self.apu.nr42(),
                 reg::NR43 => self.apu.nr43(),
                 reg::NR44 => self.apu.nr44(),
                 reg::NR50 => self.apu.nr50(),
                 reg::NR51 => self.apu.nr51(),
                 reg::NR52 => self.apu.nr52(),
+                reg::SB => self.serial.sb,
+                reg::SC => self.serial.sc(*self.cgb_mode),
+                // Only on the CGB, and FF74 is locked outside of CGB mode
+                reg::FF74 if *self.model == Model::Cgb && !*self.cgb_mode => 0xff,
+                reg::FF72..=reg::FF74 if *self.model == Model::Cgb => {
+                    self.undocumented[addr as usize - 0xff72]
+                }
+                reg::FF75 if *self.model == Model::Cgb => !FF75_MASK | self.undocumented[3],
                 0x30..=0x3f => self.apu.read_wave_ram(addr),
-                _ => 0, // unimplemented
+                _ => match self
+                    .peripherals
+                    .iter()
+                    .find(|peripheral| peripheral.io_range().contains(&addr))
+                {
+                    Some(peripheral) => peripheral.read(addr),
+                    None => self.open_bus.read(addr, &self.events),
+                },
             },
         }
     }
 
-    fn write_8(&mut self, addr: u16, val: u8) {
+    fn poke_8(&mut self, addr: u16, val: u8) {
         match (addr >> 8) as u8 {
-            0x00..=0x7f => self.cart.write_low(addr, val),
+            0x00..=0x7f => self.cart.write_low(addr, val, &self.events),
             0x80..=0x9f => self.mem.vram.write(addr, val, *self.cgb_mode),
-            0xa0..=0xbf => self.cart.write_high(addr, val),
+            0xa0..=0xbf => self.cart.write_high(addr, val, &self.events),
             0xc0..=0xcf | 0xe0..=0xef => self.mem.wram.write_low(addr, val),
             0xd0..=0xdf | 0xf0..=0xfd => self.mem.wram.write_high(addr, val, *self.cgb_mode),
             0xfe => match addr as u8 {
+                0x00..=0x9f if self.dma.oam_blocked() => (),
                 low @ 0x00..=0x9f => self.mem.oam[low as usize] = val,
                 0xa0..=0xff => (),
             },
@@ -101,22 +156,23 @@ impl CpuBus for partial!(CgbSystem ! cpu, mut *) {
                 reg::BCPS if *self.cgb_mode => self.mem.bg_palette.select = val,
                 reg::OCPS if *self.cgb_mode => self.mem.obj_palette.select = val,
                 reg::HDMA5 if *self.cgb_mode => self.dma.set_hdma5(val),
+                reg::KEY1 if *self.cgb_mode => *self.speed_switch_armed = val & 0x1 != 0,
                 reg::DMA => self.dma.set_dma(val),
                 reg::BANK if *self.boot_rom_mapped => {
                     *self.boot_rom_mapped = false;
-                    *self.cgb_mode = *self.key0 != NON_CGB_KEY0_VAL;
+                    *self.cgb_mode = *self.model == Model::Cgb && *self.key0 != NON_CGB_KEY0_VAL;
                 }
-                reg::KEY0 => *self.key0 = val,
-                reg::HDMA1 => self.dma.hdma1 = val,
-                reg::HDMA2 => self.dma.hdma2 = val,
-                reg::HDMA3 => self.dma.hdma3 = val,
-                reg::HDMA4 => self.dma.hdma4 = val,
+                reg::KEY0 if *self.model == Model::Cgb => *self.key0 = val,
+                reg::HDMA1 if *self.model == Model::Cgb => self.dma.hdma1 = val,
+                reg::HDMA2 if *self.model == Model::Cgb => self.dma.hdma2 = val,
+                reg::HDMA3 if *self.model == Model::Cgb => self.dma.hdma3 = val,
+                reg::HDMA4 if *self.model == Model::Cgb => self.dma.hdma4 = val,
                 reg::DIV => self.timer.reset_div(),
                 reg::TIMA => self.timer.set_tima(val),
                 reg::TMA => self.timer.set_tma(val),
                 reg::TAC => self.timer.set_tac(val),
-                reg::SVBK => self.mem.wram.svbk = val,
-                reg::VBK => self.mem.vram.vbk = val,
+                reg::SVBK if *self.model == Model::Cgb => self.mem.wram.svbk = val,
+                reg::VBK if *self.model == Model::Cgb => self.mem.vram.vbk = val,
                 reg::P1 => self.joypad.set_p1(val),
                 reg::IF => self.interrupt.flags = val,
                 reg::IE => self.interrupt.enable = val,
@@ -151,8 +207,35 @@ impl CpuBus for partial!(CgbSystem ! cpu, mut *) {
                 reg::NR50 => self.apu.set_nr50(val),
                 reg::NR51 => self.apu.set_nr51(val),
                 reg::NR52 => self.apu.set_nr52(val),
+                reg::SB => self.serial.sb = val,
+                reg::FF74 if *self.model == Model::Cgb && !*self.cgb_mode => (),
+                reg::FF72..=reg::FF74 if *self.model == Model::Cgb => {
+                    self.undocumented[addr as usize - 0xff72] = val
+                }
+                reg::FF75 if *self.model == Model::Cgb => self.undocumented[3] = val & FF75_MASK,
+                reg::SC => {
+                    // Nothing is ever linked, but note when a game tries to use it
+                    if val & 0x80 != 0 {
+                        self.events
+                            .push(Severity::Info, EventKind::SerialTransfer(self.serial.sb));
+                        // Only a transfer on the internal clock finishes without anything on the
+                        // other end
+                        match &mut *self.serial_out {
+                            Some(serial_out) if val & 0x01 != 0 => serial_out.push(self.serial.sb),
+                            _ => (),
+                        }
+                    }
+                    self.serial.set_sc(val, *self.cgb_mode);
+                }
                 0x30..=0x3f => self.apu.write_wave_ram(addr, val),
-                _ => (), // unimplemented
+                _ => match self
+                    .peripherals
+                    .iter_mut()
+                    .find(|peripheral| peripheral.io_range().contains(&addr))
+                {
+                    Some(peripheral) => peripheral.write(addr, val),
+                    None => self.open_bus.write(addr, val, &self.events),
+                },
             },
         }
     }
@@ -161,6 +244,17 @@ impl CpuBus for partial!(CgbSystem ! cpu, mut *) {
         self.dma.cpu_paused()
     }
 
+    fn banked_address(&self, addr: u16) -> BankedAddress {
+        match (addr >> 8) as u8 {
+            0x00..=0x00 | 0x02..=0x08
+                if *self.boot_rom_mapped && (addr as usize) < self.boot_rom.len() =>
+            {
+                addr.into()
+            }
+            _ => self.cart.banked_address(addr),
+        }
+    }
+
     fn pop_interrupt(&mut self) -> Option<u8> {
         self.interrupt.pop()
     }
@@ -168,4 +262,15 @@ impl CpuBus for partial!(CgbSystem ! cpu, mut *) {
     fn interrupt_pending(&mut self) -> bool {
         self.interrupt.pending()
     }
+
+    fn switch_speed(&mut self) -> bool {
+        if !*self.speed_switch_armed {
+            return false;
+        }
+        *self.speed_switch_armed = false;
+        *self.double_speed = !*self.double_speed;
+        // STOP resets the divider along with the switch
+        self.timer.reset_div();
+        true
+    }
 }
@@ -0,0 +1,491 @@
+// SPDX-License-Identifier: GPL-3.0-or-later
+// Copyright (C) 2023 Robert Hrusecky <jadedpastabowl@gmail.com>
+
+use std::cell::Cell;
+
+use crate::cart::BankedAddress;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Access {
+    Read,
+    Write,
+}
+
+/// What made a memory access
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Accessor {
+    Cpu,
+    Dma,
+}
+
+/// Stops execution after an address from `start` to `end`, inclusive, is read or written,
+/// depending on which of `read` and `write` are set.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Watchpoint {
+    pub start: u16,
+    pub end: u16,
+    pub read: bool,
+    pub write: bool,
+}
+
+impl Watchpoint {
+    fn matches(&self, addr: u16, access: Access) -> bool {
+        (self.start..=self.end).contains(&addr)
+            && match access {
+                Access::Read => self.read,
+                Access::Write => self.write,
+            }
+    }
+}
+
+/// The memory access that tripped a watchpoint
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct WatchHit {
+    pub addr: u16,
+    /// What was read or written
+    pub val: u8,
+    pub access: Access,
+    pub by: Accessor,
+}
+
+/// Stops execution at a point counted from power on. When the system is deterministic, that's the
+/// same point on every machine, so it can be shared in a bug report.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CountBreakpoint {
+    /// Before the first instruction to start once this many machine cycles have gone by
+    Cycle(u64),
+    /// Before the instruction after this many have started
+    Instruction(u64),
+}
+
+impl CountBreakpoint {
+    fn reached(&self, cycles: u64, instructions: u64) -> bool {
+        match *self {
+            Self::Cycle(cycle) => cycles >= cycle,
+            Self::Instruction(instruction) => instructions >= instruction,
+        }
+    }
+}
+
+/// Breakpoint and watchpoint bookkeeping for [`CgbSystem`](super::CgbSystem).
+#[derive(Default)]
+pub(super) struct Debugger {
+    breakpoints: Vec<BankedAddress>,
+    watchpoints: Vec<Watchpoint>,
+    /// Each one is removed once it's reached, since it would stop at every instruction after that
+    count_breakpoints: Vec<CountBreakpoint>,
+    /// The first watchpoint hit since the last stop. Accesses are watched from behind shared
+    /// references, like while the CPU reads memory, hence the cell.
+    watch_hit: Cell<Option<WatchHit>>,
+    /// The instruction execution is stopped at, if any
+    stopped_at: Option<BankedAddress>,
+    /// The watchpoint hit that execution is stopped after, if any
+    stopped_by: Option<WatchHit>,
+    /// Set when execution continues from a stop, so that the instruction it stopped at runs
+    /// instead of stopping again
+    resuming: bool,
+}
+
+impl Debugger {
+    pub fn add_breakpoint(&mut self, addr: BankedAddress) {
+        if !self.breakpoints.contains(&addr) {
+            self.breakpoints.push(addr);
+        }
+    }
+
+    /// Returns whether there was a breakpoint at `addr`.
+    pub fn remove_breakpoint(&mut self, addr: BankedAddress) -> bool {
+        let len = self.breakpoints.len();
+        self.breakpoints.retain(|&breakpoint| breakpoint != addr);
+        self.breakpoints.len() != len
+    }
+
+    pub fn breakpoints(&self) -> &[BankedAddress] {
+        &self.breakpoints
+    }
+
+    pub fn add_watchpoint(&mut self, watchpoint: Watchpoint) {
+        if !self.watchpoints.contains(&watchpoint) {
+            self.watchpoints.push(watchpoint);
+        }
+    }
+
+    /// Returns whether `watchpoint` was set.
+    pub fn remove_watchpoint(&mut self, watchpoint: Watchpoint) -> bool {
+        let len = self.watchpoints.len();
+        self.watchpoints.retain(|&other| other != watchpoint);
+        self.watchpoints.len() != len
+    }
+
+    pub fn watchpoints(&self) -> &[Watchpoint] {
+        &self.watchpoints
+    }
+
+    pub fn add_count_breakpoint(&mut self, breakpoint: CountBreakpoint) {
+        if !self.count_breakpoints.contains(&breakpoint) {
+            self.count_breakpoints.push(breakpoint);
+        }
+    }
+
+    /// Returns whether `breakpoint` was set, and hadn't been reached yet.
+    pub fn remove_count_breakpoint(&mut self, breakpoint: CountBreakpoint) -> bool {
+        let len = self.count_breakpoints.len();
+        self.count_breakpoints.retain(|&other| other != breakpoint);
+        self.count_breakpoints.len() != len
+    }
+
+    pub fn count_breakpoints(&self) -> &[CountBreakpoint] {
+        &self.count_breakpoints
+    }
+
+    /// Called on every memory access by the CPU or DMA.
+    pub fn watch(&self, addr: u16, val: u8, access: Access, by: Accessor) {
+        if self.watchpoints.is_empty() || self.watch_hit.get().is_some() {
+            return;
+        }
+        if self
+            .watchpoints
+            .iter()
+            .any(|watchpoint| watchpoint.matches(addr, access))
+        {
+            self.watch_hit.set(Some(WatchHit {
+                addr,
+                val,
+                access,
+                by,
+            }));
+        }
+    }
+
+    pub fn stopped_at(&self) -> Option<BankedAddress> {
+        self.stopped_at
+    }
+
+    pub fn stopped_by(&self) -> Option<WatchHit> {
+        self.stopped_by
+    }
+
+    pub fn stop_at(&mut self, pc: BankedAddress) {
+        self.stopped_at = Some(pc);
+        self.stopped_by = self.watch_hit.take();
+    }
+
+    pub fn resume(&mut self) {
+        self.resuming = self.stopped_at.take().is_some();
+        self.stopped_by = None;
+    }
+
+    /// Whether [`Self::should_stop`] needs to be asked at each instruction.
+    pub fn active(&self) -> bool {
+        self.resuming
+            || !self.breakpoints.is_empty()
+            || !self.count_breakpoints.is_empty()
+            || self.watch_hit.get().is_some()
+    }
+
+    /// Called before the CPU starts the instruction at `pc`, which is where execution stops after
+    /// a watchpoint is hit. `cycles` and `instructions` are counted since power on.
+    pub fn should_stop(&mut self, pc: BankedAddress, cycles: u64, instructions: u64) -> bool {
+        if self.resuming {
+            self.resuming = false;
+            return false;
+        }
+        let len = self.count_breakpoints.len();
+        self.count_breakpoints
+            .retain(|breakpoint| !breakpoint.reached(cycles, instructions));
+        if self.count_breakpoints.len() != len
+            || self.watch_hit.get().is_some()
+            || self
+                .breakpoints
+                .iter()
+                .any(|breakpoint| breakpoint.matches(pc))
+        {
+            self.stop_at(pc);
+            return true;
+        }
+        false
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        cart::Cart,
+        system::{
+            disasm, scheduler::Deadline, BootRom, CgbSystem, MachineCycle, SCREEN_HEIGHT,
+            SCREEN_WIDTH,
+        },
+    };
+
+    use super::*;
+
+    #[test]
+    fn resume_runs_the_stopped_instruction() {
+        let mut debugger = Debugger::default();
+        debugger.add_breakpoint(0x150.into());
+        let pc = BankedAddress::new(Some(0), 0x150);
+
+        assert!(debugger.should_stop(pc, 0, 0));
+        assert_eq!(debugger.stopped_at(), Some(pc));
+
+        debugger.resume();
+        assert_eq!(debugger.stopped_at(), None);
+        assert!(!debugger.should_stop(pc, 0, 0));
+        assert!(debugger.should_stop(pc, 0, 0));
+
+        assert!(debugger.remove_breakpoint(0x150.into()));
+        assert!(!debugger.remove_breakpoint(0x150.into()));
+        assert!(!debugger.active());
+    }
+
+    #[test]
+    fn watchpoint_stops_at_the_next_instruction() {
+        let mut debugger = Debugger::default();
+        debugger.add_watchpoint(Watchpoint {
+            start: 0xc000,
+            end: 0xc0ff,
+            read: false,
+            write: true,
+        });
+        let pc = BankedAddress::new(Some(0), 0x150);
+
+        debugger.watch(0xc010, 0x42, Access::Read, Accessor::Cpu);
+        debugger.watch(0xc100, 0x42, Access::Write, Accessor::Cpu);
+        assert!(!debugger.should_stop(pc, 0, 0));
+
+        let hit = WatchHit {
+            addr: 0xc010,
+            val: 0x42,
+            access: Access::Write,
+            by: Accessor::Dma,
+        };
+        debugger.watch(hit.addr, hit.val, hit.access, hit.by);
+        // Only the first hit counts
+        debugger.watch(0xc011, 0x43, Access::Write, Accessor::Cpu);
+        assert!(debugger.should_stop(pc, 0, 0));
+        assert_eq!(debugger.stopped_by(), Some(hit));
+
+        debugger.resume();
+        assert_eq!(debugger.stopped_by(), None);
+        assert!(!debugger.should_stop(pc, 0, 0));
+        assert!(!debugger.should_stop(pc, 0, 0));
+    }
+
+    #[test]
+    fn count_breakpoints_stop_once() {
+        let mut debugger = Debugger::default();
+        debugger.add_count_breakpoint(CountBreakpoint::Cycle(100));
+        debugger.add_count_breakpoint(CountBreakpoint::Instruction(10));
+        let pc = BankedAddress::new(Some(0), 0x150);
+
+        assert!(!debugger.should_stop(pc, 99, 9));
+        assert!(debugger.should_stop(pc, 102, 9));
+        assert_eq!(
+            debugger.count_breakpoints(),
+            [CountBreakpoint::Instruction(10)]
+        );
+
+        debugger.resume();
+        assert!(!debugger.should_stop(pc, 103, 10));
+        assert!(debugger.should_stop(pc, 104, 10));
+        assert!(debugger.count_breakpoints().is_empty());
+        debugger.resume();
+        assert!(!debugger.should_stop(pc, 105, 11));
+        assert!(!debugger.active());
+    }
+
+    #[test]
+    fn watch_cpu_and_dma() {
+        let mut rom = vec![0; 0x8000];
+        // ld a, 0x42; ld (0xc000), a; ld a, 0xc0; ldh (0x46), a; jr -2
+        rom[0x150..0x15b].copy_from_slice(&[
+            0x3e, 0x42, 0xea, 0x00, 0xc0, 0x3e, 0xc0, 0xe0, 0x46, 0x18, 0xfe,
+        ]);
+        // nop; jp 0x150
+        rom[0x100..0x104].copy_from_slice(&[0x00, 0xc3, 0x50, 0x01]);
+        let cart = Cart::from_rom(rom.into_boxed_slice()).unwrap();
+        let mut system = CgbSystem::with_boot_rom(cart, BootRom::skip());
+        let mut frame_buff = Box::new([[[0; 4]; SCREEN_WIDTH]; SCREEN_HEIGHT]);
+        let watch_wram = Watchpoint {
+            start: 0xc000,
+            end: 0xc000,
+            read: true,
+            write: true,
+        };
+        system.add_watchpoint(watch_wram);
+
+        // Debuggers looking or poking don't count
+        system.poke(0xc000, 0x11);
+        assert_eq!(system.peek(0xc000), 0x11);
+        system.execute(&mut frame_buff, |_| ());
+        assert_eq!(
+            system.stopped_by(),
+            Some(WatchHit {
+                addr: 0xc000,
+                val: 0x42,
+                access: Access::Write,
+                by: Accessor::Cpu,
+            })
+        );
+        assert_eq!(
+            system.stopped_at(),
+            Some(BankedAddress::new(Some(0), 0x155))
+        );
+
+        assert!(system.remove_watchpoint(watch_wram));
+        system.add_watchpoint(Watchpoint {
+            start: 0xfe10,
+            end: 0xfe10,
+            read: false,
+            write: true,
+        });
+        system.execute(&mut frame_buff, |_| ());
+        let hit = system.stopped_by().unwrap();
+        assert_eq!(
+            (hit.addr, hit.access, hit.by),
+            (0xfe10, Access::Write, Accessor::Dma)
+        );
+        assert_eq!(system.stopped_at().unwrap().addr, 0x159);
+    }
+
+    #[test]
+    fn break_and_step() {
+        let cart = Cart::from_rom(vec![0; 0x8000].into_boxed_slice()).unwrap();
+        let mut system = CgbSystem::new(cart);
+        let mut frame_buff = Box::new([[[0; 4]; SCREEN_WIDTH]; SCREEN_HEIGHT]);
+        system.add_breakpoint(0x100);
+
+        // Run through the boot ROM until the cartridge entry point
+        for _ in 0..600 {
+            system.execute(&mut frame_buff, |_| ());
+            if system.stopped_at().is_some() {
+                break;
+            }
+        }
+        assert_eq!(
+            system.stopped_at(),
+            Some(BankedAddress::new(Some(0), 0x100))
+        );
+        assert_eq!(system.registers().pc, 0x100);
+
+        // The ROM is all NOPs
+        let instructions = system.instructions_executed();
+        system.step_instruction(&mut frame_buff, |_| ());
+        assert_eq!(system.instructions_executed(), instructions + 1);
+        assert_eq!(
+            system.stopped_at(),
+            Some(BankedAddress::new(Some(0), 0x101))
+        );
+    }
+
+    #[test]
+    fn step_across_frame_end() {
+        let mut rom = vec![0; 0x8000];
+        // jr -2
+        rom[0x100..0x102].copy_from_slice(&[0x18, 0xfe]);
+        let cart = Cart::from_rom(rom.into_boxed_slice()).unwrap();
+        let mut system = CgbSystem::with_boot_rom(cart, BootRom::skip());
+        let mut frame_buff = Box::new([[[0; 4]; SCREEN_WIDTH]; SCREEN_HEIGHT]);
+        system.execute(&mut frame_buff, |_| ());
+
+        // Past the end of a few lines, and then the frame
+        let mut stepped = 0;
+        while stepped <= MachineCycle::PER_FRAME {
+            stepped += system.step_instruction(&mut frame_buff, |_| ()).0;
+            assert!(!system.scheduler.is_due(Deadline::LineEnd));
+        }
+        // The next frame started where stepping crossed into it
+        let cycles = system.execute(&mut frame_buff, |_| ()).0;
+        assert_eq!(stepped + cycles, 2 * MachineCycle::PER_FRAME);
+    }
+
+    #[test]
+    fn replay_to_instruction() {
+        let mut rom = vec![0; 0x8000];
+        // inc a; ld (0xc000), a; jr -6
+        rom[0x150..0x156].copy_from_slice(&[0x3c, 0xea, 0x00, 0xc0, 0x18, 0xfa]);
+        // nop; jp 0x150
+        rom[0x100..0x104].copy_from_slice(&[0x00, 0xc3, 0x50, 0x01]);
+        let cart = Cart::from_rom(rom.into_boxed_slice()).unwrap();
+        let mut system = CgbSystem::with_boot_rom(cart, BootRom::skip());
+        let mut frame_buff = Box::new([[[0; 4]; SCREEN_WIDTH]; SCREEN_HEIGHT]);
+        system.execute(&mut frame_buff, |_| ());
+        let state = system.save_state();
+        system.execute(&mut frame_buff, |_| ());
+        let target = system.instructions_executed() - 1;
+
+        // Where running stops just before the last instruction
+        system.load_state(&state).unwrap();
+        system.add_count_breakpoint(CountBreakpoint::Instruction(target));
+        system.execute(&mut frame_buff, |_| ());
+        assert_eq!(system.instructions_executed(), target);
+        let expected = (system.registers(), system.cycles_executed());
+
+        system.load_state(&state).unwrap();
+        assert!(system.run_to_instruction(target, &mut frame_buff));
+        assert_eq!(system.instructions_executed(), target);
+        assert_eq!((system.registers(), system.cycles_executed()), expected);
+    }
+
+    #[test]
+    fn trace() {
+        let mut rom = vec![0; 0x8000];
+        // ld a, 0x42; jr -4
+        rom[0x150..0x154].copy_from_slice(&[0x3e, 0x42, 0x18, 0xfc]);
+        // nop; jp 0x150
+        rom[0x100..0x104].copy_from_slice(&[0x00, 0xc3, 0x50, 0x01]);
+        let cart = Cart::from_rom(rom.into_boxed_slice()).unwrap();
+        let mut system = CgbSystem::with_boot_rom(cart, BootRom::skip());
+        let mut frame_buff = Box::new([[[0; 4]; SCREEN_WIDTH]; SCREEN_HEIGHT]);
+
+        system.execute(&mut frame_buff, |_| ());
+        assert_eq!(system.trace().count(), 0);
+        system.set_tracing(true);
+        system.execute(&mut frame_buff, |_| ());
+        let lines: Vec<_> = system.trace().map(|entry| entry.to_line()).collect();
+        assert_eq!(lines.len(), disasm::TRACE_LEN);
+        // Only the loop is recent enough to still be in the trace
+        for line in &lines {
+            assert!(
+                line == "00:0150  3e 42     ld a, 0x42" || line == "00:0152  18 fc     jr 0x0150",
+                "{line}"
+            );
+        }
+
+        let texts: Vec<_> = system
+            .disassemble(0x150, 0x154)
+            .into_iter()
+            .map(|line| line.text)
+            .collect();
+        assert_eq!(texts, ["ld a, 0x42", "jr 0x0150"]);
+
+        system.set_tracing(false);
+        assert_eq!(system.trace().count(), 0);
+    }
+
+    #[test]
+    fn break_at_counts() {
+        let cart = Cart::from_rom(vec![0; 0x8000].into_boxed_slice()).unwrap();
+        let mut system = CgbSystem::new(cart);
+        let mut frame_buff = Box::new([[[0; 4]; SCREEN_WIDTH]; SCREEN_HEIGHT]);
+        system.add_count_breakpoint(CountBreakpoint::Instruction(12345));
+        while system.stopped_at().is_none() {
+            system.execute(&mut frame_buff, |_| ());
+        }
+        assert_eq!(system.instructions_executed(), 12345);
+        let cycles = system.cycles_executed();
+        let registers = system.registers();
+
+        // The same point, found by its cycle instead, on another machine
+        let cart = Cart::from_rom(vec![0; 0x8000].into_boxed_slice()).unwrap();
+        let mut system = CgbSystem::new(cart);
+        system.add_count_breakpoint(CountBreakpoint::Cycle(cycles));
+        while system.stopped_at().is_none() {
+            system.execute(&mut frame_buff, |_| ());
+        }
+        assert_eq!(system.cycles_executed(), cycles);
+        assert_eq!(system.instructions_executed(), 12345);
+        assert_eq!(system.registers(), registers);
+        assert!(system.count_breakpoints().is_empty());
+    }
+}
@@ -2,27 +2,71 @@
 // Copyright (C) 2023 Robert Hrusecky <jadedpastabowl@gmail.com>
 use partial_borrow::prelude::*;
 
-use crate::{dma::DmaBus, memory::OamBytes};
+use crate::dma::DmaBus;
 
-use super::{CgbSystem, BOOT_ROM};
+use super::{
+    debug::{Access, Accessor},
+    CgbSystem,
+};
 
 impl DmaBus for partial!(CgbSystem ! dma, mut mem) {
     fn write_vram(&mut self, addr: u16, val: u8) {
+        let addr = 0x8000 | (addr & 0x1fff);
+        self.debugger.watch(addr, val, Access::Write, Accessor::Dma);
         self.mem.vram.write(addr, val, *self.cgb_mode);
     }
 
-    fn oam_mut(&mut self) -> &mut OamBytes {
-        &mut self.mem.oam
+    fn write_oam(&mut self, offset: u8, val: u8) {
+        self.debugger
+            .watch(0xfe00 | offset as u16, val, Access::Write, Accessor::Dma);
+        self.mem.oam[offset as usize] = val;
     }
 
     fn read_8(&self, addr: u16) -> u8 {
-        match (addr >> 8) as u8 {
-            0x00..=0x00 | 0x02..=0x08 if *self.boot_rom_mapped => BOOT_ROM[addr as usize],
-            0x00..=0x7f => self.cart.read_low(addr),
+        let val = match (addr >> 8) as u8 {
+            0x00..=0x00 | 0x02..=0x08
+                if *self.boot_rom_mapped && (addr as usize) < self.boot_rom.len() =>
+            {
+                self.boot_rom[addr as usize]
+            }
+            0x00..=0x7f => self.cheats.patch_rom(addr, self.cart.read_low(addr)),
             0x80..=0x9f => self.mem.vram.read(addr, *self.cgb_mode),
             0xa0..=0xbf => self.cart.read_high(addr),
             0xc0..=0xcf | 0xe0..=0xef => self.mem.wram.read_low(addr),
             0xd0..=0xdf | 0xf0..=0xff => self.mem.wram.read_high(addr, *self.cgb_mode),
+        };
+        self.debugger.watch(addr, val, Access::Read, Accessor::Dma);
+        val
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        cart::Cart,
+        harness::Harness,
+        joypad::Input,
+        system::{BootRom, CgbSystem},
+    };
+
+    #[test]
+    fn cpu_reads_dma_byte() {
+        let mut rom = vec![0; 0x8000];
+        // ld a, 0xc0; ldh (0x46), a; ld a, (0xd000); ldh (0x80), a; jr -2
+        rom[0x100..0x10b].copy_from_slice(&[
+            0x3e, 0xc0, 0xe0, 0x46, 0xfa, 0x00, 0xd0, 0xe0, 0x80, 0x18, 0xfe,
+        ]);
+        let cart = Cart::from_rom(rom.into_boxed_slice()).unwrap();
+        let mut system = CgbSystem::with_boot_rom(cart, BootRom::skip());
+        for addr in 0xc000..0xc0a0 {
+            system.poke(addr, 0x77);
         }
+        system.poke(0xd000, 0x42);
+        let mut harness = Harness::with_system(system);
+        harness.run_frame(Input::NONE);
+        // The code in ROM kept running, since WRAM has a bus of its own on the CGB, but the read
+        // from WRAM got the byte being copied
+        assert_eq!(harness.system_mut().peek(0xff80), 0x77);
+        assert_eq!(harness.system_mut().peek(0xd000), 0x42);
     }
 }
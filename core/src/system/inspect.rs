@@ -0,0 +1,148 @@
+// SPDX-License-Identifier: GPL-3.0-or-later
+// Copyright (C) 2023 Robert Hrusecky <jadedpastabowl@gmail.com>
+
+//! Raw access to the RAM behind every bank, for tools that dig into a game's state, like finding
+//! where it keeps a value by comparing save states from before and after it changes.
+
+use std::fmt::{self, Display, Formatter};
+
+use crate::cart::BankedAddress;
+
+use super::CgbSystem;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MemoryRegion {
+    Vram,
+    /// Cartridge RAM
+    Sram,
+    Wram,
+}
+
+impl MemoryRegion {
+    pub const ALL: [MemoryRegion; 3] = [Self::Vram, Self::Sram, Self::Wram];
+
+    /// The CPU address of a byte, from its offset into the region's banks laid end to end.
+    pub fn address(self, offset: usize) -> BankedAddress {
+        let (base, bank_size) = match self {
+            Self::Vram => (0x8000, 0x2000),
+            Self::Sram => (0xa000, 0x2000),
+            Self::Wram => (0xc000, 0x1000),
+        };
+        let bank = offset / bank_size;
+        // WRAM banks 1-7 are switched in over 0xd000
+        let window = if self == Self::Wram && bank > 0 { 1 } else { 0 };
+        let addr = base + (window * bank_size + offset % bank_size) as u16;
+        BankedAddress::new(Some(bank), addr)
+    }
+}
+
+impl Display for MemoryRegion {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::Vram => "VRAM",
+            Self::Sram => "SRAM",
+            Self::Wram => "WRAM",
+        })
+    }
+}
+
+/// A byte that differs between two systems.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MemoryChange {
+    pub region: MemoryRegion,
+    /// With the RAM bank it's in
+    pub addr: BankedAddress,
+    pub before: u8,
+    pub after: u8,
+}
+
+impl CgbSystem {
+    /// Every bank of `region`, laid end to end. There's no SRAM if the cartridge doesn't have any.
+    pub fn memory(&self, region: MemoryRegion) -> Vec<u8> {
+        match region {
+            MemoryRegion::Vram => self.mem.vram.bytes().as_flattened().to_vec(),
+            MemoryRegion::Sram => self.cart.ram().into(),
+            MemoryRegion::Wram => self.mem.wram.bytes(),
+        }
+    }
+
+    /// Lists the bytes of RAM that differ in `after`, which should be running the same game.
+    pub fn diff_memory(&self, after: &CgbSystem) -> Vec<MemoryChange> {
+        MemoryRegion::ALL
+            .into_iter()
+            .flat_map(|region| {
+                let (before, after) = (self.memory(region), after.memory(region));
+                before
+                    .into_iter()
+                    .zip(after)
+                    .enumerate()
+                    .filter(|(_, (before, after))| before != after)
+                    .map(move |(offset, (before, after))| MemoryChange {
+                        region,
+                        addr: region.address(offset),
+                        before,
+                        after,
+                    })
+                    .collect::<Vec<_>>()
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{cart::Cart, cpu::CpuBus, system::BootRom};
+
+    use super::*;
+
+    #[test]
+    fn addresses() {
+        let addr = |region: MemoryRegion, offset| region.address(offset).to_string();
+        assert_eq!(addr(MemoryRegion::Vram, 0x2010), "01:8010");
+        assert_eq!(addr(MemoryRegion::Sram, 0x4000), "02:a000");
+        assert_eq!(addr(MemoryRegion::Wram, 0x0fff), "00:cfff");
+        assert_eq!(addr(MemoryRegion::Wram, 0x1000), "01:d000");
+        assert_eq!(addr(MemoryRegion::Wram, 0x7fff), "07:dfff");
+    }
+
+    #[test]
+    fn diff() {
+        let mut rom = vec![0; 0x8000];
+        // A CGB game, for VRAM banking, on MBC1 with 8 KiB of RAM
+        rom[0x143] = 0x80;
+        rom[0x147] = 0x03;
+        rom[0x149] = 0x02;
+        let rom: Box<[u8]> = rom.into();
+        let system =
+            || CgbSystem::with_boot_rom(Cart::from_rom(rom.clone()).unwrap(), BootRom::skip());
+        let before = system();
+        let mut after = system();
+        let (_, bus) = after.split_cpu();
+        for (addr, val) in [
+            // Enable cartridge RAM
+            (0x0000, 0x0a),
+            (0xa005, 0x01),
+            (0xc123, 0x42),
+            // Switch to VRAM bank 1
+            (0xff4f, 0x01),
+            (0x8010, 0x99),
+        ] {
+            bus.write_8(addr, val);
+        }
+
+        let change = |region, addr: &str, before, after| MemoryChange {
+            region,
+            addr: addr.parse().unwrap(),
+            before,
+            after,
+        };
+        assert_eq!(
+            before.diff_memory(&after),
+            [
+                change(MemoryRegion::Vram, "01:8010", 0x00, 0x99),
+                change(MemoryRegion::Sram, "00:a005", 0x00, 0x01),
+                change(MemoryRegion::Wram, "00:c123", 0x00, 0x42),
+            ]
+        );
+    }
+}
@@ -1,35 +1,93 @@
 // SPDX-License-Identifier: GPL-3.0-or-later
 // Copyright (C) 2023 Robert Hrusecky <jadedpastabowl@gmail.com>
 mod apu;
+mod boot;
 mod cpu;
+mod debug;
 mod dma;
+mod inspect;
 mod joypad;
+mod peripheral;
 mod ppu;
+mod scheduler;
+mod serial;
+mod state;
 mod timer;
+mod video;
 
-use std::time::Duration;
+use std::{
+    borrow::Cow,
+    fmt::{self, Display, Formatter},
+    mem,
+    time::Duration,
+};
 
 use partial_borrow::{prelude::*, SplitOff};
+use serde::{Deserialize, Serialize};
 
 use crate::{
     apu::{Apu, ApuBus},
-    cart::Cart,
+    cart::{save::CartSave, BankedAddress, Cart, RtcClock},
+    cheat::{Cheat, Cheats},
     cpu::{Cpu, CpuBus},
     dma::{Dma, DmaBus},
+    event::{Event, EventLog, Severity},
     interrupt::InterruptState,
-    joypad::{Button, ButtonState, Joypad},
+    joypad::{Button, ButtonState, HeldInput, Input, Joypad, Socd},
     memory::MemoryData,
+    open_bus::OpenBus,
     ppu::{Ppu, PpuBus},
+    serial::{Serial, SerialBus},
     timer::{Timer, TimerBus},
 };
 
-const BOOT_ROM: &[u8] = include_bytes!("../../sameboy_boot.bin");
+use self::{
+    debug::Debugger,
+    scheduler::{Deadline, Scheduler},
+};
+
+pub use self::{
+    boot::{BootRom, BootRomSizeError},
+    debug::{Access, Accessor, CountBreakpoint, WatchHit, Watchpoint},
+    inspect::{MemoryChange, MemoryRegion},
+    peripheral::Peripheral,
+    state::{StateError, StateInfo, THUMBNAIL_HEIGHT, THUMBNAIL_WIDTH},
+    video::{DebugVideo, MapEntry, ObjEntry, Tile, OBJ_COUNT, TILES_PER_BANK},
+};
+pub use crate::apu::AudioChannel;
+pub use crate::cpu::{disasm, Registers};
+pub use crate::ppu::{ColorTransform, DmgPalette, PpuConfig};
 
 pub const SCREEN_WIDTH: usize = 160;
 pub const SCREEN_HEIGHT: usize = 144;
 pub const VBLANK_LINES: usize = 10;
 pub const FRAME_LINES: usize = SCREEN_HEIGHT + VBLANK_LINES;
 pub type FrameBuffer = [[[u8; 4]; SCREEN_WIDTH]; SCREEN_HEIGHT];
+pub type AudioFrame = [f32; 2];
+pub const AUDIO_FRAMES_PER_CYCLE: usize = 2;
+
+/// The hardware to emulate, which is decided by the boot ROM.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Model {
+    #[default]
+    Cgb,
+    /// The original Game Boy, with four shades of gray and none of the CGB's registers. Unlike a
+    /// CGB running an old game, there's no way to colorize it.
+    Dmg,
+}
+
+impl Model {
+    pub const ALL: [Model; 2] = [Self::Cgb, Self::Dmg];
+}
+
+impl Display for Model {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::Cgb => "Game Boy Color",
+            Self::Dmg => "Game Boy",
+        })
+    }
+}
 
 #[derive(Debug, Clone, Copy)]
 pub struct MachineCycle(pub usize);
@@ -38,6 +96,11 @@ impl MachineCycle {
     pub const FREQ: usize = 1 << 20;
     pub const PER_LINE: usize = 114;
     pub const PER_FRAME: usize = FRAME_LINES * Self::PER_LINE;
+
+    /// The number of stereo audio frames produced over this many cycles.
+    pub fn audio_frames(&self) -> usize {
+        self.0 * AUDIO_FRAMES_PER_CYCLE
+    }
 }
 
 impl From<MachineCycle> for Duration {
@@ -56,34 +119,340 @@ pub struct CgbSystem {
     mem: MemoryData,
     joypad: Joypad,
     interrupt: InterruptState,
+    model: Model,
+    /// Empty if the boot was skipped
+    boot_rom: Cow<'static, [u8]>,
     boot_rom_mapped: bool,
     cgb_mode: bool,
     key0: u8, // TODO: This can probably be combined with cgb_mode
+    /// Whether the CPU and timer run at twice their normal rate, as selected through KEY1
+    double_speed: bool,
+    speed_switch_armed: bool,
+    serial: Serial,
+    /// Bytes sent over the serial port, if they're being captured
+    serial_out: Option<Vec<u8>>,
+    /// FF72-FF75, which have no known purpose but are probed by some software to detect a CGB
+    undocumented: [u8; 4],
+    overclock: usize,
+    rtc_clock: RtcClock,
+    scheduler: Scheduler,
+    /// Whether a call to [`Self::execute`] returned early or [`Self::step_instruction`] ran since
+    /// the last full frame, in which case the frame deadlines are still pending
+    frame_in_progress: bool,
+    debugger: Debugger,
+    open_bus: OpenBus,
+    peripherals: Vec<Box<dyn Peripheral>>,
+    events: EventLog,
+    audio_buffer: Vec<AudioFrame>,
+    cheats: Cheats,
     cart: Cart,
 }
 
+// Systems must stay Send, so that they can be handed to another thread, like to emulate off of the
+// UI thread. They aren't Sync, since the event log, open bus, and debugger record reads through
+// shared references with cells.
+const _: () = {
+    const fn assert_send<T: Send>() {}
+    assert_send::<CgbSystem>();
+    assert_send::<Cart>();
+};
+
 impl CgbSystem {
+    /// Creates a system that boots with the default [`BootRom`].
     pub fn new(cart: Cart) -> Self {
-        CgbSystem {
+        Self::with_boot_rom(cart, BootRom::default())
+    }
+
+    pub fn with_boot_rom(cart: Cart, boot_rom: BootRom) -> Self {
+        let events = EventLog::new();
+        for warning in cart.warnings() {
+            events.push(Severity::Warning, warning.clone());
+        }
+        let model = boot_rom.model();
+        let boot_rom = boot_rom.into_image();
+        let skip_boot = boot_rom.is_none();
+        let mut system = CgbSystem {
             cpu: Cpu::default(),
             timer: Timer::new(),
             dma: Dma::new(),
             ppu: Ppu::new(),
-            apu: Apu::default(),
+            apu: Apu::new(model == Model::Dmg),
             mem: MemoryData::new(),
             joypad: Joypad::new(),
             interrupt: InterruptState::new(),
+            model,
+            boot_rom: boot_rom.unwrap_or_default(),
             boot_rom_mapped: true,
-            cgb_mode: true,
+            cgb_mode: model == Model::Cgb,
             key0: 0,
+            double_speed: false,
+            speed_switch_armed: false,
+            serial: Serial::new(),
+            serial_out: None,
+            undocumented: [0; 4],
+            overclock: 0,
+            rtc_clock: RtcClock::default(),
+            scheduler: Scheduler::new(),
+            frame_in_progress: false,
+            debugger: Debugger::default(),
+            open_bus: OpenBus::new(),
+            peripherals: Vec::new(),
+            events,
+            audio_buffer: Vec::new(),
+            cheats: Cheats::default(),
             cart,
+        };
+        // Start the RTC from a save loaded into the cart beforehand
+        system.cart.set_rtc_clock(system.rtc_clock.clone());
+        if skip_boot {
+            system.skip_boot();
         }
+        system
+    }
+
+    pub fn model(&self) -> Model {
+        self.model
     }
 
     pub fn cart(&self) -> &Cart {
         &self.cart
     }
 
+    /// Replaces battery backed cartridge RAM under the running game, like when importing a save
+    /// from elsewhere. Most games only read it when they start, so they need a reset to notice.
+    pub fn load_cart_save(&mut self, save: CartSave) {
+        self.cart.load_from_save(save);
+        self.cart.set_rtc_clock(self.rtc_clock.clone());
+    }
+
+    /// What the cartridge's RTC keeps time by, if it has one. Defaults to [`RtcClock::default`],
+    /// but has to be [`RtcClock::Emulated`], or a deterministic [`RtcClock::Custom`] one, for the
+    /// system to be deterministic. Not part of save states, which only catch up on the time since
+    /// they were made if they were made with a clock that keeps real time.
+    pub fn set_rtc_clock(&mut self, clock: RtcClock) {
+        self.cart.set_rtc_clock(clock.clone());
+        self.rtc_clock = clock;
+    }
+
+    /// Moves emulated time on for the RTC, after `cycles` have been run.
+    fn advance_rtc(&mut self, cycles: usize) {
+        if matches!(self.rtc_clock, RtcClock::Emulated) {
+            self.cart.advance_rtc(MachineCycle(cycles).into());
+        }
+    }
+
+    /// See [`Cart::take_dirty`].
+    pub fn take_cart_dirty(&mut self) -> bool {
+        self.cart.take_dirty()
+    }
+
+    /// Sets the value returned by reads of unimplemented IO registers. Defaults to `0xff`, like
+    /// real hardware.
+    pub fn set_open_bus_value(&mut self, value: u8) {
+        self.open_bus.value = value;
+    }
+
+    /// Sets whether the first access to each unimplemented IO register is reported as an event.
+    pub fn set_log_unmapped_io(&mut self, log: bool) {
+        self.open_bus.log_first_access = log;
+    }
+
+    /// Plugs `peripheral` into the system. See [`Peripheral`] for which accesses it receives.
+    pub fn add_peripheral(&mut self, peripheral: Box<dyn Peripheral>) {
+        self.peripherals.push(peripheral);
+    }
+
+    pub fn ppu_config(&self) -> &PpuConfig {
+        &self.ppu.config
+    }
+
+    pub fn set_dmg_palette(&mut self, palette: DmgPalette) {
+        self.ppu.dmg_palette = palette;
+    }
+
+    pub fn set_ppu_config(&mut self, config: PpuConfig) {
+        self.ppu.config = config;
+    }
+
+    pub fn set_color_transform(&mut self, transform: ColorTransform) {
+        self.ppu.config.color_transform = transform;
+    }
+
+    pub fn channel_muted(&self, channel: AudioChannel) -> bool {
+        self.apu.channel_muted(channel)
+    }
+
+    /// Silences `channel` in the audio output, for picking out parts of a game's music or
+    /// debugging the APU. The channel keeps running, so unmuting it picks up where it would be.
+    pub fn set_channel_muted(&mut self, channel: AudioChannel, muted: bool) {
+        self.apu.set_channel_muted(channel, muted);
+    }
+
+    /// The number of instructions the CPU has started since power on. Together with a snapshot
+    /// of the system, this identifies a point in a deterministic replay.
+    pub fn instructions_executed(&self) -> u64 {
+        self.cpu.instructions()
+    }
+
+    /// The number of machine cycles that have gone by since power on, which identifies a point in
+    /// a deterministic replay like [`Self::instructions_executed`] does.
+    pub fn cycles_executed(&self) -> u64 {
+        self.scheduler.now()
+    }
+
+    pub fn registers(&self) -> Registers {
+        self.cpu.registers()
+    }
+
+    /// Makes [`Self::execute`] stop before the CPU starts the instruction at `addr`. An address
+    /// without a bank stops in every ROM bank.
+    pub fn add_breakpoint(&mut self, addr: impl Into<BankedAddress>) {
+        self.debugger.add_breakpoint(addr.into());
+    }
+
+    /// Returns whether there was a breakpoint at `addr`.
+    pub fn remove_breakpoint(&mut self, addr: impl Into<BankedAddress>) -> bool {
+        self.debugger.remove_breakpoint(addr.into())
+    }
+
+    pub fn breakpoints(&self) -> &[BankedAddress] {
+        self.debugger.breakpoints()
+    }
+
+    /// Makes [`Self::execute`] stop once [`Self::cycles_executed`] or
+    /// [`Self::instructions_executed`] reaches a count, at an instruction boundary. Each one only
+    /// stops once, and is removed when it does. One that has already been passed stops right away.
+    pub fn add_count_breakpoint(&mut self, breakpoint: CountBreakpoint) {
+        self.debugger.add_count_breakpoint(breakpoint);
+    }
+
+    /// Returns whether `breakpoint` was set, and hadn't stopped yet.
+    pub fn remove_count_breakpoint(&mut self, breakpoint: CountBreakpoint) -> bool {
+        self.debugger.remove_count_breakpoint(breakpoint)
+    }
+
+    pub fn count_breakpoints(&self) -> &[CountBreakpoint] {
+        self.debugger.count_breakpoints()
+    }
+
+    /// Makes [`Self::execute`] stop once the CPU or DMA accesses memory the way `watchpoint`
+    /// watches. Execution stops before the next instruction the CPU starts, so the access that
+    /// tripped it, given by [`Self::stopped_by`], has happened by then.
+    pub fn add_watchpoint(&mut self, watchpoint: Watchpoint) {
+        self.debugger.add_watchpoint(watchpoint);
+    }
+
+    /// Returns whether `watchpoint` was set.
+    pub fn remove_watchpoint(&mut self, watchpoint: Watchpoint) -> bool {
+        self.debugger.remove_watchpoint(watchpoint)
+    }
+
+    pub fn watchpoints(&self) -> &[Watchpoint] {
+        self.debugger.watchpoints()
+    }
+
+    /// The breakpoint or step that execution is stopped at. This is cleared once execution
+    /// continues, and the instruction there runs first instead of stopping again.
+    pub fn stopped_at(&self) -> Option<BankedAddress> {
+        self.debugger.stopped_at()
+    }
+
+    /// The watchpoint hit that execution stopped for, if that's why it's stopped.
+    pub fn stopped_by(&self) -> Option<WatchHit> {
+        self.debugger.stopped_by()
+    }
+
+    /// Reads `addr` through the CPU's view of memory, for tools that inspect a running game. This
+    /// sees the current banks and any DMA blocking, just like the CPU would.
+    pub fn peek(&mut self, addr: u16) -> u8 {
+        let (_, bus) = self.split_cpu();
+        bus.peek_8(addr)
+    }
+
+    /// Writes `addr` through the CPU's view of memory, for tools that change a running game, like
+    /// scripts. Writing an IO register has the same effect as when the game does it, but doesn't
+    /// trip watchpoints.
+    pub fn poke(&mut self, addr: u16, val: u8) {
+        let (_, bus) = self.split_cpu();
+        bus.poke_8(addr, val);
+    }
+
+    /// Disassembles from `start` until an instruction starts at or after `end`, reading memory
+    /// like [`Self::peek`].
+    pub fn disassemble(&mut self, start: u16, end: u16) -> Vec<disasm::Line> {
+        let (_, bus) = self.split_cpu();
+        disasm::disassemble(start, end, |addr| bus.peek_8(addr))
+    }
+
+    /// Starts or stops keeping a trace of the last [`disasm::TRACE_LEN`] instructions executed.
+    /// It's off by default, since it slows the CPU down.
+    pub fn set_tracing(&mut self, tracing: bool) {
+        self.cpu.set_tracing(tracing);
+    }
+
+    /// The instructions executed since tracing started, oldest first, or nothing if it's off.
+    pub fn trace(&self) -> impl Iterator<Item = &disasm::TraceEntry> {
+        self.cpu
+            .trace()
+            .into_iter()
+            .flat_map(|trace| trace.entries())
+    }
+
+    /// Copies `data` into VRAM `bank` (0 or 1) starting at `offset` from 0x8000. Like the other
+    /// `load_*` methods, this goes around the bus, so it works no matter the PPU mode, VBK, or any
+    /// DMA in progress. This is meant for tests and tools, not games.
+    ///
+    /// Panics if the data doesn't fit in the bank.
+    pub fn load_vram(&mut self, bank: usize, offset: usize, data: &[u8]) {
+        self.mem.vram.load(bank, offset, data);
+    }
+
+    /// Copies `data` into OAM, starting with the first object. Panics if it is longer than OAM.
+    pub fn load_oam(&mut self, data: &[u8]) {
+        self.mem.oam[..data.len()].copy_from_slice(data);
+    }
+
+    /// Copies `bg` and `obj` into the start of the background and object palette RAM, in the
+    /// CGB's format of little endian RGB555 colors, 4 per palette. Panics if either is longer than
+    /// 64 bytes.
+    pub fn load_palettes(&mut self, bg: &[u8], obj: &[u8]) {
+        self.mem.bg_palette.load(bg);
+        self.mem.obj_palette.load(obj);
+    }
+
+    /// Runs `cycles` extra CPU machine cycles at the end of every scanline, without advancing the
+    /// PPU, APU, DMA, or timer. This can reduce slowdown in games that lag on real hardware, but it
+    /// is inaccurate and must stay at zero for anything that has to match hardware timing, like
+    /// netplay or TAS recording.
+    pub fn set_overclock(&mut self, cycles: usize) {
+        self.overclock = cycles;
+    }
+
+    /// Replaces the cheats in effect. Game Genie codes apply to every read of ROM from then on, and
+    /// GameShark codes are written at the end of each frame. Cheats aren't part of save states.
+    pub fn set_cheats(&mut self, cheats: impl IntoIterator<Item = Cheat>) {
+        self.cheats.set(cheats);
+    }
+
+    /// Records the bytes sent over the serial port on the internal clock, i.e. by writing 0x81 to
+    /// SC, for [`Self::take_serial`]. Nothing is ever on the other end, but homebrew and test ROMs
+    /// print this way.
+    pub fn set_capture_serial(&mut self, capture: bool) {
+        self.serial_out = capture.then(Vec::new);
+    }
+
+    /// Moves the bytes sent over the serial port since the last call onto the end of `out`.
+    pub fn take_serial(&mut self, out: &mut Vec<u8>) {
+        if let Some(serial_out) = &mut self.serial_out {
+            out.append(serial_out);
+        }
+    }
+
+    /// Takes all of the events raised since the last call, oldest first.
+    pub fn events(&mut self) -> impl Iterator<Item = Event> + '_ {
+        self.events.drain()
+    }
+
     fn split_cpu(&mut self) -> (&mut Cpu, &mut impl CpuBus) {
         let (bus, system) = SplitOff::split_off_mut(self);
         (&mut system.cpu, bus)
@@ -109,15 +478,55 @@ impl CgbSystem {
         (&mut system.timer, bus)
     }
 
+    fn split_serial(&mut self) -> (&mut Serial, &mut impl SerialBus) {
+        let (bus, system) = SplitOff::split_off_mut(self);
+        (&mut system.serial, bus)
+    }
+
     pub fn handle_joypad(&mut self, button: Button, state: ButtonState) {
         let (bus, system) = SplitOff::split_off_mut(self);
         system.joypad.handle(button, state, bus);
     }
 
+    /// See [`Joypad::handle_stick`].
+    pub fn handle_stick(&mut self, x: f32, y: f32, deadzone: f32) {
+        let (bus, system) = SplitOff::split_off_mut(self);
+        system.joypad.handle_stick(x, y, deadzone, bus);
+    }
+
+    /// The buttons held down, as of the last call to [`Self::handle_joypad`] or the like.
+    pub fn input(&self) -> Input {
+        self.joypad.input()
+    }
+
+    /// Holds down exactly the buttons in `input`, like for playing back a frame of a movie.
+    pub fn set_input(&mut self, input: Input) {
+        let (bus, system) = SplitOff::split_off_mut(self);
+        system.joypad.set_input(input, bus);
+    }
+
+    pub(crate) fn held_input(&self) -> HeldInput {
+        self.joypad.held_input()
+    }
+
+    /// See [`Joypad::set_held_input`].
+    pub(crate) fn set_held_input(&mut self, held: HeldInput) {
+        self.joypad.set_held_input(held);
+    }
+
+    pub fn socd(&self) -> Socd {
+        self.joypad.socd()
+    }
+
+    /// How to resolve opposing directions held at once. Defaults to [`Socd::LastWins`].
+    pub fn set_socd(&mut self, socd: Socd) {
+        self.joypad.set_socd(socd);
+    }
+
     fn execute_machine_cycle(
         &mut self,
         frame_buff: &mut FrameBuffer,
-        audio_callback: &mut impl FnMut([f32; 2]),
+        audio_callback: &mut impl FnMut(AudioFrame),
     ) {
         let (ppu, bus) = self.split_ppu();
         ppu.execute(frame_buff, bus);
@@ -125,32 +534,235 @@ impl CgbSystem {
         dma.execute(bus);
         let (apu, bus) = self.split_apu();
         apu.execute(bus).into_iter().for_each(audio_callback);
+        for _ in 0..self.cpu_cycles_per_machine_cycle() {
+            let (cpu, bus) = self.split_cpu();
+            cpu.execute(bus);
+            let (timer, bus) = self.split_timer();
+            timer.execute(bus);
+            let (serial, bus) = self.split_serial();
+            serial.execute(bus);
+        }
+    }
+
+    /// The number of CPU cycles that fit in one machine cycle of the PPU and APU, which is two in
+    /// double speed mode.
+    fn cpu_cycles_per_machine_cycle(&self) -> usize {
+        if self.double_speed {
+            2
+        } else {
+            1
+        }
+    }
+
+    /// Whether the system is guaranteed to do nothing but tick the APU and timer until the next
+    /// deadline.
+    fn idle(&self) -> bool {
+        self.cpu.idle() && !self.interrupt.pending() && self.dma.cycles_until_done().is_none()
+    }
+
+    fn skip_idle_cycles(&mut self, cycles: usize, audio_callback: &mut impl FnMut(AudioFrame)) {
+        let timer_ticks = self.cpu_cycles_per_machine_cycle();
+        for _ in 0..cycles {
+            let (apu, bus) = self.split_apu();
+            apu.execute(bus).into_iter().for_each(&mut *audio_callback);
+            let (timer, bus) = self.split_timer();
+            for _ in 0..timer_ticks {
+                timer.execute(bus);
+            }
+            let (serial, bus) = self.split_serial();
+            for _ in 0..timer_ticks {
+                serial.execute(bus);
+            }
+        }
+        self.ppu.skip(cycles);
+    }
+
+    fn execute_overclock(&mut self) {
+        let overclock = self.overclock;
         let (cpu, bus) = self.split_cpu();
-        cpu.execute(bus);
-        let (timer, bus) = self.split_timer();
-        timer.execute(bus);
+        for _ in 0..overclock {
+            cpu.execute(bus);
+        }
+    }
+
+    fn pc(&mut self) -> BankedAddress {
+        let (cpu, bus) = self.split_cpu();
+        bus.banked_address(cpu.pc())
+    }
+
+    /// Whether the CPU is about to start an instruction that has a breakpoint.
+    fn breakpoint_hit(&mut self) -> bool {
+        if !self.debugger.active() || !self.cpu.at_instruction_boundary() || self.cpu.idle() {
+            return false;
+        }
+        let pc = self.pc();
+        let (cycles, instructions) = (self.scheduler.now(), self.cpu.instructions());
+        self.debugger.should_stop(pc, cycles, instructions)
+    }
+
+    fn apply_ram_cheats(&mut self) {
+        let writes: Vec<_> = self.cheats.ram_writes().collect();
+        let (_, bus) = self.split_cpu();
+        for (addr, value) in writes {
+            bus.poke_8(addr, value);
+        }
+    }
+
+    fn start_frame(&mut self) {
+        if self.frame_in_progress {
+            return;
+        }
+        self.frame_in_progress = true;
+        self.scheduler
+            .schedule(Deadline::FrameEnd, Some(MachineCycle::PER_FRAME));
+        self.scheduler
+            .schedule(Deadline::LineEnd, Some(MachineCycle::PER_LINE));
     }
 
+    /// Re-arms the end of the line if it's due. Returns whether the frame has ended too.
+    fn end_line(&mut self) -> bool {
+        if self.scheduler.is_due(Deadline::LineEnd) {
+            self.execute_overclock();
+            self.scheduler
+                .schedule(Deadline::LineEnd, Some(MachineCycle::PER_LINE));
+        }
+        self.scheduler.is_due(Deadline::FrameEnd)
+    }
+
+    fn schedule_subsystems(&mut self) {
+        let timer_ticks = self.cpu_cycles_per_machine_cycle();
+        let scheduler = &mut self.scheduler;
+        scheduler.schedule(Deadline::Ppu, self.ppu.cycles_until_event());
+        scheduler.schedule(
+            Deadline::Timer,
+            self.timer
+                .cycles_until_overflow()
+                .map(|cycles| cycles.div_ceil(timer_ticks)),
+        );
+        scheduler.schedule(Deadline::Dma, self.dma.cycles_until_done());
+        scheduler.schedule(
+            Deadline::Serial,
+            self.serial
+                .cycles_until_done()
+                .map(|cycles| cycles.div_ceil(timer_ticks)),
+        );
+    }
+
+    /// Runs until the end of the frame, or until a breakpoint is hit, and returns how many cycles
+    /// went by. Frames are [`MachineCycle::PER_FRAME`] long, except for the one where the LCD turns
+    /// on. The PPU starts drawing from the top then, so the frame is stretched to end when it's
+    /// done, leaving a whole picture in `frame_buff` instead of a blank one.
     pub fn execute(
         &mut self,
         frame_buff: &mut FrameBuffer,
-        mut audio_callback: impl FnMut([f32; 2]),
+        mut audio_callback: impl FnMut(AudioFrame),
     ) -> MachineCycle {
-        let lcd_on = self.ppu.lcd_enabled();
-        let mut cycles = MachineCycle::PER_FRAME;
-        for c in 1..=cycles {
-            self.execute_machine_cycle(frame_buff, &mut audio_callback);
-            if !lcd_on && self.ppu.lcd_enabled() {
-                cycles = c;
+        let mut lcd_on = self.ppu.lcd_enabled();
+        let mut cycles = 0;
+        self.debugger.resume();
+        self.start_frame();
+        'frame: loop {
+            self.schedule_subsystems();
+            let mut batch = self.scheduler.cycles_until_next();
+            if batch > 1 && self.idle() {
+                // Nothing can wake the CPU before the next deadline, so race through all but the
+                // last cycle, which may raise an interrupt
+                self.skip_idle_cycles(batch - 1, &mut audio_callback);
+                self.scheduler.advance(batch - 1);
+                cycles += batch - 1;
+                batch = 1;
+            }
+            for _ in 0..batch {
+                if self.breakpoint_hit() {
+                    self.advance_rtc(cycles);
+                    return MachineCycle(cycles);
+                }
+                self.execute_machine_cycle(frame_buff, &mut audio_callback);
+                self.scheduler.advance(1);
+                cycles += 1;
+                if !lcd_on && self.ppu.lcd_enabled() {
+                    // Start the frame over, in step with the PPU
+                    lcd_on = true;
+                    self.frame_in_progress = false;
+                    self.start_frame();
+                    continue 'frame;
+                }
+            }
+
+            if self.end_line() {
                 break;
             }
         }
 
+        self.frame_in_progress = false;
+        self.advance_rtc(cycles);
+        self.apply_ram_cheats();
+
         if !lcd_on {
-            // If the LCD is off, make sure we are showing a white screen
-            *frame_buff = [[[0xff; 4]; SCREEN_WIDTH]; SCREEN_HEIGHT];
+            // If the LCD is off, make sure we are showing a blank screen
+            *frame_buff = [[self.ppu.blank_pixel(self.model); SCREEN_WIDTH]; SCREEN_HEIGHT];
         }
 
         MachineCycle(cycles)
     }
+
+    /// Runs until the CPU has executed one instruction, along with any interrupt dispatched
+    /// before it, ignoring breakpoints. Afterwards, execution is stopped at the next instruction.
+    /// A halted CPU gives up after a frame's worth of cycles if nothing wakes it.
+    pub fn step_instruction(
+        &mut self,
+        frame_buff: &mut FrameBuffer,
+        mut audio_callback: impl FnMut(AudioFrame),
+    ) -> MachineCycle {
+        self.debugger.resume();
+        self.start_frame();
+        let start = self.cpu.instructions();
+        let mut cycles = 0;
+        while cycles < MachineCycle::PER_FRAME
+            && !(self.cpu.instructions() != start && self.cpu.at_instruction_boundary())
+        {
+            self.execute_machine_cycle(frame_buff, &mut audio_callback);
+            self.scheduler.advance(1);
+            cycles += 1;
+            if self.end_line() {
+                // Start the next frame here, so the next call to execute runs a whole one
+                self.frame_in_progress = false;
+                self.apply_ram_cheats();
+                self.start_frame();
+            }
+        }
+        self.advance_rtc(cycles);
+        let pc = self.pc();
+        self.debugger.stop_at(pc);
+        MachineCycle(cycles)
+    }
+
+    /// Steps until [`Self::instructions_executed`] reaches `instruction`, ignoring breakpoints,
+    /// like to replay from an earlier state up to a point after it. Returns whether it got there,
+    /// which it doesn't if the CPU stays halted for a whole frame on the way.
+    pub fn run_to_instruction(&mut self, instruction: u64, frame_buff: &mut FrameBuffer) -> bool {
+        while self.cpu.instructions() < instruction {
+            let start = self.cpu.instructions();
+            self.step_instruction(frame_buff, |_| ());
+            if self.cpu.instructions() == start {
+                return false;
+            }
+        }
+        true
+    }
+
+    /// Like [`Self::execute`], but buffers the audio internally instead of handing it to a
+    /// callback. Retrieve it with [`Self::take_audio`] afterwards, or the buffer will grow without
+    /// bound.
+    pub fn execute_buffered(&mut self, frame_buff: &mut FrameBuffer) -> MachineCycle {
+        let mut buffer = mem::take(&mut self.audio_buffer);
+        let cycles = self.execute(frame_buff, |frame| buffer.push(frame));
+        self.audio_buffer = buffer;
+        cycles
+    }
+
+    /// Moves all of the audio buffered by [`Self::execute_buffered`] onto the end of `out`.
+    pub fn take_audio(&mut self, out: &mut Vec<AudioFrame>) {
+        out.append(&mut self.audio_buffer);
+    }
 }
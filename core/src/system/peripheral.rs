@@ -0,0 +1,61 @@
+// SPDX-License-Identifier: GPL-3.0-or-later
+// Copyright (C) 2023 Robert Hrusecky <jadedpastabowl@gmail.com>
+
+use std::ops::RangeInclusive;
+
+/// Memory-mapped hardware that isn't part of the Game Boy, like a debug port or homemade
+/// cartridge hardware, for experimenting without patching the core.
+///
+/// Peripherals live in the IO register area (0xff00-0xff7f) and only see accesses to registers
+/// that the system doesn't implement itself. If peripherals overlap, the one added first wins.
+///
+/// Peripherals must be [`Send`], since they move between threads along with the system.
+pub trait Peripheral: Send {
+    /// The IO register addresses this peripheral responds to
+    fn io_range(&self) -> RangeInclusive<u16>;
+
+    fn read(&self, addr: u16) -> u8;
+
+    fn write(&mut self, addr: u16, val: u8);
+
+    /// Sound played into the cartridge's VIN pin, from -1 to 1, which games can mix in through
+    /// NR50. It's sampled as the APU runs.
+    fn vin(&self) -> f32 {
+        0.0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{cart::Cart, cpu::CpuBus, system::CgbSystem};
+
+    use super::*;
+
+    struct Latch(u8);
+
+    impl Peripheral for Latch {
+        fn io_range(&self) -> RangeInclusive<u16> {
+            0xff60..=0xff61
+        }
+
+        fn read(&self, _addr: u16) -> u8 {
+            self.0
+        }
+
+        fn write(&mut self, _addr: u16, val: u8) {
+            self.0 = val;
+        }
+    }
+
+    #[test]
+    fn dispatch() {
+        let cart = Cart::from_rom(vec![0; 0x8000].into_boxed_slice()).unwrap();
+        let mut system = CgbSystem::new(cart);
+        system.add_peripheral(Box::new(Latch(0)));
+        let (_, bus) = system.split_cpu();
+
+        bus.write_8(0xff61, 0x42);
+        assert_eq!(bus.read_8(0xff60), 0x42);
+        assert_eq!(bus.read_8(0xff62), 0xff);
+    }
+}
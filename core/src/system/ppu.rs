@@ -8,7 +8,7 @@ use crate::{
     ppu::PpuBus,
 };
 
-use super::CgbSystem;
+use super::{CgbSystem, Model};
 
 impl PpuBus for partial!(CgbSystem ! ppu, mut mem interrupt) {
     fn request_vblank_interrupt(&mut self) {
@@ -38,4 +38,51 @@ impl PpuBus for partial!(CgbSystem ! ppu, mut mem interrupt) {
     fn cgb_mode(&self) -> bool {
         *self.cgb_mode
     }
+
+    fn model(&self) -> Model {
+        *self.model
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        cart::Cart,
+        system::{BootRom, MachineCycle, SCREEN_HEIGHT, SCREEN_WIDTH},
+    };
+
+    use super::*;
+
+    #[test]
+    fn lcd_enable_stretches_frame() {
+        #[rustfmt::skip]
+        let program = [
+            0x3e, 0x11,       // ld a, 0x11
+            0xe0, 0x40,       // ldh (LCDC), a
+            // Wait about a frame and a half
+            0x01, 0x00, 0x10, // ld bc, 0x1000
+            0x0b,             // .wait: dec bc
+            0x78,             // ld a, b
+            0xb1,             // or c
+            0x20, 0xfb,       // jr nz, .wait
+            0x3e, 0x91,       // ld a, 0x91
+            0xe0, 0x40,       // ldh (LCDC), a
+            0x18, 0xfe,       // jr -2
+        ];
+        let mut rom = vec![0; 0x8000];
+        rom[0x100..0x100 + program.len()].copy_from_slice(&program);
+        let cart = Cart::from_rom(rom.into_boxed_slice()).unwrap();
+        let mut system = CgbSystem::with_boot_rom(cart, BootRom::skip());
+        let mut frame_buff = Box::new([[[0; 4]; SCREEN_WIDTH]; SCREEN_HEIGHT]);
+        let mut frame = || system.execute(&mut frame_buff, |_| ()).0;
+
+        assert_eq!(frame(), MachineCycle::PER_FRAME);
+        // The LCD turns on partway through, and the frame runs on until the PPU's first one ends
+        let stretched = frame();
+        assert!(
+            (MachineCycle::PER_FRAME + 1..2 * MachineCycle::PER_FRAME).contains(&stretched),
+            "{stretched}"
+        );
+        assert_eq!(frame(), MachineCycle::PER_FRAME);
+    }
 }
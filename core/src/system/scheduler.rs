@@ -0,0 +1,92 @@
+// SPDX-License-Identifier: GPL-3.0-or-later
+// Copyright (C) 2023 Robert Hrusecky <jadedpastabowl@gmail.com>
+
+use serde::{Deserialize, Serialize};
+
+/// Things that need the system's attention at a known point in the future.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Deadline {
+    FrameEnd,
+    LineEnd,
+    /// The current PPU mode ends, which is the only time it can request an interrupt
+    Ppu,
+    /// TIMA overflows and requests an interrupt
+    Timer,
+    /// The current DMA transfer completes
+    Dma,
+    /// The serial transfer on the internal clock completes and requests an interrupt
+    Serial,
+}
+
+impl Deadline {
+    const COUNT: usize = 6;
+}
+
+/// Centralizes cycle accounting for the system. Subsystems report how far away their next event
+/// is, so the system knows how many machine cycles it can run before anything interesting
+/// happens.
+#[derive(Serialize, Deserialize)]
+pub struct Scheduler {
+    now: u64,
+    deadlines: [Option<u64>; Deadline::COUNT],
+}
+
+impl Scheduler {
+    pub fn new() -> Self {
+        Self {
+            now: 0,
+            deadlines: [None; Deadline::COUNT],
+        }
+    }
+
+    /// Sets `deadline` to be `cycles` machine cycles from now, or clears it if `None`.
+    pub fn schedule(&mut self, deadline: Deadline, cycles: Option<usize>) {
+        self.deadlines[deadline as usize] = cycles.map(|cycles| self.now + cycles as u64);
+    }
+
+    pub fn is_due(&self, deadline: Deadline) -> bool {
+        self.deadlines[deadline as usize].is_some_and(|time| time <= self.now)
+    }
+
+    /// The number of machine cycles until the earliest deadline, but always at least one.
+    pub fn cycles_until_next(&self) -> usize {
+        self.deadlines
+            .iter()
+            .flatten()
+            .map(|time| time.saturating_sub(self.now))
+            .min()
+            .unwrap_or(0)
+            .max(1) as usize
+    }
+
+    /// The number of machine cycles since power on.
+    pub fn now(&self) -> u64 {
+        self.now
+    }
+
+    pub fn advance(&mut self, cycles: usize) {
+        self.now += cycles as u64;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn earliest_deadline() {
+        let mut scheduler = Scheduler::new();
+        scheduler.schedule(Deadline::FrameEnd, Some(100));
+        scheduler.schedule(Deadline::Timer, Some(30));
+        scheduler.schedule(Deadline::Ppu, None);
+        assert_eq!(scheduler.cycles_until_next(), 30);
+
+        scheduler.advance(30);
+        assert!(scheduler.is_due(Deadline::Timer));
+        assert!(!scheduler.is_due(Deadline::FrameEnd));
+        assert_eq!(scheduler.cycles_until_next(), 1);
+
+        scheduler.schedule(Deadline::Timer, None);
+        assert_eq!(scheduler.cycles_until_next(), 70);
+    }
+}
@@ -0,0 +1,11 @@
+// SPDX-License-Identifier: GPL-3.0-or-later
+// Copyright (C) 2023 Robert Hrusecky <jadedpastabowl@gmail.com>
+use super::CgbSystem;
+use crate::{interrupt::Interrupt, serial::SerialBus};
+use partial_borrow::prelude::*;
+
+impl SerialBus for partial!(CgbSystem ! serial, mut interrupt) {
+    fn request_serial_interrupt(&mut self) {
+        self.interrupt.request(Interrupt::Serial);
+    }
+}
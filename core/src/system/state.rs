@@ -0,0 +1,370 @@
+// SPDX-License-Identifier: GPL-3.0-or-later
+// Copyright (C) 2023 Robert Hrusecky <jadedpastabowl@gmail.com>
+
+//! Save states, which capture the whole emulated machine so that it can be restored later. The
+//! ROM itself isn't included, so a state can only be loaded into a system running the same game.
+//!
+//! The header may carry a [`StateInfo`] for telling states apart without loading them. After it
+//! and the parts of the system that don't belong to any one subsystem, a state is a
+//! length-prefixed [`Snapshot`] of each subsystem in turn.
+
+use std::io;
+
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+use crate::{interrupt::InterruptState, joypad::Joypad, serial::Serial, snapshot::Snapshot};
+
+use super::{scheduler::Scheduler, CgbSystem, FrameBuffer, Model, SCREEN_HEIGHT, SCREEN_WIDTH};
+
+const MAGIC: &[u8; 4] = b"IBST";
+/// Must be bumped whenever the layout of [`State`] changes, so that old states are rejected
+/// instead of misread.
+const VERSION: u32 = 18;
+
+pub const THUMBNAIL_WIDTH: usize = SCREEN_WIDTH / 2;
+pub const THUMBNAIL_HEIGHT: usize = SCREEN_HEIGHT / 2;
+
+#[derive(Error, Debug)]
+pub enum StateError {
+    #[error("Not a save state")]
+    NotAState,
+    #[error("Save state version {0} is not supported, expected version {VERSION}")]
+    UnsupportedVersion(u32),
+    #[error("Save state is for a different ROM")]
+    WrongRom,
+    #[error("Save state is for a different model")]
+    WrongModel,
+    #[error("Save state is corrupt")]
+    Corrupt(#[from] bincode::Error),
+}
+
+/// What a state says about itself, for listing states to choose from.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct StateInfo {
+    /// The title from the game's header
+    pub title: String,
+    /// When the state was saved, in seconds since the Unix epoch
+    pub saved_at: u64,
+    /// The screen at half size, as [`THUMBNAIL_WIDTH`] by [`THUMBNAIL_HEIGHT`] RGBA pixels
+    pub thumbnail: Vec<u8>,
+}
+
+impl StateInfo {
+    /// Reads the info in the header of a state from [`CgbSystem::save_state_with_info`]. States
+    /// saved without it have none.
+    pub fn read(data: &[u8]) -> Result<Option<Self>, StateError> {
+        let (info, _) = split_header(data)?;
+        if info.is_empty() {
+            return Ok(None);
+        }
+        Ok(Some(bincode::deserialize(info)?))
+    }
+}
+
+/// Averages each 2x2 block of pixels into one.
+fn thumbnail(frame_buff: &FrameBuffer) -> Vec<u8> {
+    let mut thumbnail = Vec::with_capacity(THUMBNAIL_WIDTH * THUMBNAIL_HEIGHT * 4);
+    for rows in frame_buff.chunks_exact(2) {
+        for x in (0..SCREEN_WIDTH).step_by(2) {
+            for channel in 0..4 {
+                let sum: u16 = rows
+                    .iter()
+                    .flat_map(|row| &row[x..x + 2])
+                    .map(|pixel| pixel[channel] as u16)
+                    .sum();
+                thumbnail.push((sum / 4) as u8);
+            }
+        }
+    }
+    thumbnail
+}
+
+/// Checks the magic number and version, then splits the [`StateInfo`] section off the rest.
+fn split_header(data: &[u8]) -> Result<(&[u8], &[u8]), StateError> {
+    let data = data.strip_prefix(MAGIC).ok_or(StateError::NotAState)?;
+    let (version, mut data) = data.split_first_chunk().ok_or(StateError::NotAState)?;
+    let version = u32::from_le_bytes(*version);
+    if version != VERSION {
+        return Err(StateError::UnsupportedVersion(version));
+    }
+    let info = read_section(&mut data)?;
+    Ok((info, data))
+}
+
+/// The parts of the system that aren't a subsystem with a [`Snapshot`] of its own.
+#[derive(Serialize)]
+struct StateRef<'a> {
+    rom_checksums: [u8; 3],
+    joypad: &'a Joypad,
+    interrupt: &'a InterruptState,
+    model: Model,
+    boot_rom_mapped: bool,
+    cgb_mode: bool,
+    double_speed: bool,
+    speed_switch_armed: bool,
+    key0: u8,
+    serial: &'a Serial,
+    undocumented: [u8; 4],
+    scheduler: &'a Scheduler,
+    frame_in_progress: bool,
+}
+
+/// The owned counterpart of [`StateRef`]. The fields must stay in the same order.
+#[derive(Deserialize)]
+struct State {
+    rom_checksums: [u8; 3],
+    joypad: Joypad,
+    interrupt: InterruptState,
+    model: Model,
+    boot_rom_mapped: bool,
+    cgb_mode: bool,
+    double_speed: bool,
+    speed_switch_armed: bool,
+    key0: u8,
+    serial: Serial,
+    undocumented: [u8; 4],
+    scheduler: Scheduler,
+    frame_in_progress: bool,
+}
+
+fn write_section(data: &mut Vec<u8>, subsystem: &impl Snapshot) {
+    let start = data.len();
+    data.extend_from_slice(&[0; 4]);
+    subsystem.snapshot_into(data);
+    let len = (data.len() - start - 4) as u32;
+    data[start..start + 4].copy_from_slice(&len.to_le_bytes());
+}
+
+/// Splits the next section written by [`write_section`] off the front of `data`.
+fn read_section<'a>(data: &mut &'a [u8]) -> Result<&'a [u8], StateError> {
+    let truncated = || StateError::Corrupt(io::Error::from(io::ErrorKind::UnexpectedEof).into());
+    let (len, rest) = data.split_first_chunk().ok_or_else(truncated)?;
+    let len = u32::from_le_bytes(*len) as usize;
+    if rest.len() < len {
+        return Err(truncated());
+    }
+    let (section, rest) = rest.split_at(len);
+    *data = rest;
+    Ok(section)
+}
+
+impl CgbSystem {
+    /// Captures the state of the emulated machine, including cartridge RAM and the MBC. Settings
+    /// like the PPU config, muted audio channels, overclock, the RTC's clock, and breakpoints
+    /// aren't part of the state, and neither are the buttons being held.
+    pub fn save_state(&self) -> Vec<u8> {
+        let mut data = Vec::new();
+        self.save_state_into(&mut data);
+        data
+    }
+
+    /// Like [`Self::save_state`], with a [`StateInfo`] in the header for the player to recognize
+    /// the state by. `frame_buff` is the screen to show for it, and `saved_at` is the time in
+    /// seconds since the Unix epoch.
+    pub fn save_state_with_info(&self, saved_at: u64, frame_buff: &FrameBuffer) -> Vec<u8> {
+        let info = StateInfo {
+            title: self.cart.title(),
+            saved_at,
+            thumbnail: thumbnail(frame_buff),
+        };
+        let mut data = Vec::new();
+        self.write_state(&mut data, Some(&info));
+        data
+    }
+
+    /// Like [`Self::save_state`], but reuses `data`'s allocation, for callers that take a state
+    /// every few frames, like rewinding. Consecutive states can be shrunk with [`crate::delta`].
+    pub fn save_state_into(&self, data: &mut Vec<u8>) {
+        self.write_state(data, None);
+    }
+
+    fn write_state(&self, data: &mut Vec<u8>, info: Option<&StateInfo>) {
+        let state = StateRef {
+            rom_checksums: self.cart.checksums(),
+            joypad: &self.joypad,
+            interrupt: &self.interrupt,
+            model: self.model,
+            boot_rom_mapped: self.boot_rom_mapped,
+            cgb_mode: self.cgb_mode,
+            double_speed: self.double_speed,
+            speed_switch_armed: self.speed_switch_armed,
+            key0: self.key0,
+            serial: &self.serial,
+            undocumented: self.undocumented,
+            scheduler: &self.scheduler,
+            frame_in_progress: self.frame_in_progress,
+        };
+        data.clear();
+        data.extend_from_slice(MAGIC);
+        data.extend_from_slice(&VERSION.to_le_bytes());
+        // An empty section when there's no info, like a subsystem's from [`write_section`]
+        let info = info.map_or_else(Vec::new, |info| {
+            bincode::serialize(info).expect("Serializing into a Vec can't fail")
+        });
+        data.extend_from_slice(&(info.len() as u32).to_le_bytes());
+        data.extend_from_slice(&info);
+        bincode::serialize_into(&mut *data, &state).expect("Serializing into a Vec can't fail");
+        self.write_subsystems(data);
+    }
+
+    fn write_subsystems(&self, data: &mut Vec<u8>) {
+        write_section(data, &self.cpu);
+        write_section(data, &self.timer);
+        write_section(data, &self.ppu);
+        write_section(data, &self.dma);
+        write_section(data, &self.apu);
+        write_section(data, &self.mem);
+        write_section(data, &self.cart);
+    }
+
+    /// Restores the sections from [`Self::write_subsystems`]. A subsystem that fails to restore is
+    /// left untouched, but the ones before it aren't.
+    fn restore_subsystems(&mut self, mut data: &[u8]) -> Result<(), StateError> {
+        self.cpu.restore(read_section(&mut data)?)?;
+        self.timer.restore(read_section(&mut data)?)?;
+        self.ppu.restore(read_section(&mut data)?)?;
+        self.dma.restore(read_section(&mut data)?)?;
+        self.apu.restore(read_section(&mut data)?)?;
+        self.mem.restore(read_section(&mut data)?)?;
+        self.cart.restore(read_section(&mut data)?)
+    }
+
+    /// Restores a state from [`Self::save_state`]. On error, the system is left untouched.
+    pub fn load_state(&mut self, data: &[u8]) -> Result<(), StateError> {
+        let (_, mut data) = split_header(data)?;
+        let state: State = bincode::deserialize_from(&mut data)?;
+        if state.model != self.model {
+            return Err(StateError::WrongModel);
+        }
+        if state.rom_checksums != self.cart.checksums() {
+            return Err(StateError::WrongRom);
+        }
+
+        // Put back the subsystems that were already restored if a later one fails
+        let mut backup = Vec::new();
+        self.write_subsystems(&mut backup);
+        if let Err(error) = self.restore_subsystems(data) {
+            self.restore_subsystems(&backup)
+                .expect("Subsystems that were just written restore");
+            return Err(error);
+        }
+        self.joypad.restore(state.joypad);
+        self.interrupt = state.interrupt;
+        // A state taken during boot can't finish it without a boot ROM
+        self.boot_rom_mapped = state.boot_rom_mapped && !self.boot_rom.is_empty();
+        self.cgb_mode = state.cgb_mode;
+        self.double_speed = state.double_speed;
+        self.speed_switch_armed = state.speed_switch_armed;
+        self.key0 = state.key0;
+        self.serial = state.serial;
+        self.undocumented = state.undocumented;
+        self.scheduler = state.scheduler;
+        self.frame_in_progress = state.frame_in_progress;
+        self.cart.set_rtc_clock(self.rtc_clock.clone());
+        // Audio from before the load would play out of order after it
+        self.audio_buffer.clear();
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        cart::Cart,
+        system::{AudioFrame, BootRom, FrameBuffer, SCREEN_HEIGHT, SCREEN_WIDTH},
+    };
+
+    use super::*;
+
+    fn run_frames(system: &mut CgbSystem, frames: usize) -> (Box<FrameBuffer>, Vec<AudioFrame>) {
+        let mut frame_buff = Box::new([[[0; 4]; SCREEN_WIDTH]; SCREEN_HEIGHT]);
+        let mut audio = Vec::new();
+        for _ in 0..frames {
+            system.execute(&mut frame_buff, |frame| audio.push(frame));
+        }
+        (frame_buff, audio)
+    }
+
+    #[test]
+    fn round_trip() {
+        let cart = Cart::from_rom(vec![0; 0x8000].into_boxed_slice()).unwrap();
+        let mut system = CgbSystem::new(cart);
+        // Stop partway through the boot ROM's chime, so that the APU's counters are all in use
+        run_frames(&mut system, 70);
+        let state = system.save_state();
+
+        let expected = run_frames(&mut system, 10);
+        system.load_state(&state).unwrap();
+        assert_eq!(run_frames(&mut system, 10), expected);
+    }
+
+    #[test]
+    fn info() {
+        let mut rom = vec![0; 0x8000];
+        rom[0x134..0x139].copy_from_slice(b"TITLE");
+        let mut system = CgbSystem::new(Cart::from_rom(rom.into_boxed_slice()).unwrap());
+        let (mut frame_buff, _) = run_frames(&mut system, 1);
+        frame_buff[0][0] = [0, 0, 0, 0];
+        frame_buff[0][1] = [4, 8, 0, 0];
+        frame_buff[1][0] = [4, 8, 0, 0];
+        frame_buff[1][1] = [4, 8, 0, 0];
+        let state = system.save_state_with_info(1_700_000_000, &frame_buff);
+
+        let info = StateInfo::read(&state).unwrap().unwrap();
+        assert_eq!(info.title, "TITLE");
+        assert_eq!(info.saved_at, 1_700_000_000);
+        assert_eq!(info.thumbnail.len(), THUMBNAIL_WIDTH * THUMBNAIL_HEIGHT * 4);
+        assert_eq!(info.thumbnail[..4], [3, 6, 0, 0]);
+        assert!(StateInfo::read(&system.save_state()).unwrap().is_none());
+
+        // The info doesn't get in the way of loading
+        let expected = run_frames(&mut system, 10);
+        system.load_state(&state).unwrap();
+        assert_eq!(run_frames(&mut system, 10), expected);
+    }
+
+    #[test]
+    fn rejects_bad_states() {
+        let cart = Cart::from_rom(vec![0; 0x8000].into_boxed_slice()).unwrap();
+        let mut system = CgbSystem::new(cart);
+        let mut state = system.save_state();
+
+        assert!(matches!(
+            system.load_state(b"not a state"),
+            Err(StateError::NotAState)
+        ));
+        let mut frame_buff = Box::new([[[0; 4]; SCREEN_WIDTH]; SCREEN_HEIGHT]);
+        system.execute(&mut frame_buff, |_| ());
+        let before = system.save_state();
+        // Cut off partway through the subsystems, after some have been restored
+        assert!(matches!(
+            system.load_state(&state[..state.len() / 2]),
+            Err(StateError::Corrupt(_))
+        ));
+        assert!(system.save_state() == before);
+
+        let mut rom = vec![0; 0x8000];
+        rom[0x14d] = 0x42;
+        let mut other = CgbSystem::new(Cart::from_rom(rom.into_boxed_slice()).unwrap());
+        assert!(matches!(
+            other.load_state(&state),
+            Err(StateError::WrongRom)
+        ));
+
+        let mut dmg = CgbSystem::with_boot_rom(
+            Cart::from_rom(vec![0; 0x8000].into_boxed_slice()).unwrap(),
+            BootRom::skip_as(Model::Dmg),
+        );
+        assert!(matches!(
+            dmg.load_state(&state),
+            Err(StateError::WrongModel)
+        ));
+
+        state[4] += 1;
+        assert!(matches!(
+            system.load_state(&state),
+            Err(StateError::UnsupportedVersion(version)) if version == VERSION + 1
+        ));
+    }
+}
@@ -0,0 +1,316 @@
+// SPDX-License-Identifier: GPL-3.0-or-later
+// Copyright (C) 2023 Robert Hrusecky <jadedpastabowl@gmail.com>
+
+//! What the PPU draws from, decoded for tools that show it, like a tile viewer or a sprite list for
+//! finding out why a game's graphics come out wrong.
+
+use std::array;
+
+use crate::{
+    memory::{OamBytes, Palettes, VRamBytes},
+    ppu::Ppu,
+};
+
+use super::{CgbSystem, Model};
+
+/// At 0x8000-0x97ff, in each bank
+pub const TILES_PER_BANK: usize = 384;
+pub const OBJ_COUNT: usize = 40;
+
+/// A tile's color numbers 0-3, by row
+pub type Tile = [[u8; 8]; 8];
+
+/// A tile in one of the two maps, with the attributes from VRAM bank 1 in CGB mode.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MapEntry {
+    /// Of the 384 in `bank`, as addressed by LCDC
+    pub tile: usize,
+    pub bank: usize,
+    pub palette: usize,
+    pub x_flip: bool,
+    pub y_flip: bool,
+}
+
+/// An object's entry in OAM.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ObjEntry {
+    /// Of the object's top left corner on the screen, which hides it when it's off the edge
+    pub x: i16,
+    pub y: i16,
+    /// The low bit is ignored for 8x16 objects
+    pub tile: u8,
+    pub bank: usize,
+    /// Of the 8 in CGB mode, and otherwise OBP0 or OBP1
+    pub palette: usize,
+    pub x_flip: bool,
+    pub y_flip: bool,
+    /// Whether the background's colors 1-3 are drawn over it
+    pub bg_over_obj: bool,
+}
+
+/// Borrows the PPU's state without affecting it. See [`CgbSystem::debug_video`].
+pub struct DebugVideo<'a> {
+    ppu: &'a Ppu,
+    vram: &'a VRamBytes,
+    bg_palettes: &'a Palettes,
+    obj_palettes: &'a Palettes,
+    oam: &'a OamBytes,
+    model: Model,
+    cgb_mode: bool,
+}
+
+impl DebugVideo<'_> {
+    /// Whether there are 8 palettes of each kind, instead of BGP, OBP0, and OBP1.
+    pub fn cgb_mode(&self) -> bool {
+        self.cgb_mode
+    }
+
+    pub fn tile(&self, bank: usize, index: usize) -> Tile {
+        let data = &self.vram[bank][index * 16..][..16];
+        array::from_fn(|y| {
+            let (low, high) = (data[y * 2], data[y * 2 + 1]);
+            array::from_fn(|x| {
+                let bit = 7 - x;
+                (low >> bit) & 1 | ((high >> bit) & 1) << 1
+            })
+        })
+    }
+
+    /// Which of the maps, at 0x9800 and 0x9c00, the background uses.
+    pub fn bg_map(&self) -> usize {
+        (self.ppu.lcdc() >> 3) as usize & 1
+    }
+
+    pub fn window_map(&self) -> usize {
+        (self.ppu.lcdc() >> 6) as usize & 1
+    }
+
+    pub fn window_enabled(&self) -> bool {
+        self.ppu.lcdc() & 0x20 != 0
+    }
+
+    /// SCX and SCY
+    pub fn scroll(&self) -> (u8, u8) {
+        (self.ppu.scx, self.ppu.scy)
+    }
+
+    /// The window's top left corner on the screen, which can be off of it.
+    pub fn window_position(&self) -> (i16, i16) {
+        (self.ppu.wx as i16 - 7, self.ppu.wy as i16)
+    }
+
+    /// The tile at column `x` and row `y` of `map`, each 0-31.
+    pub fn map_entry(&self, map: usize, x: usize, y: usize) -> MapEntry {
+        let addr = 0x1800 | map << 10 | y << 5 | x;
+        let id = self.vram[0][addr] as usize;
+        let attributes = if self.cgb_mode { self.vram[1][addr] } else { 0 };
+        // With LCDC bit 4 clear, IDs 0-127 are the tiles at 0x9000
+        let tile = if self.ppu.lcdc() & 0x10 != 0 || id >= 0x80 {
+            id
+        } else {
+            id + 0x100
+        };
+        MapEntry {
+            tile,
+            bank: (attributes >> 3) as usize & 1,
+            palette: attributes as usize & 0x7,
+            x_flip: attributes & 0x20 != 0,
+            y_flip: attributes & 0x40 != 0,
+        }
+    }
+
+    pub fn objs_enabled(&self) -> bool {
+        self.ppu.lcdc() & 0x02 != 0
+    }
+
+    /// 8 or 16, for all objects
+    pub fn obj_height(&self) -> usize {
+        if self.ppu.lcdc() & 0x04 != 0 {
+            16
+        } else {
+            8
+        }
+    }
+
+    pub fn obj(&self, index: usize) -> ObjEntry {
+        let [y, x, tile, attributes] = self.oam[index * 4..][..4].try_into().unwrap();
+        let palette = if self.cgb_mode {
+            attributes & 0x7
+        } else {
+            (attributes >> 4) & 1
+        };
+        ObjEntry {
+            x: x as i16 - 8,
+            y: y as i16 - 16,
+            tile,
+            bank: (self.cgb_mode && attributes & 0x08 != 0) as usize,
+            palette: palette as usize,
+            x_flip: attributes & 0x20 != 0,
+            y_flip: attributes & 0x40 != 0,
+            bg_over_obj: attributes & 0x80 != 0,
+        }
+    }
+
+    /// The colors of a background palette, which is always 0 outside of CGB mode.
+    pub fn bg_palette(&self, palette: usize) -> [[u8; 3]; 4] {
+        self.palette(self.bg_palettes, palette, self.ppu.bgp)
+    }
+
+    /// The colors of an object palette, where 0 and 1 are OBP0 and OBP1 outside of CGB mode.
+    pub fn obj_palette(&self, palette: usize) -> [[u8; 3]; 4] {
+        let obp = if palette == 0 {
+            self.ppu.obp0
+        } else {
+            self.ppu.obp1
+        };
+        self.palette(self.obj_palettes, palette, obp)
+    }
+
+    /// Follows the PPU in mapping color numbers through `dmg_palette` outside of CGB mode, and on
+    /// to the colors in `palettes` on a CGB.
+    fn palette(&self, palettes: &Palettes, palette: usize, dmg_palette: u8) -> [[u8; 3]; 4] {
+        array::from_fn(|color| {
+            let color = if self.cgb_mode {
+                color
+            } else {
+                (dmg_palette >> (color * 2)) as usize & 0x3
+            };
+            let color = match self.model {
+                Model::Cgb => u16::from_le_bytes(palettes[palette][color]),
+                Model::Dmg => self.ppu.dmg_palette.shades()[color],
+            };
+            self.ppu.config.to_rgb(color)
+        })
+    }
+}
+
+impl CgbSystem {
+    pub fn debug_video(&self) -> DebugVideo<'_> {
+        DebugVideo {
+            ppu: &self.ppu,
+            vram: self.mem.vram.bytes(),
+            bg_palettes: self.mem.bg_palette.palettes(),
+            obj_palettes: self.mem.obj_palette.palettes(),
+            oam: &self.mem.oam,
+            model: self.model,
+            cgb_mode: self.cgb_mode,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{cart::Cart, cpu::CpuBus, system::BootRom};
+
+    use super::*;
+
+    fn system(cgb: bool) -> CgbSystem {
+        let mut rom = vec![0; 0x8000];
+        if cgb {
+            rom[0x143] = 0x80;
+        }
+        let cart = Cart::from_rom(rom.into_boxed_slice()).unwrap();
+        CgbSystem::with_boot_rom(cart, BootRom::skip())
+    }
+
+    #[test]
+    fn tiles() {
+        let mut system = system(true);
+        let (_, bus) = system.split_cpu();
+        for (addr, val) in [
+            // Tile 1, row 0: colors 0-3 twice over
+            (0x8010, 0b0101_0101),
+            (0x8011, 0b0011_0011),
+            // Tile 0x80 in bank 1
+            (0xff4f, 0x01),
+            (0x8800, 0xff),
+            // Map 1, column 2, row 1: palette 5, bank 1, flipped horizontally
+            (0x9c22, 0b0010_1101),
+            (0xff4f, 0x00),
+            (0x9c22, 0x80),
+            (0x9800, 0x01),
+            // Switch to map 1 and 0x8800 addressing
+            (0xff40, 0x88),
+            (0xff43, 0x12),
+            (0xff4b, 0x03),
+        ] {
+            bus.write_8(addr, val);
+        }
+
+        let video = system.debug_video();
+        assert_eq!(video.tile(0, 1)[0], [0, 1, 2, 3, 0, 1, 2, 3]);
+        assert_eq!(video.tile(1, 0x80)[0], [1; 8]);
+        assert_eq!(video.bg_map(), 1);
+        assert_eq!(video.scroll(), (0x12, 0));
+        assert_eq!(video.window_position(), (-4, 0));
+        assert_eq!(
+            video.map_entry(1, 2, 1),
+            MapEntry {
+                tile: 0x80,
+                bank: 1,
+                palette: 5,
+                x_flip: true,
+                y_flip: false,
+            }
+        );
+        // 0x8800 addressing
+        assert_eq!(video.map_entry(0, 0, 0).tile, 0x101);
+    }
+
+    #[test]
+    fn palettes() {
+        let mut system = system(false);
+        let (_, bus) = system.split_cpu();
+        // Reverse the shades
+        bus.write_8(0xff47, 0b0001_1011);
+        let video = system.debug_video();
+        assert!(!video.cgb_mode());
+        let [darkest, .., lightest] = video.bg_palette(0);
+        assert_eq!(lightest, video.obj_palette(0)[0]);
+        assert_ne!(darkest, lightest);
+    }
+
+    #[test]
+    fn objs() {
+        let mut system = system(true);
+        let (_, bus) = system.split_cpu();
+        // Object 2 at the top left corner of the screen, flipped vertically
+        for (addr, val) in [(0xfe08, 16), (0xfe09, 8), (0xfe0a, 0x42), (0xfe0b, 0x4b)] {
+            bus.write_8(addr, val);
+        }
+        bus.write_8(0xff40, 0x86);
+
+        let video = system.debug_video();
+        assert!(video.objs_enabled());
+        assert_eq!(video.obj_height(), 16);
+        assert_eq!(
+            video.obj(2),
+            ObjEntry {
+                x: 0,
+                y: 0,
+                tile: 0x42,
+                bank: 1,
+                palette: 3,
+                x_flip: false,
+                y_flip: true,
+                bg_over_obj: false,
+            }
+        );
+        assert_eq!((video.obj(0).x, video.obj(0).y), (-8, -16));
+    }
+
+    #[test]
+    fn loads() {
+        let mut system = system(true);
+        // Tile 1 in bank 1, row 0 all color 1
+        system.load_vram(1, 0x10, &[0xff, 0x00]);
+        // Object 0 at the top left corner of the screen
+        system.load_oam(&[16, 8, 0x42, 0x00]);
+
+        let video = system.debug_video();
+        assert_eq!(video.tile(1, 1)[0], [1; 8]);
+        assert_eq!(video.tile(0, 1)[0], [0; 8]);
+        let obj = video.obj(0);
+        assert_eq!((obj.x, obj.y, obj.tile), (0, 0, 0x42));
+    }
+}
@@ -0,0 +1,299 @@
+// SPDX-License-Identifier: GPL-3.0-or-later
+// Copyright (C) 2023 Robert Hrusecky <jadedpastabowl@gmail.com>
+
+//! Runs blargg's and Mooneye's test ROMs, which check themselves and signal the result. blargg's
+//! print `Passed` or `Failed` over the serial port, and Mooneye's load a Fibonacci fingerprint
+//! into the registers when they pass. Requires the `test-roms` feature.
+//!
+//! The ROMs aren't included. Point `IRON_BOY_TEST_ROMS` at a directory of them to run them all as
+//! part of the core's tests:
+//!
+//! ```text
+//! IRON_BOY_TEST_ROMS=path/to/roms cargo test -p iron-boy-core --features test-roms -- --nocapture
+//! ```
+//!
+//! The `rom-tests` tool runs them too, and writes reports for CI.
+
+use std::{
+    fmt::{self, Display, Formatter},
+    fs, io,
+    num::NonZeroUsize,
+    panic::{self, AssertUnwindSafe},
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Mutex,
+    },
+    thread,
+    time::{Duration, Instant},
+};
+
+use crate::{cart::Cart, harness::Harness, system::CgbSystem};
+
+/// Long enough for the slowest of blargg's suites, in frames (two minutes)
+pub const MAX_FRAMES: usize = 2 * 60 * 60;
+/// How long to keep running after blargg's result shows up, to catch the details that follow
+const SETTLE_FRAMES: usize = 30;
+
+/// B, C, D, E, H, and L
+const MOONEYE_PASSED: [u16; 3] = [0x0305, 0x080d, 0x1522];
+const MOONEYE_FAILED: [u16; 3] = [0x4242; 3];
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Outcome {
+    Passed,
+    /// With whatever the ROM printed
+    Failed(String),
+    TimedOut,
+}
+
+impl Display for Outcome {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Passed => write!(f, "Passed"),
+            Self::Failed(details) if details.is_empty() => write!(f, "Failed"),
+            Self::Failed(details) => write!(f, "Failed: {details}"),
+            Self::TimedOut => write!(f, "Timed out"),
+        }
+    }
+}
+
+/// How a ROM went, and how long it took to find out.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TestResult {
+    pub path: PathBuf,
+    pub outcome: Outcome,
+    pub duration: Duration,
+}
+
+fn check(system: &mut CgbSystem, serial: &mut Vec<u8>) -> Option<Outcome> {
+    let regs = system.registers();
+    match [regs.bc, regs.de, regs.hl] {
+        MOONEYE_PASSED => return Some(Outcome::Passed),
+        MOONEYE_FAILED => return Some(Outcome::Failed(String::new())),
+        _ => (),
+    }
+    system.take_serial(serial);
+    let text = String::from_utf8_lossy(serial);
+    if text.contains("Passed") {
+        Some(Outcome::Passed)
+    } else if text.contains("Failed") {
+        Some(Outcome::Failed(text.trim().to_owned()))
+    } else {
+        None
+    }
+}
+
+/// Runs a system with a test ROM for up to `max_frames`, until it signals its result.
+pub fn run(system: CgbSystem, max_frames: usize) -> Outcome {
+    let mut harness = Harness::with_system(system);
+    harness.system_mut().set_capture_serial(true);
+    let mut serial = Vec::new();
+    let mut outcome = None;
+    harness.run_until(max_frames, |system| {
+        outcome = check(system, &mut serial);
+        outcome.is_some()
+    });
+    if outcome.is_some() {
+        harness.run_frames(SETTLE_FRAMES);
+        outcome = check(harness.system_mut(), &mut serial);
+    }
+    outcome.unwrap_or(Outcome::TimedOut)
+}
+
+/// Runs `run`, failing instead if the core panics along the way, like on an illegal instruction.
+/// Otherwise one ROM would take the rest of the run down with it, before any report is written.
+fn catch_panics(run: impl FnOnce() -> Outcome) -> Outcome {
+    panic::catch_unwind(AssertUnwindSafe(run)).unwrap_or_else(|payload| {
+        let message = payload
+            .downcast_ref::<&str>()
+            .copied()
+            .or_else(|| payload.downcast_ref::<String>().map(String::as_str))
+            .unwrap_or("no message");
+        Outcome::Failed(format!("panicked: {message}"))
+    })
+}
+
+fn find_roms(dir: &Path, roms: &mut Vec<PathBuf>) -> io::Result<()> {
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        if path.is_dir() {
+            find_roms(&path, roms)?;
+        } else if path
+            .extension()
+            .is_some_and(|ext| ext == "gb" || ext == "gbc")
+        {
+            roms.push(path);
+        }
+    }
+    Ok(())
+}
+
+fn run_rom(path: &Path, max_frames: usize) -> io::Result<TestResult> {
+    let start = Instant::now();
+    let rom = fs::read(path)?;
+    let outcome = catch_panics(|| match Cart::from_rom(rom.into_boxed_slice()) {
+        Ok(cart) => run(CgbSystem::new(cart), max_frames),
+        Err(error) => Outcome::Failed(error.to_string()),
+    });
+    Ok(TestResult {
+        path: path.to_owned(),
+        outcome,
+        duration: start.elapsed(),
+    })
+}
+
+/// One for each of the machine's threads.
+pub fn default_jobs() -> usize {
+    thread::available_parallelism().map_or(1, NonZeroUsize::get)
+}
+
+/// Runs every ROM in `dir` and its subdirectories on up to `jobs` threads, and returns their
+/// results in order of their paths.
+pub fn run_dir(dir: &Path, max_frames: usize, jobs: usize) -> io::Result<Vec<TestResult>> {
+    let mut roms = Vec::new();
+    find_roms(dir, &mut roms)?;
+    roms.sort();
+    // Some ROMs take far longer than others, so rather than splitting them up front, each thread
+    // takes the next one whenever it's done with its last
+    let next = AtomicUsize::new(0);
+    let results = Mutex::new(Vec::with_capacity(roms.len()));
+    thread::scope(|scope| {
+        for _ in 0..jobs.clamp(1, roms.len().max(1)) {
+            scope.spawn(|| {
+                while let Some(path) = roms.get(next.fetch_add(1, Ordering::Relaxed)) {
+                    let result = run_rom(path, max_frames);
+                    results.lock().unwrap().push(result);
+                }
+            });
+        }
+    });
+    let mut results = results
+        .into_inner()
+        .unwrap()
+        .into_iter()
+        .collect::<io::Result<Vec<_>>>()?;
+    results.sort_by(|a, b| a.path.cmp(&b.path));
+    Ok(results)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::env;
+
+    use crate::system::BootRom;
+
+    use super::*;
+
+    /// A system running `program` at 0x150, with `data` at 0x200.
+    fn system(program: &[u8], data: &[u8]) -> CgbSystem {
+        let mut rom = vec![0; 0x8000];
+        // nop; jp 0x150
+        rom[0x100..0x104].copy_from_slice(&[0x00, 0xc3, 0x50, 0x01]);
+        rom[0x150..0x150 + program.len()].copy_from_slice(program);
+        rom[0x200..0x200 + data.len()].copy_from_slice(data);
+        let cart = Cart::from_rom(rom.into_boxed_slice()).unwrap();
+        CgbSystem::with_boot_rom(cart, BootRom::skip())
+    }
+
+    #[rustfmt::skip]
+    const PRINT: &[u8] = &[
+        0x21, 0x00, 0x02, // ld hl, 0x200
+        0x2a,             // .loop: ld a, (hl+)
+        0xa7,             // and a
+        0x28, 0x08,       // jr z, .done
+        0xe0, 0x01,       // ldh (SB), a
+        0x3e, 0x81,       // ld a, 0x81
+        0xe0, 0x02,       // ldh (SC), a
+        0x18, 0xf4,       // jr .loop
+        0x18, 0xfe,       // .done: jr .done
+    ];
+
+    #[test]
+    fn serial() {
+        assert_eq!(run(system(PRINT, b"Passed\0"), 600), Outcome::Passed);
+        assert_eq!(
+            run(system(PRINT, b"Failed #3\n\0"), 600),
+            Outcome::Failed("Failed #3".into())
+        );
+    }
+
+    /// Nothing would clock a transfer on the external clock, so it never finishes.
+    #[test]
+    fn external_clock() {
+        let mut print = PRINT.to_vec();
+        // ld a, 0x80
+        print[10] = 0x80;
+        assert_eq!(run(system(&print, b"Passed\0"), 60), Outcome::TimedOut);
+    }
+
+    #[test]
+    fn fingerprint() {
+        #[rustfmt::skip]
+        let program = [
+            0x01, 0x05, 0x03, // ld bc, 0x0305
+            0x11, 0x0d, 0x08, // ld de, 0x080d
+            0x21, 0x22, 0x15, // ld hl, 0x1522
+            0x40,             // ld b, b
+            0x18, 0xfe,       // jr -2
+        ];
+        assert_eq!(run(system(&program, &[]), 600), Outcome::Passed);
+    }
+
+    #[test]
+    fn timeout() {
+        assert_eq!(run(system(&[0x18, 0xfe], &[]), 10), Outcome::TimedOut);
+    }
+
+    #[test]
+    fn panic() {
+        // An illegal instruction
+        assert_eq!(
+            catch_panics(|| run(system(&[0xd3], &[]), 60)),
+            Outcome::Failed("panicked: Tried to execute illegal instruction".into())
+        );
+    }
+
+    #[test]
+    fn parallel() {
+        let dir = env::temp_dir().join(format!("iron-boy-test-roms-{}", std::process::id()));
+        fs::create_dir_all(dir.join("nested")).unwrap();
+        for (name, program) in [
+            ("a.gb", &[0x18, 0xfe][..]),
+            ("nested/b.gbc", &[0x18, 0xfe][..]),
+            ("c.txt", &[][..]),
+        ] {
+            let mut rom = vec![0; 0x8000];
+            rom[0x100..0x104].copy_from_slice(&[0x00, 0xc3, 0x50, 0x01]);
+            rom[0x150..0x150 + program.len()].copy_from_slice(program);
+            fs::write(dir.join(name), rom).unwrap();
+        }
+        let results = run_dir(&dir, 10, 4);
+        fs::remove_dir_all(&dir).unwrap();
+        let results = results.unwrap();
+        let paths: Vec<_> = results.iter().map(|result| &result.path).collect();
+        assert_eq!(paths, [&dir.join("a.gb"), &dir.join("nested/b.gbc")]);
+        assert!(results
+            .iter()
+            .all(|result| result.outcome == Outcome::TimedOut));
+    }
+
+    /// Runs the ROMs in `IRON_BOY_TEST_ROMS`, if it's set.
+    #[test]
+    fn suites() {
+        let Some(dir) = env::var_os("IRON_BOY_TEST_ROMS") else {
+            eprintln!("IRON_BOY_TEST_ROMS isn't set, skipping the test ROMs");
+            return;
+        };
+        let results = run_dir(dir.as_ref(), MAX_FRAMES, default_jobs()).unwrap();
+        for result in &results {
+            println!("{}: {}", result.path.display(), result.outcome);
+        }
+        let failures = results
+            .iter()
+            .filter(|result| result.outcome != Outcome::Passed)
+            .count();
+        println!("{} of {} passed", results.len() - failures, results.len());
+        assert_eq!(failures, 0);
+    }
+}
@@ -0,0 +1,85 @@
+// SPDX-License-Identifier: GPL-3.0-or-later
+// Copyright (C) 2023 Robert Hrusecky <jadedpastabowl@gmail.com>
+
+//! Mock buses for clocking the [`Apu`] or [`Ppu`] on their own, without the rest of the system,
+//! e.g. from fuzzers and microbenchmarks. Requires the `test-support` feature.
+
+use crate::system::Model;
+pub use crate::{
+    apu::{Apu, ApuBus},
+    memory::{OamBytes, Palettes, VRamBytes},
+    ppu::{Ppu, PpuBus},
+};
+
+/// An [`ApuBus`] where the caller controls DIV, which clocks the APU's frame sequencer.
+#[derive(Debug, Default)]
+pub struct MockApuBus {
+    pub div: u8,
+}
+
+impl ApuBus for MockApuBus {
+    fn div(&self) -> u8 {
+        self.div
+    }
+}
+
+/// A [`PpuBus`] backed by plain memory, which counts the interrupts it is asked for.
+pub struct MockPpuBus {
+    pub vram: VRamBytes,
+    pub bg_palette_ram: Palettes,
+    pub obj_palette_ram: Palettes,
+    pub oam: OamBytes,
+    pub cgb_mode: bool,
+    pub model: Model,
+    pub vblank_interrupts: usize,
+    pub stat_interrupts: usize,
+}
+
+impl MockPpuBus {
+    pub fn new() -> Self {
+        Self {
+            vram: [[0; 0x2000]; 2],
+            bg_palette_ram: Default::default(),
+            obj_palette_ram: Default::default(),
+            oam: [0; 0xa0],
+            cgb_mode: true,
+            model: Model::Cgb,
+            vblank_interrupts: 0,
+            stat_interrupts: 0,
+        }
+    }
+}
+
+impl PpuBus for MockPpuBus {
+    fn request_vblank_interrupt(&mut self) {
+        self.vblank_interrupts += 1;
+    }
+
+    fn request_stat_interrupt(&mut self) {
+        self.stat_interrupts += 1;
+    }
+
+    fn vram(&self) -> &VRamBytes {
+        &self.vram
+    }
+
+    fn bg_palette_ram(&self) -> &Palettes {
+        &self.bg_palette_ram
+    }
+
+    fn obj_palette_ram(&self) -> &Palettes {
+        &self.obj_palette_ram
+    }
+
+    fn oam(&self) -> &OamBytes {
+        &self.oam
+    }
+
+    fn cgb_mode(&self) -> bool {
+        self.cgb_mode
+    }
+
+    fn model(&self) -> Model {
+        self.model
+    }
+}
@@ -2,15 +2,31 @@
 // Copyright (C) 2023 Robert Hrusecky <jadedpastabowl@gmail.com>
 use std::num::Wrapping;
 
+use serde::{Deserialize, Serialize};
+
 pub trait TimerBus {
     fn request_timer_interrupt(&mut self);
 }
 
+/// Where TIMA is in reloading from TMA after it overflows.
+#[derive(Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+enum Overflow {
+    None,
+    /// TIMA overflowed at the end of the last cycle, and reads 0 for this one. Writing it cancels
+    /// the reload and the interrupt.
+    Pending,
+    /// TIMA was reloaded and the interrupt requested at the end of the last cycle. For this one,
+    /// writes to TIMA are ignored, and writes to TMA go to TIMA too.
+    Reloaded,
+}
+
+#[derive(Serialize, Deserialize)]
 pub struct Timer {
     counter: Wrapping<u16>,
     tima: Wrapping<u8>,
     tma: u8,
     tac: u8,
+    overflow: Overflow,
 }
 
 const ENABLE: u8 = 0x4;
@@ -22,43 +38,76 @@ impl Timer {
             tima: Wrapping(0),
             tma: 0,
             tac: 0,
+            overflow: Overflow::None,
         }
     }
 
-    pub fn execute(&mut self, bus: &mut impl TimerBus) {
-        let old_counter = self.counter;
-        // In real hardware, this counter increments once per T-cycle, but we only call this once
-        // per M-cycle.
-        self.counter += 4;
-
-        if self.tac & ENABLE == 0 {
-            // Timer is disabled
-            return;
-        }
-
+    /// What TIMA counts the falling edges of: the counter bit selected in TAC, while the timer is
+    /// enabled. Anything that makes it fall counts, including resetting DIV and writing TAC.
+    fn signal(&self) -> bool {
         // Increase TIMA at the TAC-configured frequency
         // 00 -> clock / 2^10
         // 01 -> clock / 2^4
         // 10 -> clock / 2^6
-        // 10 -> clock / 2^8
-        let turned_off = old_counter.0 & !self.counter.0;
-        let freq = self.tac.wrapping_sub(1) & 0x3;
-        if turned_off >> (2 * freq + 3) != 0 {
-            self.tima += 1;
-            if self.tima.0 == 0 {
-                // overflow
+        // 11 -> clock / 2^8
+        let bit = [9, 3, 5, 7][(self.tac & 0x3) as usize];
+        self.tac & ENABLE != 0 && self.counter.0 >> bit & 1 != 0
+    }
+
+    fn increment(&mut self) {
+        self.tima += 1;
+        if self.tima.0 == 0 {
+            self.overflow = Overflow::Pending;
+        }
+    }
+
+    pub fn execute(&mut self, bus: &mut impl TimerBus) {
+        match self.overflow {
+            Overflow::None => (),
+            Overflow::Pending => {
                 self.tima.0 = self.tma;
                 bus.request_timer_interrupt();
+                self.overflow = Overflow::Reloaded;
             }
+            Overflow::Reloaded => self.overflow = Overflow::None,
+        }
+
+        let old_signal = self.signal();
+        // In real hardware, this counter increments once per T-cycle, but we only call this once
+        // per M-cycle.
+        self.counter += 4;
+        if old_signal && !self.signal() {
+            self.increment();
         }
     }
 
+    /// The number of machine cycles until TIMA overflows and requests an interrupt, which comes a
+    /// cycle after the overflow itself, if the timer is enabled.
+    pub fn cycles_until_overflow(&self) -> Option<usize> {
+        if self.overflow == Overflow::Pending {
+            return Some(1);
+        }
+        if self.tac & ENABLE == 0 {
+            return None;
+        }
+        // TIMA increments whenever this many counter ticks roll over
+        let freq = self.tac.wrapping_sub(1) & 0x3;
+        let period = 1usize << (2 * freq + 4);
+        let first = period - self.counter.0 as usize % period;
+        let increments = 0xff - self.tima.0 as usize;
+        Some((first + increments * period) / 4 + 1)
+    }
+
     pub fn div(&self) -> u8 {
         (self.counter.0 >> 8) as u8
     }
 
     pub fn reset_div(&mut self) {
+        let old_signal = self.signal();
         self.counter.0 = 0;
+        if old_signal {
+            self.increment();
+        }
     }
 
     pub fn tima(&self) -> u8 {
@@ -66,6 +115,11 @@ impl Timer {
     }
 
     pub fn set_tima(&mut self, tima: u8) {
+        match self.overflow {
+            Overflow::Reloaded => return,
+            Overflow::Pending => self.overflow = Overflow::None,
+            Overflow::None => (),
+        }
         self.tima.0 = tima;
     }
 
@@ -75,6 +129,9 @@ impl Timer {
 
     pub fn set_tma(&mut self, tma: u8) {
         self.tma = tma;
+        if self.overflow == Overflow::Reloaded {
+            self.tima.0 = tma;
+        }
     }
 
     pub fn tac(&self) -> u8 {
@@ -82,12 +139,18 @@ impl Timer {
     }
 
     pub fn set_tac(&mut self, tac: u8) {
+        let old_signal = self.signal();
         self.tac = tac;
+        if old_signal && !self.signal() {
+            self.increment();
+        }
     }
 }
 
 #[cfg(test)]
 mod tests {
+    use std::cell::Cell;
+
     use super::*;
 
     struct InterruptModerator<F> {
@@ -112,13 +175,14 @@ mod tests {
         timer.set_tac(tac | ENABLE);
 
         let mut requests = 0;
-        for i in 0..10 * period {
+        for i in 0..10 * period + 1 {
             let mut bus = InterruptModerator {
                 func: || {
                     requests += 1;
-                    // Falling edge, so we increment at the end of the cycle
+                    // Falling edge, so we increment at the end of the cycle, and the interrupt
+                    // comes at the end of the next
                     assert!(
-                        (i + 1) % period == 0,
+                        i > 0 && i % period == 0,
                         "Requested interrupt when not expected"
                     );
                 },
@@ -128,6 +192,95 @@ mod tests {
         assert_eq!(requests, 10, "Did not request correct amount of interrupts");
     }
 
+    #[test]
+    fn cycles_until_overflow() {
+        let mut timer = Timer::new();
+        assert_eq!(timer.cycles_until_overflow(), None);
+        timer.set_tac(0b01 | ENABLE);
+        timer.set_tima(0xfe);
+        timer.set_tma(0x80);
+        let expected = timer.cycles_until_overflow().unwrap();
+
+        let overflowed = Cell::new(false);
+        let mut bus = InterruptModerator {
+            func: || overflowed.set(true),
+        };
+        for _ in 0..expected - 1 {
+            timer.execute(&mut bus);
+        }
+        assert!(!overflowed.get(), "Overflowed early");
+        assert_eq!(timer.tima(), 0, "Did not overflow on time");
+        assert_eq!(timer.cycles_until_overflow(), Some(1));
+        timer.execute(&mut bus);
+        assert!(overflowed.get(), "Did not request an interrupt on time");
+        assert_eq!(timer.tima(), 0x80);
+    }
+
+    /// Runs `timer` until TIMA overflows, and returns whether it requested an interrupt by then.
+    fn run_to_overflow(timer: &mut Timer) -> bool {
+        let mut requested = false;
+        let mut bus = InterruptModerator {
+            func: || requested = true,
+        };
+        while timer.overflow != Overflow::Pending {
+            timer.execute(&mut bus);
+        }
+        requested
+    }
+
+    fn overflowing_timer() -> Timer {
+        let mut timer = Timer::new();
+        timer.set_tac(0b01 | ENABLE);
+        timer.set_tma(0x80);
+        timer.set_tima(0xff);
+        assert!(!run_to_overflow(&mut timer));
+        timer
+    }
+
+    #[test]
+    fn tima_write_cancels_reload() {
+        let mut timer = overflowing_timer();
+        timer.set_tima(0x10);
+        let mut requested = false;
+        timer.execute(&mut InterruptModerator {
+            func: || requested = true,
+        });
+        assert!(!requested);
+        assert_eq!(timer.tima(), 0x10);
+    }
+
+    #[test]
+    fn writes_while_reloading() {
+        let mut timer = overflowing_timer();
+        timer.execute(&mut InterruptModerator { func: || () });
+        assert_eq!(timer.tima(), 0x80);
+        timer.set_tima(0x10);
+        assert_eq!(timer.tima(), 0x80, "TIMA write wasn't ignored");
+        timer.set_tma(0x20);
+        assert_eq!(timer.tima(), 0x20, "TMA write didn't reach TIMA");
+
+        timer.execute(&mut InterruptModerator { func: || () });
+        timer.set_tma(0x30);
+        timer.set_tima(0x40);
+        assert_eq!(timer.tima(), 0x40);
+    }
+
+    #[test]
+    fn falling_edges_from_writes() {
+        let mut timer = Timer::new();
+        timer.set_tac(0b01 | ENABLE);
+        // Two cycles in, the selected bit is set
+        timer.execute(&mut InterruptModerator { func: || () });
+        timer.execute(&mut InterruptModerator { func: || () });
+        timer.reset_div();
+        assert_eq!(timer.tima(), 1);
+
+        timer.execute(&mut InterruptModerator { func: || () });
+        timer.execute(&mut InterruptModerator { func: || () });
+        timer.set_tac(0b01);
+        assert_eq!(timer.tima(), 2);
+    }
+
     #[test]
     fn tma_ff_00() {
         tma_ff(0b00, 1 << 10);
@@ -98,6 +98,12 @@ impl FileHandle {
     }
 }
 
+impl From<File> for FileHandle {
+    fn from(file: File) -> Self {
+        Self::new(file)
+    }
+}
+
 static STYLESHEET_INJECTED: AtomicBool = AtomicBool::new(false);
 
 pub struct FileDialog {
@@ -0,0 +1,150 @@
+// SPDX-License-Identifier: GPL-3.0-or-later
+// Copyright (C) 2023 Robert Hrusecky <jadedpastabowl@gmail.com>
+
+//! Dumps the first frames of a ROM so that two builds of the emulator can be compared, then
+//! renders side-by-side diffs of the dumps for review.
+
+use std::{
+    fs::{self, File},
+    io::BufWriter,
+    path::{Path, PathBuf},
+    process::ExitCode,
+};
+
+use anyhow::{bail, Context, Result};
+use clap::{Parser, Subcommand};
+use iron_boy_core::{
+    cart::Cart,
+    system::{CgbSystem, FrameBuffer, SCREEN_HEIGHT, SCREEN_WIDTH},
+};
+
+#[derive(Parser)]
+#[command(author, version, about, long_about = None)]
+struct Options {
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Run a ROM headlessly and write each frame to a PNG
+    Dump {
+        rom: PathBuf,
+        /// Directory to write frames to
+        out: PathBuf,
+        /// Number of frames to run
+        #[arg(short, long, default_value_t = 600)]
+        frames: usize,
+    },
+    /// Compare two dumps, writing an "A | B | diff" image for every frame that differs
+    Compare {
+        a: PathBuf,
+        b: PathBuf,
+        out: PathBuf,
+    },
+}
+
+type Image = Vec<[u8; 4]>;
+
+fn frame_name(frame: usize) -> String {
+    format!("frame_{frame:05}.png")
+}
+
+fn write_png(path: &Path, width: usize, pixels: &[[u8; 4]]) -> Result<()> {
+    let file = File::create(path).with_context(|| format!("Failed to create {path:?}"))?;
+    let height = pixels.len() / width;
+    let mut encoder = png::Encoder::new(BufWriter::new(file), width as u32, height as u32);
+    encoder.set_color(png::ColorType::Rgba);
+    encoder.set_depth(png::BitDepth::Eight);
+    let mut writer = encoder.write_header()?;
+    writer.write_image_data(pixels.concat().as_slice())?;
+    Ok(())
+}
+
+fn read_png(path: &Path) -> Result<Image> {
+    let file = File::open(path).with_context(|| format!("Failed to open {path:?}"))?;
+    let mut reader = png::Decoder::new(file).read_info()?;
+    let mut buf = vec![0; reader.output_buffer_size()];
+    let info = reader.next_frame(&mut buf)?;
+    if (info.width as usize, info.height as usize) != (SCREEN_WIDTH, SCREEN_HEIGHT)
+        || info.color_type != png::ColorType::Rgba
+    {
+        bail!("{path:?} is not a frame dump");
+    }
+    buf.truncate(info.buffer_size());
+    Ok(buf.chunks_exact(4).map(|p| p.try_into().unwrap()).collect())
+}
+
+fn dump(rom: &Path, out: &Path, frames: usize) -> Result<()> {
+    let rom = fs::read(rom).with_context(|| format!("Failed to read {rom:?}"))?;
+    let cart = Cart::from_rom(rom.into_boxed_slice()).context("Failed to parse ROM")?;
+    let mut system = Box::new(CgbSystem::new(cart));
+    let mut frame_buff: Box<FrameBuffer> = Box::new([[[0; 4]; SCREEN_WIDTH]; SCREEN_HEIGHT]);
+
+    fs::create_dir_all(out)?;
+    for frame in 0..frames {
+        system.execute(&mut frame_buff, |_| ());
+        write_png(
+            &out.join(frame_name(frame)),
+            SCREEN_WIDTH,
+            frame_buff.as_flattened(),
+        )?;
+    }
+    Ok(())
+}
+
+fn diff_image(a: &Image, b: &Image) -> Image {
+    let mut image = Vec::with_capacity(a.len() * 3);
+    for (row_a, row_b) in a.chunks(SCREEN_WIDTH).zip(b.chunks(SCREEN_WIDTH)) {
+        image.extend_from_slice(row_a);
+        image.extend_from_slice(row_b);
+        image.extend(row_a.iter().zip(row_b).map(|(pa, pb)| {
+            if pa == pb {
+                // Dim matching pixels so the differences stand out
+                let [r, g, b, _] = pa.map(|c| c / 4);
+                [r, g, b, 0xff]
+            } else {
+                [0xff, 0x00, 0xff, 0xff]
+            }
+        }));
+    }
+    image
+}
+
+/// Returns the number of frames that differ.
+fn compare(a: &Path, b: &Path, out: &Path) -> Result<usize> {
+    fs::create_dir_all(out)?;
+    let mut frame = 0;
+    let mut differences = 0;
+    loop {
+        let name = frame_name(frame);
+        let (path_a, path_b) = (a.join(&name), b.join(&name));
+        if !path_a.exists() || !path_b.exists() {
+            break;
+        }
+        let (image_a, image_b) = (read_png(&path_a)?, read_png(&path_b)?);
+        if image_a != image_b {
+            differences += 1;
+            write_png(
+                &out.join(&name),
+                SCREEN_WIDTH * 3,
+                &diff_image(&image_a, &image_b),
+            )?;
+        }
+        frame += 1;
+    }
+    println!("{differences} of {frame} frames differ");
+    Ok(differences)
+}
+
+fn main() -> Result<ExitCode> {
+    match Options::parse().command {
+        Command::Dump { rom, out, frames } => dump(&rom, &out, frames)?,
+        Command::Compare { a, b, out } => {
+            if compare(&a, &b, &out)? > 0 {
+                return Ok(ExitCode::FAILURE);
+            }
+        }
+    }
+    Ok(ExitCode::SUCCESS)
+}
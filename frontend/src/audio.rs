@@ -1,6 +1,13 @@
 // SPDX-License-Identifier: GPL-3.0-or-later
 // Copyright (C) 2023 Robert Hrusecky <jadedpastabowl@gmail.com>
-use std::{f32, sync::Arc};
+use std::{
+    f32,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    },
+    time::Duration,
+};
 
 use anyhow::{anyhow, Result};
 use cpal::{
@@ -15,24 +22,58 @@ use dasp::{
 };
 
 use crossbeam_queue::ArrayQueue;
-use iron_boy_core::system::MachineCycle;
+use instant::Instant;
+use iron_boy_core::system::{AudioFrame, MachineCycle, AUDIO_FRAMES_PER_CYCLE};
+use log::error;
 
 const CHANNELS: u16 = 2;
 const ALPHA: f64 = 0.0001;
 const BEND_CENTS: f64 = 3.0;
 // const BUFFER_SIZE: u32 = 256;
 const BUFFER_SIZE: u32 = 512;
-const SAMPLES_PER_M_CYCLE: usize = 2;
-const FREQ: usize = MachineCycle::FREQ * SAMPLES_PER_M_CYCLE;
-const SAMPLES_PER_FRAME: usize = MachineCycle::PER_FRAME * SAMPLES_PER_M_CYCLE;
+const FREQ: usize = MachineCycle::FREQ * AUDIO_FRAMES_PER_CYCLE;
+const SAMPLES_PER_FRAME: usize = MachineCycle::PER_FRAME * AUDIO_FRAMES_PER_CYCLE;
 const NAT_CUT_OFF_FREQ: f32 = 2.0 * f32::consts::PI * 4000.0;
+/// How long to fade over a jump in the emulator's output, in source frames (about 2 ms)
+const FADE_FRAMES: usize = FREQ / 500;
+/// How long to ramp up from silence when a game starts, in source frames (about 10 ms)
+const RAMP_FRAMES: usize = FREQ / 100;
+/// How far to raise the target queue length after running dry, in output frames
+const GROW_FRAMES: f64 = BUFFER_SIZE as f64 / 2.0;
+/// How far to lower it after a stretch without running dry, or right away if the queue overflows
+const SHRINK_FRAMES: f64 = BUFFER_SIZE as f64 / 4.0;
+/// How many emulated frames the queue has to stay fed for before lowering the target (5 s)
+const STEADY_FRAMES: usize = 300;
+/// A gap between emulated frames longer than this means the emulator was stopped, like while
+/// paused or rewinding, rather than that it couldn't keep up. Running dry then says nothing about
+/// the latency needed.
+const STOPPED: Duration = Duration::from_millis(250);
+
+type Frame = AudioFrame;
 
-type Frame = [f32; 2];
+/// Crossfades from a frame into the emulator's output
+struct Fade {
+    from: Frame,
+    len: usize,
+    remaining: usize,
+}
+
+impl Fade {
+    fn new(from: Frame, len: usize) -> Self {
+        Self {
+            from,
+            len,
+            remaining: len,
+        }
+    }
+}
 
 fn new_stream<T>(
     device: &Device,
     config: &StreamConfig,
     queue: &Arc<ArrayQueue<Frame>>,
+    played: &Arc<AtomicUsize>,
+    underruns: &Arc<AtomicUsize>,
 ) -> Result<Stream>
 where
     T: SizedSample + FromSample<f32>,
@@ -41,14 +82,27 @@ where
     let mut low_pass = Frame::EQUILIBRIUM;
     let low_pass_alpha = 1.0 / (sample_rate / NAT_CUT_OFF_FREQ + 1.0);
 
-    let err_fn = |err| eprintln!("an error occurred on audio stream: {}", err);
+    let err_fn =
+        |err| error!(target: "iron_boy::audio", "an error occurred on audio stream: {err}");
     let queue = Arc::clone(queue);
+    let played = Arc::clone(played);
+    let underruns = Arc::clone(underruns);
     let stream = device.build_output_stream(
         config,
         move |output: &mut [T], _| {
             // println!("buf: {}", output.len() / 2);
+            let mut ran_dry = false;
             for frame in output.chunks_mut(CHANNELS as usize) {
-                let value = queue.pop().unwrap_or(DaspFrame::EQUILIBRIUM);
+                let value = match queue.pop() {
+                    Some(value) => {
+                        played.fetch_add(1, Ordering::Relaxed);
+                        value
+                    }
+                    None => {
+                        ran_dry = true;
+                        DaspFrame::EQUILIBRIUM
+                    }
+                };
                 for ((output, input), low_pass) in
                     frame.iter_mut().zip(value).zip(low_pass.iter_mut())
                 {
@@ -57,6 +111,9 @@ where
                     *output = low_pass.to_sample();
                 }
             }
+            if ran_dry {
+                underruns.fetch_add(1, Ordering::Relaxed);
+            }
         },
         err_fn,
         None,
@@ -89,26 +146,51 @@ impl<I> Resampler<I>
 where
     I: Interpolator,
 {
-    fn push_frame(&mut self, source: I::Frame, sink: &Arc<ArrayQueue<I::Frame>>) {
+    /// Returns whether `sink` was too full to take all of the output.
+    fn push_frame(&mut self, source: I::Frame, sink: &Arc<ArrayQueue<I::Frame>>) -> bool {
         self.interpolator.next_source_frame(source);
         self.progress += self.ratio;
 
+        let mut overflowed = false;
         while self.progress >= 1.0 {
             self.progress -= 1.0;
             let x = 1.0 - self.progress / self.ratio;
-            let _ = sink.push(self.interpolator.interpolate(x));
+            overflowed |= sink.push(self.interpolator.interpolate(x)).is_err();
         }
+        overflowed
     }
 }
 
 pub struct Audio {
     stream: Stream,
     queue: Arc<ArrayQueue<Frame>>,
+    /// How many frames from the queue have been handed to the output device
+    played: Arc<AtomicUsize>,
+    /// How many times the output device has found the queue empty
+    underruns: Arc<AtomicUsize>,
     resampler: Resampler<Linear<Frame>>,
+    /// The resampling ratio at full speed, which the bounds below bend around
+    base_ratio: f64,
     min_ratio: f64,
     max_ratio: f64,
     average_len: f64,
+    sample_rate: f64,
+    /// The queue length the resampler steers towards, in output frames. It starts out at half the
+    /// queue and adapts to how well the machine keeps up, within `target_range`.
+    target_len: f64,
+    target_range: (f64, f64),
+    /// The count of underruns as of the last update
+    seen_underruns: usize,
+    overflowed: bool,
+    /// How many emulated frames have gone by since the target last had to change
+    steady_frames: usize,
+    last_update: Option<Instant>,
     push_count: usize,
+    last_frame: Frame,
+    fade: Option<Fade>,
+    /// How much of the difference between the left and right channels is kept, from 0 for mono
+    /// to 1 for the game's own hard panning
+    stereo_width: f32,
 }
 
 impl Audio {
@@ -116,9 +198,67 @@ impl Audio {
         self.stream.play()
     }
 
+    /// The number of frames the output device has taken so far, not counting silence from
+    /// running out.
+    pub fn frames_played(&self) -> usize {
+        self.played.load(Ordering::Relaxed)
+    }
+
+    /// How many emulated frames have been pushed since the emulator's last frame started.
+    pub fn frames_pushed(&self) -> usize {
+        self.push_count
+    }
+
+    /// How many output frames are waiting to be played.
+    pub fn queue_len(&self) -> usize {
+        self.queue.len()
+    }
+
+    /// How long a sample takes from the emulator to the speakers, on average.
+    pub fn latency(&self) -> Duration {
+        Duration::from_secs_f64((self.average_len + BUFFER_SIZE as f64) / self.sample_rate)
+    }
+
+    /// Raises the target queue length when the output device runs out of samples, and lowers it
+    /// when the queue overflows or after a while without any trouble.
+    fn tune_latency(&mut self) {
+        let now = Instant::now();
+        let running = self
+            .last_update
+            .is_some_and(|last| now.duration_since(last) < STOPPED);
+        self.last_update = Some(now);
+        let underruns = self.underruns.load(Ordering::Relaxed);
+        let ran_dry = underruns != self.seen_underruns;
+        self.seen_underruns = underruns;
+        let overflowed = std::mem::take(&mut self.overflowed);
+        if !running {
+            return;
+        }
+
+        let (min, max) = self.target_range;
+        self.steady_frames += 1;
+        let target = if ran_dry {
+            self.target_len + GROW_FRAMES
+        } else if overflowed || self.steady_frames >= STEADY_FRAMES {
+            self.target_len - SHRINK_FRAMES
+        } else {
+            return;
+        };
+        let target = target.clamp(min, max);
+        if target != self.target_len {
+            log::info!(
+                "Audio queue target {:.0} -> {target:.0} frames",
+                self.target_len
+            );
+            self.target_len = target;
+        }
+        self.steady_frames = 0;
+    }
+
     pub fn update_ratio(&mut self) {
         // println!("push_count: {}/{}", self.push_count, MachineCycle::PER_FRAME);
         self.push_count = 0;
+        self.tune_latency();
         let len = self.queue.len();
         if len > 0 {
             // Low-pass filter on the queue length
@@ -132,15 +272,54 @@ impl Audio {
             log::warn!("hack: {}, {}", self.average_len, self.queue.len());
         }
 
-        let ratio =
-            (self.queue.capacity() as f64 / 2.0 - self.average_len) / (SAMPLES_PER_FRAME as f64);
+        let ratio = (self.target_len - self.average_len) / (SAMPLES_PER_FRAME as f64);
         self.resampler.ratio = ratio.clamp(self.min_ratio, self.max_ratio);
         // println!("ratio: {}", self.resampler.ratio);
     }
 
-    pub fn push_frame(&mut self, frame: Frame) {
+    /// Fades from the last frame into whatever comes next, to avoid a pop when the emulator's
+    /// output jumps, like after loading a state. The resampler carries on as is, so its phase and
+    /// ratio stay in step with the queue.
+    pub fn discontinuity(&mut self) {
+        self.fade = Some(Fade::new(self.last_frame, FADE_FRAMES));
+    }
+
+    /// Ramps up from silence into whatever comes next, for when the stream starts or the game is
+    /// reset. The boot sound would otherwise start with a pop on some systems.
+    pub fn ramp_in(&mut self) {
+        self.fade = Some(Fade::new(Frame::EQUILIBRIUM, RAMP_FRAMES));
+    }
+
+    /// Narrows the stereo image to `percent` of its width, since hard panned channels are tiring
+    /// to listen to on headphones.
+    pub fn set_stereo_width(&mut self, percent: u8) {
+        self.stereo_width = percent as f32 / 100.0;
+    }
+
+    /// Keeps pace with the emulator running at `percent` of full speed, by resampling so that the
+    /// audio for a frame lasts as long as the frame takes to show. The pitch follows the speed.
+    pub fn set_speed(&mut self, percent: u16) {
+        let ratio = self.base_ratio * 100.0 / f64::from(percent);
+        self.max_ratio = ratio * 2f64.powf(BEND_CENTS / 1200.0);
+        self.min_ratio = ratio * 2f64.powf(-BEND_CENTS / 1200.0);
+    }
+
+    pub fn push_frame(&mut self, mut frame: Frame) {
+        let [left, right] = frame;
+        let mid = (left + right) / 2.0;
+        let side = (left - right) / 2.0 * self.stereo_width;
+        frame = [mid + side, mid - side];
+        if let Some(fade) = &mut self.fade {
+            let t = 1.0 - fade.remaining as f32 / fade.len as f32;
+            frame = fade.from.scale_amp(1.0 - t).add_amp(frame.scale_amp(t));
+            fade.remaining -= 1;
+            if fade.remaining == 0 {
+                self.fade = None;
+            }
+        }
+        self.last_frame = frame;
         self.push_count += 1;
-        self.resampler.push_frame(frame, &self.queue);
+        self.overflowed |= self.resampler.push_frame(frame, &self.queue);
     }
 }
 
@@ -181,26 +360,51 @@ pub fn init() -> Result<Audio> {
 
     let len = (sample_rate / 10.0) as usize;
     let queue = Arc::new(ArrayQueue::<Frame>::new(len));
+    let played = Arc::new(AtomicUsize::new(0));
+    let underruns = Arc::new(AtomicUsize::new(0));
 
     let stream = match sample_format {
-        SampleFormat::F32 => new_stream::<f32>(&device, &config, &queue),
-        SampleFormat::I16 => new_stream::<i16>(&device, &config, &queue),
-        SampleFormat::U16 => new_stream::<u16>(&device, &config, &queue),
-        SampleFormat::U8 => new_stream::<u8>(&device, &config, &queue),
+        SampleFormat::F32 => new_stream::<f32>(&device, &config, &queue, &played, &underruns),
+        SampleFormat::I16 => new_stream::<i16>(&device, &config, &queue, &played, &underruns),
+        SampleFormat::U16 => new_stream::<u16>(&device, &config, &queue, &played, &underruns),
+        SampleFormat::U8 => new_stream::<u8>(&device, &config, &queue, &played, &underruns),
         sample_format => Err(anyhow!("Unsupported sample format '{sample_format}'")),
     }?;
 
     let ratio = sample_rate / FREQ as f64;
     let fps = MachineCycle::FREQ as f64 / MachineCycle::PER_FRAME as f64;
 
-    // println!("initial avg: {}", queue.capacity() as f64 / 2.0 - sample_rate / fps);
+    // One emulated frame's worth of output, which arrives all at once
+    let frame_len = sample_rate / fps;
+    let target_len = queue.capacity() as f64 / 2.0;
+    // Enough for the device to take a whole buffer right before the next frame arrives, and room
+    // for a frame on top of the most
+    let target_range = (
+        BUFFER_SIZE as f64 + frame_len,
+        queue.capacity() as f64 - frame_len,
+    );
+
+    // println!("initial avg: {}", target_len - frame_len);
 
     let audio = Audio {
         push_count: 0,
+        last_frame: Frame::EQUILIBRIUM,
+        fade: None,
+        stereo_width: 1.0,
         stream,
-        average_len: queue.capacity() as f64 / 2.0 - sample_rate / fps,
+        average_len: target_len - frame_len,
+        sample_rate,
+        target_len,
+        target_range,
+        seen_underruns: 0,
+        overflowed: false,
+        steady_frames: 0,
+        last_update: None,
         queue,
+        played,
+        underruns,
         resampler: Resampler::new(ratio),
+        base_ratio: ratio,
         max_ratio: ratio * 2f64.powf(BEND_CENTS / 1200.0),
         min_ratio: ratio * 2f64.powf(-BEND_CENTS / 1200.0),
     };
@@ -0,0 +1,93 @@
+// SPDX-License-Identifier: GPL-3.0-or-later
+// Copyright (C) 2023 Robert Hrusecky <jadedpastabowl@gmail.com>
+
+//! Everything kept for one game, bundled as a zip to carry progress between the desktop and web
+//! builds: the battery save, the quick save, and the game's profile, which is its cheat list. The
+//! files inside are in the same formats as on desktop, so a bundle can also be unzipped next to a
+//! ROM by hand.
+//!
+//! Bundles are tagged with the game's ID, its title and checksums, and are only imported for the
+//! same game, since another game's save would just look corrupt to it.
+
+use anyhow::{anyhow, ensure, Context, Result};
+use iron_boy_core::cart::Cart;
+
+use crate::zip;
+
+const ID_FILE: &str = "game.txt";
+const SAVE_FILE: &str = "save.cart";
+const STATE_FILE: &str = "quick.state";
+const CHEATS_FILE: &str = "cheats.cht";
+
+/// Identifies a ROM the same way on every platform, unlike its path.
+pub fn game_id(cart: &Cart) -> String {
+    let [header, global_high, global_low] = cart.checksums();
+    format!(
+        "{}-{header:02x}-{global_high:02x}{global_low:02x}",
+        cart.title()
+    )
+}
+
+/// What to call a file with `extension` for the game `id`, like a bundle, with anything that's not
+/// allowed in a file name replaced.
+#[cfg(target_arch = "wasm32")]
+pub fn file_name(id: &str, extension: &str) -> String {
+    let id: String = id
+        .chars()
+        .map(|c| match c {
+            c if c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.') => c,
+            _ => '_',
+        })
+        .collect();
+    format!("{id}.{extension}")
+}
+
+#[derive(Default)]
+pub struct Bundle {
+    /// Battery backed cartridge RAM, serialized like a `.cart` file
+    pub save: Option<Vec<u8>>,
+    pub state: Option<Vec<u8>>,
+    /// As a `.cht` file
+    pub cheats: Option<String>,
+}
+
+impl Bundle {
+    pub fn to_zip(&self, id: &str) -> Vec<u8> {
+        let files = [
+            Some((ID_FILE, id.as_bytes())),
+            self.save.as_deref().map(|save| (SAVE_FILE, save)),
+            self.state.as_deref().map(|state| (STATE_FILE, state)),
+            self.cheats
+                .as_deref()
+                .map(|cheats| (CHEATS_FILE, cheats.as_bytes())),
+        ];
+        zip::write(files.into_iter().flatten())
+    }
+
+    /// Reads a bundle, as long as it's for the game `id`.
+    pub fn from_zip(data: &[u8], id: &str) -> Result<Self> {
+        let files = zip::read(data).context("Failed to read the bundle")?;
+        let file = |name| {
+            files
+                .iter()
+                .find(|(file, _)| file == name)
+                .map(|(_, data)| data.clone())
+        };
+        let bundle_id = file(ID_FILE).ok_or(anyhow!("Not a save bundle, it has no {ID_FILE}"))?;
+        let bundle_id = String::from_utf8_lossy(&bundle_id);
+        let bundle_id = bundle_id.trim();
+        ensure!(
+            bundle_id == id,
+            "This bundle is for {bundle_id}, but the game running is {id}"
+        );
+        let cheats = file(CHEATS_FILE)
+            .map(String::from_utf8)
+            .transpose()
+            .with_context(|| format!("{CHEATS_FILE} isn't text"))?;
+        Ok(Self {
+            save: file(SAVE_FILE),
+            state: file(STATE_FILE),
+            cheats,
+        })
+    }
+}
@@ -0,0 +1,114 @@
+// SPDX-License-Identifier: GPL-3.0-or-later
+// Copyright (C) 2023 Robert Hrusecky <jadedpastabowl@gmail.com>
+
+//! Cheat lists in libretro's `.cht` format, which the common cheat databases are distributed in.
+//! Those are organized by game name, so an imported list is kept in the store under the checksums
+//! of the game that was running, and listed again whenever a ROM with the same header is loaded.
+//!
+//! ```text
+//! cheats = 1
+//!
+//! cheat0_desc = "Infinite Health"
+//! cheat0_code = "010F34D3+01..."
+//! cheat0_enable = false
+//! ```
+
+use std::{collections::HashMap, fmt::Write as _};
+
+use anyhow::{anyhow, Context as _, Result};
+use iron_boy_core::cheat::Cheat;
+
+use crate::store;
+
+pub struct CheatEntry {
+    pub desc: String,
+    /// As written in the list, with multiple codes joined by `+`
+    pub code: String,
+    pub enabled: bool,
+    cheats: Vec<Cheat>,
+}
+
+fn key(checksums: [u8; 3]) -> String {
+    let [header, global_high, global_low] = checksums;
+    format!("cheats/{header:02x}-{global_high:02x}{global_low:02x}.cht")
+}
+
+/// Parses a `.cht` file. Every code must be valid, so that a bad list is rejected when it is
+/// imported instead of half applied later.
+pub fn parse(text: &str) -> Result<Vec<CheatEntry>> {
+    let values: HashMap<_, _> = text
+        .lines()
+        .filter_map(|line| line.split_once('='))
+        .map(|(key, value)| (key.trim(), value.trim().trim_matches('"')))
+        .collect();
+    let count: usize = values
+        .get("cheats")
+        .ok_or(anyhow!("Not a cheat list, expected `cheats = <count>`"))?
+        .parse()
+        .context("Invalid cheat count")?;
+    (0..count)
+        .map(|i| -> Result<CheatEntry> {
+            let desc = values.get(format!("cheat{i}_desc").as_str());
+            let code = values
+                .get(format!("cheat{i}_code").as_str())
+                .ok_or_else(|| anyhow!("Cheat {i} has no code"))?;
+            let cheats = code
+                .split('+')
+                .map(str::parse::<Cheat>)
+                .collect::<Result<Vec<_>, _>>()
+                .with_context(|| format!("Cheat {i} has an invalid code"))?;
+            Ok(CheatEntry {
+                desc: desc.map_or_else(|| format!("Cheat {i}"), |desc| desc.to_string()),
+                code: code.to_string(),
+                enabled: values.get(format!("cheat{i}_enable").as_str()) == Some(&"true"),
+                cheats,
+            })
+        })
+        .collect()
+}
+
+fn format(entries: &[CheatEntry]) -> String {
+    let mut text = format!("cheats = {}\n", entries.len());
+    for (i, entry) in entries.iter().enumerate() {
+        let CheatEntry {
+            desc,
+            code,
+            enabled,
+            ..
+        } = entry;
+        let _ = write!(
+            text,
+            "\ncheat{i}_desc = \"{desc}\"\ncheat{i}_code = \"{code}\"\ncheat{i}_enable = {enabled}\n"
+        );
+    }
+    text
+}
+
+/// The list imported for the game with `checksums`, if any.
+pub fn load(checksums: [u8; 3]) -> Vec<CheatEntry> {
+    let Some(text) = store::load(&key(checksums)) else {
+        return Vec::new();
+    };
+    parse(&text).unwrap_or_else(|error| {
+        log::warn!("Ignoring a corrupt cheat list: {error:#}");
+        Vec::new()
+    })
+}
+
+/// The list imported for the game with `checksums` as it's stored, to export it.
+pub fn stored(checksums: [u8; 3]) -> Option<String> {
+    store::load(&key(checksums))
+}
+
+/// Remembers the list, including which cheats are enabled, for the game with `checksums`.
+pub fn save(checksums: [u8; 3], entries: &[CheatEntry]) -> Result<()> {
+    store::save(&key(checksums), &format(entries))
+}
+
+/// All of the codes that are enabled.
+pub fn enabled(entries: &[CheatEntry]) -> impl Iterator<Item = Cheat> + '_ {
+    entries
+        .iter()
+        .filter(|entry| entry.enabled)
+        .flat_map(|entry| entry.cheats.iter().copied())
+}
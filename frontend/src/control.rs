@@ -0,0 +1,201 @@
+// SPDX-License-Identifier: GPL-3.0-or-later
+// Copyright (C) 2023 Robert Hrusecky <jadedpastabowl@gmail.com>
+
+//! A control interface for external tools, like scripts and test frameworks. It listens on a
+//! localhost TCP port for JSON-RPC 2.0 requests, one per line, and answers each with a line. Each
+//! connection has to start with an `authenticate` request carrying the token from
+//! `--control-token` or `control_token` in the settings. For example:
+//!
+//! ```text
+//! {"jsonrpc": "2.0", "id": 0, "method": "authenticate", "params": {"token": "hunter2"}}
+//! {"jsonrpc":"2.0","id":0,"result":null}
+//! {"jsonrpc": "2.0", "id": 1, "method": "read_memory", "params": {"addr": 49152, "len": 2}}
+//! {"jsonrpc":"2.0","id":1,"result":[0,0]}
+//! ```
+//!
+//! Since any web page can make a browser send HTTP to localhost, a connection is closed at the
+//! first line that isn't a valid request, and anything that looks like HTTP gets no answer at all.
+//!
+//! Each connection is served on its own thread, and commands run on the event loop between
+//! frames.
+
+use std::{
+    io::{BufRead, BufReader, Write},
+    net::{Ipv4Addr, TcpListener, TcpStream},
+    path::PathBuf,
+    sync::mpsc,
+    thread,
+};
+
+use anyhow::{anyhow, Context as _, Result};
+use serde::Deserialize;
+use serde_json::{json, Value};
+use winit::event_loop::EventLoopProxy;
+
+use crate::event::FrontendEvent;
+
+const INVALID_REQUEST: i32 = -32600;
+const SERVER_ERROR: i32 = -32000;
+const UNAUTHORIZED: i32 = -32001;
+
+/// The methods, with their params.
+#[derive(Debug, Deserialize)]
+#[serde(tag = "method", content = "params", rename_all = "snake_case")]
+pub enum Command {
+    /// Replaces the running game, saving its cartridge RAM first
+    LoadRom {
+        path: PathBuf,
+    },
+    Pause,
+    Resume,
+    /// Saves to `path`, or the quick save next to the ROM. Returns the path.
+    SaveState {
+        path: Option<PathBuf>,
+    },
+    /// Loads from `path`, or the quick save next to the ROM. Returns the path.
+    LoadState {
+        path: Option<PathBuf>,
+    },
+    /// Presses or releases a joypad button, named like `a` or `start`
+    Button {
+        button: String,
+        pressed: bool,
+    },
+    /// Returns `len` bytes starting at `addr`, as the CPU sees them
+    ReadMemory {
+        addr: u16,
+        len: u16,
+    },
+    /// Saves a screenshot next to the ROM. Returns the path.
+    Screenshot,
+}
+
+/// Where the engine sends the result of a [`Command`].
+pub type Reply = mpsc::Sender<Result<Value>>;
+
+/// What has to come first on each connection.
+#[derive(Deserialize)]
+#[serde(tag = "method", content = "params", rename_all = "snake_case")]
+enum Handshake {
+    Authenticate { token: String },
+}
+
+#[derive(Deserialize)]
+struct Request<T> {
+    #[serde(default)]
+    id: Value,
+    #[serde(flatten)]
+    command: T,
+}
+
+/// Starts listening on `port`, only on localhost, for connections that know `token`.
+pub fn listen(port: u16, token: String, proxy: EventLoopProxy<FrontendEvent>) -> Result<()> {
+    if token.is_empty() {
+        return Err(anyhow!("The control token can't be empty"));
+    }
+    let listener = TcpListener::bind((Ipv4Addr::LOCALHOST, port))
+        .with_context(|| format!("Failed to listen for control connections on port {port}"))?;
+    log::info!(
+        "Listening for control connections on {}",
+        listener.local_addr()?
+    );
+    thread::spawn(move || {
+        for stream in listener.incoming() {
+            match stream {
+                Ok(stream) => {
+                    let proxy = proxy.clone();
+                    let token = token.clone();
+                    thread::spawn(move || {
+                        if let Err(error) = serve(stream, &token, &proxy) {
+                            log::warn!("Control connection failed: {error:#}");
+                        }
+                    });
+                }
+                Err(error) => log::warn!("Failed to accept a control connection: {error}"),
+            }
+        }
+    });
+    Ok(())
+}
+
+const HTTP_METHODS: [&str; 9] = [
+    "GET", "HEAD", "POST", "PUT", "DELETE", "CONNECT", "OPTIONS", "TRACE", "PATCH",
+];
+
+/// Whether `line` is the start of an HTTP request, like one a web page had a browser send.
+fn is_http(line: &str) -> bool {
+    let method = line.split(' ').next().unwrap_or_default();
+    HTTP_METHODS.contains(&method) || line.contains(" HTTP/")
+}
+
+/// Compares every byte, so that how long it takes doesn't give away how much of a guess was right.
+fn tokens_match(guess: &str, token: &str) -> bool {
+    let diff = guess
+        .bytes()
+        .zip(token.bytes())
+        .fold(0, |diff, (a, b)| diff | (a ^ b));
+    guess.len() == token.len() && diff == 0
+}
+
+fn serve(stream: TcpStream, token: &str, proxy: &EventLoopProxy<FrontendEvent>) -> Result<()> {
+    let mut writer = stream.try_clone()?;
+    let mut authenticated = false;
+    for line in BufReader::new(stream).lines() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        if is_http(&line) {
+            return Err(anyhow!("Refused an HTTP request"));
+        }
+        let response = if authenticated {
+            match serde_json::from_str::<Request<Command>>(&line) {
+                Ok(Request { id, command }) => match run(command, proxy) {
+                    Ok(result) => json!({ "jsonrpc": "2.0", "id": id, "result": result }),
+                    Err(error) => error_response(id, SERVER_ERROR, format!("{error:#}")),
+                },
+                Err(error) => {
+                    let response = error_response(Value::Null, INVALID_REQUEST, error.to_string());
+                    respond(&mut writer, &response)?;
+                    return Err(anyhow!("Closed after an invalid request: {error}"));
+                }
+            }
+        } else {
+            match serde_json::from_str::<Request<Handshake>>(&line) {
+                Ok(Request {
+                    id,
+                    command: Handshake::Authenticate { token: guess },
+                }) if tokens_match(&guess, token) => {
+                    authenticated = true;
+                    json!({ "jsonrpc": "2.0", "id": id, "result": null })
+                }
+                _ => {
+                    let message = "Authenticate with the control token first".to_owned();
+                    let response = error_response(Value::Null, UNAUTHORIZED, message);
+                    respond(&mut writer, &response)?;
+                    return Err(anyhow!("Closed a connection that didn't authenticate"));
+                }
+            }
+        };
+        respond(&mut writer, &response)?;
+    }
+    Ok(())
+}
+
+fn respond(writer: &mut TcpStream, response: &Value) -> Result<()> {
+    serde_json::to_writer(&mut *writer, response)?;
+    writer.write_all(b"\n")?;
+    Ok(())
+}
+
+fn run(command: Command, proxy: &EventLoopProxy<FrontendEvent>) -> Result<Value> {
+    let (reply, result) = mpsc::channel();
+    proxy
+        .send_event(FrontendEvent::Control(command, reply))
+        .map_err(|_| anyhow!("The emulator has exited"))?;
+    result.recv().context("The emulator has exited")?
+}
+
+fn error_response(id: Value, code: i32, message: String) -> Value {
+    json!({ "jsonrpc": "2.0", "id": id, "error": { "code": code, "message": message } })
+}
@@ -0,0 +1,28 @@
+// SPDX-License-Identifier: GPL-3.0-or-later
+// Copyright (C) 2023 Robert Hrusecky <jadedpastabowl@gmail.com>
+
+//! Files for the user to keep on the web, where there's nowhere to write them, are handed to the
+//! browser to save like any other download.
+
+use anyhow::{anyhow, Result};
+use js_sys::{Array, Uint8Array};
+use wasm_bindgen::{JsCast, JsValue};
+use web_sys::{Blob, HtmlAnchorElement, Url};
+
+/// Has the browser save `data` as a file called `name`.
+pub fn download(name: &str, data: &[u8]) -> Result<()> {
+    let js_error = |error: JsValue| anyhow!("Failed to download {name}: {error:?}");
+    let parts = Array::of1(&Uint8Array::from(data));
+    let blob = Blob::new_with_u8_array_sequence(&parts).map_err(js_error)?;
+    let url = Url::create_object_url_with_blob(&blob).map_err(js_error)?;
+    let anchor: HtmlAnchorElement = web_sys::window()
+        .and_then(|window| window.document())
+        .ok_or(anyhow!("No document to download {name} from"))?
+        .create_element("a")
+        .map_err(js_error)?
+        .unchecked_into();
+    anchor.set_href(&url);
+    anchor.set_download(name);
+    anchor.click();
+    Url::revoke_object_url(&url).map_err(js_error)
+}
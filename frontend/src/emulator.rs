@@ -1,25 +1,158 @@
 // SPDX-License-Identifier: GPL-3.0-or-later
 // Copyright (C) 2023 Robert Hrusecky <jadedpastabowl@gmail.com>
 
+#[cfg(target_arch = "wasm32")]
+use std::sync::{Arc, OnceLock};
+#[cfg(not(target_arch = "wasm32"))]
 use std::{
-    fs::{self, File},
-    mem,
-    time::Duration,
+    cmp::Reverse,
+    fs::File,
+    io,
+    path::Path,
+    time::{SystemTime, UNIX_EPOCH},
 };
+use std::{fs, mem, path::PathBuf, time::Duration};
 
 use anyhow::{anyhow, Context as _, Result};
 
 pub use iron_boy_core::system::{SCREEN_HEIGHT, SCREEN_WIDTH};
 
+#[cfg(target_arch = "wasm32")]
+use iron_boy_core::cart::{ClockSource, RtcClock};
+#[cfg(all(feature = "scripting", not(target_arch = "wasm32")))]
+use iron_boy_core::system::Registers;
+#[cfg(feature = "tools")]
+use iron_boy_core::system::{
+    disasm::{Line, TraceEntry},
+    DebugVideo,
+};
+#[cfg(not(target_arch = "wasm32"))]
+use iron_boy_core::{
+    cart::RtcClock,
+    joypad::Input,
+    movie::Movie,
+    system::{PpuConfig, StateInfo},
+};
 use iron_boy_core::{
-    cart::Cart,
-    joypad::{Button, ButtonState},
-    system::{CgbSystem, FrameBuffer},
+    cart::{header::Header, save::CartSave, Cart},
+    cheat::Cheat,
+    event::Event,
+    joypad::{Button, ButtonState, Socd},
+    system::{
+        AudioChannel, AudioFrame, BootRom, CgbSystem, ColorTransform, DmgPalette, FrameBuffer,
+        MachineCycle, Model,
+    },
 };
 use pixels::Pixels;
-use winit::event::{ElementState, VirtualKeyCode};
+use winit::event::ElementState;
+
+#[cfg(target_arch = "wasm32")]
+use crate::web_saves;
+use crate::{audio::Audio, bundle, options::Options, zip};
+
+/// None if the options say to skip it, otherwise the boot ROM given in the options if it loads,
+/// otherwise the built in one for the model, or none at all if there isn't one.
+fn boot_rom(options: &Options) -> BootRom {
+    if options.skip_boot_rom {
+        return BootRom::skip_as(options.model);
+    }
+    #[cfg(not(target_arch = "wasm32"))]
+    if let Some(path) = &options.boot_rom {
+        match load_boot_rom(path) {
+            Ok(boot_rom) => return boot_rom,
+            Err(error) => log::warn!("{error:#}"),
+        }
+    }
+    match options.model {
+        Model::Cgb => BootRom::default(),
+        Model::Dmg => BootRom::skip_as(Model::Dmg),
+    }
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+pub fn load_boot_rom(path: &Path) -> Result<BootRom> {
+    fs::read(path)
+        .map_err(anyhow::Error::from)
+        .and_then(|image| Ok(BootRom::new(image)?))
+        .with_context(|| format!("Failed to load boot ROM {}", path.display()))
+}
+
+/// Takes the first Game Boy ROM out of `data` if it's a zip archive, since ROMs are often
+/// distributed zipped.
+pub fn unzip_rom(data: Box<[u8]>) -> Result<Box<[u8]>> {
+    if !zip::is_zip(&data) {
+        return Ok(data);
+    }
+    let (name, rom) = zip::find(&data, |name| {
+        let name = name.to_ascii_lowercase();
+        name.ends_with(".gb") || name.ends_with(".gbc")
+    })
+    .context("Failed to read the zipped ROM")?
+    .ok_or(anyhow!("There's no .gb or .gbc file in the zip"))?;
+    log::info!("Loading {name} from the zip");
+    Ok(rom.into_boxed_slice())
+}
+
+/// Parses a ROM, with any mapper and RAM size overrides for it from the options.
+fn parse_cart(rom: Box<[u8]>, options: &Options) -> Result<Cart> {
+    let rom = unzip_rom(rom)?;
+    let cart = match options.mapper {
+        Some(mbc) => {
+            log::info!("Using {mbc} in place of the header's mapper");
+            Cart::from_rom_with_mbc(rom, mbc)
+        }
+        None => Cart::from_rom(rom),
+    };
+    let mut cart = cart.context("Failed to parse ROM")?;
+    if let Some(&size) = options.ram_sizes.get(&cart.title()) {
+        log::info!("Using {size:#x} bytes of cartridge RAM from the settings");
+        cart.set_ram_size(size);
+    }
+    Ok(cart)
+}
+
+/// Views the pixel buffer as a frame buffer. The buffer must be exactly the size of the screen.
+pub fn frame_buffer(pixels: &mut Pixels) -> &mut FrameBuffer {
+    let frame_buff = pixels.frame_mut();
+    let frame_buff: &mut [u8; mem::size_of::<FrameBuffer>()] = frame_buff.try_into().ok().unwrap();
+    unsafe { mem::transmute(frame_buff) }
+}
 
-use crate::{audio::Audio, options::Options};
+#[cfg(not(target_arch = "wasm32"))]
+pub fn write_screenshot(frame_buff: &FrameBuffer, path: &Path) -> Result<()> {
+    let file = File::create(path).with_context(|| format!("Failed to create {path:?}"))?;
+    let mut encoder = png::Encoder::new(
+        std::io::BufWriter::new(file),
+        SCREEN_WIDTH as u32,
+        SCREEN_HEIGHT as u32,
+    );
+    encoder.set_color(png::ColorType::Rgba);
+    encoder.set_depth(png::BitDepth::Eight);
+    let mut writer = encoder.write_header()?;
+    writer.write_image_data(frame_buff.as_flattened().as_flattened())?;
+    Ok(())
+}
+
+/// The browser's clock, for the RTC, since the standard library doesn't have one on the web.
+#[cfg(target_arch = "wasm32")]
+struct WebClock;
+
+#[cfg(target_arch = "wasm32")]
+impl ClockSource for WebClock {
+    fn now(&self) -> Duration {
+        static START: OnceLock<instant::Instant> = OnceLock::new();
+        START.get_or_init(instant::Instant::now).elapsed()
+    }
+
+    fn unix_time(&self) -> Option<Duration> {
+        Some(Duration::from_secs_f64(js_sys::Date::now() / 1000.0))
+    }
+}
+
+/// How long a single frame lasts on hardware.
+pub fn frame_duration() -> Duration {
+    MachineCycle(MachineCycle::PER_FRAME).into()
+}
 
 pub struct Cgb {
     system: Box<CgbSystem>,
@@ -33,73 +166,485 @@ impl Cgb {
             .ok_or(anyhow!("No ROM file"))?;
         let rom = fs::read(rom_file_name)?;
 
-        let mut cart = Cart::from_rom(rom.into_boxed_slice()).context("Failed to parse ROM")?;
+        let mut cart = parse_cart(rom.into_boxed_slice(), options)?;
         if cart.battery_backed() {
-            let save_path = rom_file_name.with_extension("cart");
+            let save_path = cart_path(options)?;
+            let sav_path = sav_path(options)?;
             if save_path.exists() {
-                let save_file = File::open(save_path)?;
-                let save = bincode::deserialize_from(save_file)?;
+                let data = fs::read(&save_path)?;
+                let save = CartSave::from_cart(&data)
+                    .with_context(|| format!("{save_path:?} is corrupt"))?;
                 cart.load_from_save(save);
+            } else if sav_path.exists() {
+                let data = fs::read(&sav_path)?;
+                cart.load_from_save(CartSave::from_sav(&data));
+                log::info!("Imported {sav_path:?}, which will be saved to {save_path:?} instead");
             }
         }
 
-        Ok(Self {
-            system: Box::new(CgbSystem::new(cart)),
-        })
+        Ok(Self::new_with_cart(cart, boot_rom(options), options))
     }
 
-    pub fn new_from_rom(rom: Box<[u8]>) -> Result<Self> {
-        let cart = Cart::from_rom(rom).context("Failed to parse ROM")?;
-        Ok(Self {
-            system: Box::new(CgbSystem::new(cart)),
-        })
+    /// Starts a ROM read on the web, along with the game's stored save, if it has one.
+    #[cfg(target_arch = "wasm32")]
+    pub fn new_from_rom(rom: Box<[u8]>, options: &Options) -> Result<Self> {
+        let mut cart = parse_cart(rom, options)?;
+        if let Err(error) = web_saves::load(&mut cart) {
+            log::warn!("{error:#}");
+        }
+        Ok(Self::new_with_cart(cart, boot_rom(options), options))
+    }
+
+    /// Starts the cartridge in `rom` right away, without a boot ROM or saves.
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn new_skipping_boot(rom: Box<[u8]>, options: &Options) -> Result<Self> {
+        let cart = parse_cart(rom, options)?;
+        Ok(Self::new_with_cart(
+            cart,
+            BootRom::skip_as(options.model),
+            options,
+        ))
+    }
+
+    fn new_with_cart(cart: Cart, boot_rom: BootRom, options: &Options) -> Self {
+        let header = cart.header();
+        if !header.header_checksum_ok() {
+            log::warn!("The ROM's header checksum is wrong, so it wouldn't start on hardware");
+        }
+        if !header.global_checksum_ok() {
+            log::warn!("The ROM's global checksum is wrong, so it may be a bad dump or modified");
+        }
+        let mut system = Box::new(CgbSystem::with_boot_rom(cart, boot_rom));
+        #[cfg(target_arch = "wasm32")]
+        system.set_rtc_clock(RtcClock::Custom(Arc::new(WebClock)));
+        if let Some(open_bus) = options.open_bus {
+            system.set_open_bus_value(open_bus);
+        }
+        system.set_log_unmapped_io(!options.quiet_unmapped_io);
+        system.set_overclock(options.overclock);
+        // For the serial console
+        system.set_capture_serial(cfg!(feature = "tools") || options.echo_serial);
+        Self { system }
     }
 
-    pub fn compute_next_frame(&mut self, pixels: &mut Pixels, audio: &mut Audio) -> Duration {
-        let frame_buff = pixels.frame_mut();
-        let frame_buff: &mut [u8; mem::size_of::<FrameBuffer>()] =
-            frame_buff.try_into().ok().unwrap();
-        let frame_buff = unsafe { mem::transmute(frame_buff) };
+    /// Runs a frame, and returns how long it lasts on hardware, which is how long to wait before
+    /// the next one. That's longer than [`frame_duration`] for the frame where the LCD turns on.
+    /// `tap` is handed the audio too, like for recording.
+    pub fn compute_next_frame(
+        &mut self,
+        frame_buff: &mut FrameBuffer,
+        audio: &mut Audio,
+        mut tap: impl FnMut(AudioFrame),
+    ) -> Duration {
         audio.update_ratio();
         self.system
-            .execute(frame_buff, |f| audio.push_frame(f))
+            .execute(frame_buff, |f| {
+                audio.push_frame(f);
+                tap(f);
+            })
             .into()
     }
 
-    fn handle_joypad(&mut self, button: Button, state: ButtonState) {
-        self.system.handle_joypad(button, state);
+    /// Runs a frame without any sound, like while rewinding.
+    pub fn compute_next_frame_muted(&mut self, frame_buff: &mut FrameBuffer) -> Duration {
+        self.system.execute(frame_buff, |_| {}).into()
     }
 
-    pub fn handle_key(&mut self, key: VirtualKeyCode, state: ElementState) {
-        use VirtualKeyCode as VK;
-        let button = match key {
-            VK::W => Button::Up,
-            VK::A => Button::Left,
-            VK::S => Button::Down,
-            VK::D => Button::Right,
-            VK::LBracket => Button::Start,
-            VK::RBracket => Button::Select,
-            VK::Comma => Button::A,
-            VK::Period => Button::B,
-            _ => return,
-        };
+    pub fn events(&mut self) -> impl Iterator<Item = Event> + '_ {
+        self.system.events()
+    }
+
+    #[cfg(feature = "tools")]
+    pub fn debug_video(&self) -> DebugVideo<'_> {
+        self.system.debug_video()
+    }
+
+    #[cfg(feature = "tools")]
+    pub fn set_tracing(&mut self, tracing: bool) {
+        self.system.set_tracing(tracing);
+    }
+
+    #[cfg(feature = "tools")]
+    pub fn trace(&self) -> impl Iterator<Item = &TraceEntry> {
+        self.system.trace()
+    }
+
+    /// The instructions at PC, up to the next 16 bytes' worth.
+    #[cfg(feature = "tools")]
+    pub fn upcoming_instructions(&mut self) -> Vec<Line> {
+        let pc = self.system.registers().pc;
+        self.system.disassemble(pc, pc.saturating_add(16))
+    }
+
+    #[cfg(feature = "tools")]
+    pub fn instructions_executed(&self) -> u64 {
+        self.system.instructions_executed()
+    }
+
+    #[cfg(feature = "tools")]
+    pub fn cycles_executed(&self) -> u64 {
+        self.system.cycles_executed()
+    }
+
+    /// Runs the next instruction, without any sound.
+    #[cfg(feature = "tools")]
+    pub fn step_instruction(&mut self, frame_buff: &mut FrameBuffer) {
+        self.system.step_instruction(frame_buff, |_| ());
+    }
+
+    /// Runs until `instruction` instructions have executed in all, without any sound. Returns
+    /// whether it got there.
+    #[cfg(feature = "tools")]
+    pub fn run_to_instruction(&mut self, instruction: u64, frame_buff: &mut FrameBuffer) -> bool {
+        self.system.run_to_instruction(instruction, frame_buff)
+    }
+
+    pub fn title(&self) -> String {
+        self.system.cart().title()
+    }
+
+    pub fn checksums(&self) -> [u8; 3] {
+        self.system.cart().checksums()
+    }
+
+    pub fn header(&self) -> &Header {
+        self.system.cart().header()
+    }
+
+    pub fn set_cheats(&mut self, cheats: impl IntoIterator<Item = Cheat>) {
+        self.system.set_cheats(cheats);
+    }
+
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn set_ppu_config(&mut self, config: PpuConfig) {
+        self.system.set_ppu_config(config);
+    }
+
+    pub fn set_dmg_palette(&mut self, palette: DmgPalette) {
+        self.system.set_dmg_palette(palette);
+    }
+
+    pub fn set_color_transform(&mut self, transform: ColorTransform) {
+        self.system.set_color_transform(transform);
+    }
+
+    /// Takes a flag for each channel, indexed like [`AudioChannel`].
+    pub fn set_muted_channels(&mut self, muted: [bool; 4]) {
+        for (channel, muted) in AudioChannel::ALL.into_iter().zip(muted) {
+            self.system.set_channel_muted(channel, muted);
+        }
+    }
+
+    pub fn handle_joypad(&mut self, button: Button, state: ElementState) {
         let state = match state {
             ElementState::Pressed => ButtonState::Pressed,
             ElementState::Released => ButtonState::Released,
         };
-        self.handle_joypad(button, state);
+        self.system.handle_joypad(button, state);
+    }
+
+    /// Moves the bytes sent over the serial port since the last call onto the end of `out`.
+    pub fn take_serial(&mut self, out: &mut Vec<u8>) {
+        self.system.take_serial(out);
+    }
+
+    pub fn handle_stick(&mut self, x: f32, y: f32, deadzone: f32) {
+        self.system.handle_stick(x, y, deadzone);
+    }
+
+    pub fn set_socd(&mut self, socd: Socd) {
+        self.system.set_socd(socd);
     }
 
-    pub fn handle_close(&self, options: &Options) -> Result<()> {
+    /// The buttons held down, from the keyboard, a controller, or a movie.
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn input(&self) -> Input {
+        self.system.input()
+    }
+
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn set_input(&mut self, input: Input) {
+        self.system.set_input(input);
+    }
+
+    /// Starts recording a movie from here. See [`Movie::record`].
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn record_movie(&mut self) -> Movie {
+        Movie::record(&mut self.system)
+    }
+
+    /// Goes back to where `movie` starts, to play it.
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn rewind_movie(&mut self, movie: &Movie) -> Result<()> {
+        Ok(movie.rewind(&mut self.system)?)
+    }
+
+    /// Movies switch the RTC to emulated time, so this switches it back afterwards.
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn set_rtc_clock(&mut self, clock: RtcClock) {
+        self.system.set_rtc_clock(clock);
+    }
+
+    pub fn battery_backed(&self) -> bool {
+        self.system.cart().battery_backed()
+    }
+
+    /// Whether cartridge RAM has changed since it was last saved.
+    pub fn cart_dirty(&self) -> bool {
+        self.system.cart().ram_dirty()
+    }
+
+    /// Notes that cartridge RAM was just saved. Only call this once the write has worked, so that
+    /// a failed one is tried again.
+    pub fn mark_cart_saved(&mut self) {
+        self.system.take_cart_dirty();
+    }
+
+    /// Writes battery backed cartridge RAM to `path`. Does nothing for carts without a battery.
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn save_cart(&self, path: &Path) -> Result<()> {
         if let Some(save) = self.system.cart().save() {
-            let path = options
-                .rom_file_name
-                .as_ref()
-                .ok_or(anyhow!("No ROM file"))?
-                .with_extension("cart");
-            let save_file = File::create(path)?;
-            bincode::serialize_into(save_file, &save)?;
+            let save_file =
+                File::create(path).with_context(|| format!("Failed to create {path:?}"))?;
+            bincode::serialize_into(save_file, &save)
+                .with_context(|| format!("Failed to write {path:?}"))?;
+        }
+        Ok(())
+    }
+
+    /// Battery backed cartridge RAM serialized like a `.cart` file, or nothing for carts without a
+    /// battery.
+    pub fn cart_save(&self) -> Result<Option<Vec<u8>>> {
+        let save = self.system.cart().save();
+        Ok(save.map(|save| bincode::serialize(&save)).transpose()?)
+    }
+
+    /// Battery backed cartridge RAM as a `.sav` file, for other emulators and flashcarts, or
+    /// nothing for carts without a battery.
+    pub fn sav(&self) -> Option<Vec<u8>> {
+        self.system.cart().save().map(|save| save.to_sav())
+    }
+
+    /// Replaces cartridge RAM with a save from [`Self::cart_save`] or [`Self::sav`], or another
+    /// emulator. Does nothing for carts without a battery.
+    pub fn load_cart_save(&mut self, save: &[u8]) {
+        if self.battery_backed() {
+            self.system.load_cart_save(CartSave::detect(save));
         }
+    }
+
+    /// Identifies the game in bundles of its saves.
+    pub fn game_id(&self) -> String {
+        bundle::game_id(self.system.cart())
+    }
+
+    /// Stores cartridge RAM in browser storage for the next time the game is chosen, like desktop
+    /// saves it next to the ROM.
+    #[cfg(target_arch = "wasm32")]
+    pub fn handle_close(&self) -> Result<()> {
+        web_saves::save(self.system.cart())
+    }
+
+    /// A state with the title, the time, and a thumbnail of `frame_buff` in its header, to
+    /// recognize it by in the state browser.
+    pub fn save_state_with_info(&self, frame_buff: &FrameBuffer) -> Vec<u8> {
+        self.system.save_state_with_info(unix_time(), frame_buff)
+    }
+
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn save_state(&self, path: &Path, frame_buff: &FrameBuffer) -> Result<()> {
+        fs::write(path, self.save_state_with_info(frame_buff))
+            .with_context(|| format!("Failed to write {path:?}"))?;
+        log::info!("Saved state to {path:?}");
         Ok(())
     }
+
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn load_state(&mut self, path: &Path) -> Result<()> {
+        let state = fs::read(path).with_context(|| format!("Failed to read {path:?}"))?;
+        self.system
+            .load_state(&state)
+            .with_context(|| format!("Failed to load {path:?}"))?;
+        log::info!("Loaded state from {path:?}");
+        Ok(())
+    }
+
+    /// The game's quick save, next to the ROM on desktop and in the store on the web, if there is
+    /// one.
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn stored_state(&self, options: &Options) -> Result<Option<Vec<u8>>> {
+        let path = state_path(options)?;
+        if !path.exists() {
+            return Ok(None);
+        }
+        let state = fs::read(&path).with_context(|| format!("Failed to read {path:?}"))?;
+        Ok(Some(state))
+    }
+
+    #[cfg(target_arch = "wasm32")]
+    pub fn stored_state(&self, _options: &Options) -> Result<Option<Vec<u8>>> {
+        web_saves::load_state(self.system.cart())
+    }
+
+    /// Replaces the game's quick save with `state`, from [`Self::save_state_into`].
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn store_state(&self, options: &Options, state: &[u8]) -> Result<()> {
+        let path = state_path(options)?;
+        fs::write(&path, state).with_context(|| format!("Failed to write {path:?}"))?;
+        log::info!("Saved state to {path:?}");
+        Ok(())
+    }
+
+    #[cfg(target_arch = "wasm32")]
+    pub fn store_state(&self, _options: &Options, state: &[u8]) -> Result<()> {
+        web_saves::save_state(self.system.cart(), state)
+    }
+
+    pub fn quick_save(&self, options: &Options, frame_buff: &FrameBuffer) -> Result<()> {
+        self.store_state(options, &self.save_state_with_info(frame_buff))
+    }
+
+    pub fn quick_load(&mut self, options: &Options) -> Result<()> {
+        let state = self
+            .stored_state(options)?
+            .ok_or(anyhow!("There's no quick save for this game"))?;
+        self.restore_state(&state)
+            .context("Failed to load the quick save")
+    }
+
+    pub fn save_state_into(&self, state: &mut Vec<u8>) {
+        self.system.save_state_into(state);
+    }
+
+    /// Restores a state from [`Self::save_state_into`].
+    pub fn restore_state(&mut self, state: &[u8]) -> Result<()> {
+        Ok(self.system.load_state(state)?)
+    }
+
+    /// Reads `len` bytes starting at `addr`, as the CPU sees them.
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn read_memory(&mut self, addr: u16, len: u16) -> Vec<u8> {
+        (0..len)
+            .map(|i| self.system.peek(addr.wrapping_add(i)))
+            .collect()
+    }
+
+    /// Reads `addr` as the CPU sees it.
+    #[cfg(all(feature = "scripting", not(target_arch = "wasm32")))]
+    pub fn peek(&mut self, addr: u16) -> u8 {
+        self.system.peek(addr)
+    }
+
+    /// Writes `addr` as the CPU would, with the same effects on IO registers.
+    #[cfg(all(feature = "scripting", not(target_arch = "wasm32")))]
+    pub fn poke(&mut self, addr: u16, val: u8) {
+        self.system.poke(addr, val);
+    }
+
+    #[cfg(all(feature = "scripting", not(target_arch = "wasm32")))]
+    pub fn registers(&self) -> Registers {
+        self.system.registers()
+    }
+}
+
+/// Where battery backed cartridge RAM is saved, next to the ROM.
+pub fn cart_path(options: &Options) -> Result<PathBuf> {
+    Ok(options
+        .rom_file_name
+        .as_ref()
+        .ok_or(anyhow!("No ROM file"))?
+        .with_extension("cart"))
+}
+
+/// Where other emulators save battery backed cartridge RAM, next to the ROM. It's imported from
+/// there when there's no save of our own, and exported there for them.
+pub fn sav_path(options: &Options) -> Result<PathBuf> {
+    Ok(cart_path(options)?.with_extension("sav"))
+}
+
+/// Where quick saves go, next to the ROM.
+#[cfg(not(target_arch = "wasm32"))]
+pub fn state_path(options: &Options) -> Result<PathBuf> {
+    Ok(options
+        .rom_file_name
+        .as_ref()
+        .ok_or(anyhow!("No ROM file"))?
+        .with_extension("state"))
+}
+
+/// Where states saved from the state browser go, in a folder next to the ROM.
+#[cfg(not(target_arch = "wasm32"))]
+pub fn states_dir(options: &Options) -> Result<PathBuf> {
+    Ok(options
+        .rom_file_name
+        .as_ref()
+        .ok_or(anyhow!("No ROM file"))?
+        .with_extension("states"))
+}
+
+/// A save state file for the running game.
+#[cfg(not(target_arch = "wasm32"))]
+pub struct SavedState {
+    pub path: PathBuf,
+    /// None for states saved without it, like the ones the control interface saves to a path
+    pub info: Option<StateInfo>,
+}
+
+/// The game's quick save and the states in [`states_dir`], newest first. Files that aren't states
+/// this version can load are skipped.
+#[cfg(not(target_arch = "wasm32"))]
+pub fn saved_states(options: &Options) -> Result<Vec<SavedState>> {
+    let mut paths = vec![state_path(options)?];
+    let dir = states_dir(options)?;
+    match fs::read_dir(&dir) {
+        Ok(entries) => paths.extend(
+            entries
+                .filter_map(|entry| Some(entry.ok()?.path()))
+                .filter(|path| path.extension() == Some("state".as_ref())),
+        ),
+        Err(error) if error.kind() == io::ErrorKind::NotFound => {}
+        Err(error) => return Err(error).with_context(|| format!("Failed to read {dir:?}")),
+    }
+    let mut states: Vec<_> = paths
+        .into_iter()
+        .filter(|path| path.exists())
+        .filter_map(|path| {
+            let info = fs::read(&path)
+                .map_err(anyhow::Error::from)
+                .and_then(|state| Ok(StateInfo::read(&state)?));
+            match info {
+                Ok(info) => Some(SavedState { path, info }),
+                Err(error) => {
+                    log::warn!("Skipping {path:?}: {error:#}");
+                    None
+                }
+            }
+        })
+        .collect();
+    states.sort_by_key(|state| Reverse(state.info.as_ref().map(|info| info.saved_at)));
+    Ok(states)
+}
+
+/// Seconds since the Unix epoch, to stamp save states with.
+fn unix_time() -> u64 {
+    #[cfg(not(target_arch = "wasm32"))]
+    {
+        SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |since| since.as_secs())
+    }
+    #[cfg(target_arch = "wasm32")]
+    {
+        (js_sys::Date::now() / 1000.0) as u64
+    }
+}
+
+/// Where bundles of the game's saves are exported to, next to the ROM.
+#[cfg(not(target_arch = "wasm32"))]
+pub fn bundle_path(options: &Options) -> Result<PathBuf> {
+    Ok(options
+        .rom_file_name
+        .as_ref()
+        .ok_or(anyhow!("No ROM file"))?
+        .with_extension("saves.zip"))
 }
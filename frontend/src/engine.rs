@@ -1,25 +1,67 @@
 // SPDX-License-Identifier: GPL-3.0-or-later
 // Copyright (C) 2023 Robert Hrusecky <jadedpastabowl@gmail.com>
 
-use anyhow::Result;
+#[cfg(not(target_arch = "wasm32"))]
+use std::path::{Path, PathBuf};
+use std::{
+    io::{self, Write as _},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    time::Duration,
+};
+
+use anyhow::{anyhow, Context as _, Result};
 use instant::Instant;
+use iron_boy_core::joypad::Button;
+#[cfg(not(target_arch = "wasm32"))]
+use iron_boy_core::system::StateInfo;
 use pixels::{
-    wgpu::{PresentMode, TextureFormat},
+    wgpu::{Device, PresentMode, SurfaceError, TextureFormat},
     Pixels, PixelsBuilder, SurfaceTexture,
 };
+#[cfg(not(target_arch = "wasm32"))]
+use serde_json::{json, Value};
 use winit::{
     dpi::LogicalSize,
-    event::{Event, KeyboardInput, WindowEvent},
+    event::{ElementState, Event, KeyboardInput, ModifiersState, VirtualKeyCode, WindowEvent},
     event_loop::{ControlFlow, EventLoop, EventLoopProxy},
-    window::WindowBuilder,
+    window::{Fullscreen, Window, WindowBuilder},
 };
 
+#[cfg(all(feature = "scripting", not(target_arch = "wasm32")))]
+use crate::script::Script;
 use crate::{
     audio::{self, Audio},
+    bundle::Bundle,
+    cheats,
     emulator::{self, Cgb},
     event::FrontendEvent,
-    gui::GuiEngine,
+    filter::{FilterRenderer, ScalingFilter},
+    frame_stats::FrameStats,
+    gamepad::{GamepadInput, Gamepads},
+    gui::{GuiEngine, ReportHeader},
+    input::{Hotkey, InputMap, Player},
     options::Options,
+    power::SleepInhibitor,
+    rewind::Rewind,
+    skin::Skin,
+    zip,
+};
+#[cfg(target_arch = "wasm32")]
+use crate::{bundle, download};
+#[cfg(not(target_arch = "wasm32"))]
+use crate::{
+    control::{self, Command},
+    emulator::SavedState,
+    file_name::{FileKind, FileNamer},
+    library::{Library, LibraryWatcher},
+    movie::MovieSession,
+    recorder::Recorder,
+    self_test::SelfTest,
+    settings::{ControllerButtons, Keys, Settings, SettingsWatcher},
+    wav::WavWriter,
 };
 
 #[cfg(target_arch = "wasm32")]
@@ -76,24 +118,157 @@ mod wasm {
 #[cfg(target_arch = "wasm32")]
 use wasm::EngineWindow;
 #[cfg(not(target_arch = "wasm32"))]
-type EngineWindow = winit::window::Window;
+type EngineWindow = Window;
+
+/// Consecutive frames that can fail to render before the GPU device is assumed to be lost.
+const MAX_RENDER_FAILURES: u32 = 30;
+/// How often cartridge RAM is saved while the game is writing to it, so that a crash loses at most
+/// this much progress.
+const AUTOSAVE_INTERVAL: Duration = Duration::from_secs(5);
+
+/// Sleeps until the GUI needs updating, but no more often than frames would run.
+fn idle_control_flow(now: Instant, repaint_after: Duration) -> ControlFlow {
+    match now.checked_add(repaint_after.max(emulator::frame_duration())) {
+        Some(wakeup) => ControlFlow::WaitUntil(wakeup),
+        None => ControlFlow::Wait,
+    }
+}
+
+fn pixels_builder(
+    window: &Window,
+    width: u32,
+    height: u32,
+) -> PixelsBuilder<'static, 'static, '_, Window> {
+    let window_size = window.inner_size();
+    let surface_texture = SurfaceTexture::new(window_size.width, window_size.height, window);
+    PixelsBuilder::new(width, height, surface_texture)
+        .texture_format(TextureFormat::Rgba8Unorm)
+        // .surface_texture_format(TextureFormat::Bgra8Unorm)
+        .surface_texture_format(TextureFormat::Rgba8Unorm)
+        .present_mode(PresentMode::Fifo)
+}
+
+/// Flags uncaptured errors on `device`, which usually mean it was lost, instead of panicking.
+fn watch_device(device: &Device, device_lost: &Arc<AtomicBool>) {
+    let device_lost = Arc::clone(device_lost);
+    device.on_uncaptured_error(Box::new(move |error| {
+        log::error!("GPU error: {error}");
+        device_lost.store(true, Ordering::Relaxed);
+    }));
+}
+
+/// Where to save files like screenshots: next to the ROM, or else the working directory.
+#[cfg(not(target_arch = "wasm32"))]
+fn output_dir(options: &Options) -> &Path {
+    options
+        .rom_file_name
+        .as_deref()
+        .and_then(Path::parent)
+        .unwrap_or(".".as_ref())
+}
+
+/// The running game's save states, for the state browser. Failing to list them isn't worth
+/// stopping the game over.
+#[cfg(not(target_arch = "wasm32"))]
+fn list_states(options: &Options) -> Vec<SavedState> {
+    emulator::saved_states(options).unwrap_or_else(|error| {
+        log::warn!("{error:#}");
+        Vec::new()
+    })
+}
+
+/// Lists the ROMs in `dir` in the library and watches it for more, or empties the library without
+/// a folder. The games are looked up in the ROM database at `source`, if there is one.
+#[cfg(not(target_arch = "wasm32"))]
+fn open_library(
+    dir: Option<&Path>,
+    source: Option<&str>,
+    library: &mut Library,
+    proxy: EventLoopProxy<FrontendEvent>,
+) -> Result<Option<LibraryWatcher>> {
+    library.clear();
+    let Some(dir) = dir else {
+        return Ok(None);
+    };
+    // Watching first means a ROM added during the scan still turns up
+    let watcher = LibraryWatcher::new(dir, proxy.clone())?;
+    library.scan(dir)?;
+    library.look_up(source, &proxy);
+    Ok(Some(watcher))
+}
+
+/// A cartridge save that failed, waiting on the user to retry or give up.
+struct FailedSave {
+    /// Whether to exit once it's resolved
+    exit: bool,
+    was_paused: bool,
+}
 
 pub struct Engine {
     proxy: EventLoopProxy<FrontendEvent>,
     gui: GuiEngine,
     audio: Audio,
     pixels: Pixels,
+    filter_renderer: FilterRenderer,
+    /// The filter being drawn with, which the GUI's choice is checked against for changes
+    filter: ScalingFilter,
+    /// The stereo width last settled on in the GUI, which is remembered for the running game
+    stereo_width: u8,
     cgb: Option<Cgb>,
+    input_map: InputMap,
+    /// Missing if controllers aren't supported here
+    gamepads: Option<Gamepads>,
+    skin: Option<Skin>,
     window: EngineWindow,
     options: Options,
+    render_failures: u32,
+    device_lost: Arc<AtomicBool>,
+    rebuilding_renderer: bool,
+    paused: bool,
+    /// Whether no frames ran in the last update, so the event loop is waiting on input or the GUI
+    /// rather than the next frame
+    idle: bool,
+    sleep_inhibitor: SleepInhibitor,
+    modifiers: ModifiersState,
+    rewind: Rewind,
+    /// Whether the rewind key is held
+    rewinding: bool,
+    last_autosave: Instant,
+    frame_stats: FrameStats,
+    #[cfg(not(target_arch = "wasm32"))]
+    file_namer: FileNamer,
+    failed_save: Option<FailedSave>,
+    #[cfg(not(target_arch = "wasm32"))]
+    recorder: Option<Recorder>,
+    #[cfg(not(target_arch = "wasm32"))]
+    audio_dump: Option<WavWriter>,
+    /// A movie of input being recorded or played
+    #[cfg(not(target_arch = "wasm32"))]
+    movie: Option<MovieSession>,
+    /// Running instead of a game with `--self-test`
+    #[cfg(not(target_arch = "wasm32"))]
+    self_test: Option<SelfTest>,
+    /// Given with `--script`
+    #[cfg(all(feature = "scripting", not(target_arch = "wasm32")))]
+    script: Option<Script>,
+    #[cfg(not(target_arch = "wasm32"))]
+    settings: Settings,
+    /// Missing if the settings file can't be watched, in which case edits apply next time
+    #[cfg(not(target_arch = "wasm32"))]
+    _settings_watcher: Option<SettingsWatcher>,
+    /// Missing without a ROM folder in the settings
+    #[cfg(not(target_arch = "wasm32"))]
+    _library_watcher: Option<LibraryWatcher>,
 }
 
 impl Engine {
     pub async fn new(event_loop: &EventLoop<FrontendEvent>, options: Options) -> Result<Self> {
-        let size = LogicalSize::new(
-            emulator::SCREEN_WIDTH as u16,
-            emulator::SCREEN_HEIGHT as u16,
-        );
+        let skin = options.skin.map(Skin::new);
+        let (width, height) = match skin {
+            Some(_) => (Skin::WIDTH, Skin::HEIGHT),
+            None => (emulator::SCREEN_WIDTH, emulator::SCREEN_HEIGHT),
+        };
+        let size = LogicalSize::new(width as u16, height as u16);
         let window = WindowBuilder::new()
             .with_title("Iron Boy")
             .with_inner_size(size)
@@ -102,31 +277,18 @@ impl Engine {
 
         #[cfg(target_arch = "wasm32")]
         let window = wasm::attach_window(window);
+        #[cfg(target_arch = "wasm32")]
+        crate::gui::listen_for_transfers(&event_loop.create_proxy())?;
 
         let window_size = window.inner_size();
         let scale_factor = window.scale_factor() as f32;
-        let pixels = {
-            #[cfg(target_arch = "wasm32")]
-            let window = &*window;
-            #[cfg(not(target_arch = "wasm32"))]
-            let window = &window;
-
-            let surface_texture =
-                SurfaceTexture::new(window_size.width, window_size.height, window);
-            PixelsBuilder::new(
-                emulator::SCREEN_WIDTH as u32,
-                emulator::SCREEN_HEIGHT as u32,
-                surface_texture,
-            )
-            .texture_format(TextureFormat::Rgba8Unorm)
-            // .surface_texture_format(TextureFormat::Bgra8Unorm)
-            .surface_texture_format(TextureFormat::Rgba8Unorm)
-            .present_mode(PresentMode::Fifo)
+        let mut pixels = pixels_builder(&window, width as u32, height as u32)
             .build_async()
-            .await?
-        };
+            .await?;
+        let device_lost = Arc::new(AtomicBool::new(false));
+        watch_device(pixels.device(), &device_lost);
 
-        let gui = GuiEngine::new(
+        let mut gui = GuiEngine::new(
             event_loop,
             window_size.width,
             window_size.height,
@@ -134,6 +296,140 @@ impl Engine {
             pixels.device(),
             pixels.render_texture_format(),
         )?;
+        gui.ui.overclocked = options.overclock > 0;
+        gui.ui.model = options.model;
+        gui.ui.mbc = options.mapper;
+        gui.ui.dmg_palette = options.dmg_palette;
+        gui.ui.skip_boot_rom = options.skip_boot_rom;
+        #[cfg(not(target_arch = "wasm32"))]
+        {
+            gui.ui.boot_rom = options.boot_rom.clone();
+        }
+        // Broken settings shouldn't stop the emulator from starting, so fall back on the defaults
+        #[cfg(not(target_arch = "wasm32"))]
+        let settings = Settings::load().unwrap_or_else(|error| {
+            log::warn!("{error:#}");
+            Settings::default()
+        });
+        #[cfg(not(target_arch = "wasm32"))]
+        let options = Options {
+            ram_sizes: settings.ram_sizes.clone(),
+            ..options
+        };
+        #[cfg(not(target_arch = "wasm32"))]
+        let self_test = options.self_test.then(SelfTest::new);
+        #[cfg(not(target_arch = "wasm32"))]
+        let mut cgb = if options.self_test {
+            Some(SelfTest::cgb(&options)?)
+        } else {
+            Cgb::new(&options).ok()
+        };
+        #[cfg(target_arch = "wasm32")]
+        let mut cgb = Cgb::new(&options).ok();
+        #[cfg(not(target_arch = "wasm32"))]
+        if let (Some(_), Some(path), false) = (&cgb, &options.rom_file_name, options.self_test) {
+            if let Err(error) = gui.ui.recent.push(path.to_path_buf()) {
+                log::warn!("{error:#}");
+            }
+        }
+        gui.ui
+            .compat
+            .set_game(cgb.as_ref().map(|cgb| ReportHeader::new(cgb, &options)));
+        gui.ui
+            .rom_info
+            .set_game(cgb.as_ref().map(|cgb| cgb.header().clone()));
+        gui.ui.cheats.set_game(cgb.as_ref().map(Cgb::checksums));
+        if let Some(cgb) = &mut cgb {
+            cgb.set_cheats(gui.ui.cheats.enabled());
+        }
+        #[cfg(not(target_arch = "wasm32"))]
+        if let Some(&filter) = cgb
+            .as_ref()
+            .and_then(|cgb| settings.filters.get(&cgb.title()))
+        {
+            gui.ui.filter = filter;
+        }
+        #[cfg(not(target_arch = "wasm32"))]
+        if let Some(&width) = cgb
+            .as_ref()
+            .and_then(|cgb| settings.stereo_widths.get(&cgb.title()))
+        {
+            gui.ui.stereo_width = width;
+        }
+        #[cfg(not(target_arch = "wasm32"))]
+        if cgb.is_some() {
+            gui.ui.states.set_states(list_states(&options));
+        }
+        let stereo_width = gui.ui.stereo_width;
+        let filter = gui.ui.filter;
+        let filter_renderer = FilterRenderer::new(
+            &pixels,
+            window_size.width,
+            window_size.height,
+            gui.ui.display_options(),
+        );
+        if let Some(skin) = &skin {
+            skin.draw_shell(pixels.frame_mut());
+        }
+        let gamepads = match Gamepads::new() {
+            Ok(gamepads) => Some(gamepads),
+            // The keyboard still works, so this isn't worth stopping over
+            Err(error) => {
+                log::warn!("{error:#}");
+                None
+            }
+        };
+        gui.ui.gamepads = gamepads.as_ref().map(Gamepads::names).unwrap_or_default();
+        #[cfg(not(target_arch = "wasm32"))]
+        if let Some(port) = options.control_port {
+            let token = options
+                .control_token
+                .clone()
+                .or_else(|| settings.control_token.clone())
+                .context("The control port needs a token, from --control-token or the settings")?;
+            control::listen(port, token, event_loop.create_proxy())?;
+        }
+        #[allow(unused_mut)]
+        let mut input_map = InputMap::default();
+        #[cfg(not(target_arch = "wasm32"))]
+        {
+            input_map.set_bindings(Player::One, settings.keys.bindings());
+            input_map.set_controller_bindings(settings.controller.bindings());
+            gui.ui.color_transform = settings.video.color_transform;
+            if let Some(cgb) = &mut cgb {
+                cgb.set_ppu_config(settings.video.clone());
+            }
+        }
+        #[cfg(all(feature = "scripting", not(target_arch = "wasm32")))]
+        let script = options.script.as_deref().map(Script::load).transpose()?;
+        #[cfg(not(target_arch = "wasm32"))]
+        let movie = match (&options.movie, &mut cgb) {
+            (Some(path), Some(cgb)) => Some(MovieSession::play(cgb, path)?),
+            _ => None,
+        };
+        #[cfg(not(target_arch = "wasm32"))]
+        let audio_dump = match &options.dump_audio {
+            Some(path) => {
+                gui.ui.dumping_audio = true;
+                Some(WavWriter::create(path)?)
+            }
+            None => None,
+        };
+        #[cfg(not(target_arch = "wasm32"))]
+        let settings_watcher = SettingsWatcher::new(event_loop.create_proxy())
+            .map_err(|error| log::warn!("{error:#}"))
+            .ok();
+        #[cfg(not(target_arch = "wasm32"))]
+        let library_watcher = open_library(
+            settings.rom_dir.as_deref(),
+            settings.rom_database.as_deref(),
+            &mut gui.ui.library,
+            event_loop.create_proxy(),
+        )
+        .unwrap_or_else(|error| {
+            log::warn!("{error:#}");
+            None
+        });
 
         Ok(Self {
             proxy: event_loop.create_proxy(),
@@ -141,11 +437,135 @@ impl Engine {
             window,
             audio: audio::init()?,
             pixels,
-            cgb: Cgb::new(&options).ok(),
+            filter_renderer,
+            filter,
+            stereo_width,
+            cgb,
+            input_map,
+            gamepads,
+            skin,
+            #[cfg(not(target_arch = "wasm32"))]
+            file_namer: FileNamer::new(options.file_name_template.clone()),
             options,
+            render_failures: 0,
+            device_lost,
+            rebuilding_renderer: false,
+            paused: false,
+            idle: true,
+            sleep_inhibitor: SleepInhibitor::new(),
+            modifiers: ModifiersState::empty(),
+            rewind: Rewind::new(),
+            rewinding: false,
+            last_autosave: Instant::now(),
+            frame_stats: FrameStats::new(),
+            failed_save: None,
+            #[cfg(not(target_arch = "wasm32"))]
+            recorder: None,
+            #[cfg(not(target_arch = "wasm32"))]
+            audio_dump,
+            #[cfg(not(target_arch = "wasm32"))]
+            movie,
+            #[cfg(not(target_arch = "wasm32"))]
+            self_test,
+            #[cfg(all(feature = "scripting", not(target_arch = "wasm32")))]
+            script,
+            #[cfg(not(target_arch = "wasm32"))]
+            settings,
+            #[cfg(not(target_arch = "wasm32"))]
+            _settings_watcher: settings_watcher,
+            #[cfg(not(target_arch = "wasm32"))]
+            _library_watcher: library_watcher,
         })
     }
 
+    fn render(&mut self) -> Result<()> {
+        if self.rebuilding_renderer {
+            return Ok(());
+        }
+        let start = Instant::now();
+        let result = self.pixels.render_with(|encoder, render_target, context| {
+            self.filter_renderer.render(encoder, render_target);
+
+            self.gui
+                .render(encoder, render_target, &context.device, &context.queue);
+
+            Ok(())
+        });
+        self.frame_stats.add_render(start.elapsed());
+        match result {
+            Ok(()) => self.render_failures = 0,
+            // Pixels reconfigures the surface before trying again, which usually fixes these by the
+            // next frame, e.g. after a resume from suspend or a monitor change
+            Err(pixels::Error::Surface(
+                error @ (SurfaceError::Lost | SurfaceError::Outdated | SurfaceError::Timeout),
+            )) => {
+                log::warn!("Skipped a frame: {error}");
+                self.render_failures += 1;
+            }
+            Err(pixels::Error::Surface(SurfaceError::OutOfMemory)) => {
+                self.device_lost.store(true, Ordering::Relaxed);
+            }
+            Err(error) => return Err(error.into()),
+        }
+        if self.render_failures >= MAX_RENDER_FAILURES
+            || self.device_lost.swap(false, Ordering::Relaxed)
+        {
+            self.rebuild_renderer()?;
+        }
+        Ok(())
+    }
+
+    /// Replaces the renderer with one on a fresh device.
+    fn rebuild_renderer(&mut self) -> Result<()> {
+        log::warn!("The GPU device was lost, rebuilding the renderer");
+        self.render_failures = 0;
+        let extent = self.pixels.context().texture_extent;
+
+        #[cfg(not(target_arch = "wasm32"))]
+        {
+            let pixels = pixels_builder(&self.window, extent.width, extent.height).build()?;
+            self.replace_renderer(pixels);
+        }
+
+        #[cfg(target_arch = "wasm32")]
+        {
+            use std::rc::Rc;
+
+            self.rebuilding_renderer = true;
+            let window = Rc::clone(&self.window);
+            let proxy = self.proxy.clone();
+            wasm_bindgen_futures::spawn_local(async move {
+                let event = match pixels_builder(&window, extent.width, extent.height)
+                    .build_async()
+                    .await
+                {
+                    Ok(pixels) => FrontendEvent::NewRenderer(pixels),
+                    Err(error) => FrontendEvent::Error(error.into()),
+                };
+                // Sending only fails once the event loop is gone, at which point nobody cares
+                let _ = proxy.send_event(event);
+            });
+        }
+        Ok(())
+    }
+
+    fn replace_renderer(&mut self, mut pixels: Pixels) {
+        // Carry over the last frame, which includes the skin's shell that is only drawn once
+        pixels.frame_mut().copy_from_slice(self.pixels.frame());
+        watch_device(pixels.device(), &self.device_lost);
+        self.gui
+            .rebuild_renderer(pixels.device(), pixels.render_texture_format());
+        let size = self.window.inner_size();
+        self.filter_renderer = FilterRenderer::new(
+            &pixels,
+            size.width,
+            size.height,
+            self.gui.ui.display_options(),
+        );
+        self.pixels = pixels;
+        self.rebuilding_renderer = false;
+    }
+
     fn handle_event_impl(
         &mut self,
         event: Event<FrontendEvent>,
@@ -154,52 +574,183 @@ impl Engine {
         match event {
             Event::MainEventsCleared => {
                 let now = Instant::now();
-                let target = if let ControlFlow::WaitUntil(target) = *control_flow {
-                    target
-                } else {
-                    now
+                // While idle, anything that woke the event loop is worth updating for, like input
+                // or a game starting
+                let target = match *control_flow {
+                    ControlFlow::WaitUntil(target) if !self.idle => target,
+                    _ => now,
                 };
                 if target > now {
                     // Not enough time has elapsed yet; nothing to do
                     return Ok(());
                 }
-                self.gui.update(&self.window, &self.proxy)?;
+                self.poll_gamepads();
+                let repaint_after =
+                    self.gui
+                        .update(&self.window, &self.proxy, &mut self.input_map)?;
+                #[cfg(not(target_arch = "wasm32"))]
+                self.save_keys()?;
+                self.apply_filter()?;
+                self.apply_stereo_width()?;
+                self.apply_color_transform()?;
+                self.audio.set_speed(self.gui.ui.speed);
+                #[cfg(feature = "tools")]
+                self.apply_highlight();
                 self.window.request_redraw();
+                // Frames always run to completion, so pausing only ever takes effect between them
+                self.idle = self.cgb.is_none() || self.paused;
+                self.sleep_inhibitor.set_inhibited(!self.idle);
+                if self.idle {
+                    if let Some(gamepads) = &mut self.gamepads {
+                        gamepads.stop_rumble();
+                    }
+                    self.frame_stats.pause();
+                    *control_flow = idle_control_flow(now, repaint_after);
+                    return Ok(());
+                }
                 let Some(cgb) = &mut self.cgb else {
-                    *control_flow = ControlFlow::Poll;
                     return Ok(());
                 };
-                let wakeup = target + cgb.compute_next_frame(&mut self.pixels, &mut self.audio);
-                *control_flow = ControlFlow::WaitUntil(wakeup);
-            }
-            Event::RedrawRequested(window_id) if window_id == self.window.id() => {
-                self.pixels
-                    .render_with(|encoder, render_target, context| {
-                        context.scaling_renderer.render(encoder, render_target);
-
-                        self.gui
-                            .render(encoder, render_target, &context.device, &context.queue);
-
-                        Ok(())
-                    })
-                    .unwrap();
+                let frame_buff = match &mut self.skin {
+                    Some(skin) => skin.screen_mut(),
+                    None => emulator::frame_buffer(&mut self.pixels),
+                };
+                cgb.set_muted_channels(self.gui.ui.muted_channels);
+                cgb.set_dmg_palette(self.gui.ui.dmg_palette);
+                cgb.set_socd(self.gui.ui.socd);
+                self.rewind.set_interval(self.gui.ui.rewind_interval);
+                let start = Instant::now();
+                let duration = if self.rewinding {
+                    self.rewind.step_back(cgb, self.gui.ui.rewind_speed)?;
+                    self.gui.ui.rewind = Some(self.rewind.buffered());
+                    cgb.compute_next_frame_muted(frame_buff)
+                } else {
+                    #[cfg(not(target_arch = "wasm32"))]
+                    if let Some(movie) = &mut self.movie {
+                        if !movie.frame(cgb) {
+                            // Played to the end
+                            self.movie.take().unwrap().finish(cgb)?;
+                        }
+                        self.gui.ui.movie_progress =
+                            self.movie.as_ref().and_then(MovieSession::progress);
+                    }
+                    #[cfg(not(target_arch = "wasm32"))]
+                    let duration = cgb.compute_next_frame(frame_buff, &mut self.audio, |frame| {
+                        if let Some(recorder) = &mut self.recorder {
+                            recorder.push_audio(frame);
+                        }
+                        if let Some(wav) = &mut self.audio_dump {
+                            wav.push_frame(frame);
+                        }
+                    });
+                    #[cfg(target_arch = "wasm32")]
+                    let duration = cgb.compute_next_frame(frame_buff, &mut self.audio, |_| ());
+                    self.gui.ui.audio_latency = self.audio.latency();
+                    self.rewind.record(cgb);
+                    duration
+                };
+                let audio_pushed = if self.rewinding {
+                    0
+                } else {
+                    self.audio.frames_pushed()
+                };
+                self.frame_stats
+                    .push(start, start.elapsed(), audio_pushed, self.audio.queue_len());
+                self.gui.ui.frame_times = self.frame_stats.recent_average();
+                #[cfg(not(target_arch = "wasm32"))]
+                if let Some(recorder) = &mut self.recorder {
+                    if let Err(error) = recorder.push_frame(frame_buff, duration) {
+                        self.recorder = None;
+                        self.gui.ui.recording = false;
+                        return Err(error.context("Stopped recording"));
+                    }
+                }
+                #[cfg(not(target_arch = "wasm32"))]
+                if let Some(wav) = &mut self.audio_dump {
+                    if let Err(error) = wav.write_pending() {
+                        self.audio_dump = None;
+                        self.gui.ui.dumping_audio = false;
+                        return Err(error.context("Stopped writing audio"));
+                    }
+                }
+                #[cfg(not(target_arch = "wasm32"))]
+                if let Some(self_test) = &mut self.self_test {
+                    self_test.frame(
+                        cgb,
+                        frame_buff,
+                        &self.audio,
+                        &self.input_map,
+                        &self.gui.ui.gamepads,
+                    );
+                }
+                if let Some(skin) = &self.skin {
+                    skin.present(self.pixels.frame_mut());
+                }
+                let events: Vec<_> = cgb.events().collect();
+                if let Some(gamepads) = &mut self.gamepads {
+                    gamepads.follow_rumble(&events);
+                }
+                #[cfg(feature = "tools")]
+                self.gui.ui.capture_tools(cgb, events);
+                let mut serial = Vec::new();
+                cgb.take_serial(&mut serial);
+                if !serial.is_empty() {
+                    if self.options.echo_serial {
+                        let mut stdout = io::stdout().lock();
+                        let _ = stdout.write_all(&serial);
+                        let _ = stdout.flush();
+                    }
+                    #[cfg(feature = "tools")]
+                    self.gui.ui.serial_console.extend(&serial);
+                }
+                #[cfg(all(feature = "scripting", not(target_arch = "wasm32")))]
+                if let (false, Some(script)) = (self.rewinding, &mut self.script) {
+                    if let Err(error) = script.frame(&mut self.cgb) {
+                        self.script = None;
+                        return Err(error.context("Stopped the script"));
+                    }
+                }
+                // Scheduling frames further apart or closer together is all it takes to change
+                // the speed, since each one is emulated in full
+                let duration = duration.mul_f64(100.0 / f64::from(self.gui.ui.speed));
+                *control_flow = ControlFlow::WaitUntil(target + duration);
+                self.autosave(now)?;
             }
+            Event::RedrawRequested(window_id) if window_id == self.window.id() => self.render()?,
             Event::WindowEvent { window_id, event }
                 if window_id == self.window.id() && !self.gui.handle_event(&event) =>
             {
                 match event {
                     WindowEvent::CloseRequested => {
-                        if let Some(cgb) = &mut self.cgb {
-                            cgb.handle_close(&self.options)?;
+                        if !self.flush_cart(true) {
+                            return Ok(());
+                        }
+                        #[cfg(not(target_arch = "wasm32"))]
+                        if let Err(error) = self.stop_recording() {
+                            log::error!("{error:#}");
                         }
+                        #[cfg(not(target_arch = "wasm32"))]
+                        if let Err(error) = self.stop_audio_dump() {
+                            log::error!("{error:#}");
+                        }
+                        #[cfg(not(target_arch = "wasm32"))]
+                        if let Err(error) = self.stop_movie() {
+                            log::error!("{error:#}");
+                        }
+                        self.save_layout();
                         *control_flow = ControlFlow::Exit;
                         return Ok(());
                     }
+                    // There is no reliable close event on the web, so also save whenever focus is
+                    // lost
+                    WindowEvent::Focused(false) => self.save_layout(),
+                    WindowEvent::ModifiersChanged(modifiers) => self.modifiers = modifiers,
                     WindowEvent::ScaleFactorChanged { scale_factor, .. } => {
                         self.gui.set_scale_factor(scale_factor);
                     }
                     WindowEvent::Resized(size) => {
                         self.pixels.resize_surface(size.width, size.height)?;
+                        self.filter_renderer.resize(size.width, size.height);
                         self.gui.resize(size.into());
                     }
                     WindowEvent::KeyboardInput {
@@ -211,31 +762,962 @@ impl Engine {
                             },
                         ..
                     } => {
-                        if let Some(cgb) = &mut self.cgb {
-                            cgb.handle_key(key, state)
+                        // Alt+Enter is fixed rather than a binding, since it's what players expect
+                        // from most every other program
+                        if key == VirtualKeyCode::Return && self.modifiers.alt() {
+                            if state == ElementState::Pressed {
+                                self.toggle_fullscreen();
+                            }
+                        } else if let Some(hotkey) = self.input_map.hotkey(key) {
+                            self.handle_hotkey(hotkey, state)?;
+                        } else if let (Some(cgb), Some((Player::One, button))) =
+                            (&mut self.cgb, self.input_map.lookup(key))
+                        {
+                            // Only a single instance is emulated for now, so only player 1's
+                            // bindings drive the joypad
+                            cgb.handle_joypad(button, state)
                         }
                     }
                     _ => (),
                 }
             }
             Event::UserEvent(event) => match event {
+                #[cfg(target_arch = "wasm32")]
                 FrontendEvent::NewRom(rom) => {
-                    let cgb = Cgb::new_from_rom(rom)?;
-                    // Make sure the audio stream has started. On the web, browsers block playing
-                    // audio streams until the user has sufficiently interacted with the page.
-                    self.audio.resume()?;
-                    self.cgb = Some(cgb)
+                    // Keep the progress in the game being replaced
+                    if !self.flush_cart(false) {
+                        return Err(anyhow!(
+                            "Failed to save the running game. Retry or discard the save first."
+                        ));
+                    }
+                    self.apply_hardware_choices();
+                    let cgb = Cgb::new_from_rom(rom.clone(), &self.options)?;
+                    let recent = self.gui.ui.recent.push(cgb.title(), cgb.game_id(), &rom);
+                    if let Err(error) = recent {
+                        log::warn!("{error:#}");
+                    }
+                    self.start_game(cgb)?;
                 }
+                #[cfg(not(target_arch = "wasm32"))]
+                FrontendEvent::OpenRom(path) => self.open_rom(path)?,
                 FrontendEvent::Error(error) => return Err(error),
+                FrontendEvent::ImportCheats(data) => {
+                    self.gui.ui.cheats.import(&String::from_utf8_lossy(&data))?;
+                    self.apply_cheats();
+                }
+                FrontendEvent::CheatsChanged => self.apply_cheats(),
+                FrontendEvent::ExportBundle => self.export_bundle()?,
+                FrontendEvent::ExportFrameTimings => self.export_frame_timings()?,
+                FrontendEvent::ExportSav => self.export_sav()?,
+                FrontendEvent::ImportSaves(data) => self.import_saves(&data)?,
+                #[cfg(not(target_arch = "wasm32"))]
+                FrontendEvent::NewState => self.new_state()?,
+                #[cfg(not(target_arch = "wasm32"))]
+                FrontendEvent::LoadState(path) => self.load_state(&path)?,
+                #[cfg(not(target_arch = "wasm32"))]
+                FrontendEvent::DeleteState(path) => self.delete_state(&path)?,
+                #[cfg(not(target_arch = "wasm32"))]
+                FrontendEvent::ExportState(path) => self.export_state(&path)?,
+                #[cfg(not(target_arch = "wasm32"))]
+                FrontendEvent::ImportState(path) => self.import_state(&path)?,
+                #[cfg(not(target_arch = "wasm32"))]
+                FrontendEvent::SaveCompatReport(report) => self.save_compat_report(&report)?,
+                #[cfg(not(target_arch = "wasm32"))]
+                FrontendEvent::RetrySave(path) => {
+                    if let Some(cgb) = &mut self.cgb {
+                        if let Err(error) = cgb.save_cart(&path) {
+                            log::error!("{error:#}");
+                            self.gui.ui.save_failed.open(error, &path);
+                            return Ok(());
+                        }
+                        cgb.mark_cart_saved();
+                        log::info!("Saved the cartridge to {path:?}");
+                    }
+                    self.resolve_failed_save(control_flow);
+                }
+                #[cfg(target_arch = "wasm32")]
+                FrontendEvent::RetrySave => {
+                    if let Some(cgb) = &mut self.cgb {
+                        if let Err(error) = cgb.handle_close() {
+                            log::error!("{error:#}");
+                            self.gui.ui.save_failed.open(error);
+                            return Ok(());
+                        }
+                        cgb.mark_cart_saved();
+                        log::info!("Saved the cartridge to browser storage");
+                    }
+                    self.resolve_failed_save(control_flow);
+                }
+                FrontendEvent::DiscardSave => self.resolve_failed_save(control_flow),
+                #[cfg(not(target_arch = "wasm32"))]
+                FrontendEvent::ChooseBootRom(path) => {
+                    emulator::load_boot_rom(&path)?;
+                    self.gui.ui.boot_rom = Some(path.into());
+                }
+                #[cfg(not(target_arch = "wasm32"))]
+                FrontendEvent::SettingsChanged => self.reload_settings()?,
+                #[cfg(not(target_arch = "wasm32"))]
+                FrontendEvent::LibraryChanged(paths) => {
+                    for path in paths {
+                        self.gui.ui.library.update(&path);
+                    }
+                    self.gui
+                        .ui
+                        .library
+                        .look_up(self.settings.rom_database.as_deref(), &self.proxy);
+                }
+                #[cfg(not(target_arch = "wasm32"))]
+                FrontendEvent::GameInfoFound(crc, info) => {
+                    self.gui.ui.library.set_info(crc, info);
+                }
+                FrontendEvent::ToggleFullscreen => self.toggle_fullscreen(),
+                #[cfg(feature = "tools")]
+                FrontendEvent::StepInstruction => self.step_instruction(false)?,
+                #[cfg(feature = "tools")]
+                FrontendEvent::StepBack => self.step_instruction(true)?,
+                #[cfg(not(target_arch = "wasm32"))]
+                FrontendEvent::ToggleRecording => self.toggle_recording()?,
+                #[cfg(not(target_arch = "wasm32"))]
+                FrontendEvent::ToggleAudioDump => self.toggle_audio_dump()?,
+                #[cfg(not(target_arch = "wasm32"))]
+                FrontendEvent::ToggleMovieRecording => self.toggle_movie_recording()?,
+                #[cfg(not(target_arch = "wasm32"))]
+                FrontendEvent::PlayMovie(path) => self.play_movie(&path)?,
+                #[cfg(not(target_arch = "wasm32"))]
+                FrontendEvent::Control(command, reply) => {
+                    // Failed commands are the tool's problem, so they go back to it instead of
+                    // popping up
+                    let _ = reply.send(self.handle_control(command));
+                }
+                #[cfg(target_arch = "wasm32")]
+                FrontendEvent::NewRenderer(pixels) => self.replace_renderer(pixels),
             },
             _ => (),
         }
         Ok(())
     }
 
+    fn save_layout(&self) {
+        // Losing the layout isn't worth interrupting the user over
+        if let Err(error) = self.gui.save_layout() {
+            log::warn!("Failed to save GUI layout: {error:#}");
+        }
+    }
+
+    /// Copies the hardware and boot ROM chosen in the GUI into the options, which only the next
+    /// game started picks up.
+    fn apply_hardware_choices(&mut self) {
+        self.options.model = self.gui.ui.model;
+        self.options.mapper = self.gui.ui.mbc;
+        self.options.skip_boot_rom = self.gui.ui.skip_boot_rom;
+        #[cfg(not(target_arch = "wasm32"))]
+        {
+            self.options.boot_rom = self.gui.ui.boot_rom.clone();
+        }
+    }
+
+    fn start_game(&mut self, mut cgb: Cgb) -> Result<()> {
+        // A recording is of one game, and so is a movie
+        #[cfg(not(target_arch = "wasm32"))]
+        {
+            self.stop_recording()?;
+            self.stop_movie()?;
+        }
+        self.gui
+            .ui
+            .compat
+            .set_game(Some(ReportHeader::new(&cgb, &self.options)));
+        self.gui.ui.rom_info.set_game(Some(cgb.header().clone()));
+        self.gui.ui.cheats.set_game(Some(cgb.checksums()));
+        cgb.set_cheats(self.gui.ui.cheats.enabled());
+        #[cfg(not(target_arch = "wasm32"))]
+        {
+            cgb.set_ppu_config(self.settings.video.clone());
+            if let Some(&filter) = self.settings.filters.get(&cgb.title()) {
+                self.gui.ui.filter = filter;
+                self.filter = filter;
+            }
+            if let Some(&width) = self.settings.stereo_widths.get(&cgb.title()) {
+                self.gui.ui.stereo_width = width;
+                self.stereo_width = width;
+            }
+        }
+        // Make sure the audio stream has started. On the web, browsers block playing audio
+        // streams until the user has sufficiently interacted with the page.
+        self.audio.resume()?;
+        self.audio.ramp_in();
+        self.rewind.clear();
+        self.frame_stats.clear();
+        self.cgb = Some(cgb);
+        #[cfg(not(target_arch = "wasm32"))]
+        self.gui.ui.states.set_states(list_states(&self.options));
+        Ok(())
+    }
+
+    /// Applies edits to the settings file.
+    #[cfg(not(target_arch = "wasm32"))]
+    fn reload_settings(&mut self) -> Result<()> {
+        let settings = Settings::load()?;
+        if settings == self.settings {
+            // Probably our own write
+            return Ok(());
+        }
+        log::info!("Reloaded settings");
+        self.input_map
+            .set_bindings(Player::One, settings.keys.bindings());
+        self.input_map
+            .set_controller_bindings(settings.controller.bindings());
+        self.gui.ui.color_transform = settings.video.color_transform;
+        if let Some(cgb) = &mut self.cgb {
+            cgb.set_ppu_config(settings.video.clone());
+            if let Some(&filter) = settings.filters.get(&cgb.title()) {
+                self.gui.ui.filter = filter;
+                self.filter = filter;
+            }
+            if let Some(&width) = settings.stereo_widths.get(&cgb.title()) {
+                self.gui.ui.stereo_width = width;
+                self.stereo_width = width;
+            }
+        }
+        self.options.ram_sizes = settings.ram_sizes.clone();
+        // A new database is worth asking about the games the old one failed on
+        let library_changed = settings.rom_dir != self.settings.rom_dir
+            || settings.rom_database != self.settings.rom_database;
+        self.settings = settings;
+        if library_changed {
+            self._library_watcher = None;
+            self._library_watcher = open_library(
+                self.settings.rom_dir.as_deref(),
+                self.settings.rom_database.as_deref(),
+                &mut self.gui.ui.library,
+                self.proxy.clone(),
+            )?;
+        }
+        Ok(())
+    }
+
+    /// Writes keys and controller buttons remapped in the GUI to the settings file.
+    #[cfg(not(target_arch = "wasm32"))]
+    fn save_keys(&mut self) -> Result<()> {
+        let keys = Keys::new(self.input_map.player(Player::One));
+        let controller = ControllerButtons::new(self.input_map.controller());
+        if keys != self.settings.keys || controller != self.settings.controller {
+            self.settings.keys = keys;
+            self.settings.controller = controller;
+            self.settings.save()?;
+        }
+        Ok(())
+    }
+
+    /// Outlines the object picked in the OAM viewer, wherever the screen is in the texture.
+    #[cfg(feature = "tools")]
+    fn apply_highlight(&mut self) {
+        let (x, y) = match self.skin {
+            Some(_) => Skin::SCREEN_POSITION,
+            None => (0, 0),
+        };
+        let highlight = self
+            .gui
+            .ui
+            .oam_viewer
+            .highlight()
+            .map(|[obj_x, obj_y, width, height]| {
+                [x as f32 + obj_x, y as f32 + obj_y, width, height]
+            });
+        self.filter_renderer
+            .set_highlight(self.pixels.queue(), highlight);
+    }
+
+    /// Switches to the filter picked in the GUI, and remembers it for the running game.
+    fn apply_filter(&mut self) -> Result<()> {
+        let filter = self.gui.ui.filter;
+        self.filter_renderer
+            .set_options(self.pixels.queue(), self.gui.ui.display_options());
+        if filter == self.filter {
+            return Ok(());
+        }
+        self.filter = filter;
+        #[cfg(not(target_arch = "wasm32"))]
+        if let Some(cgb) = &self.cgb {
+            self.settings.filters.insert(cgb.title(), filter);
+            self.settings.save()?;
+        }
+        Ok(())
+    }
+
+    /// Applies the stereo width picked in the GUI, and remembers it for the running game once the
+    /// slider is let go.
+    fn apply_stereo_width(&mut self) -> Result<()> {
+        let width = self.gui.ui.stereo_width;
+        self.audio.set_stereo_width(width);
+        if self.gui.ui.adjusting_stereo_width || width == self.stereo_width {
+            return Ok(());
+        }
+        self.stereo_width = width;
+        #[cfg(not(target_arch = "wasm32"))]
+        if let Some(cgb) = &self.cgb {
+            self.settings.stereo_widths.insert(cgb.title(), width);
+            self.settings.save()?;
+        }
+        Ok(())
+    }
+
+    /// Applies the color transform picked in the GUI, and saves it in the video settings.
+    fn apply_color_transform(&mut self) -> Result<()> {
+        let transform = self.gui.ui.color_transform;
+        if let Some(cgb) = &mut self.cgb {
+            cgb.set_color_transform(transform);
+        }
+        #[cfg(not(target_arch = "wasm32"))]
+        if transform != self.settings.video.color_transform {
+            self.settings.video.color_transform = transform;
+            self.settings.save()?;
+        }
+        Ok(())
+    }
+
+    fn apply_cheats(&mut self) {
+        if let Some(cgb) = &mut self.cgb {
+            cgb.set_cheats(self.gui.ui.cheats.enabled());
+        }
+    }
+
+    /// Writes the running game's battery save, quick save, and cheats to a zip, next to the ROM on
+    /// desktop and as a download on the web.
+    fn export_bundle(&self) -> Result<()> {
+        let cgb = self
+            .cgb
+            .as_ref()
+            .ok_or(anyhow!("Load a game before exporting its saves"))?;
+        let bundle = Bundle {
+            save: cgb.cart_save()?,
+            state: cgb.stored_state(&self.options)?,
+            cheats: cheats::stored(cgb.checksums()),
+        };
+        let id = cgb.game_id();
+        let zip = bundle.to_zip(&id);
+        #[cfg(not(target_arch = "wasm32"))]
+        {
+            let path = emulator::bundle_path(&self.options)?;
+            std::fs::write(&path, zip).with_context(|| format!("Failed to write {path:?}"))?;
+            log::info!("Exported saves to {path:?}");
+        }
+        #[cfg(target_arch = "wasm32")]
+        download::download(&bundle::file_name(&id, "zip"), &zip)?;
+        Ok(())
+    }
+
+    /// Writes the running game's battery save as a `.sav` file, for other emulators and
+    /// flashcarts, next to the ROM on desktop and as a download on the web.
+    fn export_sav(&self) -> Result<()> {
+        let cgb = self
+            .cgb
+            .as_ref()
+            .ok_or(anyhow!("Load a game before exporting its save"))?;
+        let sav = cgb.sav().ok_or(anyhow!("This game doesn't save"))?;
+        #[cfg(not(target_arch = "wasm32"))]
+        {
+            let path = emulator::sav_path(&self.options)?;
+            std::fs::write(&path, sav).with_context(|| format!("Failed to write {path:?}"))?;
+            log::info!("Exported the save to {path:?}");
+        }
+        #[cfg(target_arch = "wasm32")]
+        download::download(&bundle::file_name(&cgb.game_id(), "sav"), &sav)?;
+        Ok(())
+    }
+
+    /// Writes the timings of the last frames as CSV, next to screenshots on desktop and as a
+    /// download on the web.
+    fn export_frame_timings(&mut self) -> Result<()> {
+        let csv = self.frame_stats.to_csv();
+        #[cfg(not(target_arch = "wasm32"))]
+        {
+            let title = self.cgb.as_ref().map(Cgb::title).unwrap_or_default();
+            let path = self.file_namer.next_path(
+                output_dir(&self.options),
+                &title,
+                FileKind::FrameTimings,
+            );
+            std::fs::write(&path, csv).with_context(|| format!("Failed to write {path:?}"))?;
+            log::info!("Exported frame timings to {path:?}");
+        }
+        #[cfg(target_arch = "wasm32")]
+        download::download("frame-timings.csv", csv.as_bytes())?;
+        Ok(())
+    }
+
+    /// Imports either a bundle or a battery save, telling them apart by whether it's a zip.
+    fn import_saves(&mut self, data: &[u8]) -> Result<()> {
+        if zip::is_zip(data) {
+            self.import_bundle(data)
+        } else {
+            self.import_cart_save(data)
+        }
+    }
+
+    /// Replaces the running game's battery save with one in either format, from this or another
+    /// emulator. The game likely needs a reset to notice.
+    fn import_cart_save(&mut self, data: &[u8]) -> Result<()> {
+        let cgb = self
+            .cgb
+            .as_mut()
+            .ok_or(anyhow!("Load a game before importing a save for it"))?;
+        if !cgb.battery_backed() {
+            return Err(anyhow!("This game doesn't save"));
+        }
+        cgb.load_cart_save(data);
+        self.flush_cart(false);
+        log::info!("Imported the save. Reset the game if it doesn't show up.");
+        Ok(())
+    }
+
+    /// Replaces the running game's saves and cheats with the ones in a bundle from
+    /// [`Self::export_bundle`], and picks up from its quick save if it has one.
+    fn import_bundle(&mut self, data: &[u8]) -> Result<()> {
+        let cgb = self
+            .cgb
+            .as_mut()
+            .ok_or(anyhow!("Load a game before importing saves for it"))?;
+        let id = cgb.game_id();
+        let bundle = Bundle::from_zip(data, &id)?;
+        if let Some(save) = &bundle.save {
+            cgb.load_cart_save(save);
+            self.flush_cart(false);
+        }
+        if let Some(cheats) = &bundle.cheats {
+            self.gui.ui.cheats.import(cheats)?;
+            self.apply_cheats();
+        }
+        #[cfg(not(target_arch = "wasm32"))]
+        if bundle.state.is_some() {
+            self.stop_movie()?;
+        }
+        if let (Some(state), Some(cgb)) = (&bundle.state, &mut self.cgb) {
+            cgb.store_state(&self.options, state)?;
+            cgb.restore_state(state)
+                .context("Failed to load the bundle's quick save")?;
+            self.audio.discontinuity();
+            self.rewind.clear();
+        }
+        log::info!("Imported saves for {id}");
+        Ok(())
+    }
+
+    /// Saves cartridge RAM if it's been [`AUTOSAVE_INTERVAL`] since the last autosave and the game
+    /// has written to it since.
+    fn autosave(&mut self, now: Instant) -> Result<()> {
+        if now - self.last_autosave < AUTOSAVE_INTERVAL {
+            return Ok(());
+        }
+        if self.failed_save.is_some() {
+            // Already waiting on the user about the last one
+            return Ok(());
+        }
+        self.last_autosave = now;
+        let Some(cgb) = &mut self.cgb else {
+            return Ok(());
+        };
+        if !cgb.battery_backed() || !cgb.cart_dirty() {
+            return Ok(());
+        }
+        self.flush_cart(false);
+        Ok(())
+    }
+
+    /// Saves the running game's cartridge RAM next to the ROM, or to browser storage on the web,
+    /// and returns whether that worked. If it didn't, the game is paused and the user is asked to
+    /// retry or give up, rather than carrying on and risking their progress. `exit` is whether to
+    /// exit once that's resolved.
+    fn flush_cart(&mut self, exit: bool) -> bool {
+        let Some(cgb) = &mut self.cgb else {
+            return true;
+        };
+        if !cgb.battery_backed() {
+            return true;
+        }
+        #[cfg(target_arch = "wasm32")]
+        let result = cgb.handle_close();
+        #[cfg(not(target_arch = "wasm32"))]
+        let (path, result) = match emulator::cart_path(&self.options) {
+            Ok(path) => {
+                let result = cgb.save_cart(&path);
+                (path, result)
+            }
+            Err(error) => (PathBuf::new(), Err(error)),
+        };
+        let Err(error) = result else {
+            cgb.mark_cart_saved();
+            return true;
+        };
+        log::error!("{error:#}");
+        let (exit, was_paused) = match &self.failed_save {
+            Some(failed) => (exit || failed.exit, failed.was_paused),
+            None => (exit, self.paused),
+        };
+        self.failed_save = Some(FailedSave { exit, was_paused });
+        self.set_paused(true);
+        #[cfg(not(target_arch = "wasm32"))]
+        self.gui.ui.save_failed.open(error, &path);
+        #[cfg(target_arch = "wasm32")]
+        self.gui.ui.save_failed.open(error);
+        false
+    }
+
+    fn resolve_failed_save(&mut self, control_flow: &mut ControlFlow) {
+        let Some(failed) = self.failed_save.take() else {
+            return;
+        };
+        self.gui.ui.save_failed.close();
+        if failed.exit {
+            self.save_layout();
+            *control_flow = ControlFlow::Exit;
+        } else {
+            self.set_paused(failed.was_paused);
+        }
+    }
+
+    fn poll_gamepads(&mut self) {
+        let Some(gamepads) = &mut self.gamepads else {
+            return;
+        };
+        let deadzone = self.gui.ui.gamepad_deadzone;
+        let hotplugged = gamepads.poll(|input| {
+            // A binding being remapped takes the next button press
+            if let GamepadInput::Button(button, ElementState::Pressed) = input {
+                if self.gui.ui.controls.capture_controller(button) {
+                    return;
+                }
+            }
+            let Some(cgb) = &mut self.cgb else {
+                return;
+            };
+            match input {
+                GamepadInput::Button(button, state) => {
+                    if let Some(button) = self.input_map.controller().button(button) {
+                        cgb.handle_joypad(button, state);
+                    }
+                }
+                GamepadInput::Stick(x, y) => cgb.handle_stick(x, y, deadzone),
+                GamepadInput::Disconnected => {
+                    // Whatever it was holding would otherwise stay held
+                    for button in Button::ALL {
+                        cgb.handle_joypad(button, ElementState::Released);
+                    }
+                    cgb.handle_stick(0.0, 0.0, deadzone);
+                }
+            }
+        });
+        if hotplugged {
+            self.gui.ui.gamepads = gamepads.names();
+        }
+    }
+
+    fn set_rewinding(&mut self, rewinding: bool) -> Result<()> {
+        // Rewinding would leave a movie out of step with its input
+        #[cfg(not(target_arch = "wasm32"))]
+        if rewinding {
+            self.stop_movie()?;
+        }
+        if rewinding != self.rewinding {
+            // Rewound frames are silent, so smooth over the sound cutting out and back in
+            self.audio.discontinuity();
+        }
+        self.rewinding = rewinding;
+        if !rewinding {
+            self.gui.ui.rewind = None;
+        }
+        Ok(())
+    }
+
+    fn toggle_fullscreen(&mut self) {
+        let fullscreen = match self.window.fullscreen() {
+            Some(_) => None,
+            None => Some(Fullscreen::Borderless(None)),
+        };
+        self.window.set_fullscreen(fullscreen);
+    }
+
+    fn set_paused(&mut self, paused: bool) {
+        self.paused = paused;
+        self.gui.ui.paused = paused;
+    }
+
+    /// Pauses and runs the next instruction, or goes back to before the last one, then shows where
+    /// that left the game.
+    #[cfg(feature = "tools")]
+    fn step_instruction(&mut self, back: bool) -> Result<()> {
+        if self.cgb.is_none() {
+            return Ok(());
+        }
+        self.set_paused(true);
+        let cgb = self.cgb.as_mut().unwrap();
+        let frame_buff = match &mut self.skin {
+            Some(skin) => skin.screen_mut(),
+            None => emulator::frame_buffer(&mut self.pixels),
+        };
+        let result = if back {
+            self.rewind.step_back_instruction(cgb, frame_buff)
+        } else {
+            cgb.step_instruction(frame_buff);
+            Ok(())
+        };
+        if let Some(skin) = &self.skin {
+            skin.present(self.pixels.frame_mut());
+        }
+        let events = cgb.events().collect();
+        self.gui.ui.capture_tools(cgb, events);
+        self.window.request_redraw();
+        result
+    }
+
+    fn handle_hotkey(&mut self, hotkey: Hotkey, state: ElementState) -> Result<()> {
+        match hotkey {
+            // Rewinding lasts as long as the key is held. Everything else happens on press.
+            Hotkey::Rewind => self.set_rewinding(state == ElementState::Pressed)?,
+            _ if state == ElementState::Released => (),
+            Hotkey::ToggleCheatsheet => self.gui.ui.cheatsheet.toggle(),
+            Hotkey::TogglePause => self.set_paused(!self.paused),
+            Hotkey::ToggleFilter => self.gui.ui.filter = self.gui.ui.filter.next(),
+            Hotkey::ToggleFullscreen => self.toggle_fullscreen(),
+            #[cfg(not(target_arch = "wasm32"))]
+            Hotkey::Screenshot => {
+                self.save_screenshot()?;
+            }
+            Hotkey::SaveState => {
+                if let Some(cgb) = &self.cgb {
+                    let frame_buff = match &mut self.skin {
+                        Some(skin) => skin.screen_mut(),
+                        None => emulator::frame_buffer(&mut self.pixels),
+                    };
+                    cgb.quick_save(&self.options, frame_buff)?;
+                    #[cfg(not(target_arch = "wasm32"))]
+                    self.gui.ui.states.set_states(list_states(&self.options));
+                }
+            }
+            Hotkey::LoadState => {
+                #[cfg(not(target_arch = "wasm32"))]
+                self.stop_movie()?;
+                if let Some(cgb) = &mut self.cgb {
+                    cgb.quick_load(&self.options)?;
+                    self.audio.discontinuity();
+                    self.rewind.clear();
+                }
+            }
+            Hotkey::SaveGame => self.save_game()?,
+        }
+        Ok(())
+    }
+
+    /// Writes out cartridge RAM right away instead of waiting for the next autosave, like before
+    /// copying the save somewhere else.
+    fn save_game(&mut self) -> Result<()> {
+        let Some(cgb) = &self.cgb else {
+            return Ok(());
+        };
+        if !cgb.battery_backed() {
+            log::info!("This game doesn't save");
+            return Ok(());
+        }
+        if !self.flush_cart(false) {
+            return Ok(());
+        }
+        log::info!("Saved the game");
+        Ok(())
+    }
+
+    /// Saves the running game and starts the ROM at `path` in its place, with the saves next to it.
+    /// The running game carries on if either fails.
+    #[cfg(not(target_arch = "wasm32"))]
+    fn open_rom(&mut self, path: PathBuf) -> Result<()> {
+        if !self.flush_cart(false) {
+            return Err(anyhow!(
+                "Failed to save the running game. Retry or discard the save in the emulator first."
+            ));
+        }
+        let old_path = self.options.rom_file_name.replace(path.as_path().into());
+        self.apply_hardware_choices();
+        let cgb = Cgb::new(&self.options).map_err(|error| {
+            self.options.rom_file_name = old_path;
+            error
+        })?;
+        if let Err(error) = self.gui.ui.recent.push(path) {
+            log::warn!("{error:#}");
+        }
+        self.start_game(cgb)
+    }
+
+    #[cfg(not(target_arch = "wasm32"))]
+    fn handle_control(&mut self, command: Command) -> Result<Value> {
+        let no_game = || anyhow!("No game is running");
+        Ok(match command {
+            Command::LoadRom { path } => {
+                self.open_rom(path)?;
+                Value::Null
+            }
+            Command::Pause => {
+                self.set_paused(true);
+                Value::Null
+            }
+            Command::Resume => {
+                self.set_paused(false);
+                Value::Null
+            }
+            Command::SaveState { path } => {
+                let path = path.map_or_else(|| emulator::state_path(&self.options), Ok)?;
+                let cgb = self.cgb.as_ref().ok_or_else(no_game)?;
+                let frame_buff = match &mut self.skin {
+                    Some(skin) => skin.screen_mut(),
+                    None => emulator::frame_buffer(&mut self.pixels),
+                };
+                cgb.save_state(&path, frame_buff)?;
+                self.gui.ui.states.set_states(list_states(&self.options));
+                json!(path)
+            }
+            Command::LoadState { path } => {
+                let path = path.map_or_else(|| emulator::state_path(&self.options), Ok)?;
+                if self.cgb.is_none() {
+                    return Err(no_game());
+                }
+                self.load_state(&path)?;
+                json!(path)
+            }
+            Command::Button { button, pressed } => {
+                let button = Button::ALL
+                    .into_iter()
+                    .find(|b| format!("{b:?}").eq_ignore_ascii_case(&button))
+                    .ok_or_else(|| anyhow!("Unknown button {button:?}"))?;
+                let state = if pressed {
+                    ElementState::Pressed
+                } else {
+                    ElementState::Released
+                };
+                self.cgb
+                    .as_mut()
+                    .ok_or_else(no_game)?
+                    .handle_joypad(button, state);
+                Value::Null
+            }
+            Command::ReadMemory { addr, len } => {
+                json!(self
+                    .cgb
+                    .as_mut()
+                    .ok_or_else(no_game)?
+                    .read_memory(addr, len))
+            }
+            Command::Screenshot => json!(self.save_screenshot()?.ok_or_else(no_game)?),
+        })
+    }
+
+    /// Saves a new state in the game's states folder, with the screen as its thumbnail.
+    #[cfg(not(target_arch = "wasm32"))]
+    fn new_state(&mut self) -> Result<()> {
+        let cgb = self
+            .cgb
+            .as_ref()
+            .ok_or(anyhow!("Load a game before saving a state"))?;
+        let dir = emulator::states_dir(&self.options)?;
+        std::fs::create_dir_all(&dir).with_context(|| format!("Failed to create {dir:?}"))?;
+        let path = self
+            .file_namer
+            .next_path(&dir, &cgb.title(), FileKind::State);
+        let frame_buff = match &mut self.skin {
+            Some(skin) => skin.screen_mut(),
+            None => emulator::frame_buffer(&mut self.pixels),
+        };
+        cgb.save_state(&path, frame_buff)?;
+        self.gui.ui.states.set_states(list_states(&self.options));
+        Ok(())
+    }
+
+    #[cfg(not(target_arch = "wasm32"))]
+    fn load_state(&mut self, path: &Path) -> Result<()> {
+        let Some(cgb) = &mut self.cgb else {
+            return Ok(());
+        };
+        self.stop_movie()?;
+        cgb.load_state(path)?;
+        self.audio.discontinuity();
+        self.rewind.clear();
+        Ok(())
+    }
+
+    #[cfg(not(target_arch = "wasm32"))]
+    fn delete_state(&mut self, path: &Path) -> Result<()> {
+        std::fs::remove_file(path).with_context(|| format!("Failed to delete {path:?}"))?;
+        log::info!("Deleted {path:?}");
+        self.gui.ui.states.set_states(list_states(&self.options));
+        Ok(())
+    }
+
+    /// Writes a copy of a state next to the ROM, to move to another computer.
+    #[cfg(not(target_arch = "wasm32"))]
+    fn export_state(&mut self, path: &Path) -> Result<()> {
+        let title = self.cgb.as_ref().map(Cgb::title).unwrap_or_default();
+        let to = self
+            .file_namer
+            .next_path(output_dir(&self.options), &title, FileKind::State);
+        std::fs::copy(path, &to).with_context(|| format!("Failed to copy {path:?} to {to:?}"))?;
+        log::info!("Exported the state to {to:?}");
+        Ok(())
+    }
+
+    /// Copies a state from elsewhere into the game's states folder, once it's checked to be one.
+    /// Whether it's for this game is only checked when it's loaded.
+    #[cfg(not(target_arch = "wasm32"))]
+    fn import_state(&mut self, path: &Path) -> Result<()> {
+        let cgb = self
+            .cgb
+            .as_ref()
+            .ok_or(anyhow!("Load a game before importing a state for it"))?;
+        let state = std::fs::read(path).with_context(|| format!("Failed to read {path:?}"))?;
+        StateInfo::read(&state).with_context(|| format!("Failed to import {path:?}"))?;
+        let dir = emulator::states_dir(&self.options)?;
+        std::fs::create_dir_all(&dir).with_context(|| format!("Failed to create {dir:?}"))?;
+        let to = self
+            .file_namer
+            .next_path(&dir, &cgb.title(), FileKind::State);
+        std::fs::write(&to, state).with_context(|| format!("Failed to write {to:?}"))?;
+        log::info!("Imported {path:?} to {to:?}");
+        self.gui.ui.states.set_states(list_states(&self.options));
+        Ok(())
+    }
+
+    /// Returns where the screenshot went, or `None` if no game is running.
+    #[cfg(not(target_arch = "wasm32"))]
+    fn save_screenshot(&mut self) -> Result<Option<PathBuf>> {
+        let Some(cgb) = &self.cgb else {
+            return Ok(None);
+        };
+        let path = self.file_namer.next_path(
+            output_dir(&self.options),
+            &cgb.title(),
+            FileKind::Screenshot,
+        );
+        let frame_buff = match &mut self.skin {
+            Some(skin) => skin.screen_mut(),
+            None => emulator::frame_buffer(&mut self.pixels),
+        };
+        emulator::write_screenshot(frame_buff, &path)?;
+        log::info!("Saved a screenshot to {path:?}");
+        Ok(Some(path))
+    }
+
+    #[cfg(not(target_arch = "wasm32"))]
+    fn toggle_recording(&mut self) -> Result<()> {
+        if self.recorder.is_some() {
+            return self.stop_recording();
+        }
+        let Some(cgb) = &self.cgb else {
+            return Ok(());
+        };
+        let path = self.file_namer.next_path(
+            output_dir(&self.options),
+            &cgb.title(),
+            FileKind::Recording(self.gui.ui.recording_format),
+        );
+        self.recorder = Some(Recorder::new(path, self.gui.ui.recording_format)?);
+        self.gui.ui.recording = true;
+        Ok(())
+    }
+
+    #[cfg(not(target_arch = "wasm32"))]
+    fn stop_recording(&mut self) -> Result<()> {
+        self.gui.ui.recording = false;
+        match self.recorder.take() {
+            Some(recorder) => recorder.finish().map(|_| ()),
+            None => Ok(()),
+        }
+    }
+
+    #[cfg(not(target_arch = "wasm32"))]
+    fn toggle_audio_dump(&mut self) -> Result<()> {
+        if self.audio_dump.is_some() {
+            return self.stop_audio_dump();
+        }
+        let Some(cgb) = &self.cgb else {
+            return Ok(());
+        };
+        let path =
+            self.file_namer
+                .next_path(output_dir(&self.options), &cgb.title(), FileKind::AudioDump);
+        self.audio_dump = Some(WavWriter::create(&path)?);
+        self.gui.ui.dumping_audio = true;
+        log::info!("Writing audio to {path:?}");
+        Ok(())
+    }
+
+    #[cfg(not(target_arch = "wasm32"))]
+    fn stop_audio_dump(&mut self) -> Result<()> {
+        self.gui.ui.dumping_audio = false;
+        match self.audio_dump.take() {
+            Some(wav) => wav.finish(),
+            None => Ok(()),
+        }
+    }
+
+    #[cfg(not(target_arch = "wasm32"))]
+    fn toggle_movie_recording(&mut self) -> Result<()> {
+        if self.movie.as_ref().is_some_and(MovieSession::recording) {
+            return self.stop_movie();
+        }
+        self.stop_movie()?;
+        let Some(cgb) = &mut self.cgb else {
+            return Ok(());
+        };
+        let path =
+            self.file_namer
+                .next_path(output_dir(&self.options), &cgb.title(), FileKind::Movie);
+        self.movie = Some(MovieSession::record(cgb, path));
+        self.gui.ui.recording_movie = true;
+        Ok(())
+    }
+
+    #[cfg(not(target_arch = "wasm32"))]
+    fn play_movie(&mut self, path: &Path) -> Result<()> {
+        self.stop_movie()?;
+        let cgb = self
+            .cgb
+            .as_mut()
+            .ok_or(anyhow!("Load a game before playing a movie of it"))?;
+        self.movie = Some(MovieSession::play(cgb, path)?);
+        self.audio.discontinuity();
+        self.rewind.clear();
+        Ok(())
+    }
+
+    /// Saves a movie being recorded, or stops the one playing.
+    #[cfg(not(target_arch = "wasm32"))]
+    fn stop_movie(&mut self) -> Result<()> {
+        self.gui.ui.recording_movie = false;
+        self.gui.ui.movie_progress = None;
+        match (self.movie.take(), &mut self.cgb) {
+            (Some(movie), Some(cgb)) => movie.finish(cgb),
+            _ => Ok(()),
+        }
+    }
+
+    #[cfg(not(target_arch = "wasm32"))]
+    fn save_compat_report(&mut self, report: &str) -> Result<()> {
+        let Some(cgb) = &self.cgb else {
+            return Ok(());
+        };
+        let path = self.file_namer.next_path(
+            output_dir(&self.options),
+            &cgb.title(),
+            FileKind::CompatReport,
+        );
+        std::fs::write(&path, report)?;
+        log::info!("Saved a compatibility report to {path:?}");
+        Ok(())
+    }
+
     pub fn handle_event(&mut self, event: Event<FrontendEvent>, control_flow: &mut ControlFlow) {
         if let Err(error) = self.handle_event_impl(event, control_flow) {
             log::error!("{error:#}");
+            #[cfg(not(target_arch = "wasm32"))]
+            if let Some(self_test) = &mut self.self_test {
+                self_test.fail(&error);
+            }
             self.gui.ui.add_error_popup(error);
         }
     }
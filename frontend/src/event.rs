@@ -2,8 +2,101 @@
 // Copyright (C) 2023 Robert Hrusecky <jadedpastabowl@gmail.com>
 
 use anyhow::Error;
+#[cfg(target_arch = "wasm32")]
+use pixels::Pixels;
+
+#[cfg(not(target_arch = "wasm32"))]
+use std::path::PathBuf;
+
+#[cfg(not(target_arch = "wasm32"))]
+use crate::{
+    control::{Command, Reply},
+    rom_db::GameInfo,
+};
 
 pub enum FrontendEvent {
+    /// A ROM read on the web, where there's no path to open it from
+    #[cfg(target_arch = "wasm32")]
     NewRom(Box<[u8]>),
+    /// A ROM picked in the GUI, to start along with the saves next to it
+    #[cfg(not(target_arch = "wasm32"))]
+    OpenRom(PathBuf),
     Error(Error),
+    /// A `.cht` file to import for the running game
+    ImportCheats(Box<[u8]>),
+    /// Cheats were toggled in the GUI
+    CheatsChanged,
+    /// Write out a bundle of the running game's saves, from the GUI
+    ExportBundle,
+    /// Write out the running game's battery save as a `.sav` file, from the GUI
+    ExportSav,
+    /// A bundle of saves, or a battery save in either format, to import for the running game
+    ImportSaves(Box<[u8]>),
+    /// Write out the timings of the last frames, from the GUI
+    ExportFrameTimings,
+    /// Save a new state to the game's states folder, from the state browser
+    #[cfg(not(target_arch = "wasm32"))]
+    NewState,
+    /// A state picked in the state browser, to load
+    #[cfg(not(target_arch = "wasm32"))]
+    LoadState(PathBuf),
+    /// A state to delete, from the state browser
+    #[cfg(not(target_arch = "wasm32"))]
+    DeleteState(PathBuf),
+    /// A state to write a copy of next to the ROM, from the state browser
+    #[cfg(not(target_arch = "wasm32"))]
+    ExportState(PathBuf),
+    /// A state from elsewhere to copy into the game's states folder, from the state browser
+    #[cfg(not(target_arch = "wasm32"))]
+    ImportState(PathBuf),
+    /// A compatibility report as JSON, to save next to the ROM
+    #[cfg(not(target_arch = "wasm32"))]
+    SaveCompatReport(String),
+    /// Try saving cartridge RAM again after a failure, possibly somewhere else
+    #[cfg(not(target_arch = "wasm32"))]
+    RetrySave(PathBuf),
+    /// Try saving cartridge RAM to browser storage again after a failure
+    #[cfg(target_arch = "wasm32")]
+    RetrySave,
+    /// Give up on cartridge RAM that failed to save
+    DiscardSave,
+    /// A command from the control interface, and where to send its result
+    #[cfg(not(target_arch = "wasm32"))]
+    Control(Command, Reply),
+    /// A boot ROM picked in the GUI, to check before using it for the next game
+    #[cfg(not(target_arch = "wasm32"))]
+    ChooseBootRom(PathBuf),
+    /// Enter or leave fullscreen, from the GUI
+    ToggleFullscreen,
+    /// Start or stop recording, from the GUI
+    #[cfg(not(target_arch = "wasm32"))]
+    ToggleRecording,
+    /// Start or stop recording a movie of input, from the GUI
+    #[cfg(not(target_arch = "wasm32"))]
+    ToggleMovieRecording,
+    /// A movie picked in the GUI, to play from its start
+    #[cfg(not(target_arch = "wasm32"))]
+    PlayMovie(PathBuf),
+    /// Start or stop writing the audio to a WAV file, from the GUI
+    #[cfg(not(target_arch = "wasm32"))]
+    ToggleAudioDump,
+    /// The settings file was edited
+    #[cfg(not(target_arch = "wasm32"))]
+    SettingsChanged,
+    /// Pause and run the next instruction, from the trace window
+    #[cfg(feature = "tools")]
+    StepInstruction,
+    /// Pause and go back to before the last instruction, from the trace window
+    #[cfg(feature = "tools")]
+    StepBack,
+    /// ROMs in the library's folder that were added, written, renamed, or removed
+    #[cfg(not(target_arch = "wasm32"))]
+    LibraryChanged(Vec<PathBuf>),
+    /// What the ROM database had on the game in the library with a CRC
+    #[cfg(not(target_arch = "wasm32"))]
+    GameInfoFound(u32, GameInfo),
+    /// A replacement for a renderer whose device was lost. Only the web has to build it
+    /// asynchronously.
+    #[cfg(target_arch = "wasm32")]
+    NewRenderer(Pixels),
 }
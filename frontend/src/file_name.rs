@@ -0,0 +1,165 @@
+// SPDX-License-Identifier: GPL-3.0-or-later
+// Copyright (C) 2023 Robert Hrusecky <jadedpastabowl@gmail.com>
+
+//! Names for the files the frontend writes, like screenshots. Names come from a template that is
+//! filled in with details about the game and the time, plus a tag that counts up so that nothing
+//! is ever overwritten.
+
+use std::{
+    path::{Path, PathBuf},
+    str::FromStr,
+    time::SystemTime,
+};
+
+use anyhow::{anyhow, bail, Error};
+
+use crate::recorder::RecordingFormat;
+
+/// Names files like `PM_CRYSTAL_2024-05-01_123456_shot007.png`.
+pub const DEFAULT_TEMPLATE: &str = "{title}_{date}_{time}_{kind}";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FileKind {
+    Screenshot,
+    CompatReport,
+    Recording(RecordingFormat),
+    AudioDump,
+    FrameTimings,
+    Movie,
+    State,
+}
+
+impl FileKind {
+    /// None for a directory
+    fn extension(&self) -> Option<&'static str> {
+        match self {
+            Self::Screenshot | Self::Recording(RecordingFormat::Apng) => Some("png"),
+            Self::CompatReport => Some("json"),
+            Self::AudioDump => Some("wav"),
+            Self::FrameTimings => Some("csv"),
+            Self::Movie => Some("ibmv"),
+            Self::State => Some("state"),
+            Self::Recording(RecordingFormat::Frames) => None,
+        }
+    }
+
+    /// Tells apart files of this kind, where `n` counts up to avoid collisions.
+    fn tag(&self, n: usize) -> String {
+        match self {
+            Self::Screenshot => format!("shot{n:03}"),
+            Self::CompatReport => format!("compat{n:03}"),
+            Self::Recording(_) => format!("rec{n:03}"),
+            Self::AudioDump => format!("audio{n:03}"),
+            Self::FrameTimings => format!("timing{n:03}"),
+            Self::Movie => format!("movie{n:03}"),
+            Self::State => format!("state{n:03}"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Part {
+    Text(String),
+    Title,
+    /// UTC date as `YYYY-MM-DD`
+    Date,
+    /// UTC time as `HHMMSS`
+    Time,
+    Kind,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Template(Vec<Part>);
+
+impl FromStr for Template {
+    type Err = Error;
+
+    /// Parses text with `{title}`, `{date}`, `{time}`, and `{kind}` fields. `{kind}` is required,
+    /// since it is what keeps names unique.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut parts = Vec::new();
+        let mut rest = s;
+        while let Some(start) = rest.find('{') {
+            if start > 0 {
+                parts.push(Part::Text(rest[..start].to_owned()));
+            }
+            let end = start
+                + rest[start..]
+                    .find('}')
+                    .ok_or_else(|| anyhow!("Unclosed `{{` in {s:?}"))?;
+            parts.push(match &rest[start + 1..end] {
+                "title" => Part::Title,
+                "date" => Part::Date,
+                "time" => Part::Time,
+                "kind" => Part::Kind,
+                field => bail!(
+                    "Unknown field {{{field}}}, expected {{title}}, {{date}}, {{time}}, or {{kind}}"
+                ),
+            });
+            rest = &rest[end + 1..];
+        }
+        if !rest.is_empty() {
+            parts.push(Part::Text(rest.to_owned()));
+        }
+        if !parts.contains(&Part::Kind) {
+            bail!("{s:?} is missing {{kind}}, which keeps names from colliding");
+        }
+        Ok(Self(parts))
+    }
+}
+
+impl Default for Template {
+    fn default() -> Self {
+        DEFAULT_TEMPLATE.parse().unwrap()
+    }
+}
+
+/// Picks paths for new files without overwriting existing ones.
+pub struct FileNamer {
+    template: Template,
+    next: usize,
+}
+
+impl FileNamer {
+    pub fn new(template: Template) -> Self {
+        Self { template, next: 1 }
+    }
+
+    /// A path in `dir` for a new file of `kind` for the game with `title` that doesn't exist yet.
+    pub fn next_path(&mut self, dir: &Path, title: &str, kind: FileKind) -> PathBuf {
+        let mut title: String = title
+            .chars()
+            .filter(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '_'))
+            .collect();
+        if title.is_empty() {
+            title = "untitled".into();
+        }
+        // e.g. 2024-05-01T12:34:56Z
+        let timestamp = humantime::format_rfc3339_seconds(SystemTime::now()).to_string();
+        let (date, time) = (&timestamp[..10], timestamp[11..19].replace(':', ""));
+
+        loop {
+            let tag = kind.tag(self.next);
+            self.next += 1;
+            let name: String = self
+                .template
+                .0
+                .iter()
+                .map(|part| match part {
+                    Part::Text(text) => text,
+                    Part::Title => &title,
+                    Part::Date => date,
+                    Part::Time => &time,
+                    Part::Kind => &tag,
+                })
+                .collect();
+            let path = match kind.extension() {
+                Some(extension) => dir.join(format!("{name}.{extension}")),
+                None => dir.join(name),
+            };
+            if !path.exists() {
+                return path;
+            }
+        }
+    }
+}
@@ -0,0 +1,297 @@
+// SPDX-License-Identifier: GPL-3.0-or-later
+// Copyright (C) 2023 Robert Hrusecky <jadedpastabowl@gmail.com>
+
+//! Display filters for the screen, drawn by a shader in place of Pixels' own scaling renderer,
+//! along with how the screen is scaled to fit the window. CGB color correction can go with any
+//! filter. The whole texture is filtered, so with a skin, the shell is too. Debug tools can also
+//! have a rectangle outlined over it.
+
+use std::fmt::{self, Display, Formatter};
+
+use pixels::{
+    wgpu::{
+        self, BindGroup, Buffer, Color, CommandEncoder, FilterMode, LoadOp, Operations, Queue,
+        RenderPassColorAttachment, RenderPassDescriptor, RenderPipeline, TextureView,
+    },
+    Pixels,
+};
+use serde::{Deserialize, Serialize};
+
+/// Numbered like `filter.wgsl` expects.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ScalingFilter {
+    #[default]
+    Nearest,
+    Bilinear,
+    LcdGrid,
+    Scanlines,
+}
+
+impl ScalingFilter {
+    pub const ALL: [ScalingFilter; 4] = [
+        Self::Nearest,
+        Self::Bilinear,
+        Self::LcdGrid,
+        Self::Scanlines,
+    ];
+
+    /// The filter after this one, going back around to the first.
+    pub fn next(self) -> Self {
+        Self::ALL[(self as usize + 1) % Self::ALL.len()]
+    }
+}
+
+impl Display for ScalingFilter {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::Nearest => "Sharp",
+            Self::Bilinear => "Smooth",
+            Self::LcdGrid => "LCD grid",
+            Self::Scanlines => "Scanlines",
+        })
+    }
+}
+
+/// How the screen is scaled to fit the window.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ScaleMode {
+    /// By whole numbers, so every pixel is the same size, leaving a border at most window sizes
+    #[default]
+    Integer,
+    /// As big as fits without changing the aspect ratio, letterboxing the rest
+    Fit,
+    Stretch,
+}
+
+impl ScaleMode {
+    pub const ALL: [ScaleMode; 3] = [Self::Integer, Self::Fit, Self::Stretch];
+}
+
+impl Display for ScaleMode {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::Integer => "Whole multiples",
+            Self::Fit => "Keep aspect ratio",
+            Self::Stretch => "Stretch",
+        })
+    }
+}
+
+/// Everything about how the screen is drawn that the GUI picks.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DisplayOptions {
+    pub filter: ScalingFilter,
+    pub scale: ScaleMode,
+}
+
+pub struct FilterRenderer {
+    pipeline: RenderPipeline,
+    bind_group: BindGroup,
+    params: Buffer,
+    options: DisplayOptions,
+    texture_size: (f32, f32),
+    surface_size: (f32, f32),
+    /// The x, y, width, and height of the area of the surface drawn to
+    viewport: [f32; 4],
+    /// Of the outlined rectangle, in texels, which is empty for none
+    highlight: [f32; 4],
+}
+
+impl FilterRenderer {
+    /// Draws `pixels`' texture to a surface `width` by `height` pixels big. It has to be rebuilt
+    /// along with `pixels`.
+    pub fn new(pixels: &Pixels, width: u32, height: u32, options: DisplayOptions) -> Self {
+        let context = pixels.context();
+        let device = &context.device;
+        let module = device.create_shader_module(wgpu::include_wgsl!("filter.wgsl"));
+        let sampler = device.create_sampler(&wgpu::SamplerDescriptor {
+            label: Some("filter_sampler"),
+            mag_filter: FilterMode::Linear,
+            min_filter: FilterMode::Linear,
+            ..Default::default()
+        });
+        let view = context
+            .texture
+            .create_view(&wgpu::TextureViewDescriptor::default());
+        let params = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("filter_params"),
+            size: 32,
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+
+        let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("filter_bind_group_layout"),
+            entries: &[
+                wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Texture {
+                        sample_type: wgpu::TextureSampleType::Float { filterable: true },
+                        multisampled: false,
+                        view_dimension: wgpu::TextureViewDimension::D2,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 1,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 2,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+            ],
+        });
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("filter_bind_group"),
+            layout: &bind_group_layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: wgpu::BindingResource::TextureView(&view),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: wgpu::BindingResource::Sampler(&sampler),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 2,
+                    resource: params.as_entire_binding(),
+                },
+            ],
+        });
+
+        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("filter_pipeline_layout"),
+            bind_group_layouts: &[&bind_group_layout],
+            push_constant_ranges: &[],
+        });
+        let pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: Some("filter_pipeline"),
+            layout: Some(&pipeline_layout),
+            vertex: wgpu::VertexState {
+                module: &module,
+                entry_point: "vs_main",
+                buffers: &[],
+            },
+            primitive: wgpu::PrimitiveState::default(),
+            depth_stencil: None,
+            multisample: wgpu::MultisampleState::default(),
+            fragment: Some(wgpu::FragmentState {
+                module: &module,
+                entry_point: "fs_main",
+                targets: &[Some(wgpu::ColorTargetState {
+                    format: pixels.render_texture_format(),
+                    blend: None,
+                    write_mask: wgpu::ColorWrites::ALL,
+                })],
+            }),
+            multiview: None,
+        });
+
+        let extent = context.texture_extent;
+        let mut renderer = Self {
+            pipeline,
+            bind_group,
+            params,
+            options,
+            texture_size: (extent.width as f32, extent.height as f32),
+            surface_size: (width as f32, height as f32),
+            viewport: [0.0; 4],
+            highlight: [0.0; 4],
+        };
+        renderer.write_params(&context.queue);
+        renderer.fit_viewport();
+        renderer
+    }
+
+    pub fn set_options(&mut self, queue: &Queue, options: DisplayOptions) {
+        if options == self.options {
+            return;
+        }
+        self.options = options;
+        self.write_params(queue);
+        self.fit_viewport();
+    }
+
+    /// Outlines the x, y, width, and height given in texels, or nothing.
+    pub fn set_highlight(&mut self, queue: &Queue, highlight: Option<[f32; 4]>) {
+        let highlight = highlight.unwrap_or_default();
+        if highlight == self.highlight {
+            return;
+        }
+        self.highlight = highlight;
+        self.write_params(queue);
+    }
+
+    fn write_params(&self, queue: &Queue) {
+        let (width, height) = self.texture_size;
+        let mut params = [0; 32];
+        params[0..4].copy_from_slice(&width.to_le_bytes());
+        params[4..8].copy_from_slice(&height.to_le_bytes());
+        params[8..12].copy_from_slice(&(self.options.filter as u32).to_le_bytes());
+        for (i, value) in self.highlight.iter().enumerate() {
+            params[16 + i * 4..][..4].copy_from_slice(&value.to_le_bytes());
+        }
+        queue.write_buffer(&self.params, 0, &params);
+    }
+
+    pub fn resize(&mut self, width: u32, height: u32) {
+        self.surface_size = (width as f32, height as f32);
+        self.fit_viewport();
+    }
+
+    /// Fits the screen to the surface, centered. A window too small for even one whole multiple
+    /// gets the screen shrunk to fit instead.
+    fn fit_viewport(&mut self) {
+        let (width, height) = self.surface_size;
+        let (texture_width, texture_height) = self.texture_size;
+        let (scale_x, scale_y) = (width / texture_width, height / texture_height);
+        let fit = scale_x.min(scale_y);
+        let (scale_x, scale_y) = match self.options.scale {
+            ScaleMode::Integer if fit >= 1.0 => (fit.floor(), fit.floor()),
+            ScaleMode::Integer | ScaleMode::Fit => (fit, fit),
+            ScaleMode::Stretch => (scale_x, scale_y),
+        };
+        let (scaled_width, scaled_height) = (texture_width * scale_x, texture_height * scale_y);
+        self.viewport = [
+            ((width - scaled_width) / 2.0).floor(),
+            ((height - scaled_height) / 2.0).floor(),
+            scaled_width,
+            scaled_height,
+        ];
+    }
+
+    pub fn render(&self, encoder: &mut CommandEncoder, render_target: &TextureView) {
+        let mut pass = encoder.begin_render_pass(&RenderPassDescriptor {
+            label: Some("filter_render_pass"),
+            color_attachments: &[Some(RenderPassColorAttachment {
+                view: render_target,
+                resolve_target: None,
+                ops: Operations {
+                    load: LoadOp::Clear(Color::BLACK),
+                    store: true,
+                },
+            })],
+            depth_stencil_attachment: None,
+        });
+        let [x, y, width, height] = self.viewport;
+        if width < 1.0 || height < 1.0 {
+            // Minimized
+            return;
+        }
+        pass.set_pipeline(&self.pipeline);
+        pass.set_bind_group(0, &self.bind_group, &[]);
+        pass.set_viewport(x, y, width, height, 0.0, 1.0);
+        pass.draw(0..3, 0..1);
+    }
+}
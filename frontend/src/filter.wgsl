@@ -0,0 +1,79 @@
+// Draws the screen texture over the whole viewport, through one of the display filters.
+
+struct Params {
+    texture_size: vec2<f32>,
+    // A `ScalingFilter`
+    mode: u32,
+    // The x, y, width, and height of a rectangle to outline, in texels
+    highlight: vec4<f32>,
+}
+
+@group(0) @binding(0) var screen: texture_2d<f32>;
+@group(0) @binding(1) var screen_sampler: sampler;
+@group(0) @binding(2) var<uniform> params: Params;
+
+const BILINEAR: u32 = 1u;
+const LCD_GRID: u32 = 2u;
+const SCANLINES: u32 = 3u;
+
+const PI: f32 = 3.14159265;
+const HIGHLIGHT_COLOR: vec3<f32> = vec3<f32>(1.0, 0.0, 1.0);
+
+struct VertexOutput {
+    @builtin(position) position: vec4<f32>,
+    @location(0) uv: vec2<f32>,
+}
+
+@vertex
+fn vs_main(@builtin(vertex_index) index: u32) -> VertexOutput {
+    // One triangle covering the viewport, with the texture's top left corner at the top left
+    let uv = vec2<f32>(f32((index << 1u) & 2u), f32(index & 2u));
+    var out: VertexOutput;
+    out.position = vec4<f32>(uv.x * 2.0 - 1.0, 1.0 - uv.y * 2.0, 0.0, 1.0);
+    out.uv = uv;
+    return out;
+}
+
+// Splits each pixel into red, green, and blue columns, with dark gaps between pixels.
+fn lcd_grid(color: vec3<f32>, cell: vec2<f32>) -> vec3<f32> {
+    var mask = vec3<f32>(0.4);
+    let column = u32(cell.x * 3.0);
+    mask[column] = 1.0;
+    let edge = min(min(cell.x, 1.0 - cell.x), min(cell.y, 1.0 - cell.y));
+    return color * mask * mix(0.6, 1.0, smoothstep(0.0, 0.1, edge));
+}
+
+// Darkens the boundary between rows.
+fn scanlines(color: vec3<f32>, cell: vec2<f32>) -> vec3<f32> {
+    return color * mix(0.55, 1.0, sin(cell.y * PI));
+}
+
+// Whether a texel is on the inside edge of the highlighted rectangle.
+fn on_highlight(texel: vec2<f32>) -> bool {
+    let min = params.highlight.xy;
+    let max = min + params.highlight.zw;
+    let inside = all(texel >= min) && all(texel < max);
+    return inside && (any(texel < min + 1.0) || any(texel >= max - 1.0));
+}
+
+@fragment
+fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
+    let texel = in.uv * params.texture_size;
+    var color: vec3<f32>;
+    if params.mode == BILINEAR {
+        color = textureSample(screen, screen_sampler, in.uv).rgb;
+    } else {
+        let coords = clamp(texel, vec2<f32>(0.0), params.texture_size - 1.0);
+        color = textureLoad(screen, vec2<i32>(coords), 0).rgb;
+    }
+    let cell = fract(texel);
+    if params.mode == LCD_GRID {
+        color = lcd_grid(color, cell);
+    } else if params.mode == SCANLINES {
+        color = scanlines(color, cell);
+    }
+    if on_highlight(texel) {
+        color = HIGHLIGHT_COLOR;
+    }
+    return vec4<f32>(color, 1.0);
+}
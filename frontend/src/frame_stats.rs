@@ -0,0 +1,128 @@
+// SPDX-License-Identifier: GPL-3.0-or-later
+// Copyright (C) 2023 Robert Hrusecky <jadedpastabowl@gmail.com>
+
+//! How long the host took over each of the last frames, to export as CSV when reporting stutter.
+//! A frame that ran late shows up as a long interval, and the other columns tell whether the
+//! emulator, the renderer, or the audio queue was to blame.
+
+use std::{collections::VecDeque, fmt::Write as _, time::Duration};
+
+use instant::Instant;
+
+/// A minute at 60 fps
+const CAPACITY: usize = 3600;
+
+struct FrameTiming {
+    /// Since the first frame recorded
+    time: Duration,
+    /// Since the frame before, which is about 16.7 ms when keeping up
+    interval: Option<Duration>,
+    emulation: Duration,
+    /// Of everything drawn since the frame before
+    render: Duration,
+    /// Emulated audio frames pushed to the queue
+    audio_pushed: usize,
+    /// Output frames waiting in the queue afterwards
+    audio_queued: usize,
+}
+
+pub struct FrameStats {
+    timings: VecDeque<FrameTiming>,
+    start: Option<Instant>,
+    last_frame: Option<Instant>,
+    /// Rendered since the last frame
+    render: Duration,
+    /// Counts frames since the first one recorded, including ones that have been dropped
+    frames: usize,
+}
+
+fn millis(duration: Duration) -> f64 {
+    duration.as_secs_f64() * 1000.0
+}
+
+impl FrameStats {
+    pub fn new() -> Self {
+        Self {
+            timings: VecDeque::with_capacity(CAPACITY),
+            start: None,
+            last_frame: None,
+            render: Duration::ZERO,
+            frames: 0,
+        }
+    }
+
+    pub fn add_render(&mut self, render: Duration) {
+        self.render += render;
+    }
+
+    /// Records a frame that started emulating at `start`.
+    pub fn push(
+        &mut self,
+        start: Instant,
+        emulation: Duration,
+        audio_pushed: usize,
+        audio_queued: usize,
+    ) {
+        if self.timings.len() == CAPACITY {
+            self.timings.pop_front();
+        }
+        let first = *self.start.get_or_insert(start);
+        self.timings.push_back(FrameTiming {
+            time: start - first,
+            interval: self.last_frame.map(|last| start - last),
+            emulation,
+            render: std::mem::take(&mut self.render),
+            audio_pushed,
+            audio_queued,
+        });
+        self.last_frame = Some(start);
+        self.frames += 1;
+    }
+
+    /// Forgets everything, like when the game changes.
+    pub fn clear(&mut self) {
+        *self = Self::new();
+    }
+
+    /// Leaves out the interval to the next frame, which would look like a stutter after a pause.
+    pub fn pause(&mut self) {
+        self.last_frame = None;
+    }
+
+    /// The average time spent emulating and rendering a frame, over the last second.
+    pub fn recent_average(&self) -> Option<(Duration, Duration)> {
+        let recent = self.timings.iter().rev().take(60);
+        let count = recent.len() as u32;
+        if count == 0 {
+            return None;
+        }
+        let (emulation, render) = recent.fold((Duration::ZERO, Duration::ZERO), |sums, timing| {
+            (sums.0 + timing.emulation, sums.1 + timing.render)
+        });
+        Some((emulation / count, render / count))
+    }
+
+    /// One row per frame, with times in milliseconds.
+    pub fn to_csv(&self) -> String {
+        let mut csv = String::from(
+            "frame,time_ms,interval_ms,emulation_ms,render_ms,audio_pushed,audio_queued\n",
+        );
+        let first = self.frames - self.timings.len();
+        for (i, timing) in self.timings.iter().enumerate() {
+            let interval = timing
+                .interval
+                .map_or_else(String::new, |interval| format!("{:.3}", millis(interval)));
+            let _ = writeln!(
+                csv,
+                "{},{:.3},{interval},{:.3},{:.3},{},{}",
+                first + i,
+                millis(timing.time),
+                millis(timing.emulation),
+                millis(timing.render),
+                timing.audio_pushed,
+                timing.audio_queued,
+            );
+        }
+        csv
+    }
+}
@@ -0,0 +1,155 @@
+// SPDX-License-Identifier: GPL-3.0-or-later
+// Copyright (C) 2023 Robert Hrusecky <jadedpastabowl@gmail.com>
+
+//! Controller input through gilrs. Every connected controller drives player 1's joypad, alongside
+//! the keyboard, and controllers can be plugged in or out at any time. Controllers with force
+//! feedback rumble along with rumble carts.
+
+use anyhow::{anyhow, Result};
+use gilrs::{
+    ff::{BaseEffect, BaseEffectType, Effect, EffectBuilder, Repeat, Replay, Ticks},
+    Axis, EventType, Gilrs,
+};
+use iron_boy_core::event::{Event, EventKind};
+use winit::event::ElementState;
+
+/// The default for how far a stick has to move before it counts as a direction, out of 1.
+pub const DEFAULT_DEADZONE: f32 = 0.5;
+
+/// Input from a controller, for player 1's joypad.
+pub enum GamepadInput {
+    /// A controller button, which the [`InputMap`](crate::input::InputMap) binds to the joypad
+    Button(gilrs::Button, ElementState),
+    /// Where the left stick is, with up and to the right positive. The core turns it into
+    /// directions.
+    Stick(f32, f32),
+    /// A controller was unplugged, so whatever it was holding should be let go
+    Disconnected,
+}
+
+pub struct Gamepads {
+    gilrs: Gilrs,
+    /// The left stick's last position on each axis, since they change one at a time
+    stick: [f32; 2],
+    /// Whether the cart's motor was left on at the end of the last frame
+    motor: bool,
+    /// Playing on every controller that supports it while the motor runs
+    rumble: Option<Effect>,
+}
+
+impl Gamepads {
+    pub fn new() -> Result<Self> {
+        let gilrs =
+            Gilrs::new().map_err(|error| anyhow!("Failed to set up controllers: {error}"))?;
+        Ok(Self {
+            gilrs,
+            stick: [0.0; 2],
+            motor: false,
+            rumble: None,
+        })
+    }
+
+    /// Rumbles the controllers while the cart's motor is on, going by the
+    /// [`EventKind::Rumble`] events from a frame. Games pulse the motor to set its strength, far
+    /// faster than a controller could follow, so the motor counts as on for the whole frame if it
+    /// was on at any point during it.
+    pub fn follow_rumble(&mut self, events: &[Event]) {
+        let mut on = self.motor;
+        for event in events {
+            if let EventKind::Rumble(motor) = event.kind {
+                self.motor = motor;
+                on |= motor;
+            }
+        }
+        if !on {
+            self.stop_rumble();
+        } else if self.rumble.is_none() {
+            if let Err(error) = self.start_rumble() {
+                log::warn!("Failed to rumble the controllers: {error}");
+            }
+        }
+    }
+
+    fn start_rumble(&mut self) -> Result<(), gilrs::ff::Error> {
+        let ids: Vec<_> = self
+            .gilrs
+            .gamepads()
+            .filter(|(_, gamepad)| gamepad.is_ff_supported())
+            .map(|(id, _)| id)
+            .collect();
+        if ids.is_empty() {
+            return Ok(());
+        }
+        let effect = EffectBuilder::new()
+            .add_effect(BaseEffect {
+                kind: BaseEffectType::Strong {
+                    magnitude: u16::MAX,
+                },
+                scheduling: Replay {
+                    play_for: Ticks::from_ms(100),
+                    ..Default::default()
+                },
+                ..Default::default()
+            })
+            .repeat(Repeat::Infinitely)
+            .gamepads(&ids)
+            .finish(&mut self.gilrs)?;
+        effect.play()?;
+        self.rumble = Some(effect);
+        Ok(())
+    }
+
+    /// Stops the controllers rumbling, like while the game is paused.
+    pub fn stop_rumble(&mut self) {
+        if let Some(effect) = self.rumble.take() {
+            if let Err(error) = effect.stop() {
+                log::warn!("Failed to stop the controllers rumbling: {error}");
+            }
+        }
+    }
+
+    /// The names of the connected controllers.
+    pub fn names(&self) -> Vec<String> {
+        self.gilrs
+            .gamepads()
+            .map(|(_, gamepad)| gamepad.name().to_owned())
+            .collect()
+    }
+
+    /// Passes every change since the last call to `handle`. Returns whether a controller was
+    /// connected or disconnected.
+    pub fn poll(&mut self, mut handle: impl FnMut(GamepadInput)) -> bool {
+        let mut hotplugged = false;
+        while let Some(event) = self.gilrs.next_event() {
+            match event.event {
+                EventType::ButtonPressed(button, _) => {
+                    handle(GamepadInput::Button(button, ElementState::Pressed));
+                }
+                EventType::ButtonReleased(button, _) => {
+                    handle(GamepadInput::Button(button, ElementState::Released));
+                }
+                EventType::AxisChanged(axis @ (Axis::LeftStickX | Axis::LeftStickY), value, _) => {
+                    let i = if axis == Axis::LeftStickX { 0 } else { 1 };
+                    self.stick[i] = value;
+                    let [x, y] = self.stick;
+                    handle(GamepadInput::Stick(x, y));
+                }
+                EventType::Connected => {
+                    log::info!("Connected {}", self.gilrs.gamepad(event.id).name());
+                    // Rumbling starts over on whichever controllers are connected now
+                    self.stop_rumble();
+                    hotplugged = true;
+                }
+                EventType::Disconnected => {
+                    log::info!("Disconnected {}", self.gilrs.gamepad(event.id).name());
+                    self.stick = [0.0; 2];
+                    handle(GamepadInput::Disconnected);
+                    self.stop_rumble();
+                    hotplugged = true;
+                }
+                _ => (),
+            }
+        }
+        hotplugged
+    }
+}
@@ -0,0 +1,104 @@
+// SPDX-License-Identifier: GPL-3.0-or-later
+// Copyright (C) 2023 Robert Hrusecky <jadedpastabowl@gmail.com>
+
+use anyhow::{anyhow, Context as _, Result};
+use egui::{Context, ScrollArea, Window};
+use file_dialog::{FileDialog, FileHandle};
+use iron_boy_core::cheat::Cheat;
+use winit::event_loop::EventLoopProxy;
+
+use crate::{
+    background,
+    cheats::{self, CheatEntry},
+    event::FrontendEvent,
+};
+
+/// Lists the cheats imported for the running game, ready to toggle.
+pub struct CheatsWindow {
+    pub open: bool,
+    /// The checksums of the game that is running, if any
+    checksums: Option<[u8; 3]>,
+    entries: Vec<CheatEntry>,
+    file_dialog: FileDialog,
+}
+
+impl CheatsWindow {
+    pub fn new() -> Result<Self> {
+        Ok(Self {
+            open: false,
+            checksums: None,
+            entries: Vec::new(),
+            file_dialog: FileDialog::new().context("Failed to initalize file dialog")?,
+        })
+    }
+
+    /// Lists the cheats imported for a newly loaded game.
+    pub fn set_game(&mut self, checksums: Option<[u8; 3]>) {
+        self.checksums = checksums;
+        self.entries = checksums.map(cheats::load).unwrap_or_default();
+    }
+
+    /// Replaces the running game's list with the `.cht` file in `text`.
+    pub fn import(&mut self, text: &str) -> Result<()> {
+        let checksums = self
+            .checksums
+            .ok_or(anyhow!("Load a game before importing cheats for it"))?;
+        self.entries = cheats::parse(text).context("Failed to import cheats")?;
+        cheats::save(checksums, &self.entries)
+    }
+
+    pub fn enabled(&self) -> impl Iterator<Item = Cheat> + '_ {
+        cheats::enabled(&self.entries)
+    }
+
+    pub fn show(&mut self, ctx: &Context, proxy: &EventLoopProxy<FrontendEvent>) -> Result<()> {
+        self.file_dialog.show(ctx);
+        if let Some(file) = self.file_dialog.file() {
+            spawn_import(file, proxy);
+        }
+
+        let mut result = Ok(());
+        let mut changed = false;
+        Window::new("Cheats").open(&mut self.open).show(ctx, |ui| {
+            if self.checksums.is_none() {
+                ui.label("Load a game to use cheats with it");
+                return;
+            }
+            if ui.button("Import .cht...").clicked() {
+                result = self
+                    .file_dialog
+                    .open()
+                    .context("Failed to open file dialog");
+            }
+            ui.separator();
+            if self.entries.is_empty() {
+                ui.label("No cheats have been imported for this game");
+            }
+            ScrollArea::vertical().show(ui, |ui| {
+                for entry in &mut self.entries {
+                    changed |= ui
+                        .checkbox(&mut entry.enabled, &entry.desc)
+                        .on_hover_text(&entry.code)
+                        .changed();
+                }
+            });
+        });
+
+        if let (true, Some(checksums)) = (changed, self.checksums) {
+            let _ = proxy.send_event(FrontendEvent::CheatsChanged);
+            result = result.and(cheats::save(checksums, &self.entries));
+        }
+        result
+    }
+}
+
+fn spawn_import(file: FileHandle, proxy: &EventLoopProxy<FrontendEvent>) {
+    let proxy = proxy.clone();
+    background::spawn(async move {
+        let event = match file.read().await.context("Failed to read cheat file") {
+            Ok(data) => FrontendEvent::ImportCheats(data),
+            Err(error) => FrontendEvent::Error(error),
+        };
+        let _ = proxy.send_event(event);
+    });
+}
@@ -0,0 +1,57 @@
+// SPDX-License-Identifier: GPL-3.0-or-later
+// Copyright (C) 2023 Robert Hrusecky <jadedpastabowl@gmail.com>
+
+use egui::{Align2, Context, Grid, Ui, Window};
+
+use crate::input::{self, InputMap, Player};
+
+/// Lays out every active binding as a two column table, so the GUI always reflects the live
+/// configuration.
+pub fn bindings_grid(ui: &mut Ui, id: &str, input_map: &InputMap) {
+    Grid::new(id).striped(true).num_columns(2).show(ui, |ui| {
+        let joypad = input_map
+            .player(Player::One)
+            .iter()
+            .map(|(key, button)| (key, format!("{button:?}")));
+        let hotkeys = input_map
+            .hotkeys()
+            .map(|(key, hotkey)| (key, hotkey.description().to_owned()));
+
+        for (i, (key, action)) in joypad.chain(hotkeys).enumerate() {
+            ui.monospace(input::key_name(key));
+            if i == 0 {
+                ui.horizontal(|ui| {
+                    ui.label(action);
+                    // Force stripes to take up the whole width
+                    ui.add_space(ui.available_width());
+                });
+            } else {
+                ui.label(action);
+            }
+            ui.end_row();
+        }
+    });
+}
+
+pub struct Cheatsheet {
+    open: bool,
+}
+
+impl Cheatsheet {
+    pub fn new() -> Self {
+        Self { open: false }
+    }
+
+    pub fn toggle(&mut self) {
+        self.open = !self.open;
+    }
+
+    pub fn show(&mut self, ctx: &Context, input_map: &InputMap) {
+        Window::new("Keyboard Shortcuts")
+            .open(&mut self.open)
+            .anchor(Align2::CENTER_CENTER, [0.0, 0.0])
+            .collapsible(false)
+            .resizable(false)
+            .show(ctx, |ui| bindings_grid(ui, "cheatsheet table", input_map));
+    }
+}
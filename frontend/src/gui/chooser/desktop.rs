@@ -1,7 +1,7 @@
 // SPDX-License-Identifier: GPL-3.0-or-later
 // Copyright (C) 2023 Robert Hrusecky <jadedpastabowl@gmail.com>
 
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 
 use anyhow::{Context as _, Result};
 use egui::{Align, Layout, Ui};
@@ -11,8 +11,6 @@ use winit::event_loop::EventLoopProxy;
 
 use crate::event::FrontendEvent;
 
-use super::util;
-
 pub struct RomChooser {
     file_dialog: FileDialog,
     rom_path: OsStrTextBuffer,
@@ -29,11 +27,16 @@ impl RomChooser {
     pub fn show_dialog(&mut self, ctx: &egui::Context, proxy: &EventLoopProxy<FrontendEvent>) {
         self.file_dialog.show(ctx);
         if let Some(file) = self.file_dialog.file() {
-            self.rom_path = file.name().into();
-            util::spawn_file_read(file, proxy);
+            self.open(file.name(), proxy);
         }
     }
 
+    /// Starts the ROM at `path`, and shows it in the text box to reset from.
+    pub fn open(&mut self, path: &Path, proxy: &EventLoopProxy<FrontendEvent>) {
+        self.rom_path = path.into();
+        let _ = proxy.send_event(FrontendEvent::OpenRom(path.into()));
+    }
+
     pub fn show(&mut self, ui: &mut Ui, proxy: &EventLoopProxy<FrontendEvent>) -> Result<()> {
         let mut result = Ok(());
 
@@ -42,7 +45,7 @@ impl RomChooser {
         ui.allocate_ui_with_layout(row, Layout::right_to_left(Align::Center), |ui| {
             if ui.button("Reset").clicked() {
                 let path = PathBuf::from(self.rom_path.clone_as_os_string());
-                util::spawn_file_read(path.into(), proxy);
+                let _ = proxy.send_event(FrontendEvent::OpenRom(path));
             }
             if ui.button("Browse...").clicked() {
                 result = self
@@ -11,6 +11,7 @@ mod desktop;
 #[cfg(not(target_family = "wasm"))]
 pub use desktop::*;
 
+#[cfg(target_family = "wasm")]
 mod util {
     use anyhow::Context;
     use file_dialog::FileHandle;
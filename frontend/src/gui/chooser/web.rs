@@ -1,12 +1,16 @@
 // SPDX-License-Identifier: GPL-3.0-or-later
 // Copyright (C) 2023 Robert Hrusecky <jadedpastabowl@gmail.com>
 
-use anyhow::{Context as _, Result};
+use anyhow::{anyhow, Context as _, Result};
 use egui::{Align, Context, Layout, Ui};
-use file_dialog::{FileDialog, FileHandle};
+use file_dialog::{FileDialog, FileHandle, JsError};
+use js_sys::Array;
+use wasm_bindgen::{prelude::Closure, JsCast};
+use wasm_bindgen_futures::JsFuture;
+use web_sys::{Blob, ClipboardEvent, ClipboardItem, DataTransfer, Document, DragEvent};
 use winit::event_loop::EventLoopProxy;
 
-use crate::event::FrontendEvent;
+use crate::{background, event::FrontendEvent, zip};
 
 use super::util;
 
@@ -49,6 +53,13 @@ impl RomChooser {
                     .open()
                     .context("Failed to open file dialog");
             }
+            if ui
+                .button("Paste")
+                .on_hover_text("Loads a ROM copied as base64 text")
+                .clicked()
+            {
+                paste_rom(proxy);
+            }
             let mut text = self.file.as_ref().map(|f| f.name()).unwrap_or_default();
             ui.centered_and_justified(|ui| ui.text_edit_singleline(&mut text));
         });
@@ -56,3 +67,118 @@ impl RomChooser {
         result
     }
 }
+
+/// The ROM in `text` as base64, or `None` for any other text. Even the smallest ROM is 32 KiB, so
+/// short text that happens to be valid base64 isn't mistaken for one.
+fn decode_rom(text: &str) -> Option<Box<[u8]>> {
+    let text = text.trim();
+    // Accept `data:` URLs as well as bare base64
+    let payload = match text.split_once(";base64,") {
+        Some((_, payload)) => payload,
+        None => text,
+    };
+    let binary = web_sys::window()?.atob(payload).ok()?;
+    // `atob` produces a "binary string" with one byte per character
+    let rom: Box<[u8]> = binary.chars().map(|c| c as u8).collect();
+    (rom.len() >= 0x8000 || zip::is_zip(&rom)).then_some(rom)
+}
+
+/// Loads the ROM in `data`, and returns whether there was one.
+fn handle_transfer(data: &DataTransfer, proxy: &EventLoopProxy<FrontendEvent>) -> bool {
+    if let Some(file) = data.files().and_then(|files| files.item(0)) {
+        util::spawn_file_read(file.into(), proxy);
+        return true;
+    }
+    match decode_rom(&data.get_data("text/plain").unwrap_or_default()) {
+        Some(rom) => {
+            let _ = proxy.send_event(FrontendEvent::NewRom(rom));
+            true
+        }
+        None => false,
+    }
+}
+
+/// Finds a ROM copied as base64 text on the clipboard.
+async fn read_clipboard() -> Result<Option<Box<[u8]>>> {
+    let clipboard = web_sys::window()
+        .and_then(|window| window.navigator().clipboard())
+        .ok_or(anyhow!("The clipboard is unavailable"))?;
+    let items: Array = JsFuture::from(clipboard.read())
+        .await
+        .map_err(JsError::from)
+        .context("Failed to read the clipboard")?
+        .unchecked_into();
+    for item in items.iter() {
+        let item: ClipboardItem = item.unchecked_into();
+        if !item.types().includes(&"text/plain".into(), 0) {
+            continue;
+        }
+        let blob: Blob = JsFuture::from(item.get_type("text/plain"))
+            .await
+            .map_err(JsError::from)?
+            .unchecked_into();
+        let text = JsFuture::from(blob.text()).await.map_err(JsError::from)?;
+        if let Some(rom) = decode_rom(&text.as_string().unwrap_or_default()) {
+            return Ok(Some(rom));
+        }
+    }
+    Ok(None)
+}
+
+/// Loads a ROM from the clipboard. The browser asks for permission the first time.
+fn paste_rom(proxy: &EventLoopProxy<FrontendEvent>) {
+    let proxy = proxy.clone();
+    background::spawn(async move {
+        let event = match read_clipboard().await {
+            Ok(Some(rom)) => FrontendEvent::NewRom(rom),
+            Ok(None) => FrontendEvent::Error(anyhow!("There's no ROM on the clipboard")),
+            Err(error) => FrontendEvent::Error(error),
+        };
+        let _ = proxy.send_event(event);
+    });
+}
+
+fn listen<T: ?Sized>(document: &Document, event: &str, callback: &Closure<T>) -> Result<()> {
+    document
+        .add_event_listener_with_callback(event, callback.as_ref().unchecked_ref())
+        .map_err(JsError::from)
+        .with_context(|| format!("Failed to listen for {event} events"))
+}
+
+/// Loads ROMs that are pasted into the page or dropped onto it, including drags that originate in
+/// other tabs. Files are read as-is, while text is decoded as base64. Anything else is left for
+/// the page, like text pasted into a text box.
+pub fn listen_for_transfers(proxy: &EventLoopProxy<FrontendEvent>) -> Result<()> {
+    let document = web_sys::window()
+        .and_then(|window| window.document())
+        .ok_or(anyhow!("Failed to access document"))?;
+
+    let paste_proxy = proxy.clone();
+    let paste = Closure::<dyn FnMut(_)>::new(move |e: ClipboardEvent| {
+        if let Some(data) = e.clipboard_data() {
+            if handle_transfer(&data, &paste_proxy) {
+                e.prevent_default();
+            }
+        }
+    });
+    listen(&document, "paste", &paste)?;
+    paste.forget();
+
+    // The browser only fires `drop` on targets that cancel `dragover`
+    let drag_over = Closure::<dyn FnMut(_)>::new(|e: DragEvent| e.prevent_default());
+    listen(&document, "dragover", &drag_over)?;
+    drag_over.forget();
+
+    let drop_proxy = proxy.clone();
+    let drop = Closure::<dyn FnMut(_)>::new(move |e: DragEvent| {
+        if let Some(data) = e.data_transfer() {
+            // Dropping anything else would navigate away from the emulator
+            e.prevent_default();
+            handle_transfer(&data, &drop_proxy);
+        }
+    });
+    listen(&document, "drop", &drop)?;
+    drop.forget();
+
+    Ok(())
+}
@@ -0,0 +1,142 @@
+// SPDX-License-Identifier: GPL-3.0-or-later
+// Copyright (C) 2023 Robert Hrusecky <jadedpastabowl@gmail.com>
+
+//! Compatibility reports, which record how well a game runs for a community compatibility list.
+//! Reports are only ever made when the user asks, and nothing is sent anywhere. The user copies
+//! the JSON or, on desktop, saves it next to the ROM.
+
+use anyhow::Result;
+use egui::{Context, TextEdit, Window};
+use serde::Serialize;
+use winit::event_loop::EventLoopProxy;
+
+use crate::{emulator::Cgb, event::FrontendEvent, options::Options};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Rating {
+    Perfect,
+    Playable,
+    Broken,
+}
+
+impl Rating {
+    const ALL: [Rating; 3] = [Rating::Perfect, Rating::Playable, Rating::Broken];
+}
+
+/// Everything in a report that doesn't come from the user.
+#[derive(Debug, Clone, Serialize)]
+pub struct ReportHeader {
+    title: String,
+    /// The header and global checksums from the ROM header, in hex
+    checksums: String,
+    emulator_version: &'static str,
+    /// Settings that make emulation less accurate
+    overclock: usize,
+    open_bus: u8,
+}
+
+impl ReportHeader {
+    pub fn new(cgb: &Cgb, options: &Options) -> Self {
+        let [header, global_high, global_low] = cgb.checksums();
+        Self {
+            title: cgb.title(),
+            checksums: format!("{header:02x}:{global_high:02x}{global_low:02x}"),
+            emulator_version: env!("CARGO_PKG_VERSION"),
+            overclock: options.overclock,
+            open_bus: options.open_bus.unwrap_or(0xff),
+        }
+    }
+}
+
+#[derive(Serialize)]
+struct Report<'a> {
+    #[serde(flatten)]
+    header: &'a ReportHeader,
+    rating: Rating,
+    notes: &'a str,
+}
+
+pub struct CompatReportWindow {
+    pub open: bool,
+    /// The game that is running, if any
+    header: Option<ReportHeader>,
+    rating: Option<Rating>,
+    notes: String,
+}
+
+impl CompatReportWindow {
+    pub fn new() -> Self {
+        Self {
+            open: false,
+            header: None,
+            rating: None,
+            notes: String::new(),
+        }
+    }
+
+    /// Starts a fresh report for a newly loaded game.
+    pub fn set_game(&mut self, header: Option<ReportHeader>) {
+        self.header = header;
+        self.rating = None;
+        self.notes.clear();
+    }
+
+    fn report(&self) -> Result<Option<String>> {
+        let (Some(header), Some(rating)) = (&self.header, self.rating) else {
+            return Ok(None);
+        };
+        let report = Report {
+            header,
+            rating,
+            notes: &self.notes,
+        };
+        Ok(Some(serde_json::to_string_pretty(&report)?))
+    }
+
+    // Only desktop can save reports through the event loop
+    #[cfg_attr(target_arch = "wasm32", allow(unused_variables))]
+    pub fn show(&mut self, ctx: &Context, proxy: &EventLoopProxy<FrontendEvent>) -> Result<()> {
+        let mut result = Ok(());
+        let mut open = self.open;
+        Window::new("Report Compatibility")
+            .open(&mut open)
+            .resizable(false)
+            .show(ctx, |ui| {
+                let Some(header) = &self.header else {
+                    ui.label("Load a game to report on it");
+                    return;
+                };
+                ui.label(format!("How well does {} run?", header.title));
+                ui.horizontal(|ui| {
+                    for rating in Rating::ALL {
+                        ui.radio_value(&mut self.rating, Some(rating), format!("{rating:?}"));
+                    }
+                });
+                ui.add(TextEdit::multiline(&mut self.notes).hint_text("Notes, like what's broken"));
+                ui.separator();
+
+                let report = match self.report() {
+                    Ok(report) => report,
+                    Err(error) => {
+                        result = Err(error);
+                        return;
+                    }
+                };
+                ui.add_enabled_ui(report.is_some(), |ui| {
+                    ui.horizontal(|ui| {
+                        let report = report.unwrap_or_default();
+                        if ui.button("Copy").clicked() {
+                            ui.output_mut(|output| output.copied_text = report.clone());
+                        }
+                        #[cfg(not(target_arch = "wasm32"))]
+                        if ui.button("Save").clicked() {
+                            let _ = proxy.send_event(FrontendEvent::SaveCompatReport(report));
+                        }
+                    });
+                });
+            });
+        self.open = open;
+        result
+    }
+}
@@ -0,0 +1,53 @@
+// SPDX-License-Identifier: GPL-3.0-or-later
+// Copyright (C) 2023 Robert Hrusecky <jadedpastabowl@gmail.com>
+
+//! Text sent over the serial port, which homebrew and test ROMs use like a printf, since nothing is
+//! ever on the other end.
+
+use egui::{Context, RichText, ScrollArea, Window};
+
+/// How much text to keep, in bytes
+const MAX_LEN: usize = 0x10000;
+
+pub struct SerialConsoleWindow {
+    pub open: bool,
+    text: String,
+}
+
+impl SerialConsoleWindow {
+    pub fn new() -> Self {
+        Self {
+            open: false,
+            text: String::new(),
+        }
+    }
+
+    pub fn extend(&mut self, bytes: &[u8]) {
+        self.text.push_str(&String::from_utf8_lossy(bytes));
+        if self.text.len() > MAX_LEN {
+            let mut start = self.text.len() - MAX_LEN;
+            while !self.text.is_char_boundary(start) {
+                start += 1;
+            }
+            self.text.drain(..start);
+        }
+    }
+
+    pub fn show(&mut self, ctx: &Context) {
+        Window::new("Serial Console")
+            .open(&mut self.open)
+            .default_width(400.0)
+            .show(ctx, |ui| {
+                if ui.button("Clear").clicked() {
+                    self.text.clear();
+                }
+                ui.separator();
+                ScrollArea::vertical()
+                    .stick_to_bottom(true)
+                    .auto_shrink([false; 2])
+                    .show(ui, |ui| {
+                        ui.label(RichText::new(&self.text).monospace());
+                    });
+            });
+    }
+}
@@ -0,0 +1,148 @@
+// SPDX-License-Identifier: GPL-3.0-or-later
+// Copyright (C) 2023 Robert Hrusecky <jadedpastabowl@gmail.com>
+
+use egui::{Color32, Context, Grid, Window};
+use iron_boy_core::joypad::Button;
+use winit::event::VirtualKeyCode;
+
+use crate::input::{self, InputMap, Player};
+
+/// A key or controller button pressed while a binding was being remapped
+#[derive(Clone, Copy)]
+enum Captured {
+    Key(VirtualKeyCode),
+    Controller(gilrs::Button),
+}
+
+/// Lets the user rebind player 1's joypad by clicking a button and pressing the new key or
+/// controller button.
+pub struct ControlsWindow {
+    pub open: bool,
+    /// The button waiting for a key or controller button press
+    capturing: Option<Button>,
+    /// A press taken by [`Self::capture`] or [`Self::capture_controller`], which is bound on the
+    /// next update
+    captured: Option<Captured>,
+    conflict: Option<String>,
+}
+
+impl ControlsWindow {
+    pub fn new() -> Self {
+        Self {
+            open: false,
+            capturing: None,
+            captured: None,
+            conflict: None,
+        }
+    }
+
+    /// Offers a key press to the window. Returns whether it was taken for a binding, in which case
+    /// it shouldn't go anywhere else.
+    pub fn capture(&mut self, key: VirtualKeyCode) -> bool {
+        self.take(Captured::Key(key))
+    }
+
+    /// Like [`Self::capture`], for a controller button.
+    pub fn capture_controller(&mut self, button: gilrs::Button) -> bool {
+        self.take(Captured::Controller(button))
+    }
+
+    fn take(&mut self, captured: Captured) -> bool {
+        if self.capturing.is_none() {
+            return false;
+        }
+        self.captured = Some(captured);
+        true
+    }
+
+    fn bind_captured(&mut self, input_map: &mut InputMap) {
+        let (Some(button), Some(captured)) = (self.capturing, self.captured.take()) else {
+            return;
+        };
+        self.capturing = None;
+        let (name, result) = match captured {
+            Captured::Key(VirtualKeyCode::Escape) => return,
+            Captured::Key(key) => (
+                input::key_name(key),
+                input_map.bind(Player::One, button, key),
+            ),
+            Captured::Controller(pad) => (
+                input::controller_button_name(pad),
+                input_map.bind_controller(button, pad),
+            ),
+        };
+        if let Err(action) = result {
+            self.conflict = Some(format!(
+                "{name} is already bound to {}",
+                action.description()
+            ));
+        }
+    }
+
+    pub fn show(&mut self, ctx: &Context, input_map: &mut InputMap) {
+        self.bind_captured(input_map);
+
+        Window::new("Controls")
+            .open(&mut self.open)
+            .resizable(false)
+            .show(ctx, |ui| {
+                Grid::new("controls window table")
+                    .striped(true)
+                    .num_columns(3)
+                    .show(ui, |ui| {
+                        ui.label("");
+                        ui.strong("Keyboard");
+                        ui.strong("Controller");
+                        ui.end_row();
+                        for button in Button::ALL {
+                            ui.label(format!("{button:?}"));
+                            let (key, pad) = if self.capturing == Some(button) {
+                                let waiting = String::from("Press a key or button...");
+                                (waiting.clone(), waiting)
+                            } else {
+                                let key = input_map
+                                    .player(Player::One)
+                                    .key(button)
+                                    .map_or("Unbound".into(), input::key_name);
+                                let pads: Vec<_> = input_map
+                                    .controller()
+                                    .keys(button)
+                                    .map(input::controller_button_name)
+                                    .collect();
+                                let pad = if pads.is_empty() {
+                                    "Unbound".into()
+                                } else {
+                                    pads.join(", ")
+                                };
+                                (key, pad)
+                            };
+                            // Either one takes a key or a controller button
+                            let clicked = ui.button(key).clicked();
+                            if ui.button(pad).clicked() || clicked {
+                                self.capturing = Some(button);
+                                self.conflict = None;
+                            }
+                            ui.end_row();
+                        }
+                    });
+
+                if self.capturing.is_some() {
+                    ui.label("Press Escape to cancel");
+                }
+                if let Some(conflict) = &self.conflict {
+                    ui.colored_label(Color32::YELLOW, format!("⚠ {conflict}"));
+                }
+                ui.separator();
+                if ui.button("Reset to defaults").clicked() {
+                    *input_map = InputMap::default();
+                    self.capturing = None;
+                    self.conflict = None;
+                }
+            });
+
+        // Closing the window abandons the capture
+        if !self.open {
+            self.capturing = None;
+        }
+    }
+}
@@ -1,10 +1,10 @@
 // SPDX-License-Identifier: GPL-3.0-or-later
 // Copyright (C) 2023 Robert Hrusecky <jadedpastabowl@gmail.com>
 
-use std::mem;
+use std::{mem, time::Duration};
 
 use anyhow::Result;
-use egui::{ClippedPrimitive, Context, TexturesDelta};
+use egui::{ClippedPrimitive, Context, Memory, TexturesDelta};
 use egui_wgpu::{
     renderer::ScreenDescriptor,
     wgpu::{
@@ -14,15 +14,24 @@ use egui_wgpu::{
     Renderer,
 };
 use egui_winit::State;
+use serde::{Deserialize, Serialize};
 use winit::{
-    event::WindowEvent,
+    event::{ElementState, KeyboardInput, WindowEvent},
     event_loop::{EventLoop, EventLoopProxy},
     window::Window,
 };
 
-use crate::event::FrontendEvent;
+use crate::{event::FrontendEvent, input::InputMap, store};
 
-use super::ui::Ui;
+use super::ui::{Ui, UiLayout};
+
+const LAYOUT_KEY: &str = "layout.ron";
+
+#[derive(Serialize, Deserialize)]
+struct Layout {
+    memory: Memory,
+    ui: UiLayout,
+}
 
 pub struct GuiEngine {
     egui_ctx: Context,
@@ -55,6 +64,15 @@ impl GuiEngine {
         };
         let renderer = Renderer::new(device, texture_format, None, 1);
 
+        let mut ui = Ui::new()?;
+        // A missing or outdated layout just means starting from the defaults
+        if let Some(layout) =
+            store::load(LAYOUT_KEY).and_then(|layout| ron::from_str::<Layout>(&layout).ok())
+        {
+            egui_ctx.memory_mut(|memory| *memory = layout.memory);
+            ui.set_layout(layout.ui);
+        }
+
         Ok(Self {
             egui_ctx,
             egui_state,
@@ -62,27 +80,71 @@ impl GuiEngine {
             renderer,
             textures: Default::default(),
             paint_jobs: Vec::new(),
-            ui: Ui::new()?,
+            ui,
         })
     }
 
+    /// Remembers the panel and window layout for the next session.
+    pub fn save_layout(&self) -> Result<()> {
+        let layout = Layout {
+            memory: self.egui_ctx.memory(Memory::clone),
+            ui: self.ui.layout(),
+        };
+        store::save(LAYOUT_KEY, &ron::to_string(&layout)?)
+    }
+
+    /// Recreates the renderer on a new device. Textures from the old device are gone, so this also
+    /// starts a fresh egui context, which uploads them again on its next frame.
+    pub fn rebuild_renderer(&mut self, device: &Device, texture_format: TextureFormat) {
+        self.renderer = Renderer::new(device, texture_format, None, 1);
+        let memory = self.egui_ctx.memory(Memory::clone);
+        self.egui_ctx = Context::default();
+        self.egui_ctx.memory_mut(|new_memory| *new_memory = memory);
+        self.textures = Default::default();
+        self.paint_jobs.clear();
+        #[cfg(feature = "tools")]
+        self.ui.vram_viewer.forget_textures();
+    }
+
     pub fn handle_event(&mut self, event: &WindowEvent) -> bool {
+        // A binding being remapped takes the next key press, wherever egui's focus is
+        if let WindowEvent::KeyboardInput {
+            input:
+                KeyboardInput {
+                    virtual_keycode: Some(key),
+                    state: ElementState::Pressed,
+                    ..
+                },
+            ..
+        } = event
+        {
+            if self.ui.controls.capture(*key) {
+                return true;
+            }
+        }
         self.egui_state.on_event(&self.egui_ctx, event).consumed
     }
 
-    pub fn update(&mut self, window: &Window, proxy: &EventLoopProxy<FrontendEvent>) -> Result<()> {
+    /// Runs the UI, and returns how long until it needs to run again even without any input, like
+    /// for an animation. That's [`Duration::MAX`] if it can wait for input.
+    pub fn update(
+        &mut self,
+        window: &Window,
+        proxy: &EventLoopProxy<FrontendEvent>,
+        input_map: &mut InputMap,
+    ) -> Result<Duration> {
         let raw_input = self.egui_state.take_egui_input(window);
         let mut result = Ok(());
-        let output = self
-            .egui_ctx
-            .run(raw_input, |ctx| result = self.ui.update(ctx, proxy));
+        let output = self.egui_ctx.run(raw_input, |ctx| {
+            result = self.ui.update(ctx, proxy, input_map)
+        });
         result?;
 
         self.textures.append(output.textures_delta);
         self.egui_state
             .handle_platform_output(window, &self.egui_ctx, output.platform_output);
         self.paint_jobs = self.egui_ctx.tessellate(output.shapes);
-        Ok(())
+        Ok(output.repaint_after)
     }
 
     pub fn render(
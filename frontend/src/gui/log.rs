@@ -0,0 +1,89 @@
+// SPDX-License-Identifier: GPL-3.0-or-later
+// Copyright (C) 2023 Robert Hrusecky <jadedpastabowl@gmail.com>
+
+use std::collections::VecDeque;
+
+use egui::{Color32, ComboBox, Context, RichText, ScrollArea, Window};
+use iron_boy_core::event::{Event, Severity};
+
+const MAX_ENTRIES: usize = 512;
+const SEVERITIES: [Severity; 4] = [
+    Severity::Debug,
+    Severity::Info,
+    Severity::Warning,
+    Severity::Error,
+];
+
+pub struct EventLogWindow {
+    pub open: bool,
+    entries: VecDeque<Event>,
+    min_severity: Severity,
+}
+
+impl EventLogWindow {
+    pub fn new() -> Self {
+        Self {
+            open: false,
+            entries: VecDeque::new(),
+            min_severity: Severity::Info,
+        }
+    }
+
+    pub fn extend(&mut self, events: impl IntoIterator<Item = Event>) {
+        for event in events {
+            if self.entries.len() == MAX_ENTRIES {
+                self.entries.pop_front();
+            }
+            self.entries.push_back(event);
+        }
+    }
+
+    fn color(severity: Severity) -> Color32 {
+        match severity {
+            Severity::Debug => Color32::GRAY,
+            Severity::Info => Color32::LIGHT_BLUE,
+            Severity::Warning => Color32::YELLOW,
+            Severity::Error => Color32::LIGHT_RED,
+        }
+    }
+
+    pub fn show(&mut self, ctx: &Context) {
+        Window::new("Event Log")
+            .open(&mut self.open)
+            .default_width(400.0)
+            .show(ctx, |ui| {
+                ui.horizontal(|ui| {
+                    ComboBox::from_label("Minimum severity")
+                        .selected_text(self.min_severity.to_string())
+                        .show_ui(ui, |ui| {
+                            for severity in SEVERITIES {
+                                ui.selectable_value(
+                                    &mut self.min_severity,
+                                    severity,
+                                    severity.to_string(),
+                                );
+                            }
+                        });
+                    if ui.button("Clear").clicked() {
+                        self.entries.clear();
+                    }
+                });
+                ui.separator();
+                ScrollArea::vertical()
+                    .stick_to_bottom(true)
+                    .auto_shrink([false; 2])
+                    .show(ui, |ui| {
+                        for event in self
+                            .entries
+                            .iter()
+                            .filter(|event| event.severity >= self.min_severity)
+                        {
+                            let text = RichText::new(event.to_string())
+                                .monospace()
+                                .color(Self::color(event.severity));
+                            ui.label(text);
+                        }
+                    });
+            });
+    }
+}
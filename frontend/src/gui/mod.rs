@@ -1,8 +1,30 @@
 // SPDX-License-Identifier: GPL-3.0-or-later
 // Copyright (C) 2023 Robert Hrusecky <jadedpastabowl@gmail.com>
 
+mod cheats;
+mod cheatsheet;
 mod chooser;
+mod compat;
+#[cfg(feature = "tools")]
+mod console;
+mod controls;
 mod engine;
+#[cfg(feature = "tools")]
+mod log;
+#[cfg(feature = "tools")]
+mod oam;
+mod rom_info;
+mod save_failed;
+#[cfg(not(target_arch = "wasm32"))]
+mod states;
+#[cfg(feature = "tools")]
+mod trace;
 mod ui;
+#[cfg(feature = "tools")]
+mod vram;
 
+pub use compat::ReportHeader;
 pub use engine::GuiEngine;
+
+#[cfg(target_family = "wasm")]
+pub use chooser::listen_for_transfers;
@@ -0,0 +1,116 @@
+// SPDX-License-Identifier: GPL-3.0-or-later
+// Copyright (C) 2023 Robert Hrusecky <jadedpastabowl@gmail.com>
+
+//! Lists the objects in OAM, and can outline one of them on the screen to find which entry is
+//! which sprite.
+
+use egui::{Context, Grid, RichText, ScrollArea, Window};
+use iron_boy_core::system::{DebugVideo, ObjEntry, OBJ_COUNT, SCREEN_HEIGHT, SCREEN_WIDTH};
+
+/// Whether any of an object `height` pixels tall is on the screen.
+fn visible(obj: &ObjEntry, height: usize) -> bool {
+    let height = height as i16;
+    (-7..SCREEN_WIDTH as i16).contains(&obj.x)
+        && (1 - height..SCREEN_HEIGHT as i16).contains(&obj.y)
+}
+
+pub struct OamViewerWindow {
+    pub open: bool,
+    objs: Vec<ObjEntry>,
+    obj_height: usize,
+    objs_enabled: bool,
+    selected: Option<usize>,
+    highlight: bool,
+}
+
+impl OamViewerWindow {
+    pub fn new() -> Self {
+        Self {
+            open: false,
+            objs: Vec::new(),
+            obj_height: 8,
+            objs_enabled: true,
+            selected: None,
+            highlight: true,
+        }
+    }
+
+    pub fn capture(&mut self, video: &DebugVideo) {
+        if !self.open {
+            return;
+        }
+        self.objs = (0..OBJ_COUNT).map(|index| video.obj(index)).collect();
+        self.obj_height = video.obj_height();
+        self.objs_enabled = video.objs_enabled();
+    }
+
+    /// The x, y, width, and height on the screen of the object to outline, if any.
+    pub fn highlight(&self) -> Option<[f32; 4]> {
+        if !self.open || !self.highlight {
+            return None;
+        }
+        let obj = self.objs.get(self.selected?)?;
+        Some([obj.x as f32, obj.y as f32, 8.0, self.obj_height as f32])
+    }
+
+    pub fn show(&mut self, ctx: &Context) {
+        let mut open = self.open;
+        Window::new("OAM Viewer")
+            .open(&mut open)
+            .default_height(400.0)
+            .show(ctx, |ui| {
+                ui.checkbox(&mut self.highlight, "Outline the selected object");
+                ui.label(format!(
+                    "8x{} objects, {}",
+                    self.obj_height,
+                    if self.objs_enabled { "shown" } else { "hidden" }
+                ));
+                ui.separator();
+                ScrollArea::vertical()
+                    .auto_shrink([false; 2])
+                    .show(ui, |ui| {
+                        Grid::new("oam")
+                            .striped(true)
+                            .num_columns(7)
+                            .show(ui, |ui| {
+                                for header in
+                                    ["#", "X", "Y", "Tile", "Palette", "Flip", "Behind BG"]
+                                {
+                                    ui.strong(header);
+                                }
+                                ui.end_row();
+                                for (index, obj) in self.objs.iter().enumerate() {
+                                    let text = RichText::new(format!("{index:02}")).monospace();
+                                    // Off-screen objects are how games hide unused ones
+                                    let text = if visible(obj, self.obj_height) {
+                                        text
+                                    } else {
+                                        text.weak()
+                                    };
+                                    if ui
+                                        .selectable_label(self.selected == Some(index), text)
+                                        .clicked()
+                                    {
+                                        self.selected =
+                                            (self.selected != Some(index)).then_some(index);
+                                    }
+                                    ui.monospace(obj.x.to_string());
+                                    ui.monospace(obj.y.to_string());
+                                    ui.monospace(format!("{}:{:02x}", obj.bank, obj.tile));
+                                    ui.monospace(obj.palette.to_string());
+                                    let flip = match (obj.x_flip, obj.y_flip) {
+                                        (false, false) => "",
+                                        (true, false) => "X",
+                                        (false, true) => "Y",
+                                        (true, true) => "XY",
+                                    };
+                                    ui.monospace(flip);
+                                    ui.monospace(if obj.bg_over_obj { "Yes" } else { "" });
+                                    ui.end_row();
+                                }
+                            });
+                    });
+            });
+        self.open = open;
+    }
+}
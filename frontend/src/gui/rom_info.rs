@@ -0,0 +1,107 @@
+// SPDX-License-Identifier: GPL-3.0-or-later
+// Copyright (C) 2023 Robert Hrusecky <jadedpastabowl@gmail.com>
+
+//! What the running game's cartridge header says about it, for telling ROM dumps apart and
+//! spotting bad ones.
+
+use egui::{Color32, Context, Grid, Window};
+use iron_boy_core::cart::header::{CgbSupport, Header};
+
+fn size(size: Option<usize>, id: u8) -> String {
+    match size {
+        Some(0) => "None".into(),
+        Some(size) => format!("{} KiB", size / 1024),
+        None => format!("Unknown ({id:#04x})"),
+    }
+}
+
+pub struct RomInfoWindow {
+    pub open: bool,
+    /// The header of the game that is running, if any
+    header: Option<Header>,
+}
+
+impl RomInfoWindow {
+    pub fn new() -> Self {
+        Self {
+            open: false,
+            header: None,
+        }
+    }
+
+    pub fn set_game(&mut self, header: Option<Header>) {
+        self.header = header;
+    }
+
+    pub fn show(&mut self, ctx: &Context) {
+        Window::new("ROM Info")
+            .open(&mut self.open)
+            .resizable(false)
+            .show(ctx, |ui| {
+                let Some(header) = &self.header else {
+                    ui.label("Load a game to see its header");
+                    return;
+                };
+                Grid::new("rom info table")
+                    .striped(true)
+                    .num_columns(2)
+                    .show(ui, |ui| {
+                        let cgb = match header.cgb {
+                            CgbSupport::None => "No",
+                            CgbSupport::Enhanced => "Enhanced",
+                            CgbSupport::Only => "Required",
+                        };
+                        let rows = [
+                            ("Title", header.title.clone()),
+                            (
+                                "Manufacturer",
+                                header.manufacturer.clone().unwrap_or_default(),
+                            ),
+                            ("Licensee", header.licensee.clone()),
+                            ("CGB", cgb.into()),
+                            ("SGB", if header.sgb { "Yes" } else { "No" }.into()),
+                            ("Cartridge type", format!("{:#04x}", header.cart_type)),
+                            ("ROM size", size(header.rom_size(), header.rom_size_id)),
+                            ("RAM size", size(header.ram_size(), header.ram_size_id)),
+                            (
+                                "Region",
+                                if header.japanese { "Japan" } else { "Overseas" }.into(),
+                            ),
+                            ("Version", header.version.to_string()),
+                        ];
+                        for (name, value) in rows {
+                            ui.label(name);
+                            ui.label(value);
+                            ui.end_row();
+                        }
+
+                        let checksums = [
+                            (
+                                "Header checksum",
+                                format!("{:02x}", header.header_checksum),
+                                format!("{:02x}", header.expected_header_checksum),
+                                header.header_checksum_ok(),
+                            ),
+                            (
+                                "Global checksum",
+                                format!("{:04x}", header.global_checksum),
+                                format!("{:04x}", header.expected_global_checksum),
+                                header.global_checksum_ok(),
+                            ),
+                        ];
+                        for (name, value, expected, ok) in checksums {
+                            ui.label(name);
+                            if ok {
+                                ui.label(format!("{value} ✔"));
+                            } else {
+                                ui.colored_label(
+                                    Color32::YELLOW,
+                                    format!("⚠ {value}, should be {expected}"),
+                                );
+                            }
+                            ui.end_row();
+                        }
+                    });
+            });
+    }
+}
@@ -0,0 +1,95 @@
+// SPDX-License-Identifier: GPL-3.0-or-later
+// Copyright (C) 2023 Robert Hrusecky <jadedpastabowl@gmail.com>
+
+#[cfg(not(target_arch = "wasm32"))]
+use std::path::{Path, PathBuf};
+
+use anyhow::Error;
+#[cfg(not(target_arch = "wasm32"))]
+use egui::TextEdit;
+use egui::{Align2, Context, Window};
+#[cfg(not(target_arch = "wasm32"))]
+use egui_osstr::OsStrTextBuffer;
+use winit::event_loop::EventLoopProxy;
+
+use crate::event::FrontendEvent;
+
+/// Asks what to do about cartridge RAM that couldn't be saved, while the game waits paused.
+pub struct SaveFailedWindow {
+    /// The failure being resolved, if any
+    error: Option<Error>,
+    /// Where to save instead. The web always saves to browser storage.
+    #[cfg(not(target_arch = "wasm32"))]
+    path: OsStrTextBuffer,
+}
+
+impl SaveFailedWindow {
+    pub fn new() -> Self {
+        Self {
+            error: None,
+            #[cfg(not(target_arch = "wasm32"))]
+            path: Default::default(),
+        }
+    }
+
+    /// Shows the window for a failed save to `path`, or updates it after a failed retry.
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn open(&mut self, error: Error, path: &Path) {
+        self.error = Some(error);
+        self.path = path.into();
+    }
+
+    /// Shows the window for a failed save to browser storage, or updates it after a failed retry.
+    #[cfg(target_arch = "wasm32")]
+    pub fn open(&mut self, error: Error) {
+        self.error = Some(error);
+    }
+
+    pub fn close(&mut self) {
+        self.error = None;
+    }
+
+    pub fn show(&mut self, ctx: &Context, proxy: &EventLoopProxy<FrontendEvent>) {
+        let Some(error) = &self.error else {
+            return;
+        };
+        Window::new("⚠ Save Failed")
+            .anchor(Align2::CENTER_CENTER, [0.0, 0.0])
+            .collapsible(false)
+            .resizable(false)
+            .show(ctx, |ui| {
+                ui.label("The game's save couldn't be written, so it has been paused.");
+                ui.label(format!("{error:#}"));
+                ui.separator();
+                #[cfg(not(target_arch = "wasm32"))]
+                {
+                    ui.label("Save to:");
+                    ui.add(TextEdit::singleline(&mut self.path).desired_width(f32::INFINITY));
+                }
+                #[cfg(target_arch = "wasm32")]
+                ui.label(
+                    "Browser storage may be full. Export the save to keep a copy of it, then \
+                     retry or carry on without saving.",
+                );
+                ui.horizontal(|ui| {
+                    if ui.button("Retry").clicked() {
+                        #[cfg(not(target_arch = "wasm32"))]
+                        let event = {
+                            let path = PathBuf::from(self.path.clone_as_os_string());
+                            FrontendEvent::RetrySave(path)
+                        };
+                        #[cfg(target_arch = "wasm32")]
+                        let event = FrontendEvent::RetrySave;
+                        let _ = proxy.send_event(event);
+                    }
+                    #[cfg(target_arch = "wasm32")]
+                    if ui.button("Export Save").clicked() {
+                        let _ = proxy.send_event(FrontendEvent::ExportSav);
+                    }
+                    if ui.button("Don't Save").clicked() {
+                        let _ = proxy.send_event(FrontendEvent::DiscardSave);
+                    }
+                });
+            });
+    }
+}
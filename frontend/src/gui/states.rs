@@ -0,0 +1,152 @@
+// SPDX-License-Identifier: GPL-3.0-or-later
+// Copyright (C) 2023 Robert Hrusecky <jadedpastabowl@gmail.com>
+
+//! The running game's save states, with a thumbnail of each, to load, delete, or move between
+//! computers.
+
+use std::time::{Duration, UNIX_EPOCH};
+
+use anyhow::{Context as _, Result};
+use egui::{ColorImage, Context, Label, ScrollArea, TextureHandle, TextureOptions, Window};
+use file_dialog::FileDialog;
+use iron_boy_core::system::{THUMBNAIL_HEIGHT, THUMBNAIL_WIDTH};
+use winit::event_loop::EventLoopProxy;
+
+use crate::{emulator::SavedState, event::FrontendEvent};
+
+struct Entry {
+    state: SavedState,
+    /// Uploaded the first time the state is shown
+    thumbnail: Option<TextureHandle>,
+}
+
+pub struct StatesWindow {
+    pub open: bool,
+    states: Vec<Entry>,
+    /// The state waiting for the delete to be confirmed, by its index
+    deleting: Option<usize>,
+    import_dialog: FileDialog,
+}
+
+impl StatesWindow {
+    pub fn new() -> Result<Self> {
+        Ok(Self {
+            open: false,
+            states: Vec::new(),
+            deleting: None,
+            import_dialog: FileDialog::new().context("Failed to initalize file dialog")?,
+        })
+    }
+
+    pub fn set_states(&mut self, states: Vec<SavedState>) {
+        self.states = states
+            .into_iter()
+            .map(|state| Entry {
+                state,
+                thumbnail: None,
+            })
+            .collect();
+        self.deleting = None;
+    }
+
+    pub fn show(&mut self, ctx: &Context, proxy: &EventLoopProxy<FrontendEvent>) -> Result<()> {
+        let mut result = Ok(());
+        Window::new("Save States")
+            .open(&mut self.open)
+            .default_height(400.0)
+            .show(ctx, |ui| {
+                ui.horizontal(|ui| {
+                    if ui.button("Save new state").clicked() {
+                        let _ = proxy.send_event(FrontendEvent::NewState);
+                    }
+                    if ui.button("Import...").clicked() {
+                        result = self
+                            .import_dialog
+                            .open()
+                            .context("Failed to open file dialog");
+                    }
+                });
+                ui.separator();
+                if self.states.is_empty() {
+                    ui.label("No save states for this game yet");
+                    return;
+                }
+                ScrollArea::vertical().show(ui, |ui| {
+                    for (i, entry) in self.states.iter_mut().enumerate() {
+                        ui.horizontal(|ui| {
+                            let size = [THUMBNAIL_WIDTH as f32, THUMBNAIL_HEIGHT as f32];
+                            match entry.state.info.as_ref() {
+                                Some(info) => {
+                                    let texture = entry.thumbnail.get_or_insert_with(|| {
+                                        let image = ColorImage::from_rgba_unmultiplied(
+                                            [THUMBNAIL_WIDTH, THUMBNAIL_HEIGHT],
+                                            &info.thumbnail,
+                                        );
+                                        ctx.load_texture(
+                                            "state thumbnail",
+                                            image,
+                                            TextureOptions::NEAREST,
+                                        )
+                                    });
+                                    ui.image(texture.id(), size);
+                                }
+                                None => {
+                                    ui.add_sized(size, Label::new("No preview"));
+                                }
+                            }
+                            ui.vertical(|ui| {
+                                let name = entry
+                                    .state
+                                    .path
+                                    .file_stem()
+                                    .unwrap_or_default()
+                                    .to_string_lossy()
+                                    .into_owned();
+                                ui.strong(name);
+                                if let Some(info) = &entry.state.info {
+                                    ui.label(info.title.as_str());
+                                    let saved_at = UNIX_EPOCH + Duration::from_secs(info.saved_at);
+                                    ui.label(
+                                        humantime::format_rfc3339_seconds(saved_at).to_string(),
+                                    );
+                                }
+                                ui.horizontal(|ui| {
+                                    let path = &entry.state.path;
+                                    if self.deleting == Some(i) {
+                                        ui.label("Delete it?");
+                                        if ui.button("Yes").clicked() {
+                                            let _ = proxy.send_event(FrontendEvent::DeleteState(
+                                                path.clone(),
+                                            ));
+                                        }
+                                        if ui.button("No").clicked() {
+                                            self.deleting = None;
+                                        }
+                                        return;
+                                    }
+                                    if ui.button("Load").clicked() {
+                                        let _ = proxy
+                                            .send_event(FrontendEvent::LoadState(path.clone()));
+                                    }
+                                    if ui.button("Export").clicked() {
+                                        let _ = proxy
+                                            .send_event(FrontendEvent::ExportState(path.clone()));
+                                    }
+                                    if ui.button("Delete").clicked() {
+                                        self.deleting = Some(i);
+                                    }
+                                });
+                            });
+                        });
+                        ui.separator();
+                    }
+                });
+            });
+
+        self.import_dialog.show(ctx);
+        if let Some(file) = self.import_dialog.file() {
+            let _ = proxy.send_event(FrontendEvent::ImportState(file.name().into()));
+        }
+        result
+    }
+}
@@ -0,0 +1,89 @@
+// SPDX-License-Identifier: GPL-3.0-or-later
+// Copyright (C) 2023 Robert Hrusecky <jadedpastabowl@gmail.com>
+
+//! The last instructions the CPU ran, disassembled, and the ones it's about to run. The game can
+//! be stepped an instruction at a time from here, backwards too with the rewind buffer.
+
+use egui::{Context, RichText, ScrollArea, TextStyle, Window};
+use iron_boy_core::system::disasm::{Line, TraceEntry};
+use winit::event_loop::EventLoopProxy;
+
+use crate::event::FrontendEvent;
+
+pub struct TraceWindow {
+    pub open: bool,
+    recording: bool,
+    trace: Vec<String>,
+    upcoming: Vec<String>,
+}
+
+fn format_line(line: &Line) -> String {
+    let bytes: Vec<_> = line
+        .bytes
+        .iter()
+        .map(|byte| format!("{byte:02x}"))
+        .collect();
+    format!("{:04x}  {:<9} {}", line.addr, bytes.join(" "), line.text)
+}
+
+impl TraceWindow {
+    pub fn new() -> Self {
+        Self {
+            open: false,
+            recording: false,
+            trace: Vec::new(),
+            upcoming: Vec::new(),
+        }
+    }
+
+    /// Whether the CPU should keep a trace, since it runs slower while it does.
+    pub fn tracing(&self) -> bool {
+        self.open && self.recording
+    }
+
+    pub fn capture<'a>(&mut self, trace: impl Iterator<Item = &'a TraceEntry>, upcoming: &[Line]) {
+        if !self.open {
+            return;
+        }
+        self.trace = trace.map(TraceEntry::to_line).collect();
+        self.upcoming = upcoming.iter().map(format_line).collect();
+    }
+
+    pub fn show(&mut self, ctx: &Context, proxy: &EventLoopProxy<FrontendEvent>) {
+        let mut open = self.open;
+        Window::new("Instruction Trace")
+            .open(&mut open)
+            .default_width(300.0)
+            .show(ctx, |ui| {
+                ui.horizontal(|ui| {
+                    ui.checkbox(&mut self.recording, "Record");
+                    // Stepping pauses the game
+                    if ui.button("Step back").clicked() {
+                        let _ = proxy.send_event(FrontendEvent::StepBack);
+                    }
+                    if ui.button("Step").clicked() {
+                        let _ = proxy.send_event(FrontendEvent::StepInstruction);
+                    }
+                });
+                ui.label("Next");
+                for line in &self.upcoming {
+                    ui.label(RichText::new(line).monospace());
+                }
+                ui.separator();
+                if !self.recording {
+                    ui.label("Not recording");
+                    return;
+                }
+                let row_height = ui.text_style_height(&TextStyle::Monospace);
+                ScrollArea::vertical()
+                    .stick_to_bottom(true)
+                    .auto_shrink([false; 2])
+                    .show_rows(ui, row_height, self.trace.len(), |ui, rows| {
+                        for line in &self.trace[rows] {
+                            ui.label(RichText::new(line).monospace());
+                        }
+                    });
+            });
+        self.open = open;
+    }
+}
@@ -1,23 +1,175 @@
 // SPDX-License-Identifier: GPL-3.0-or-later
 // Copyright (C) 2023 Robert Hrusecky <jadedpastabowl@gmail.com>
 
-use anyhow::{Error, Result};
-use egui::{Context, Frame, Grid, Id, InnerResponse, Margin, SidePanel, TopBottomPanel, Window};
+#[cfg(not(target_arch = "wasm32"))]
+use std::path::Path;
+use std::time::Duration;
+
+use anyhow::{Context as _, Error, Result};
+use egui::{
+    Align2, Area, Color32, ComboBox, Context, Frame, Id, InnerResponse, Margin, ProgressBar,
+    SidePanel, Slider, TopBottomPanel, Window,
+};
+use file_dialog::{FileDialog, FileHandle};
+#[cfg(feature = "tools")]
+use iron_boy_core::event::{Event, EventKind};
+use iron_boy_core::{
+    cart::MbcKind,
+    joypad::Socd,
+    system::{ColorTransform, DmgPalette, Model},
+};
+use serde::{Deserialize, Serialize};
 use winit::event_loop::EventLoopProxy;
 
-use crate::event::FrontendEvent;
+#[cfg(feature = "tools")]
+use crate::emulator::Cgb;
+use crate::{
+    background,
+    event::FrontendEvent,
+    filter::{DisplayOptions, ScaleMode, ScalingFilter},
+    gamepad,
+    input::InputMap,
+    recent::RecentRoms,
+    rewind,
+};
+#[cfg(not(target_arch = "wasm32"))]
+use crate::{library::Library, recorder::RecordingFormat};
+
+#[cfg(not(target_arch = "wasm32"))]
+use super::states::StatesWindow;
 
-use super::chooser::RomChooser;
+use super::{
+    cheats::CheatsWindow,
+    cheatsheet::{self, Cheatsheet},
+    chooser::RomChooser,
+    compat::CompatReportWindow,
+    controls::ControlsWindow,
+    rom_info::RomInfoWindow,
+    save_failed::SaveFailedWindow,
+};
+#[cfg(feature = "tools")]
+use super::{
+    console::SerialConsoleWindow, log::EventLogWindow, oam::OamViewerWindow, trace::TraceWindow,
+    vram::VramViewerWindow,
+};
+
+const CHANNEL_NAMES: [&str; 4] = ["Pulse 1", "Pulse 2", "Wave", "Noise"];
+/// How tall box art is in the library, in points
+#[cfg(not(target_arch = "wasm32"))]
+const BOX_ART_HEIGHT: f32 = 48.0;
 
 struct ErrorWindow {
     open: bool,
     error: Error,
 }
 
+/// The parts of the UI's state that are remembered between sessions. Window positions and sizes
+/// are handled by egui's memory.
+#[derive(Serialize, Deserialize)]
+#[serde(default)]
+pub struct UiLayout {
+    panel_open: bool,
+    gamepad_deadzone: f32,
+    rewind_speed: usize,
+    rewind_interval: usize,
+    color_transform: ColorTransform,
+    scale: ScaleMode,
+    #[cfg(feature = "tools")]
+    event_log_open: bool,
+}
+
+impl Default for UiLayout {
+    fn default() -> Self {
+        Self {
+            panel_open: true,
+            gamepad_deadzone: gamepad::DEFAULT_DEADZONE,
+            rewind_speed: 1,
+            rewind_interval: rewind::DEFAULT_INTERVAL,
+            color_transform: ColorTransform::default(),
+            scale: ScaleMode::default(),
+            #[cfg(feature = "tools")]
+            event_log_open: false,
+        }
+    }
+}
+
 pub struct Ui {
     panel_open: bool,
     rom_chooser: RomChooser,
     errors: Vec<ErrorWindow>,
+    #[cfg(feature = "tools")]
+    pub event_log: EventLogWindow,
+    #[cfg(feature = "tools")]
+    pub serial_console: SerialConsoleWindow,
+    #[cfg(feature = "tools")]
+    pub vram_viewer: VramViewerWindow,
+    #[cfg(feature = "tools")]
+    pub oam_viewer: OamViewerWindow,
+    #[cfg(feature = "tools")]
+    pub trace: TraceWindow,
+    pub cheatsheet: Cheatsheet,
+    pub controls: ControlsWindow,
+    pub compat: CompatReportWindow,
+    pub rom_info: RomInfoWindow,
+    pub recent: RecentRoms,
+    #[cfg(not(target_arch = "wasm32"))]
+    pub library: Library,
+    pub cheats: CheatsWindow,
+    pub save_failed: SaveFailedWindow,
+    #[cfg(not(target_arch = "wasm32"))]
+    pub states: StatesWindow,
+    /// The names of the connected controllers
+    pub gamepads: Vec<String>,
+    pub gamepad_deadzone: f32,
+    pub socd: Socd,
+    /// Indexed like `AudioChannel`
+    pub muted_channels: [bool; 4],
+    pub audio_latency: Duration,
+    /// In percent, from mono to the game's own panning
+    pub stereo_width: u8,
+    /// Whether the stereo width slider is being dragged, so the width isn't settled yet
+    pub adjusting_stereo_width: bool,
+    /// The average time spent emulating and rendering each frame lately
+    pub frame_times: Option<(Duration, Duration)>,
+    /// Frames to go back for each one shown while rewinding
+    pub rewind_speed: usize,
+    /// Frames between rewind states
+    pub rewind_interval: usize,
+    /// How far back rewinding can go and how full the buffer is, while rewinding
+    pub rewind: Option<(Duration, f32)>,
+    /// Takes effect when the next game starts, like the boot ROM choices
+    pub model: Model,
+    /// The mapper to use instead of the one in the header, for games that fail to load
+    pub mbc: Option<MbcKind>,
+    bundle_dialog: FileDialog,
+    #[cfg(not(target_arch = "wasm32"))]
+    boot_rom_dialog: FileDialog,
+    /// The boot ROM to use instead of the built in one, once the engine has checked that it loads
+    #[cfg(not(target_arch = "wasm32"))]
+    pub boot_rom: Option<Box<Path>>,
+    pub skip_boot_rom: bool,
+    pub dmg_palette: DmgPalette,
+    /// Saved in the video settings on desktop, which take precedence over the layout
+    pub color_transform: ColorTransform,
+    pub filter: ScalingFilter,
+    pub scale: ScaleMode,
+    #[cfg(not(target_arch = "wasm32"))]
+    pub recording_format: RecordingFormat,
+    #[cfg(not(target_arch = "wasm32"))]
+    pub recording: bool,
+    #[cfg(not(target_arch = "wasm32"))]
+    pub dumping_audio: bool,
+    #[cfg(not(target_arch = "wasm32"))]
+    pub recording_movie: bool,
+    /// The frame being played and the length of the movie, while one plays
+    #[cfg(not(target_arch = "wasm32"))]
+    pub movie_progress: Option<(u64, u64)>,
+    #[cfg(not(target_arch = "wasm32"))]
+    movie_dialog: FileDialog,
+    pub overclocked: bool,
+    /// In percent of full speed, which the audio's pitch follows
+    pub speed: u16,
+    pub paused: bool,
 }
 
 impl Ui {
@@ -26,13 +178,113 @@ impl Ui {
             panel_open: true,
             rom_chooser: RomChooser::new()?,
             errors: Vec::new(),
+            #[cfg(feature = "tools")]
+            event_log: EventLogWindow::new(),
+            #[cfg(feature = "tools")]
+            serial_console: SerialConsoleWindow::new(),
+            #[cfg(feature = "tools")]
+            vram_viewer: VramViewerWindow::new(),
+            #[cfg(feature = "tools")]
+            oam_viewer: OamViewerWindow::new(),
+            #[cfg(feature = "tools")]
+            trace: TraceWindow::new(),
+            cheatsheet: Cheatsheet::new(),
+            controls: ControlsWindow::new(),
+            compat: CompatReportWindow::new(),
+            rom_info: RomInfoWindow::new(),
+            recent: RecentRoms::load(),
+            #[cfg(not(target_arch = "wasm32"))]
+            library: Library::default(),
+            cheats: CheatsWindow::new()?,
+            save_failed: SaveFailedWindow::new(),
+            #[cfg(not(target_arch = "wasm32"))]
+            states: StatesWindow::new()?,
+            gamepads: Vec::new(),
+            gamepad_deadzone: gamepad::DEFAULT_DEADZONE,
+            socd: Socd::default(),
+            muted_channels: [false; 4],
+            audio_latency: Duration::ZERO,
+            stereo_width: 100,
+            adjusting_stereo_width: false,
+            frame_times: None,
+            rewind_speed: 1,
+            rewind_interval: rewind::DEFAULT_INTERVAL,
+            rewind: None,
+            model: Model::Cgb,
+            mbc: None,
+            bundle_dialog: FileDialog::new().context("Failed to initalize file dialog")?,
+            #[cfg(not(target_arch = "wasm32"))]
+            boot_rom_dialog: FileDialog::new().context("Failed to initalize file dialog")?,
+            #[cfg(not(target_arch = "wasm32"))]
+            boot_rom: None,
+            skip_boot_rom: false,
+            dmg_palette: DmgPalette::Gray,
+            color_transform: ColorTransform::default(),
+            filter: ScalingFilter::Nearest,
+            scale: ScaleMode::default(),
+            #[cfg(not(target_arch = "wasm32"))]
+            recording_format: RecordingFormat::default(),
+            #[cfg(not(target_arch = "wasm32"))]
+            recording: false,
+            #[cfg(not(target_arch = "wasm32"))]
+            dumping_audio: false,
+            #[cfg(not(target_arch = "wasm32"))]
+            recording_movie: false,
+            #[cfg(not(target_arch = "wasm32"))]
+            movie_progress: None,
+            #[cfg(not(target_arch = "wasm32"))]
+            movie_dialog: FileDialog::new().context("Failed to initalize file dialog")?,
+            overclocked: false,
+            speed: 100,
+            paused: false,
         })
     }
 
+    pub fn layout(&self) -> UiLayout {
+        UiLayout {
+            panel_open: self.panel_open,
+            gamepad_deadzone: self.gamepad_deadzone,
+            rewind_speed: self.rewind_speed,
+            rewind_interval: self.rewind_interval,
+            color_transform: self.color_transform,
+            scale: self.scale,
+            #[cfg(feature = "tools")]
+            event_log_open: self.event_log.open,
+        }
+    }
+
+    pub fn set_layout(&mut self, layout: UiLayout) {
+        self.panel_open = layout.panel_open;
+        self.gamepad_deadzone = layout.gamepad_deadzone;
+        self.rewind_speed = layout.rewind_speed;
+        self.rewind_interval = layout.rewind_interval;
+        self.color_transform = layout.color_transform;
+        self.scale = layout.scale;
+        #[cfg(feature = "tools")]
+        self.event_log.open = layout.event_log_open;
+    }
+
     pub fn add_error_popup(&mut self, error: Error) {
         self.errors.push(ErrorWindow { open: true, error });
     }
 
+    /// Updates the tools windows with where `cgb` is now, and the events it raised getting there.
+    #[cfg(feature = "tools")]
+    pub fn capture_tools(&mut self, cgb: &mut Cgb, events: Vec<Event>) {
+        // Games pulse the rumble motor to set its strength, which would flood the log
+        self.event_log.extend(
+            events
+                .into_iter()
+                .filter(|event| !matches!(event.kind, EventKind::Rumble(_))),
+        );
+        let video = cgb.debug_video();
+        self.vram_viewer.capture(&video);
+        self.oam_viewer.capture(&video);
+        let upcoming = cgb.upcoming_instructions();
+        self.trace.capture(cgb.trace(), &upcoming);
+        cgb.set_tracing(self.trace.tracing());
+    }
+
     fn show_errors(&mut self, ctx: &Context) {
         let mut i = 0;
         while i < self.errors.len() {
@@ -58,7 +310,346 @@ impl Ui {
         }
     }
 
-    pub fn update(&mut self, ctx: &Context, proxy: &EventLoopProxy<FrontendEvent>) -> Result<()> {
+    #[cfg(feature = "tools")]
+    fn show_tools(&mut self, ui: &mut egui::Ui) {
+        ui.separator();
+        if ui.button("Event Log").clicked() {
+            self.event_log.open = !self.event_log.open;
+        }
+        if ui.button("Serial Console").clicked() {
+            self.serial_console.open = !self.serial_console.open;
+        }
+        if ui.button("VRAM Viewer").clicked() {
+            self.vram_viewer.open = !self.vram_viewer.open;
+        }
+        if ui.button("OAM Viewer").clicked() {
+            self.oam_viewer.open = !self.oam_viewer.open;
+        }
+        if ui.button("Instruction Trace").clicked() {
+            self.trace.open = !self.trace.open;
+        }
+    }
+
+    fn show_recent(&mut self, ui: &mut egui::Ui, proxy: &EventLoopProxy<FrontendEvent>) {
+        if self.recent.roms().is_empty() {
+            return;
+        }
+        ui.collapsing("Recent games", |ui| {
+            for rom in self.recent.roms() {
+                #[cfg(not(target_arch = "wasm32"))]
+                if ui
+                    .button(rom.name())
+                    .on_hover_text(rom.path.display().to_string())
+                    .clicked()
+                {
+                    self.rom_chooser.open(&rom.path, proxy);
+                }
+                #[cfg(target_arch = "wasm32")]
+                if ui.button(rom.name()).clicked() {
+                    let event = match rom.load() {
+                        Ok(rom) => FrontendEvent::NewRom(rom),
+                        Err(error) => FrontendEvent::Error(error),
+                    };
+                    let _ = proxy.send_event(event);
+                }
+            }
+        });
+    }
+
+    #[cfg(not(target_arch = "wasm32"))]
+    fn show_library(&mut self, ui: &mut egui::Ui, proxy: &EventLoopProxy<FrontendEvent>) {
+        if self.library.games().next().is_none() {
+            return;
+        }
+        ui.collapsing("Library", |ui| {
+            egui::ScrollArea::vertical()
+                .max_height(300.0)
+                .show(ui, |ui| {
+                    for game in self.library.games() {
+                        // The ROM database's title and box art, where it has them
+                        let info = self.library.info(game);
+                        let name = info
+                            .and_then(|info| info.title.clone())
+                            .unwrap_or_else(|| game.name());
+                        let clicked = ui
+                            .horizontal(|ui| {
+                                if let Some(texture) = info.and_then(|info| info.box_art(ui.ctx()))
+                                {
+                                    let size = texture.size_vec2();
+                                    ui.image(texture.id(), size * (BOX_ART_HEIGHT / size.y));
+                                }
+                                ui.button(name)
+                                    .on_hover_text(game.path.display().to_string())
+                                    .clicked()
+                            })
+                            .inner;
+                        if clicked {
+                            self.rom_chooser.open(&game.path, proxy);
+                        }
+                    }
+                });
+        });
+    }
+
+    fn show_hardware(&mut self, ui: &mut egui::Ui) {
+        ui.separator();
+        ComboBox::from_label("Hardware")
+            .selected_text(self.model.to_string())
+            .show_ui(ui, |ui| {
+                for model in Model::ALL {
+                    ui.selectable_value(&mut self.model, model, model.to_string());
+                }
+            })
+            .response
+            .on_hover_text("Takes effect when the next game starts");
+        let mbc_name = |mbc: Option<MbcKind>| match mbc {
+            Some(mbc) => mbc.to_string(),
+            None => "From header".to_owned(),
+        };
+        ComboBox::from_label("Mapper")
+            .selected_text(mbc_name(self.mbc))
+            .show_ui(ui, |ui| {
+                for mbc in [None].into_iter().chain(MbcKind::ALL.map(Some)) {
+                    ui.selectable_value(&mut self.mbc, mbc, mbc_name(mbc));
+                }
+            })
+            .response
+            .on_hover_text(
+                "Overrides the cartridge's mapper chip, for games with a broken header. Takes \
+                 effect when the next game starts",
+            );
+        ComboBox::from_label("Game Boy shades")
+            .selected_text(self.dmg_palette.to_string())
+            .show_ui(ui, |ui| {
+                for palette in DmgPalette::ALL {
+                    ui.selectable_value(&mut self.dmg_palette, palette, palette.to_string());
+                }
+            });
+        ui.checkbox(&mut self.skip_boot_rom, "Skip boot ROM");
+    }
+
+    #[cfg(not(target_arch = "wasm32"))]
+    fn show_boot_rom(&mut self, ui: &mut egui::Ui) -> Result<()> {
+        let mut result = Ok(());
+        ui.add_enabled_ui(!self.skip_boot_rom, |ui| {
+            ui.horizontal(|ui| {
+                let name = match &self.boot_rom {
+                    Some(path) => path.file_name().unwrap_or_default().to_string_lossy(),
+                    None => "Built in".into(),
+                };
+                ui.label(format!("Boot ROM: {name}"));
+                if ui.small_button("Browse...").clicked() {
+                    result = self
+                        .boot_rom_dialog
+                        .open()
+                        .context("Failed to open file dialog");
+                }
+                if self.boot_rom.is_some() && ui.small_button("Clear").clicked() {
+                    self.boot_rom = None;
+                }
+            });
+        });
+        result
+    }
+
+    pub fn display_options(&self) -> DisplayOptions {
+        DisplayOptions {
+            filter: self.filter,
+            scale: self.scale,
+        }
+    }
+
+    fn show_video(&mut self, ui: &mut egui::Ui, proxy: &EventLoopProxy<FrontendEvent>) {
+        ui.separator();
+        ComboBox::from_label("Scaling")
+            .selected_text(self.scale.to_string())
+            .show_ui(ui, |ui| {
+                for scale in ScaleMode::ALL {
+                    ui.selectable_value(&mut self.scale, scale, scale.to_string());
+                }
+            });
+        ComboBox::from_label("Display filter")
+            .selected_text(self.filter.to_string())
+            .show_ui(ui, |ui| {
+                for filter in ScalingFilter::ALL {
+                    ui.selectable_value(&mut self.filter, filter, filter.to_string());
+                }
+            });
+        ComboBox::from_label("Color transform")
+            .selected_text(self.color_transform.to_string())
+            .show_ui(ui, |ui| {
+                for transform in ColorTransform::ALL {
+                    ui.selectable_value(
+                        &mut self.color_transform,
+                        transform,
+                        transform.to_string(),
+                    );
+                }
+            })
+            .response
+            .on_hover_text("How CGB colors are adjusted to look like they did on its screen");
+        if ui.button("Fullscreen").clicked() {
+            let _ = proxy.send_event(FrontendEvent::ToggleFullscreen);
+        }
+    }
+
+    #[cfg(not(target_arch = "wasm32"))]
+    fn show_recording(
+        &mut self,
+        ui: &mut egui::Ui,
+        proxy: &EventLoopProxy<FrontendEvent>,
+    ) -> Result<()> {
+        ui.add_enabled_ui(!self.recording, |ui| {
+            ComboBox::from_label("Recording format")
+                .selected_text(self.recording_format.to_string())
+                .show_ui(ui, |ui| {
+                    for format in RecordingFormat::ALL {
+                        ui.selectable_value(&mut self.recording_format, format, format.to_string());
+                    }
+                });
+        });
+        let text = if self.recording {
+            "⏹ Stop recording"
+        } else {
+            "⏺ Record"
+        };
+        if ui.button(text).clicked() {
+            let _ = proxy.send_event(FrontendEvent::ToggleRecording);
+        }
+        let text = if self.dumping_audio {
+            "⏹ Stop writing audio"
+        } else {
+            "🎵 Write audio to WAV"
+        };
+        if ui.button(text).clicked() {
+            let _ = proxy.send_event(FrontendEvent::ToggleAudioDump);
+        }
+
+        let mut result = Ok(());
+        ui.horizontal(|ui| {
+            let text = if self.recording_movie {
+                "⏹ Stop movie"
+            } else {
+                "🎞 Record movie"
+            };
+            if ui
+                .button(text)
+                .on_hover_text("Records the buttons held each frame, to play back exactly")
+                .clicked()
+            {
+                let _ = proxy.send_event(FrontendEvent::ToggleMovieRecording);
+            }
+            if ui.button("▶ Play movie...").clicked() {
+                result = self
+                    .movie_dialog
+                    .open()
+                    .context("Failed to open file dialog");
+            }
+        });
+        if let Some((frame, frames)) = self.movie_progress {
+            ui.label(format!("Playing movie: frame {frame} of {frames}"));
+        }
+        result
+    }
+
+    fn show_rewind(&mut self, ui: &mut egui::Ui) {
+        ui.separator();
+        ui.add(
+            Slider::new(&mut self.rewind_speed, 1..=8)
+                .text("Rewind speed")
+                .suffix("×"),
+        );
+        ui.add(
+            Slider::new(&mut self.rewind_interval, 1..=8)
+                .text("Rewind granularity")
+                .suffix(" frames"),
+        )
+        .on_hover_text("Fewer frames between states rewinds more smoothly, but uses more memory");
+    }
+
+    /// Shows how far back the rewind buffer reaches, at the bottom of the screen.
+    fn show_rewind_progress(&self, ctx: &Context) {
+        let Some((duration, fill)) = self.rewind else {
+            return;
+        };
+        Area::new("rewind progress")
+            .anchor(Align2::CENTER_BOTTOM, [0.0, -20.0])
+            .interactable(false)
+            .show(ctx, |ui| {
+                Frame::popup(ui.style()).show(ui, |ui| {
+                    ui.set_width(200.0);
+                    ui.add(
+                        ProgressBar::new(fill).text(format!("⏪ {:.1} s", duration.as_secs_f32())),
+                    );
+                });
+            });
+    }
+
+    fn show_performance(&mut self, ui: &mut egui::Ui, proxy: &EventLoopProxy<FrontendEvent>) {
+        ui.separator();
+        ui.horizontal(|ui| {
+            ui.add(
+                Slider::new(&mut self.speed, 50..=400)
+                    .step_by(25.0)
+                    .text("Speed")
+                    .suffix("%"),
+            )
+            .on_hover_text("Slows down hard sections or speeds through grinding");
+            if ui.small_button("Reset").clicked() {
+                self.speed = 100;
+            }
+        });
+        if let Some((emulation, render)) = self.frame_times {
+            ui.label(format!(
+                "Frame time: {:.1} ms emulating, {:.1} ms rendering",
+                emulation.as_secs_f64() * 1000.0,
+                render.as_secs_f64() * 1000.0,
+            ));
+        }
+        if ui
+            .button("Export frame timings")
+            .on_hover_text("Saves the last minute of them as CSV, to attach to a report of stutter")
+            .clicked()
+        {
+            let _ = proxy.send_event(FrontendEvent::ExportFrameTimings);
+        }
+    }
+
+    fn show_audio_channels(&mut self, ui: &mut egui::Ui) {
+        ui.separator();
+        ui.label(format!(
+            "Audio latency: {} ms",
+            self.audio_latency.as_millis()
+        ));
+        self.adjusting_stereo_width = ui
+            .add(
+                Slider::new(&mut self.stereo_width, 0..=100)
+                    .text("Stereo width")
+                    .suffix("%"),
+            )
+            .on_hover_text("Lower blends the left and right channels, down to mono at 0%")
+            .dragged();
+        ui.label("Audio channels");
+        for (i, name) in CHANNEL_NAMES.into_iter().enumerate() {
+            ui.horizontal(|ui| {
+                let mut playing = !self.muted_channels[i];
+                if ui.checkbox(&mut playing, name).changed() {
+                    self.muted_channels[i] = !playing;
+                }
+                if ui.small_button("Solo").clicked() {
+                    self.muted_channels = [true; 4];
+                    self.muted_channels[i] = false;
+                }
+            });
+        }
+    }
+
+    pub fn update(
+        &mut self,
+        ctx: &Context,
+        proxy: &EventLoopProxy<FrontendEvent>,
+        input_map: &mut InputMap,
+    ) -> Result<()> {
         let mut result = Ok(());
         if let Some(pos) = ctx.input(|i| i.pointer.interact_pos()) {
             if pos.x < ctx.screen_rect().width() * 0.05 {
@@ -69,9 +660,86 @@ impl Ui {
             .frame(Frame::side_top_panel(&ctx.style()).inner_margin(Margin::same(10.0)))
             .show_animated(ctx, self.panel_open, |ui| {
                 ui.heading("Iron Boy");
+                if self.overclocked {
+                    ui.colored_label(Color32::YELLOW, "⚠ CPU overclocked (inaccurate)");
+                }
+                if self.paused {
+                    ui.label("⏸ Paused");
+                }
                 ui.separator();
 
                 result = self.rom_chooser.show(ui, proxy);
+                self.show_recent(ui, proxy);
+                #[cfg(not(target_arch = "wasm32"))]
+                self.show_library(ui, proxy);
+
+                #[cfg(feature = "tools")]
+                self.show_tools(ui);
+
+                ui.separator();
+                ui.horizontal(|ui| {
+                    if ui.button("Cheats").clicked() {
+                        self.cheats.open = !self.cheats.open;
+                    }
+                    #[cfg(not(target_arch = "wasm32"))]
+                    if ui.button("Save States").clicked() {
+                        self.states.open = !self.states.open;
+                    }
+                });
+                ui.horizontal(|ui| {
+                    if ui
+                        .button("Export saves")
+                        .on_hover_text(
+                            "Bundles the battery save, quick save, and cheats to move to another \
+                             computer or the web version",
+                        )
+                        .clicked()
+                    {
+                        let _ = proxy.send_event(FrontendEvent::ExportBundle);
+                    }
+                    if ui
+                        .button("Export .sav")
+                        .on_hover_text(
+                            "Writes just the battery save, in the format other emulators and \
+                             flashcarts use",
+                        )
+                        .clicked()
+                    {
+                        let _ = proxy.send_event(FrontendEvent::ExportSav);
+                    }
+                    if ui
+                        .button("Import saves...")
+                        .on_hover_text("Takes an exported bundle, or a battery save from anywhere")
+                        .clicked()
+                    {
+                        result = result.and(
+                            self.bundle_dialog
+                                .open()
+                                .context("Failed to open file dialog"),
+                        );
+                    }
+                });
+                ui.horizontal(|ui| {
+                    if ui.button("ROM Info").clicked() {
+                        self.rom_info.open = !self.rom_info.open;
+                    }
+                    if ui.button("Report Compatibility").clicked() {
+                        self.compat.open = !self.compat.open;
+                    }
+                });
+                self.show_hardware(ui);
+                #[cfg(not(target_arch = "wasm32"))]
+                {
+                    result = result.and(self.show_boot_rom(ui));
+                }
+                self.show_video(ui, proxy);
+                self.show_rewind(ui);
+                #[cfg(not(target_arch = "wasm32"))]
+                {
+                    result = result.and(self.show_recording(ui, proxy));
+                }
+                self.show_performance(ui, proxy);
+                self.show_audio_channels(ui);
 
                 TopBottomPanel::bottom("controls panel")
                     .frame(Frame::none())
@@ -80,29 +748,32 @@ impl Ui {
                     .show_inside(ui, |ui| {
                         ui.heading("Controls");
                         ui.separator();
-                        Grid::new("controls table")
-                            .striped(true)
-                            .num_columns(2)
-                            .show(ui, |ui| {
-                                ui.monospace("WASD");
-                                ui.horizontal(|ui| {
-                                    ui.label("Joy Pad");
-                                    // Force stripes to take up the whole width
-                                    ui.add_space(ui.available_width());
-                                });
-                                ui.end_row();
-                                ui.monospace("<");
-                                ui.label("A");
-                                ui.end_row();
-                                ui.monospace(">");
-                                ui.label("B");
-                                ui.end_row();
-                                ui.monospace("[");
-                                ui.label("Start");
-                                ui.end_row();
-                                ui.monospace("]");
-                                ui.label("Select");
-                            });
+                        cheatsheet::bindings_grid(ui, "controls table", input_map);
+                        if ui.button("Remap").clicked() {
+                            self.controls.open = !self.controls.open;
+                        }
+                        ui.separator();
+                        if self.gamepads.is_empty() {
+                            ui.label("No controllers connected");
+                        }
+                        for name in &self.gamepads {
+                            ui.label(format!("🎮 {name}"));
+                        }
+                        ui.add(
+                            Slider::new(&mut self.gamepad_deadzone, 0.05..=0.95)
+                                .text("Stick deadzone"),
+                        );
+                        ComboBox::from_label("Opposing directions")
+                            .selected_text(self.socd.to_string())
+                            .show_ui(ui, |ui| {
+                                for socd in Socd::ALL {
+                                    ui.selectable_value(&mut self.socd, socd, socd.to_string());
+                                }
+                            })
+                            .response
+                            .on_hover_text(
+                                "What the game sees when opposite directions are held together",
+                            );
                     });
             });
 
@@ -122,9 +793,58 @@ impl Ui {
         }
 
         self.rom_chooser.show_dialog(ctx, proxy);
+        self.bundle_dialog.show(ctx);
+        if let Some(file) = self.bundle_dialog.file() {
+            spawn_bundle_read(file, proxy);
+        }
+        #[cfg(not(target_arch = "wasm32"))]
+        {
+            self.boot_rom_dialog.show(ctx);
+            if let Some(file) = self.boot_rom_dialog.file() {
+                let _ = proxy.send_event(FrontendEvent::ChooseBootRom(file.name().into()));
+            }
+            self.movie_dialog.show(ctx);
+            if let Some(file) = self.movie_dialog.file() {
+                let _ = proxy.send_event(FrontendEvent::PlayMovie(file.name().into()));
+            }
+        }
+
+        #[cfg(feature = "tools")]
+        {
+            self.event_log.show(ctx);
+            self.serial_console.show(ctx);
+            self.vram_viewer.show(ctx);
+            self.oam_viewer.show(ctx);
+            self.trace.show(ctx, proxy);
+        }
+        self.cheatsheet.show(ctx, input_map);
+        self.controls.show(ctx, input_map);
+        self.rom_info.show(ctx);
+        let compat_result = self.compat.show(ctx, proxy);
+        let cheats_result = self.cheats.show(ctx, proxy);
+        self.save_failed.show(ctx, proxy);
+        #[cfg(not(target_arch = "wasm32"))]
+        {
+            result = result.and(self.states.show(ctx, proxy));
+        }
 
+        self.show_rewind_progress(ctx);
         self.show_errors(ctx);
 
-        result.map_err(From::from)
+        result
+            .map_err(From::from)
+            .and(compat_result)
+            .and(cheats_result)
     }
 }
+
+fn spawn_bundle_read(file: FileHandle, proxy: &EventLoopProxy<FrontendEvent>) {
+    let proxy = proxy.clone();
+    background::spawn(async move {
+        let event = match file.read().await.context("Failed to read save bundle") {
+            Ok(data) => FrontendEvent::ImportSaves(data),
+            Err(error) => FrontendEvent::Error(error),
+        };
+        let _ = proxy.send_event(event);
+    });
+}
@@ -0,0 +1,276 @@
+// SPDX-License-Identifier: GPL-3.0-or-later
+// Copyright (C) 2023 Robert Hrusecky <jadedpastabowl@gmail.com>
+
+//! Shows what's in VRAM the way the PPU sees it: the tiles in both banks, the two tile maps with
+//! where the screen and the window are over them, and the palettes.
+
+use egui::{
+    pos2, vec2, Color32, ColorImage, ComboBox, Context, Rect, Sense, Stroke, TextureHandle,
+    TextureOptions, Window,
+};
+use iron_boy_core::system::{DebugVideo, Tile, SCREEN_HEIGHT, SCREEN_WIDTH, TILES_PER_BANK};
+
+/// Of the tile sheet, for each bank
+const SHEET_COLUMNS: usize = 16;
+const SHEET_ROWS: usize = TILES_PER_BANK / SHEET_COLUMNS;
+const MAP_SIZE: usize = 256;
+/// How much bigger than a pixel to draw everything
+const ZOOM: f32 = 2.0;
+const SCREEN_COLOR: Color32 = Color32::RED;
+const WINDOW_COLOR: Color32 = Color32::LIGHT_BLUE;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Tab {
+    Tiles,
+    Maps,
+    Palettes,
+}
+
+/// Which palette to color the tile sheet with, since tiles don't have one of their own
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum SheetPalette {
+    Bg(usize),
+    Obj(usize),
+}
+
+impl SheetPalette {
+    fn name(self) -> String {
+        match self {
+            Self::Bg(palette) => format!("BG {palette}"),
+            Self::Obj(palette) => format!("OBJ {palette}"),
+        }
+    }
+}
+
+/// Everything shown, copied out of the system after each frame.
+struct Capture {
+    bg_palettes: [[[u8; 3]; 4]; 8],
+    obj_palettes: [[[u8; 3]; 4]; 8],
+    cgb_mode: bool,
+    tiles: ColorImage,
+    map: ColorImage,
+    bg_map: usize,
+    window_map: usize,
+    window_enabled: bool,
+    scroll: (u8, u8),
+    window_position: (i16, i16),
+}
+
+pub struct VramViewerWindow {
+    pub open: bool,
+    tab: Tab,
+    sheet_palette: SheetPalette,
+    map: usize,
+    capture: Option<Capture>,
+    tiles_texture: Option<TextureHandle>,
+    map_texture: Option<TextureHandle>,
+}
+
+fn color([r, g, b]: [u8; 3]) -> Color32 {
+    Color32::from_rgb(r, g, b)
+}
+
+fn draw_tile(
+    image: &mut ColorImage,
+    (x, y): (usize, usize),
+    tile: &Tile,
+    palette: &[[u8; 3]; 4],
+    (x_flip, y_flip): (bool, bool),
+) {
+    for (row, colors) in tile.iter().enumerate() {
+        let row = if y_flip { 7 - row } else { row };
+        for (column, &index) in colors.iter().enumerate() {
+            let column = if x_flip { 7 - column } else { column };
+            image[(x + column, y + row)] = color(palette[index as usize]);
+        }
+    }
+}
+
+impl VramViewerWindow {
+    pub fn new() -> Self {
+        Self {
+            open: false,
+            tab: Tab::Tiles,
+            sheet_palette: SheetPalette::Bg(0),
+            map: 0,
+            capture: None,
+            tiles_texture: None,
+            map_texture: None,
+        }
+    }
+
+    /// Drops the textures, which belong to an egui context that's been replaced.
+    pub fn forget_textures(&mut self) {
+        self.tiles_texture = None;
+        self.map_texture = None;
+    }
+
+    /// Decodes VRAM for showing, which is only worth doing while the window is open.
+    pub fn capture(&mut self, video: &DebugVideo) {
+        if !self.open {
+            return;
+        }
+        let bg_palettes = std::array::from_fn(|palette| video.bg_palette(palette));
+        let obj_palettes = std::array::from_fn(|palette| video.obj_palette(palette));
+
+        let sheet_palette = match self.sheet_palette {
+            SheetPalette::Bg(palette) => &bg_palettes[palette],
+            SheetPalette::Obj(palette) => &obj_palettes[palette],
+        };
+        let mut tiles = ColorImage::new([SHEET_COLUMNS * 8 * 2, SHEET_ROWS * 8], Color32::BLACK);
+        for bank in 0..2 {
+            for index in 0..TILES_PER_BANK {
+                let x = (bank * SHEET_COLUMNS + index % SHEET_COLUMNS) * 8;
+                let y = index / SHEET_COLUMNS * 8;
+                let tile = video.tile(bank, index);
+                draw_tile(&mut tiles, (x, y), &tile, sheet_palette, (false, false));
+            }
+        }
+
+        let mut map = ColorImage::new([MAP_SIZE; 2], Color32::BLACK);
+        for y in 0..MAP_SIZE / 8 {
+            for x in 0..MAP_SIZE / 8 {
+                let entry = video.map_entry(self.map, x, y);
+                let tile = video.tile(entry.bank, entry.tile);
+                let palette = &bg_palettes[entry.palette];
+                let flip = (entry.x_flip, entry.y_flip);
+                draw_tile(&mut map, (x * 8, y * 8), &tile, palette, flip);
+            }
+        }
+
+        self.capture = Some(Capture {
+            bg_palettes,
+            obj_palettes,
+            cgb_mode: video.cgb_mode(),
+            tiles,
+            map,
+            bg_map: video.bg_map(),
+            window_map: video.window_map(),
+            window_enabled: video.window_enabled(),
+            scroll: video.scroll(),
+            window_position: video.window_position(),
+        });
+    }
+
+    pub fn show(&mut self, ctx: &Context) {
+        let mut open = self.open;
+        Window::new("VRAM Viewer")
+            .open(&mut open)
+            .resizable(false)
+            .show(ctx, |ui| {
+                ui.horizontal(|ui| {
+                    ui.selectable_value(&mut self.tab, Tab::Tiles, "Tiles");
+                    ui.selectable_value(&mut self.tab, Tab::Maps, "Maps");
+                    ui.selectable_value(&mut self.tab, Tab::Palettes, "Palettes");
+                });
+                ui.separator();
+                let Some(capture) = &self.capture else {
+                    ui.label("Nothing is running");
+                    return;
+                };
+                match self.tab {
+                    Tab::Tiles => {
+                        ComboBox::from_label("Palette")
+                            .selected_text(self.sheet_palette.name())
+                            .show_ui(ui, |ui| {
+                                let palettes = (0..8)
+                                    .map(SheetPalette::Bg)
+                                    .chain((0..8).map(SheetPalette::Obj));
+                                for palette in palettes {
+                                    let name = palette.name();
+                                    ui.selectable_value(&mut self.sheet_palette, palette, name);
+                                }
+                            });
+                        let texture =
+                            upload(ctx, &mut self.tiles_texture, "vram-tiles", &capture.tiles);
+                        ui.label("Bank 0 and bank 1");
+                        ui.image(texture.id(), texture.size_vec2() * ZOOM);
+                    }
+                    Tab::Maps => {
+                        ui.horizontal(|ui| {
+                            ui.selectable_value(&mut self.map, 0, "9800");
+                            ui.selectable_value(&mut self.map, 1, "9C00");
+                        });
+                        let texture = upload(ctx, &mut self.map_texture, "vram-map", &capture.map);
+                        let (rect, _) =
+                            ui.allocate_exact_size(texture.size_vec2() * ZOOM, Sense::hover());
+                        let uv = Rect::from_min_max(pos2(0.0, 0.0), pos2(1.0, 1.0));
+                        let painter = ui.painter_at(rect);
+                        painter.image(texture.id(), rect, uv, Color32::WHITE);
+                        if self.map == capture.bg_map {
+                            let (x, y) = capture.scroll;
+                            // The screen wraps around the edges of the map
+                            for dx in [0.0, -(MAP_SIZE as f32)] {
+                                for dy in [0.0, -(MAP_SIZE as f32)] {
+                                    let min = rect.min + vec2(x as f32 + dx, y as f32 + dy) * ZOOM;
+                                    let size = vec2(SCREEN_WIDTH as f32, SCREEN_HEIGHT as f32);
+                                    let screen = Rect::from_min_size(min, size * ZOOM);
+                                    painter.rect_stroke(
+                                        screen,
+                                        0.0,
+                                        Stroke::new(1.0, SCREEN_COLOR),
+                                    );
+                                }
+                            }
+                        }
+                        let (x, y) = capture.window_position;
+                        let on_screen = x < SCREEN_WIDTH as i16 && y < SCREEN_HEIGHT as i16;
+                        if self.map == capture.window_map && capture.window_enabled && on_screen {
+                            // Only as much of the window as fits on the screen is drawn
+                            let size = vec2(
+                                (SCREEN_WIDTH as i16 - x.max(0)) as f32,
+                                (SCREEN_HEIGHT as i16 - y) as f32,
+                            );
+                            let window = Rect::from_min_size(rect.min, size * ZOOM);
+                            painter.rect_stroke(window, 0.0, Stroke::new(1.0, WINDOW_COLOR));
+                        }
+                        ui.horizontal(|ui| {
+                            ui.colored_label(SCREEN_COLOR, "Screen");
+                            ui.colored_label(WINDOW_COLOR, "Window");
+                        });
+                    }
+                    Tab::Palettes => {
+                        // Outside of CGB mode, there's BGP, OBP0, and OBP1
+                        let (bg_count, obj_count) = if capture.cgb_mode { (8, 8) } else { (1, 2) };
+                        let kinds = [
+                            ("BG", &capture.bg_palettes[..bg_count]),
+                            ("OBJ", &capture.obj_palettes[..obj_count]),
+                        ];
+                        for (kind, palettes) in kinds {
+                            ui.label(kind);
+                            for (i, palette) in palettes.iter().enumerate() {
+                                ui.horizontal(|ui| {
+                                    ui.monospace(i.to_string());
+                                    for &rgb in palette {
+                                        let size = vec2(16.0, 16.0) * ZOOM / 2.0;
+                                        let (rect, response) =
+                                            ui.allocate_exact_size(size, Sense::hover());
+                                        ui.painter().rect_filled(rect, 0.0, color(rgb));
+                                        let [r, g, b] = rgb;
+                                        response.on_hover_text(format!("#{r:02x}{g:02x}{b:02x}"));
+                                    }
+                                });
+                            }
+                        }
+                    }
+                }
+            });
+        self.open = open;
+    }
+}
+
+/// Replaces the texture's image, creating it the first time.
+fn upload<'a>(
+    ctx: &Context,
+    texture: &'a mut Option<TextureHandle>,
+    name: &str,
+    image: &ColorImage,
+) -> &'a TextureHandle {
+    match texture {
+        Some(texture) => {
+            texture.set(image.clone(), TextureOptions::NEAREST);
+            texture
+        }
+        None => texture.insert(ctx.load_texture(name, image.clone(), TextureOptions::NEAREST)),
+    }
+}
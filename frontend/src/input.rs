@@ -0,0 +1,273 @@
+// SPDX-License-Identifier: GPL-3.0-or-later
+// Copyright (C) 2023 Robert Hrusecky <jadedpastabowl@gmail.com>
+
+use iron_boy_core::joypad::Button;
+use winit::event::VirtualKeyCode;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Player {
+    One = 0,
+    Two,
+}
+
+impl Player {
+    pub const ALL: [Player; 2] = [Player::One, Player::Two];
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Hotkey {
+    ToggleCheatsheet,
+    TogglePause,
+    Rewind,
+    ToggleFilter,
+    ToggleFullscreen,
+    #[cfg(not(target_arch = "wasm32"))]
+    Screenshot,
+    SaveState,
+    LoadState,
+    SaveGame,
+}
+
+impl Hotkey {
+    pub fn description(&self) -> &'static str {
+        match self {
+            Self::ToggleCheatsheet => "Show shortcuts",
+            Self::TogglePause => "Pause",
+            Self::Rewind => "Rewind (hold)",
+            Self::ToggleFilter => "Next display filter",
+            Self::ToggleFullscreen => "Fullscreen",
+            #[cfg(not(target_arch = "wasm32"))]
+            Self::Screenshot => "Save a screenshot",
+            Self::SaveState => "Quick save",
+            Self::LoadState => "Quick load",
+            Self::SaveGame => "Write the game's save now",
+        }
+    }
+}
+
+/// Something a key can be bound to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Action {
+    Joypad(Player, Button),
+    Hotkey(Hotkey),
+}
+
+impl Action {
+    pub fn description(&self) -> String {
+        match self {
+            Self::Joypad(player, button) => {
+                format!("{button:?} for player {}", *player as usize + 1)
+            }
+            Self::Hotkey(hotkey) => hotkey.description().into(),
+        }
+    }
+}
+
+/// A human readable name for `key`, for display in the GUI.
+pub fn key_name(key: VirtualKeyCode) -> String {
+    use VirtualKeyCode as VK;
+    match key {
+        VK::LBracket => "[".into(),
+        VK::RBracket => "]".into(),
+        VK::Comma => ",".into(),
+        VK::Period => ".".into(),
+        VK::Semicolon => ";".into(),
+        VK::Apostrophe => "'".into(),
+        VK::Slash => "/".into(),
+        VK::Backslash => "\\".into(),
+        VK::Minus => "-".into(),
+        VK::Equals => "=".into(),
+        VK::Grave => "`".into(),
+        VK::Back => "Backspace".into(),
+        key => format!("{key:?}"),
+    }
+}
+
+/// A human readable name for a controller button. gilrs names the face buttons by where they sit,
+/// like `East`, since what's printed on them differs between controllers.
+pub fn controller_button_name(button: gilrs::Button) -> String {
+    format!("{button:?}")
+}
+
+/// Which keys, or controller buttons, press which of the joypad's buttons. A button can have more
+/// than one.
+#[derive(Debug, Clone)]
+pub struct Bindings<K = VirtualKeyCode> {
+    keys: Vec<(K, Button)>,
+}
+
+impl<K> Default for Bindings<K> {
+    fn default() -> Self {
+        Self { keys: Vec::new() }
+    }
+}
+
+impl<K: Copy + PartialEq> Bindings<K> {
+    pub fn new(keys: impl IntoIterator<Item = (K, Button)>) -> Self {
+        Self {
+            keys: keys.into_iter().collect(),
+        }
+    }
+
+    pub fn button(&self, key: K) -> Option<Button> {
+        self.keys
+            .iter()
+            .find_map(|&(k, button)| (k == key).then_some(button))
+    }
+
+    /// The first key bound to `button`.
+    pub fn key(&self, button: Button) -> Option<K> {
+        self.keys(button).next()
+    }
+
+    pub fn keys(&self, button: Button) -> impl Iterator<Item = K> + '_ {
+        self.keys
+            .iter()
+            .filter_map(move |&(key, b)| (b == button).then_some(key))
+    }
+
+    /// Binds `key` to `button`, replacing the keys that were bound to it where the first one was.
+    pub fn set(&mut self, button: Button, key: K) {
+        let at = self
+            .keys
+            .iter()
+            .position(|&(_, b)| b == button)
+            .unwrap_or(self.keys.len());
+        self.keys.retain(|&(_, b)| b != button);
+        self.keys.insert(at, (key, button));
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (K, Button)> + '_ {
+        self.keys.iter().copied()
+    }
+}
+
+pub struct InputMap {
+    players: [Bindings; 2],
+    /// Every controller drives player 1
+    controller: Bindings<gilrs::Button>,
+    hotkeys: Vec<(VirtualKeyCode, Hotkey)>,
+}
+
+impl InputMap {
+    pub fn player(&self, player: Player) -> &Bindings {
+        &self.players[player as usize]
+    }
+
+    pub fn controller(&self) -> &Bindings<gilrs::Button> {
+        &self.controller
+    }
+
+    pub fn lookup(&self, key: VirtualKeyCode) -> Option<(Player, Button)> {
+        Player::ALL
+            .into_iter()
+            .find_map(|player| Some((player, self.player(player).button(key)?)))
+    }
+
+    pub fn hotkey(&self, key: VirtualKeyCode) -> Option<Hotkey> {
+        self.hotkeys
+            .iter()
+            .find_map(|&(k, hotkey)| (k == key).then_some(hotkey))
+    }
+
+    pub fn hotkeys(&self) -> impl Iterator<Item = (VirtualKeyCode, Hotkey)> + '_ {
+        self.hotkeys.iter().copied()
+    }
+
+    pub fn action(&self, key: VirtualKeyCode) -> Option<Action> {
+        self.lookup(key)
+            .map(|(player, button)| Action::Joypad(player, button))
+            .or_else(|| self.hotkey(key).map(Action::Hotkey))
+    }
+
+    /// Replaces all of `player`'s bindings.
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn set_bindings(&mut self, player: Player, bindings: Bindings) {
+        self.players[player as usize] = bindings;
+    }
+
+    /// Replaces all of the controller bindings.
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn set_controller_bindings(&mut self, bindings: Bindings<gilrs::Button>) {
+        self.controller = bindings;
+    }
+
+    /// Binds `key` to `player`'s `button`. Fails with the conflicting action if `key` is already
+    /// bound to something else.
+    pub fn bind(
+        &mut self,
+        player: Player,
+        button: Button,
+        key: VirtualKeyCode,
+    ) -> Result<(), Action> {
+        match self.action(key) {
+            Some(action) if action != Action::Joypad(player, button) => Err(action),
+            _ => {
+                self.players[player as usize].set(button, key);
+                Ok(())
+            }
+        }
+    }
+
+    /// Binds the controller button `pad` to player 1's `button`, in place of the ones that were.
+    /// Fails with the conflicting action if `pad` is already bound to a different button.
+    pub fn bind_controller(&mut self, button: Button, pad: gilrs::Button) -> Result<(), Action> {
+        match self.controller.button(pad) {
+            Some(other) if other != button => Err(Action::Joypad(Player::One, other)),
+            _ => {
+                self.controller.set(button, pad);
+                Ok(())
+            }
+        }
+    }
+}
+
+impl Default for InputMap {
+    fn default() -> Self {
+        use VirtualKeyCode as VK;
+        let one = Bindings::new([
+            (VK::W, Button::Up),
+            (VK::A, Button::Left),
+            (VK::S, Button::Down),
+            (VK::D, Button::Right),
+            (VK::LBracket, Button::Start),
+            (VK::RBracket, Button::Select),
+            (VK::Comma, Button::A),
+            (VK::Period, Button::B),
+        ]);
+        // The Game Boy's A and B sit diagonally, so both pairs of face buttons work. The right one
+        // of each pair is A, like on Nintendo's controllers.
+        let controller = Bindings::new([
+            (gilrs::Button::DPadRight, Button::Right),
+            (gilrs::Button::DPadLeft, Button::Left),
+            (gilrs::Button::DPadUp, Button::Up),
+            (gilrs::Button::DPadDown, Button::Down),
+            (gilrs::Button::East, Button::A),
+            (gilrs::Button::North, Button::A),
+            (gilrs::Button::South, Button::B),
+            (gilrs::Button::West, Button::B),
+            (gilrs::Button::Start, Button::Start),
+            (gilrs::Button::Select, Button::Select),
+        ]);
+        #[allow(unused_mut)]
+        let mut hotkeys = vec![
+            (VK::F1, Hotkey::ToggleCheatsheet),
+            (VK::P, Hotkey::TogglePause),
+            (VK::Back, Hotkey::Rewind),
+            (VK::F9, Hotkey::ToggleFilter),
+            (VK::F11, Hotkey::ToggleFullscreen),
+            (VK::F5, Hotkey::SaveState),
+            (VK::F8, Hotkey::LoadState),
+            (VK::F2, Hotkey::SaveGame),
+        ];
+        // The web has nowhere to save screenshots to
+        #[cfg(not(target_arch = "wasm32"))]
+        hotkeys.push((VK::F12, Hotkey::Screenshot));
+        // Player 2 has no bindings until there is a second instance to drive
+        Self {
+            players: [one, Bindings::default()],
+            controller,
+            hotkeys,
+        }
+    }
+}
@@ -0,0 +1,175 @@
+// SPDX-License-Identifier: GPL-3.0-or-later
+// Copyright (C) 2023 Robert Hrusecky <jadedpastabowl@gmail.com>
+
+//! The games in the ROM folder given in the settings, for starting from the side panel. The folder
+//! is watched, so ROMs copied in, renamed, or deleted show up in the list right away. Games can be
+//! given titles and box art from a ROM database, through [`rom_db`].
+
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    fs,
+    path::{Path, PathBuf},
+};
+
+use anyhow::{Context as _, Result};
+use iron_boy_core::cart::header::Header;
+use notify::{Event, RecommendedWatcher, RecursiveMode, Watcher};
+use winit::event_loop::EventLoopProxy;
+
+use crate::{
+    emulator,
+    event::FrontendEvent,
+    rom_db::{self, GameInfo},
+    zip,
+};
+
+/// Whether `path` looks like something the emulator opens, going by its extension.
+fn is_rom(path: &Path) -> bool {
+    path.extension()
+        .and_then(|extension| extension.to_str())
+        .is_some_and(|extension| {
+            ["gb", "gbc", "zip"]
+                .iter()
+                .any(|rom| extension.eq_ignore_ascii_case(rom))
+        })
+}
+
+pub struct Game {
+    pub path: PathBuf,
+    /// The title from the header, which may be empty
+    title: String,
+    /// Of the whole ROM, which is what ROM databases go by
+    crc: u32,
+}
+
+impl Game {
+    /// Reads the header of the ROM at `path`, or `None` if it isn't one.
+    fn read(path: &Path) -> Option<Self> {
+        if !is_rom(path) {
+            return None;
+        }
+        // A ROM still being copied in may not read yet, but it's read again once it's written
+        let rom = fs::read(path).ok()?;
+        let rom = emulator::unzip_rom(rom.into()).ok()?;
+        Some(Self {
+            path: path.into(),
+            title: Header::parse(&rom).title,
+            crc: zip::crc32(&rom),
+        })
+    }
+
+    /// What to call the game in the list.
+    pub fn name(&self) -> String {
+        if self.title.is_empty() {
+            self.path
+                .file_name()
+                .unwrap_or(self.path.as_os_str())
+                .to_string_lossy()
+                .into_owned()
+        } else {
+            self.title.clone()
+        }
+    }
+}
+
+#[derive(Default)]
+pub struct Library {
+    /// By path, so that a changed file is simple to find again
+    games: BTreeMap<PathBuf, Game>,
+    /// What the ROM database has on the games, by CRC
+    info: BTreeMap<u32, GameInfo>,
+    /// Games being looked up, or that failed to be, so that they're only tried once
+    requested: BTreeSet<u32>,
+}
+
+impl Library {
+    /// Lists every ROM in `dir`, in place of whatever was listed before.
+    pub fn scan(&mut self, dir: &Path) -> Result<()> {
+        self.games.clear();
+        let entries =
+            fs::read_dir(dir).with_context(|| format!("Failed to read {}", dir.display()))?;
+        for entry in entries {
+            let path = entry?.path();
+            if let Some(game) = Game::read(&path) {
+                self.games.insert(path, game);
+            }
+        }
+        Ok(())
+    }
+
+    /// Reads the ROM at `path` again, or drops it from the list if it's gone.
+    pub fn update(&mut self, path: &Path) {
+        match Game::read(path) {
+            Some(game) => self.games.insert(path.into(), game),
+            None => self.games.remove(path),
+        };
+    }
+
+    pub fn clear(&mut self) {
+        self.games.clear();
+        self.requested.clear();
+    }
+
+    /// The games, in order of file name.
+    pub fn games(&self) -> impl Iterator<Item = &Game> {
+        self.games.values()
+    }
+
+    /// What the ROM database has on `game`, if it's been looked up.
+    pub fn info(&self, game: &Game) -> Option<&GameInfo> {
+        self.info.get(&game.crc)
+    }
+
+    /// Looks up the games that haven't been yet, in the cache or else in `source`, if there is
+    /// one. Lookups from the source arrive as [`FrontendEvent::GameInfoFound`].
+    pub fn look_up(&mut self, source: Option<&str>, proxy: &EventLoopProxy<FrontendEvent>) {
+        for game in self.games.values() {
+            if self.info.contains_key(&game.crc) || self.requested.contains(&game.crc) {
+                continue;
+            }
+            if let Some(info) = rom_db::cached(game.crc) {
+                self.info.insert(game.crc, info);
+            } else if let Some(source) = source {
+                self.requested.insert(game.crc);
+                rom_db::fetch(source.into(), game.crc, proxy.clone());
+            }
+        }
+    }
+
+    pub fn set_info(&mut self, crc: u32, info: GameInfo) {
+        self.info.insert(crc, info);
+    }
+}
+
+/// Watches the ROM folder until dropped.
+pub struct LibraryWatcher {
+    _watcher: RecommendedWatcher,
+}
+
+impl LibraryWatcher {
+    /// Sends [`FrontendEvent::LibraryChanged`] whenever a ROM in `dir` is added, written, renamed,
+    /// or removed.
+    pub fn new(dir: &Path, proxy: EventLoopProxy<FrontendEvent>) -> Result<Self> {
+        let mut watcher = notify::recommended_watcher(move |event: notify::Result<Event>| {
+            match event {
+                Ok(event) if !event.kind.is_access() => {
+                    let paths: Vec<_> = event
+                        .paths
+                        .into_iter()
+                        .filter(|path| is_rom(path))
+                        .collect();
+                    if !paths.is_empty() {
+                        // Sending only fails once the event loop is gone
+                        let _ = proxy.send_event(FrontendEvent::LibraryChanged(paths));
+                    }
+                }
+                Ok(_) => (),
+                Err(error) => log::warn!("Failed to watch the ROM folder: {error}"),
+            }
+        })?;
+        watcher
+            .watch(dir, RecursiveMode::NonRecursive)
+            .with_context(|| format!("Failed to watch {}", dir.display()))?;
+        Ok(Self { _watcher: watcher })
+    }
+}
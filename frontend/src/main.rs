@@ -5,11 +5,47 @@
 
 mod audio;
 mod background;
+mod bundle;
+mod cheats;
+#[cfg(not(target_arch = "wasm32"))]
+mod control;
+#[cfg(target_arch = "wasm32")]
+mod download;
 mod emulator;
 mod engine;
 mod event;
+#[cfg(not(target_arch = "wasm32"))]
+mod file_name;
+mod filter;
+mod frame_stats;
+mod gamepad;
 mod gui;
+mod input;
+#[cfg(not(target_arch = "wasm32"))]
+mod library;
+#[cfg(not(target_arch = "wasm32"))]
+mod movie;
 mod options;
+mod power;
+mod recent;
+#[cfg(not(target_arch = "wasm32"))]
+mod recorder;
+mod rewind;
+#[cfg(not(target_arch = "wasm32"))]
+mod rom_db;
+#[cfg(all(feature = "scripting", not(target_arch = "wasm32")))]
+mod script;
+#[cfg(not(target_arch = "wasm32"))]
+mod self_test;
+#[cfg(not(target_arch = "wasm32"))]
+mod settings;
+mod skin;
+mod store;
+#[cfg(not(target_arch = "wasm32"))]
+mod wav;
+#[cfg(target_arch = "wasm32")]
+mod web_saves;
+mod zip;
 
 use engine::Engine;
 use event::FrontendEvent;
@@ -19,10 +55,14 @@ use winit::event_loop::{EventLoop, EventLoopBuilder};
 async fn init(options: Options) -> (EventLoop<FrontendEvent>, Engine) {
     let event_loop = EventLoopBuilder::with_user_event().build();
 
-    let engine = Engine::new(&event_loop, options)
-        .await
-        .expect("Error while initializing");
-    (event_loop, engine)
+    #[cfg(not(target_arch = "wasm32"))]
+    let self_test = options.self_test;
+    let engine = Engine::new(&event_loop, options).await;
+    #[cfg(not(target_arch = "wasm32"))]
+    if let (true, Err(error)) = (self_test, &engine) {
+        self_test::fail_setup(error);
+    }
+    (event_loop, engine.expect("Error while initializing"))
 }
 
 fn run(event_loop: EventLoop<FrontendEvent>, mut engine: Engine) {
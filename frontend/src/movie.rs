@@ -0,0 +1,89 @@
+// SPDX-License-Identifier: GPL-3.0-or-later
+// Copyright (C) 2023 Robert Hrusecky <jadedpastabowl@gmail.com>
+
+//! Recording and playing back movies of the buttons held each frame, for tool-assisted runs and
+//! reproducing bugs. See [`iron_boy_core::movie`] for what's recorded. Input from the keyboard and
+//! controllers is ignored while a movie plays, and rewinding or loading a state ends the movie.
+
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+use anyhow::{Context as _, Result};
+use iron_boy_core::{cart::RtcClock, joypad::Input, movie::Movie};
+
+use crate::emulator::Cgb;
+
+pub enum MovieSession {
+    Recording { movie: Movie, path: PathBuf },
+    Playing { movie: Movie, frame: u64 },
+}
+
+impl MovieSession {
+    /// Starts recording from where the game is now, to write to `path` when finished.
+    pub fn record(cgb: &mut Cgb, path: PathBuf) -> Self {
+        log::info!("Recording a movie to {path:?}");
+        Self::Recording {
+            movie: cgb.record_movie(),
+            path,
+        }
+    }
+
+    /// Goes back to the start of the movie at `path`, to play it from there.
+    pub fn play(cgb: &mut Cgb, path: &Path) -> Result<Self> {
+        let data = fs::read(path).with_context(|| format!("Failed to read {path:?}"))?;
+        let movie = Movie::from_bytes(&data).with_context(|| format!("Failed to load {path:?}"))?;
+        cgb.rewind_movie(&movie)
+            .with_context(|| format!("Failed to play {path:?}"))?;
+        log::info!("Playing the movie {path:?}");
+        Ok(Self::Playing { movie, frame: 0 })
+    }
+
+    pub fn recording(&self) -> bool {
+        matches!(self, Self::Recording { .. })
+    }
+
+    /// The frame being played and the number of frames in the movie, while playing.
+    pub fn progress(&self) -> Option<(u64, u64)> {
+        match self {
+            Self::Recording { .. } => None,
+            Self::Playing { movie, frame } => Some((*frame, movie.frames())),
+        }
+    }
+
+    /// Records the buttons held for the frame about to run, or holds down the ones recorded for
+    /// it. Returns false once there's nothing left to play.
+    pub fn frame(&mut self, cgb: &mut Cgb) -> bool {
+        match self {
+            Self::Recording { movie, .. } => movie.push(cgb.input()),
+            Self::Playing { movie, frame } => {
+                let Some(input) = movie.input(*frame) else {
+                    return false;
+                };
+                cgb.set_socd(movie.socd());
+                cgb.set_input(input);
+                *frame += 1;
+            }
+        }
+        true
+    }
+
+    /// Writes out a recording, and puts the RTC back on the host's clock either way.
+    pub fn finish(self, cgb: &mut Cgb) -> Result<()> {
+        cgb.set_rtc_clock(RtcClock::Host);
+        match self {
+            Self::Recording { movie, path } => {
+                fs::write(&path, movie.to_bytes())
+                    .with_context(|| format!("Failed to write {path:?}"))?;
+                log::info!("Saved a movie of {} frames to {path:?}", movie.frames());
+            }
+            Self::Playing { .. } => {
+                // Let go of whatever the movie was holding
+                cgb.set_input(Input::NONE);
+                log::info!("Stopped playing the movie");
+            }
+        }
+        Ok(())
+    }
+}
@@ -1,12 +1,122 @@
 // SPDX-License-Identifier: GPL-3.0-or-later
 // Copyright (C) 2023 Robert Hrusecky <jadedpastabowl@gmail.com>
 
-use std::path::Path;
+use std::{collections::BTreeMap, num::ParseIntError, path::Path};
 
 use clap::Parser;
+use iron_boy_core::{
+    cart::MbcKind,
+    system::{DmgPalette, Model},
+};
+
+#[cfg(not(target_arch = "wasm32"))]
+use crate::file_name::{self, Template};
+use crate::skin::ShellColor;
 
 #[derive(Parser, Default)]
 #[command(author, version, about, long_about = None)]
 pub struct Options {
     pub rom_file_name: Option<Box<Path>>,
+    /// Value read back from unimplemented IO registers [default: 0xff]
+    #[arg(long, value_parser = parse_u8)]
+    pub open_bus: Option<u8>,
+    /// Don't report the first access to each unimplemented IO register in the event log
+    #[arg(long)]
+    pub quiet_unmapped_io: bool,
+    /// Extra CPU cycles to run per scanline to reduce slowdown in games that lag. INACCURATE: games
+    /// may behave differently than on real hardware
+    #[arg(long, default_value_t = 0)]
+    pub overclock: usize,
+    /// The hardware to emulate: cgb, or dmg for the original Game Boy. There's no built in DMG boot
+    /// ROM, so a DMG starts the game right away unless given one with --boot-rom, whose size
+    /// decides the model instead
+    #[arg(long, value_parser = parse_model, default_value = "cgb")]
+    pub model: Model,
+    /// Use this mapper instead of the one the ROM's header asks for, for ROMs with a broken or
+    /// unknown cartridge type: none, mbc1, mbc2, mbc3, or mbc5
+    #[arg(long, value_parser = parse_mbc)]
+    pub mapper: Option<MbcKind>,
+    /// The shades a DMG shows: gray, or green like the original screen
+    #[arg(long, value_parser = parse_dmg_palette, default_value = "gray")]
+    pub dmg_palette: DmgPalette,
+    /// Draw the game inside of a handheld shell with the given color: berry, grape, kiwi,
+    /// dandelion, teal, atomic-purple, or #rrggbb
+    #[arg(long, value_name = "COLOR")]
+    pub skin: Option<ShellColor>,
+    /// How to name screenshots, which are saved next to the ROM. Fields: {title} from the ROM
+    /// header, {date} and {time} in UTC, and {kind}, a counter like shot007
+    #[cfg(not(target_arch = "wasm32"))]
+    #[arg(long, value_name = "TEMPLATE", default_value = file_name::DEFAULT_TEMPLATE)]
+    pub file_name_template: Template,
+    /// Listen on this localhost port for JSON-RPC commands from external tools, like loading ROMs,
+    /// pressing buttons, and reading memory. Requires a control token.
+    #[cfg(not(target_arch = "wasm32"))]
+    #[arg(long, value_name = "PORT")]
+    pub control_port: Option<u16>,
+    /// The token that control connections authenticate with, in place of `control_token` in the
+    /// settings
+    #[cfg(not(target_arch = "wasm32"))]
+    #[arg(long, value_name = "TOKEN")]
+    pub control_token: Option<String>,
+    /// Boot with this boot ROM image, like a dump from a CGB, instead of the built in one
+    #[cfg(not(target_arch = "wasm32"))]
+    #[arg(long, value_name = "FILE")]
+    pub boot_rom: Option<Box<Path>>,
+    /// Write the audio to this WAV file, from when the emulator starts until it exits
+    #[cfg(not(target_arch = "wasm32"))]
+    #[arg(long, value_name = "FILE")]
+    pub dump_audio: Option<Box<Path>>,
+    /// Play this movie of input, recorded with the emulator, once the game starts
+    #[cfg(not(target_arch = "wasm32"))]
+    #[arg(long, value_name = "FILE")]
+    pub movie: Option<Box<Path>>,
+    /// Run this Rhai script alongside the game, which can read and write memory and press buttons
+    /// every frame
+    #[cfg(all(feature = "scripting", not(target_arch = "wasm32")))]
+    #[arg(long, value_name = "FILE")]
+    pub script: Option<Box<Path>>,
+    /// Print what games send over the serial port to stdout, like the output of test ROMs
+    #[arg(long)]
+    pub echo_serial: bool,
+    /// Start games right away, without running a boot ROM, even one given with --boot-rom
+    #[arg(long)]
+    pub skip_boot_rom: bool,
+    /// Check that video, audio, and input work by running a built in test program instead of a
+    /// game, then exit with a nonzero status if anything failed
+    #[cfg(not(target_arch = "wasm32"))]
+    #[arg(long)]
+    pub self_test: bool,
+    /// Cartridge RAM sizes by game title that override the header, from the settings file
+    #[arg(skip)]
+    pub ram_sizes: BTreeMap<String, usize>,
+}
+
+fn parse_u8(s: &str) -> Result<u8, ParseIntError> {
+    match s.strip_prefix("0x") {
+        Some(hex) => u8::from_str_radix(hex, 16),
+        None => s.parse(),
+    }
+}
+
+fn parse_model(s: &str) -> Result<Model, String> {
+    match s {
+        "cgb" => Ok(Model::Cgb),
+        "dmg" => Ok(Model::Dmg),
+        _ => Err(format!("unknown model {s:?}, expected cgb or dmg")),
+    }
+}
+
+fn parse_mbc(s: &str) -> Result<MbcKind, String> {
+    MbcKind::ALL
+        .into_iter()
+        .find(|mbc| mbc.to_string().eq_ignore_ascii_case(s))
+        .ok_or_else(|| format!("unknown mapper {s:?}, expected none, mbc1, mbc2, mbc3, or mbc5"))
+}
+
+fn parse_dmg_palette(s: &str) -> Result<DmgPalette, String> {
+    match s {
+        "gray" => Ok(DmgPalette::Gray),
+        "green" => Ok(DmgPalette::Green),
+        _ => Err(format!("unknown palette {s:?}, expected gray or green")),
+    }
 }
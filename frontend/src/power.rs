@@ -0,0 +1,214 @@
+// SPDX-License-Identifier: GPL-3.0-or-later
+// Copyright (C) 2023 Robert Hrusecky <jadedpastabowl@gmail.com>
+
+//! Keeps the computer from going to sleep or starting the screensaver while a game is running,
+//! since playing with a controller doesn't count as activity to most systems. Failing to is only
+//! logged, since it's nothing the user needs to act on.
+
+/// Inhibits sleep for as long as it's set to, and releases it when dropped.
+pub struct SleepInhibitor {
+    inhibited: bool,
+    platform: platform::Inhibitor,
+}
+
+impl SleepInhibitor {
+    pub fn new() -> Self {
+        Self {
+            inhibited: false,
+            platform: platform::Inhibitor::new(),
+        }
+    }
+
+    pub fn set_inhibited(&mut self, inhibited: bool) {
+        if inhibited == self.inhibited {
+            return;
+        }
+        self.inhibited = inhibited;
+        if inhibited {
+            log::debug!("Inhibiting sleep");
+            self.platform.inhibit();
+        } else {
+            log::debug!("Allowing sleep");
+            self.platform.release();
+        }
+    }
+}
+
+#[cfg(windows)]
+mod platform {
+    const ES_CONTINUOUS: u32 = 0x8000_0000;
+    const ES_SYSTEM_REQUIRED: u32 = 0x0000_0001;
+    const ES_DISPLAY_REQUIRED: u32 = 0x0000_0002;
+
+    #[link(name = "kernel32")]
+    extern "system" {
+        fn SetThreadExecutionState(flags: u32) -> u32;
+    }
+
+    /// Windows ties the state to the thread that set it, which is always the event loop's.
+    pub struct Inhibitor;
+
+    impl Inhibitor {
+        pub fn new() -> Self {
+            Self
+        }
+
+        pub fn inhibit(&mut self) {
+            let flags = ES_CONTINUOUS | ES_SYSTEM_REQUIRED | ES_DISPLAY_REQUIRED;
+            if unsafe { SetThreadExecutionState(flags) } == 0 {
+                log::warn!("Failed to inhibit sleep");
+            }
+        }
+
+        pub fn release(&mut self) {
+            unsafe { SetThreadExecutionState(ES_CONTINUOUS) };
+        }
+    }
+}
+
+/// Elsewhere on desktop, the system's own tool holds the inhibition for as long as it runs:
+/// `caffeinate` on macOS, and `systemd-inhibit` on Linux. Either one also exits on its own once
+/// this process is gone, so a crash can't leave the computer awake for good.
+#[cfg(all(not(windows), not(target_arch = "wasm32")))]
+mod platform {
+    use std::process::{self, Child, Command, Stdio};
+
+    #[cfg(target_os = "macos")]
+    fn command() -> Command {
+        let mut command = Command::new("caffeinate");
+        // The display, and the system while idle, until this process exits
+        command.args(["-d", "-i", "-w"]);
+        command.arg(process::id().to_string());
+        command
+    }
+
+    #[cfg(not(target_os = "macos"))]
+    fn command() -> Command {
+        let mut command = Command::new("systemd-inhibit");
+        command.args([
+            "--what=idle:sleep",
+            "--who=Iron Boy",
+            "--why=A game is running",
+            "--mode=block",
+        ]);
+        // systemd-inhibit holds the lock until its command exits, which `tail` does once this
+        // process is gone. Killing systemd-inhibit to release it takes `tail` down with it.
+        command.args(["tail", "-f", "/dev/null"]);
+        command.arg(format!("--pid={}", process::id()));
+        command
+    }
+
+    pub struct Inhibitor {
+        child: Option<Child>,
+        /// Set once the tool turns out to be missing, to not keep trying
+        unavailable: bool,
+    }
+
+    impl Inhibitor {
+        pub fn new() -> Self {
+            Self {
+                child: None,
+                unavailable: false,
+            }
+        }
+
+        pub fn inhibit(&mut self) {
+            if self.unavailable {
+                return;
+            }
+            let mut command = command();
+            command
+                .stdin(Stdio::null())
+                .stdout(Stdio::null())
+                .stderr(Stdio::null());
+            match command.spawn() {
+                Ok(child) => self.child = Some(child),
+                Err(error) => {
+                    log::warn!("Failed to inhibit sleep: {error}");
+                    self.unavailable = true;
+                }
+            }
+        }
+
+        pub fn release(&mut self) {
+            if let Some(mut child) = self.child.take() {
+                let _ = child.kill();
+                let _ = child.wait();
+            }
+        }
+    }
+
+    impl Drop for Inhibitor {
+        fn drop(&mut self) {
+            self.release();
+        }
+    }
+}
+
+/// Browsers offer a screen wake lock, which they drop on their own whenever the page is hidden, so
+/// it's taken again when the page is shown.
+#[cfg(target_arch = "wasm32")]
+mod platform {
+    use wasm_bindgen::prelude::wasm_bindgen;
+
+    #[wasm_bindgen(inline_js = r#"
+        let sentinel = null;
+        let requesting = false;
+        let wanted = false;
+
+        export function request_wake_lock() {
+            wanted = true;
+            if (!navigator.wakeLock || sentinel || requesting) {
+                return;
+            }
+            requesting = true;
+            navigator.wakeLock.request("screen").then(
+                (lock) => {
+                    requesting = false;
+                    sentinel = lock;
+                    lock.addEventListener("release", () => { sentinel = null; });
+                    if (!wanted) {
+                        lock.release();
+                    }
+                },
+                (error) => {
+                    requesting = false;
+                    console.warn(`Failed to inhibit sleep: ${error}`);
+                },
+            );
+        }
+
+        document.addEventListener("visibilitychange", () => {
+            if (wanted && document.visibilityState === "visible") {
+                request_wake_lock();
+            }
+        });
+
+        export function release_wake_lock() {
+            wanted = false;
+            if (sentinel) {
+                sentinel.release();
+            }
+        }
+    "#)]
+    extern "C" {
+        fn request_wake_lock();
+        fn release_wake_lock();
+    }
+
+    pub struct Inhibitor;
+
+    impl Inhibitor {
+        pub fn new() -> Self {
+            Self
+        }
+
+        pub fn inhibit(&mut self) {
+            request_wake_lock();
+        }
+
+        pub fn release(&mut self) {
+            release_wake_lock();
+        }
+    }
+}
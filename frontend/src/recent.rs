@@ -0,0 +1,173 @@
+// SPDX-License-Identifier: GPL-3.0-or-later
+// Copyright (C) 2023 Robert Hrusecky <jadedpastabowl@gmail.com>
+
+//! The games played lately, newest first, for starting again from the side panel. On desktop
+//! they're remembered by path. A web page can't open a file again without the user picking it, so
+//! on the web a copy of each ROM is kept in the store instead, one byte to a character.
+
+#[cfg(not(target_arch = "wasm32"))]
+use std::path::PathBuf;
+
+#[cfg(target_arch = "wasm32")]
+use anyhow::anyhow;
+use anyhow::{Context as _, Result};
+use serde::{Deserialize, Serialize};
+
+use crate::store;
+#[cfg(target_arch = "wasm32")]
+use crate::web_saves;
+
+const KEY: &str = "recent.ron";
+/// How many games are remembered
+const MAX_GAMES: usize = 8;
+/// How many bytes of ROMs are kept on the web, oldest forgotten first. Local storage only holds
+/// about 5 MB, which the saves need room in too. If it fills up anyway, the oldest ROMs are
+/// forgotten until the new one fits.
+#[cfg(target_arch = "wasm32")]
+const MAX_ROM_BYTES: usize = 2 * 1024 * 1024;
+
+#[derive(Clone, PartialEq, Serialize, Deserialize)]
+pub struct RecentRom {
+    #[cfg(not(target_arch = "wasm32"))]
+    pub path: PathBuf,
+    #[cfg(target_arch = "wasm32")]
+    pub title: String,
+    /// Which copy of the ROM in the store to load, from [`crate::bundle::game_id`]
+    #[cfg(target_arch = "wasm32")]
+    pub id: String,
+    /// The size of the ROM in bytes, which counts against [`MAX_ROM_BYTES`]
+    #[cfg(target_arch = "wasm32")]
+    #[serde(default)]
+    size: usize,
+}
+
+impl RecentRom {
+    /// What to call the game in the list.
+    pub fn name(&self) -> String {
+        #[cfg(not(target_arch = "wasm32"))]
+        return self
+            .path
+            .file_name()
+            .unwrap_or(self.path.as_os_str())
+            .to_string_lossy()
+            .into_owned();
+        #[cfg(target_arch = "wasm32")]
+        return if self.title.is_empty() {
+            self.id.clone()
+        } else {
+            self.title.clone()
+        };
+    }
+
+    /// The copy of the ROM kept in the store.
+    #[cfg(target_arch = "wasm32")]
+    pub fn load(&self) -> Result<Box<[u8]>> {
+        let rom = match store::load(&rom_key(&self.id)) {
+            Some(chars) => chars
+                .chars()
+                .map(|c| u8::try_from(c).ok())
+                .collect::<Option<Vec<_>>>()
+                .ok_or(anyhow!("Stored ROM is corrupt"))?,
+            None => {
+                let hex = store::load(&hex_rom_key(&self.id))
+                    .ok_or_else(|| anyhow!("{} is no longer stored", self.name()))?;
+                web_saves::decode(&hex).ok_or(anyhow!("Stored ROM isn't valid hex"))?
+            }
+        };
+        Ok(rom.into_boxed_slice())
+    }
+
+    /// Drops the copy of the ROM kept in the store.
+    #[cfg(target_arch = "wasm32")]
+    fn forget(&self) -> Result<()> {
+        store::remove(&rom_key(&self.id))?;
+        store::remove(&hex_rom_key(&self.id))
+    }
+}
+
+/// Where a ROM is kept, as a character for each byte. Local storage holds UTF-16, so that's two
+/// bytes of storage for each byte of ROM, where hex took four.
+#[cfg(target_arch = "wasm32")]
+fn rom_key(id: &str) -> String {
+    format!("roms/{id}.rom")
+}
+
+/// Where a ROM was kept as hex, before [`rom_key`].
+#[cfg(target_arch = "wasm32")]
+fn hex_rom_key(id: &str) -> String {
+    format!("roms/{id}.gb")
+}
+
+pub struct RecentRoms {
+    roms: Vec<RecentRom>,
+}
+
+impl RecentRoms {
+    /// Loads the list from the store. A missing or unreadable one starts out empty, since it's
+    /// only a convenience.
+    pub fn load() -> Self {
+        let roms = store::load(KEY)
+            .and_then(|roms| ron::from_str(&roms).ok())
+            .unwrap_or_default();
+        Self { roms }
+    }
+
+    pub fn roms(&self) -> &[RecentRom] {
+        &self.roms
+    }
+
+    /// Moves the ROM at `path` to the top of the list.
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn push(&mut self, path: PathBuf) -> Result<()> {
+        // Resuming from a different working directory should still find it
+        let path = path.canonicalize().unwrap_or(path);
+        self.push_rom(RecentRom { path })
+    }
+
+    /// Keeps a copy of `rom` and moves it to the top of the list, forgetting the oldest ROMs to
+    /// make room for it.
+    #[cfg(target_arch = "wasm32")]
+    pub fn push(&mut self, title: String, id: String, rom: &[u8]) -> Result<()> {
+        if rom.len() > MAX_ROM_BYTES {
+            return Err(anyhow!("{title} is too big to keep a copy of"));
+        }
+        if let Some(index) = self.roms.iter().position(|other| other.id == id) {
+            self.roms.remove(index).forget()?;
+        }
+        while self.roms.len() >= MAX_GAMES
+            || self.roms.iter().map(|rom| rom.size).sum::<usize>() + rom.len() > MAX_ROM_BYTES
+        {
+            self.forget_oldest()?;
+        }
+        let chars: String = rom.iter().copied().map(char::from).collect();
+        // Local storage is shared with the saves, so it may be full even under the limit
+        while let Err(error) = store::save(&rom_key(&id), &chars) {
+            if self.roms.is_empty() {
+                self.save()?;
+                return Err(error.context("Failed to store a copy of the ROM"));
+            }
+            self.forget_oldest()?;
+        }
+        let size = rom.len();
+        self.push_rom(RecentRom { title, id, size })
+    }
+
+    #[cfg(target_arch = "wasm32")]
+    fn forget_oldest(&mut self) -> Result<()> {
+        match self.roms.pop() {
+            Some(rom) => rom.forget(),
+            None => Ok(()),
+        }
+    }
+
+    fn push_rom(&mut self, rom: RecentRom) -> Result<()> {
+        self.roms.retain(|other| *other != rom);
+        self.roms.insert(0, rom);
+        self.roms.truncate(MAX_GAMES);
+        self.save()
+    }
+
+    fn save(&self) -> Result<()> {
+        store::save(KEY, &ron::to_string(&self.roms)?).context("Failed to save recent games")
+    }
+}
@@ -0,0 +1,202 @@
+// SPDX-License-Identifier: GPL-3.0-or-later
+// Copyright (C) 2023 Robert Hrusecky <jadedpastabowl@gmail.com>
+
+//! Recording gameplay, either as an animated PNG, or as numbered PNG frames with a WAV of the
+//! audio alongside for putting together in a video editor. Frames are taken from what's drawn to
+//! the screen, and audio from the APU before it's resampled for the output device.
+
+use std::{
+    fmt::{self, Display, Formatter},
+    fs::{self, File, OpenOptions},
+    io::{BufWriter, Read, Seek, SeekFrom, Write},
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+use anyhow::{bail, Context as _, Result};
+use iron_boy_core::system::{AudioFrame, FrameBuffer};
+
+use crate::{
+    emulator::{SCREEN_HEIGHT, SCREEN_WIDTH},
+    wav::WavWriter,
+};
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum RecordingFormat {
+    /// Video only
+    #[default]
+    Apng,
+    Frames,
+}
+
+impl RecordingFormat {
+    pub const ALL: [RecordingFormat; 2] = [Self::Apng, Self::Frames];
+}
+
+impl Display for RecordingFormat {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::Apng => "Animated PNG",
+            Self::Frames => "PNG frames and WAV",
+        })
+    }
+}
+
+fn png_encoder<W: Write>(writer: W) -> png::Encoder<'static, W> {
+    let mut encoder = png::Encoder::new(writer, SCREEN_WIDTH as u32, SCREEN_HEIGHT as u32);
+    encoder.set_color(png::ColorType::Rgba);
+    encoder.set_depth(png::BitDepth::Eight);
+    encoder
+}
+
+/// Where the `acTL` chunk starts in an APNG, after the signature and `IHDR`
+const ACTL_OFFSET: u64 = 8 + 25;
+
+/// An APNG written out as it's recorded. Its header gives its number of frames, which isn't known
+/// until the end, so that's filled in once it's finished.
+struct ApngWriter {
+    writer: png::Writer<BufWriter<File>>,
+    /// The last frame, which is held until a different one comes along, so that repeated frames
+    /// are only written once and shown for longer
+    pending: Option<(Box<FrameBuffer>, Duration)>,
+    frames: u32,
+    /// How long the written frames are shown for altogether
+    elapsed: Duration,
+}
+
+impl ApngWriter {
+    fn create(path: &Path) -> Result<Self> {
+        let file = File::create(path).with_context(|| format!("Failed to create {path:?}"))?;
+        let mut encoder = png_encoder(BufWriter::new(file));
+        // The real number of frames is patched in at the end
+        encoder.set_animated(u32::MAX, 0)?;
+        encoder.validate_sequence(false);
+        Ok(Self {
+            writer: encoder.write_header()?,
+            pending: None,
+            frames: 0,
+            elapsed: Duration::ZERO,
+        })
+    }
+
+    fn push_frame(&mut self, frame_buff: &FrameBuffer, duration: Duration) -> Result<()> {
+        match &mut self.pending {
+            Some((last, shown)) if **last == *frame_buff => *shown += duration,
+            pending => {
+                if let Some((last, shown)) = pending.replace((Box::new(*frame_buff), duration)) {
+                    self.write_frame(&last, shown)?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn write_frame(&mut self, frame_buff: &FrameBuffer, duration: Duration) -> Result<()> {
+        // Delays are rounded to milliseconds from the start, so that the error doesn't add up
+        let start = self.elapsed.as_millis();
+        self.elapsed += duration;
+        let delay = (self.elapsed.as_millis() - start).min(u16::MAX as u128) as u16;
+        self.writer.set_frame_delay(delay, 1000)?;
+        self.writer
+            .write_image_data(frame_buff.as_flattened().as_flattened())?;
+        self.frames += 1;
+        Ok(())
+    }
+
+    /// Writes the last frame, and fills in the number of frames.
+    fn finish(mut self, path: &Path) -> Result<()> {
+        if let Some((last, shown)) = self.pending.take() {
+            self.write_frame(&last, shown)?;
+        }
+        let frames = self.frames;
+        self.writer.finish()?;
+        if frames == 0 {
+            fs::remove_file(path)?;
+            bail!("Nothing was recorded");
+        }
+        let mut file = OpenOptions::new().read(true).write(true).open(path)?;
+        let mut kind = [0; 4];
+        file.seek(SeekFrom::Start(ACTL_OFFSET + 4))?;
+        file.read_exact(&mut kind)?;
+        if kind != *b"acTL" {
+            bail!("Failed to find the animation's header in {path:?}");
+        }
+        file.seek(SeekFrom::Start(ACTL_OFFSET))?;
+        let actl = png::AnimationControl {
+            num_frames: frames,
+            num_plays: 0,
+        };
+        actl.encode(&mut file)?;
+        Ok(())
+    }
+}
+
+enum Sink {
+    Apng(ApngWriter),
+    Frames {
+        dir: PathBuf,
+        frames: usize,
+        wav: WavWriter,
+    },
+}
+
+pub struct Recorder {
+    path: PathBuf,
+    sink: Sink,
+}
+
+impl Recorder {
+    /// Starts recording to `path`, which is a file for an APNG and a new directory for frames.
+    pub fn new(path: PathBuf, format: RecordingFormat) -> Result<Self> {
+        let sink = match format {
+            RecordingFormat::Apng => Sink::Apng(ApngWriter::create(&path)?),
+            RecordingFormat::Frames => {
+                fs::create_dir(&path).with_context(|| format!("Failed to create {path:?}"))?;
+                Sink::Frames {
+                    wav: WavWriter::create(&path.join("audio.wav"))?,
+                    dir: path.clone(),
+                    frames: 0,
+                }
+            }
+        };
+        log::info!("Recording to {path:?}");
+        Ok(Self { path, sink })
+    }
+
+    pub fn push_audio(&mut self, frame: AudioFrame) {
+        if let Sink::Frames { wav, .. } = &mut self.sink {
+            wav.push_frame(frame);
+        }
+    }
+
+    /// Adds a frame that's shown for `duration`, along with the audio pushed since the last one.
+    /// A frame without any audio, like while rewinding, gets silence to keep the audio in sync.
+    pub fn push_frame(&mut self, frame_buff: &FrameBuffer, duration: Duration) -> Result<()> {
+        match &mut self.sink {
+            Sink::Apng(apng) => apng.push_frame(frame_buff, duration)?,
+            Sink::Frames { dir, frames, wav } => {
+                *frames += 1;
+                let path = dir.join(format!("frame{frames:06}.png"));
+                let file =
+                    File::create(&path).with_context(|| format!("Failed to create {path:?}"))?;
+                let mut writer = png_encoder(BufWriter::new(file)).write_header()?;
+                writer.write_image_data(frame_buff.as_flattened().as_flattened())?;
+                if !wav.has_pending() {
+                    wav.push_silence(duration);
+                }
+                wav.write_pending()?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Finishes writing the recording, and returns where it is.
+    pub fn finish(self) -> Result<PathBuf> {
+        match self.sink {
+            Sink::Apng(apng) => apng.finish(&self.path)?,
+            Sink::Frames { wav, .. } => wav.finish()?,
+        }
+        log::info!("Saved a recording to {:?}", self.path);
+        Ok(self.path)
+    }
+}
@@ -0,0 +1,191 @@
+// SPDX-License-Identifier: GPL-3.0-or-later
+// Copyright (C) 2023 Robert Hrusecky <jadedpastabowl@gmail.com>
+
+//! Rewinding, by taking a state every few frames and stepping back through them. Only the newest
+//! state is kept whole. Each older one is a delta from the state after it, so the oldest can be
+//! dropped once the buffer is full.
+//!
+//! How often states are taken is adjustable. Taking them more often makes rewinding smoother, but
+//! they're spread over the same number of frames, so it takes more memory.
+//!
+//! The buffer also lets the debugger step back an instruction, by restoring the newest state from
+//! before it and running forward again. The emulator is deterministic, so that lands exactly where
+//! the CPU was, as long as the input hasn't changed since the state was taken. The real time clock
+//! of carts that follow the wall clock is the exception.
+
+use std::{collections::VecDeque, mem, time::Duration};
+
+use anyhow::{bail, Result};
+use iron_boy_core::delta;
+#[cfg(feature = "tools")]
+use iron_boy_core::system::{FrameBuffer, MachineCycle};
+
+use crate::emulator::{self, Cgb};
+
+pub const DEFAULT_INTERVAL: usize = 2;
+/// About 20 seconds of play
+const CAPACITY: usize = 1200;
+
+struct Delta {
+    data: Vec<u8>,
+    /// How many frames apart the states on either side of it are
+    frames: usize,
+}
+
+pub struct Rewind {
+    /// Frames between states
+    interval: usize,
+    /// Frames run since the last state was taken
+    frames: usize,
+    /// Frames still to be rewound, that didn't add up to a whole state yet
+    owed: usize,
+    newest: Vec<u8>,
+    deltas: VecDeque<Delta>,
+    /// The frames spanned by `deltas`
+    buffered: usize,
+    scratch: Vec<u8>,
+}
+
+impl Rewind {
+    pub fn new() -> Self {
+        Self {
+            interval: DEFAULT_INTERVAL,
+            frames: 0,
+            owed: 0,
+            newest: Vec::new(),
+            deltas: VecDeque::new(),
+            buffered: 0,
+            scratch: Vec::new(),
+        }
+    }
+
+    /// Takes a state every `interval` frames from now on.
+    pub fn set_interval(&mut self, interval: usize) {
+        self.interval = interval.max(1);
+    }
+
+    /// How far back rewinding can go, and how full the buffer is from 0 to 1.
+    pub fn buffered(&self) -> (Duration, f32) {
+        let duration = emulator::frame_duration() * self.buffered as u32;
+        (duration, self.buffered as f32 / CAPACITY as f32)
+    }
+
+    /// Forgets every state, for when the game's timeline has been replaced, like by loading a
+    /// different game or a save state.
+    pub fn clear(&mut self) {
+        self.frames = 0;
+        self.owed = 0;
+        self.newest.clear();
+        self.deltas.clear();
+        self.buffered = 0;
+    }
+
+    /// Notes that `cgb` ran a frame forward, taking a state if one is due.
+    pub fn record(&mut self, cgb: &Cgb) {
+        self.frames += 1;
+        self.owed = 0;
+        if self.frames < self.interval && !self.newest.is_empty() {
+            return;
+        }
+        cgb.save_state_into(&mut self.scratch);
+        if !self.newest.is_empty() {
+            self.deltas.push_back(Delta {
+                data: delta::encode(&self.scratch, &self.newest),
+                frames: self.frames,
+            });
+            self.buffered += self.frames;
+            while self.buffered > CAPACITY {
+                let Some(oldest) = self.deltas.pop_front() else {
+                    break;
+                };
+                self.buffered -= oldest.frames;
+            }
+        }
+        self.frames = 0;
+        mem::swap(&mut self.newest, &mut self.scratch);
+    }
+
+    /// Goes back `frames` frames, to the newest state at least that far back, counting frames
+    /// left over from earlier calls that didn't reach a state. Going back a frame at a time with
+    /// states taken every few frames shows each of them for a few frames, so rewinding runs at
+    /// real time. Once the buffer runs out, this keeps restoring the oldest state.
+    pub fn step_back(&mut self, cgb: &mut Cgb, frames: usize) -> Result<()> {
+        if self.newest.is_empty() {
+            return Ok(());
+        }
+        self.owed += frames;
+        while self
+            .deltas
+            .back()
+            .is_some_and(|delta| delta.frames <= self.owed)
+        {
+            let delta = self.deltas.pop_back().unwrap();
+            let Some(state) = delta::decode(&self.newest, &delta.data) else {
+                self.clear();
+                bail!("A rewind state is corrupt");
+            };
+            self.owed -= delta.frames;
+            self.buffered -= delta.frames;
+            self.newest = state;
+        }
+        if self.deltas.is_empty() {
+            self.owed = 0;
+        }
+        self.frames = 0;
+        cgb.restore_state(&self.newest)
+    }
+
+    /// Goes back to just before the last instruction `cgb` ran. States newer than the one it
+    /// replays from are dropped, like rewinding past them would. If the buffer doesn't go back far
+    /// enough, or the replay doesn't get there, `cgb` is left where it was.
+    #[cfg(feature = "tools")]
+    pub fn step_back_instruction(
+        &mut self,
+        cgb: &mut Cgb,
+        frame_buff: &mut FrameBuffer,
+    ) -> Result<()> {
+        let Some(target) = cgb.instructions_executed().checked_sub(1) else {
+            return Ok(());
+        };
+        cgb.save_state_into(&mut self.scratch);
+
+        // Find the newest state from before the target without dropping anything yet, in case
+        // there isn't one
+        let mut state = self.newest.clone();
+        let mut dropped = 0;
+        loop {
+            if !state.is_empty() {
+                cgb.restore_state(&state)?;
+                if cgb.instructions_executed() <= target {
+                    break;
+                }
+            }
+            let Some(delta) = self.deltas.iter().rev().nth(dropped) else {
+                cgb.restore_state(&self.scratch)?;
+                bail!("Rewinding doesn't go back far enough to step back");
+            };
+            let Some(older) = delta::decode(&state, &delta.data) else {
+                cgb.restore_state(&self.scratch)?;
+                self.clear();
+                bail!("A rewind state is corrupt");
+            };
+            state = older;
+            dropped += 1;
+        }
+
+        let start = cgb.cycles_executed();
+        if !cgb.run_to_instruction(target, frame_buff) {
+            cgb.restore_state(&self.scratch)?;
+            bail!("Replaying from the rewind buffer didn't reach the previous instruction");
+        }
+        for _ in 0..dropped {
+            let delta = self.deltas.pop_back().unwrap();
+            self.buffered -= delta.frames;
+        }
+        self.newest = state;
+        self.owed = 0;
+        // The replay may have run whole frames past the state
+        self.frames = ((cgb.cycles_executed() - start) / MachineCycle::PER_FRAME as u64) as usize;
+        Ok(())
+    }
+}
@@ -0,0 +1,178 @@
+// SPDX-License-Identifier: GPL-3.0-or-later
+// Copyright (C) 2023 Robert Hrusecky <jadedpastabowl@gmail.com>
+
+//! Titles and box art for the library, looked up by the ROM's CRC32 in a database given in the
+//! settings, like an export of OpenVGDB. Nothing is looked up unless a source is set. Whatever is
+//! found is cached in the config directory, so it's still there offline, and a game that can't be
+//! looked up is just listed by its header's title.
+//!
+//! The source is a URL or a path with `{crc32}` in it, like
+//! `https://example.com/gb/{crc32}.json`, which gives JSON like
+//! `{"title": "Tetris", "box_art": "https://example.com/gb/tetris.png"}`. Both fields are
+//! optional, and box art has to be a PNG.
+
+use std::{cell::OnceCell, fs, io::Read, path::Path};
+
+use anyhow::{anyhow, Context as _, Result};
+use egui::{ColorImage, Context, TextureHandle, TextureOptions};
+use serde::{Deserialize, Serialize};
+use winit::event_loop::EventLoopProxy;
+
+use crate::{event::FrontendEvent, store};
+
+const PLACEHOLDER: &str = "{crc32}";
+/// Anything bigger is refused rather than downloaded in full
+const MAX_SIZE: u64 = 4 << 20;
+
+/// What the source says about a game, and what's cached of it, with the box art in its own file.
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct Record {
+    title: Option<String>,
+    box_art: Option<String>,
+}
+
+fn record_key(crc: u32) -> String {
+    format!("rom-db/{crc:08x}.json")
+}
+
+fn box_art_key(crc: u32) -> String {
+    format!("rom-db/{crc:08x}.png")
+}
+
+pub struct GameInfo {
+    pub title: Option<String>,
+    box_art: Option<ColorImage>,
+    /// Uploaded the first time the box art is shown
+    texture: OnceCell<TextureHandle>,
+}
+
+impl GameInfo {
+    fn new(title: Option<String>, box_art: Option<ColorImage>) -> Self {
+        Self {
+            title,
+            box_art,
+            texture: OnceCell::new(),
+        }
+    }
+
+    pub fn box_art(&self, ctx: &Context) -> Option<&TextureHandle> {
+        let image = self.box_art.as_ref()?;
+        Some(
+            self.texture
+                .get_or_init(|| ctx.load_texture("box art", image.clone(), TextureOptions::LINEAR)),
+        )
+    }
+}
+
+/// What's cached for the game with `crc`, if it's been looked up before. Games the source didn't
+/// have are cached too, with nothing in them.
+pub fn cached(crc: u32) -> Option<GameInfo> {
+    let record: Record = serde_json::from_str(&store::load(&record_key(crc))?).ok()?;
+    let box_art = store::path(&box_art_key(crc))
+        .and_then(|path| fs::read(path).ok())
+        .and_then(|png| match decode_png(&png) {
+            Ok(image) => Some(image),
+            Err(error) => {
+                log::warn!("Cached box art for {crc:08x} is corrupt: {error:#}");
+                None
+            }
+        });
+    Some(GameInfo::new(record.title, box_art))
+}
+
+/// Looks up the game with `crc` in `source` in the background, then caches it and sends
+/// [`FrontendEvent::GameInfoFound`]. Failing to reach the source leaves the game as it was, and
+/// it's tried again the next time the library is opened.
+pub fn fetch(source: String, crc: u32, proxy: EventLoopProxy<FrontendEvent>) {
+    tokio::task::spawn_blocking(move || match look_up(&source, crc) {
+        Ok(info) => {
+            // Sending only fails once the event loop is gone
+            let _ = proxy.send_event(FrontendEvent::GameInfoFound(crc, info));
+        }
+        Err(error) => log::warn!("Failed to look up {crc:08x}: {error:#}"),
+    });
+}
+
+fn look_up(source: &str, crc: u32) -> Result<GameInfo> {
+    let location = source.replace(PLACEHOLDER, &format!("{crc:08x}"));
+    let record = match read(&location)? {
+        Some(json) => {
+            serde_json::from_slice(&json).with_context(|| format!("Failed to parse {location}"))?
+        }
+        None => Record::default(),
+    };
+    let box_art = match &record.box_art {
+        Some(location) => read(location)?,
+        None => None,
+    };
+    let image = box_art
+        .as_deref()
+        .map(decode_png)
+        .transpose()
+        .with_context(|| format!("Failed to decode the box art for {crc:08x}"))?;
+
+    if let Some(png) = &box_art {
+        let path = store::path(&box_art_key(crc)).ok_or(anyhow!("No config directory"))?;
+        fs::create_dir_all(path.parent().unwrap())?;
+        fs::write(&path, png).with_context(|| format!("Failed to write {path:?}"))?;
+    }
+    let cached = Record {
+        title: record.title.clone(),
+        box_art: None,
+    };
+    store::save(&record_key(crc), &serde_json::to_string(&cached)?)
+        .context("Failed to cache game info")?;
+    Ok(GameInfo::new(record.title, image))
+}
+
+/// Reads a URL or a path, or `None` if there's nothing there.
+fn read(location: &str) -> Result<Option<Vec<u8>>> {
+    if !location.starts_with("http://") && !location.starts_with("https://") {
+        return match fs::read(Path::new(location)) {
+            Ok(data) => Ok(Some(data)),
+            Err(error) if error.kind() == std::io::ErrorKind::NotFound => Ok(None),
+            Err(error) => Err(error).with_context(|| format!("Failed to read {location}")),
+        };
+    }
+    let response = match ureq::get(location).call() {
+        Ok(response) => response,
+        Err(ureq::Error::Status(404, _)) => return Ok(None),
+        Err(error) => return Err(error).with_context(|| format!("Failed to get {location}")),
+    };
+    let mut data = Vec::new();
+    response
+        .into_reader()
+        .take(MAX_SIZE + 1)
+        .read_to_end(&mut data)
+        .with_context(|| format!("Failed to get {location}"))?;
+    if data.len() as u64 > MAX_SIZE {
+        return Err(anyhow!("{location} is too big"));
+    }
+    Ok(Some(data))
+}
+
+fn decode_png(png: &[u8]) -> Result<ColorImage> {
+    let mut decoder = png::Decoder::new(png);
+    decoder.set_transformations(png::Transformations::normalize_to_color8());
+    let mut reader = decoder.read_info()?;
+    let mut buf = vec![0; reader.output_buffer_size()];
+    let frame = reader.next_frame(&mut buf)?;
+    let size = [frame.width as usize, frame.height as usize];
+    let pixels = &buf[..frame.buffer_size()];
+    let to_rgba = |pixel: &[u8]| match *pixel {
+        [r, g, b, a] => [r, g, b, a],
+        [r, g, b] => [r, g, b, 0xff],
+        [gray, a] => [gray, gray, gray, a],
+        [gray] => [gray, gray, gray, 0xff],
+        _ => unreachable!("Pixels are one to four bytes"),
+    };
+    let channels = match frame.color_type {
+        png::ColorType::Rgba => 4,
+        png::ColorType::Rgb => 3,
+        png::ColorType::GrayscaleAlpha => 2,
+        png::ColorType::Grayscale => 1,
+        png::ColorType::Indexed => return Err(anyhow!("Palette wasn't expanded")),
+    };
+    let rgba: Vec<u8> = pixels.chunks_exact(channels).flat_map(to_rgba).collect();
+    Ok(ColorImage::from_rgba_unmultiplied(size, &rgba))
+}
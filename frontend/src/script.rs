@@ -0,0 +1,180 @@
+// SPDX-License-Identifier: GPL-3.0-or-later
+// Copyright (C) 2023 Robert Hrusecky <jadedpastabowl@gmail.com>
+
+//! Rhai scripts for automating the emulator, like bots, auto-splitters, and research, given with
+//! `--script`. Requires the `scripting` feature.
+//!
+//! A script's top level runs once, on the first frame of the game, and an `on_frame` function, if
+//! it defines one, runs after every frame from then on. While rewinding, scripts don't run. For
+//! example, this holds A whenever the player's health at 0xc0a0 is low:
+//!
+//! ```text
+//! fn on_frame() {
+//!     if peek(0xc0a0) < 3 { press("a") } else { release("a") }
+//! }
+//! ```
+//!
+//! Along with Rhai's standard library, scripts can call:
+//!
+//! - `peek(addr)` and `poke(addr, val)`, to read and write memory as the CPU sees it
+//! - `registers()`, the CPU's registers as a map, like `registers().pc`
+//! - `press(button)` and `release(button)`, with buttons named like `"a"` or `"start"`
+//! - `frame()`, the number of frames since the script started
+//!
+//! `print` and `debug` go to the log. A script that runs more than [`MAX_OPERATIONS`] operations in
+//! one frame is stopped, so that an endless loop can't hang the emulator.
+
+use std::{
+    cell::{Cell, RefCell},
+    path::Path,
+    rc::Rc,
+};
+
+use anyhow::{anyhow, Context as _, Result};
+use iron_boy_core::joypad::Button;
+use rhai::{CallFnOptions, Dynamic, Engine, EvalAltResult, Map, Scope, AST};
+use winit::event::ElementState;
+
+use crate::emulator::Cgb;
+
+const LOG_TARGET: &str = "iron_boy::script";
+/// The most operations Rhai runs in each call into a script, which is plenty for a frame's work
+const MAX_OPERATIONS: u64 = 1_000_000;
+
+type ScriptResult<T> = Result<T, Box<EvalAltResult>>;
+
+/// Where the running game is lent to the functions registered with Rhai, during calls into the
+/// script.
+type Game = Rc<RefCell<Option<Cgb>>>;
+
+fn with_game<T>(game: &Game, f: impl FnOnce(&mut Cgb) -> T) -> ScriptResult<T> {
+    let mut game = game.borrow_mut();
+    let cgb = game.as_mut().ok_or("No game is running")?;
+    Ok(f(cgb))
+}
+
+fn parse_addr(addr: i64) -> ScriptResult<u16> {
+    u16::try_from(addr).map_err(|_| format!("{addr:#x} is not an address").into())
+}
+
+fn button(name: &str) -> ScriptResult<Button> {
+    Button::ALL
+        .into_iter()
+        .find(|button| format!("{button:?}").eq_ignore_ascii_case(name))
+        .ok_or_else(|| format!("Unknown button {name:?}").into())
+}
+
+pub struct Script {
+    engine: Engine,
+    ast: AST,
+    scope: Scope<'static>,
+    game: Game,
+    frames: Rc<Cell<i64>>,
+    started: bool,
+    on_frame: bool,
+}
+
+impl Script {
+    pub fn load(path: &Path) -> Result<Self> {
+        let game = Game::default();
+        let frames = Rc::new(Cell::new(0));
+        let mut engine = Engine::new();
+        engine
+            .set_max_operations(MAX_OPERATIONS)
+            .on_print(|text| log::info!(target: LOG_TARGET, "{text}"))
+            .on_debug(|text, _, pos| log::debug!(target: LOG_TARGET, "{pos}: {text}"));
+
+        let peek_game = Rc::clone(&game);
+        engine.register_fn("peek", move |addr: i64| -> ScriptResult<i64> {
+            let addr = parse_addr(addr)?;
+            with_game(&peek_game, |cgb| cgb.peek(addr).into())
+        });
+        let poke_game = Rc::clone(&game);
+        engine.register_fn("poke", move |addr: i64, val: i64| -> ScriptResult<()> {
+            let addr = parse_addr(addr)?;
+            let val = u8::try_from(val).map_err(|_| format!("{val} doesn't fit in a byte"))?;
+            with_game(&poke_game, |cgb| cgb.poke(addr, val))
+        });
+        let registers_game = Rc::clone(&game);
+        engine.register_fn("registers", move || -> ScriptResult<Map> {
+            let regs = with_game(&registers_game, |cgb| cgb.registers())?;
+            let mut map = Map::new();
+            for (name, val) in [
+                ("af", regs.af),
+                ("bc", regs.bc),
+                ("de", regs.de),
+                ("hl", regs.hl),
+                ("sp", regs.sp),
+                ("pc", regs.pc),
+            ] {
+                map.insert(name.into(), Dynamic::from_int(val.into()));
+            }
+            map.insert("ime".into(), regs.ime.into());
+            map.insert("halted".into(), regs.halted.into());
+            Ok(map)
+        });
+        for (name, state) in [
+            ("press", ElementState::Pressed),
+            ("release", ElementState::Released),
+        ] {
+            let game = Rc::clone(&game);
+            engine.register_fn(name, move |name: &str| -> ScriptResult<()> {
+                let button = button(name)?;
+                with_game(&game, |cgb| cgb.handle_joypad(button, state))
+            });
+        }
+        let frame_count = Rc::clone(&frames);
+        engine.register_fn("frame", move || frame_count.get());
+
+        let ast = engine
+            .compile_file(path.into())
+            .map_err(|error| anyhow!("{error}"))
+            .with_context(|| format!("Failed to load the script {}", path.display()))?;
+        let on_frame = ast
+            .iter_functions()
+            .any(|function| function.name == "on_frame" && function.params.is_empty());
+        Ok(Self {
+            engine,
+            ast,
+            scope: Scope::new(),
+            game,
+            frames,
+            started: false,
+            on_frame,
+        })
+    }
+
+    /// Runs the script for the frame that just finished in `cgb`.
+    pub fn frame(&mut self, cgb: &mut Option<Cgb>) -> Result<()> {
+        if cgb.is_none() {
+            return Ok(());
+        }
+        *self.game.borrow_mut() = cgb.take();
+        let result = if self.started {
+            if self.on_frame {
+                // The top level ran already
+                let options = CallFnOptions::new().eval_ast(false);
+                self.engine.call_fn_with_options::<()>(
+                    options,
+                    &mut self.scope,
+                    &self.ast,
+                    "on_frame",
+                    (),
+                )
+            } else {
+                Ok(())
+            }
+        } else {
+            self.started = true;
+            self.engine.run_ast_with_scope(&mut self.scope, &self.ast)
+        };
+        *cgb = self.game.borrow_mut().take();
+        self.frames.set(self.frames.get() + 1);
+        result.map_err(|error| match *error {
+            EvalAltResult::ErrorTooManyOperations(pos) => {
+                anyhow!("Script ran more than {MAX_OPERATIONS} operations in one frame, at {pos}")
+            }
+            error => anyhow!("Script error: {error}"),
+        })
+    }
+}
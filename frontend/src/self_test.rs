@@ -0,0 +1,171 @@
+// SPDX-License-Identifier: GPL-3.0-or-later
+// Copyright (C) 2023 Robert Hrusecky <jadedpastabowl@gmail.com>
+
+//! A smoke test of the window, renderer, audio output, and input handling, for packaging CI and
+//! for telling a broken setup apart from a broken emulator. It runs a tiny built in program
+//! instead of a game, which draws a checkerboard and plays a tone on channel 2.
+
+use std::process;
+
+use anyhow::{Error, Result};
+use iron_boy_core::{joypad::Button, system::FrameBuffer};
+use winit::event::ElementState;
+
+use crate::{
+    audio::Audio,
+    emulator::Cgb,
+    input::{InputMap, Player},
+    options::Options,
+};
+
+/// Long enough for the audio device to have started pulling samples
+const FRAMES: usize = 60;
+/// When to press a button through the input map, leaving the program time to set itself up
+const PRESS_FRAME: usize = 30;
+const RELEASE_FRAME: usize = PRESS_FRAME + 1;
+
+const P1: u16 = 0xff00;
+const NR52: u16 = 0xff26;
+
+/// Starts at 0x150, right after the header.
+#[rustfmt::skip]
+const PROGRAM: &[u8] = &[
+    // Tile 1 is solid black
+    0x21, 0x10, 0x80, // ld hl, 0x8010
+    0x3e, 0xff,       // ld a, 0xff
+    0x06, 0x10,       // ld b, 16
+    0x22,             // .tile: ld (hl+), a
+    0x05,             // dec b
+    0x20, 0xfc,       // jr nz, .tile
+    // Checkerboard the background map, with tile (row ^ column) & 1
+    0x21, 0x00, 0x98, // ld hl, 0x9800
+    0x7d,             // .map: ld a, l
+    0xcb, 0x37,       // swap a
+    0x0f,             // rrca
+    0xad,             // xor l
+    0xe6, 0x01,       // and 1
+    0x22,             // ld (hl+), a
+    0x7c,             // ld a, h
+    0xfe, 0x9c,       // cp 0x9c
+    0x20, 0xf3,       // jr nz, .map
+    // Select the directions in P1
+    0x3e, 0x20,       // ld a, 0x20
+    0xe0, 0x00,       // ldh (P1), a
+    // 440 Hz on channel 2 at full volume
+    0x3e, 0x80,       // ld a, 0x80
+    0xe0, 0x16,       // ldh (NR21), a
+    0x3e, 0xf0,       // ld a, 0xf0
+    0xe0, 0x17,       // ldh (NR22), a
+    0x3e, 0xd6,       // ld a, 0xd6
+    0xe0, 0x18,       // ldh (NR23), a
+    0x3e, 0x86,       // ld a, 0x86
+    0xe0, 0x19,       // ldh (NR24), a
+    0x18, 0xfe,       // .idle: jr .idle
+];
+
+fn rom() -> Box<[u8]> {
+    let mut rom = vec![0; 0x8000];
+    // nop; jp 0x150
+    rom[0x100..0x104].copy_from_slice(&[0x00, 0xc3, 0x50, 0x01]);
+    let title = b"SELF TEST";
+    rom[0x134..0x134 + title.len()].copy_from_slice(title);
+    rom[0x150..0x150 + PROGRAM.len()].copy_from_slice(PROGRAM);
+    rom.into_boxed_slice()
+}
+
+/// Whether the screen shows the checkerboard, going by a few pixels.
+fn shows_checkerboard(frame_buff: &FrameBuffer) -> bool {
+    let [white, black, white_again] = [(0, 0), (0, 8), (8, 8)].map(|(y, x)| frame_buff[y][x]);
+    white != black && white == white_again
+}
+
+pub struct SelfTest {
+    frames: usize,
+    failures: Vec<String>,
+}
+
+impl SelfTest {
+    pub fn new() -> Self {
+        Self {
+            frames: 0,
+            failures: Vec::new(),
+        }
+    }
+
+    /// The emulator running the test program.
+    pub fn cgb(options: &Options) -> Result<Cgb> {
+        Cgb::new_skipping_boot(rom(), options)
+    }
+
+    /// Notes an error from anywhere in the frontend, like a failed render.
+    pub fn fail(&mut self, error: &Error) {
+        self.failures.push(format!("{error:#}"));
+    }
+
+    /// Checks on the test after `cgb` runs a frame. Once it's over, prints the results and exits.
+    pub fn frame(
+        &mut self,
+        cgb: &mut Cgb,
+        frame_buff: &FrameBuffer,
+        audio: &Audio,
+        input_map: &InputMap,
+        controllers: &[String],
+    ) {
+        self.frames += 1;
+        match self.frames {
+            PRESS_FRAME => {
+                // Look up the key for Right and back, the same way a key press is handled
+                let key = input_map.player(Player::One).key(Button::Right);
+                match key.and_then(|key| input_map.lookup(key)) {
+                    Some((Player::One, button)) => cgb.handle_joypad(button, ElementState::Pressed),
+                    _ => self.failures.push("No key is bound to Right".into()),
+                }
+            }
+            RELEASE_FRAME => {
+                if cgb.read_memory(P1, 1)[0] & 0x01 != 0 {
+                    self.failures
+                        .push("A key press didn't reach the joypad".into());
+                }
+                cgb.handle_joypad(Button::Right, ElementState::Released);
+            }
+            FRAMES => {
+                println!("Video: {FRAMES} frames drawn");
+                if !shows_checkerboard(frame_buff) {
+                    self.failures
+                        .push("The test pattern didn't come out of the PPU".into());
+                }
+                println!("Audio: {} frames played", audio.frames_played());
+                if cgb.read_memory(NR52, 1)[0] & 0x02 == 0 {
+                    self.failures.push("The test tone isn't playing".into());
+                }
+                if audio.frames_played() == 0 {
+                    self.failures
+                        .push("The audio device hasn't taken any samples".into());
+                }
+                println!("Input: {} controllers connected", controllers.len());
+                for name in controllers {
+                    println!("  {name}");
+                }
+                self.finish();
+            }
+            _ => (),
+        }
+    }
+
+    fn finish(&self) -> ! {
+        if self.failures.is_empty() {
+            println!("Self-test passed");
+            process::exit(0);
+        }
+        for failure in &self.failures {
+            println!("FAILED: {failure}");
+        }
+        process::exit(1);
+    }
+}
+
+/// Reports that the frontend couldn't even start, like when there's no GPU or audio device.
+pub fn fail_setup(error: &Error) -> ! {
+    println!("FAILED: {error:#}");
+    process::exit(1);
+}
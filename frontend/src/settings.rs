@@ -0,0 +1,201 @@
+// SPDX-License-Identifier: GPL-3.0-or-later
+// Copyright (C) 2023 Robert Hrusecky <jadedpastabowl@gmail.com>
+
+//! Settings meant for editing by hand, in `settings.toml` in the config directory. The defaults
+//! are written out the first time, and the file is watched so that edits apply right away, e.g.
+//! from a text editor or a stream deck. Remapping keys or controller buttons in the GUI writes the
+//! file too.
+
+use std::{collections::BTreeMap, path::PathBuf};
+
+use anyhow::{anyhow, Context, Result};
+use iron_boy_core::{joypad::Button, system::PpuConfig};
+use notify::{Event, RecommendedWatcher, RecursiveMode, Watcher};
+use serde::{Deserialize, Serialize};
+use winit::{event::VirtualKeyCode, event_loop::EventLoopProxy};
+
+use crate::{
+    event::FrontendEvent,
+    filter::ScalingFilter,
+    input::{Bindings, InputMap, Player},
+    store,
+};
+
+const KEY: &str = "settings.toml";
+
+/// Player 1's keys for each button.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Keys {
+    pub up: VirtualKeyCode,
+    pub down: VirtualKeyCode,
+    pub left: VirtualKeyCode,
+    pub right: VirtualKeyCode,
+    pub a: VirtualKeyCode,
+    pub b: VirtualKeyCode,
+    pub start: VirtualKeyCode,
+    pub select: VirtualKeyCode,
+}
+
+impl Keys {
+    /// The keys in `bindings`, falling back on the defaults for unbound buttons.
+    pub fn new(bindings: &Bindings) -> Self {
+        let defaults = InputMap::default();
+        let key = |button| {
+            bindings
+                .key(button)
+                .or_else(|| defaults.player(Player::One).key(button))
+                .expect("Every button has a default key")
+        };
+        Self {
+            up: key(Button::Up),
+            down: key(Button::Down),
+            left: key(Button::Left),
+            right: key(Button::Right),
+            a: key(Button::A),
+            b: key(Button::B),
+            start: key(Button::Start),
+            select: key(Button::Select),
+        }
+    }
+
+    pub fn bindings(&self) -> Bindings {
+        Bindings::new([
+            (self.up, Button::Up),
+            (self.down, Button::Down),
+            (self.left, Button::Left),
+            (self.right, Button::Right),
+            (self.a, Button::A),
+            (self.b, Button::B),
+            (self.start, Button::Start),
+            (self.select, Button::Select),
+        ])
+    }
+}
+
+impl Default for Keys {
+    fn default() -> Self {
+        Self::new(InputMap::default().player(Player::One))
+    }
+}
+
+/// The controller buttons for each of player 1's buttons, by gilrs' names, which go by where the
+/// face buttons sit. Any of a button's list works, and an empty one leaves it unbound.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ControllerButtons {
+    pub up: Vec<gilrs::Button>,
+    pub down: Vec<gilrs::Button>,
+    pub left: Vec<gilrs::Button>,
+    pub right: Vec<gilrs::Button>,
+    pub a: Vec<gilrs::Button>,
+    pub b: Vec<gilrs::Button>,
+    pub start: Vec<gilrs::Button>,
+    pub select: Vec<gilrs::Button>,
+}
+
+impl ControllerButtons {
+    pub fn new(bindings: &Bindings<gilrs::Button>) -> Self {
+        let buttons = |button| bindings.keys(button).collect();
+        Self {
+            up: buttons(Button::Up),
+            down: buttons(Button::Down),
+            left: buttons(Button::Left),
+            right: buttons(Button::Right),
+            a: buttons(Button::A),
+            b: buttons(Button::B),
+            start: buttons(Button::Start),
+            select: buttons(Button::Select),
+        }
+    }
+
+    pub fn bindings(&self) -> Bindings<gilrs::Button> {
+        Bindings::new(
+            [
+                (&self.up, Button::Up),
+                (&self.down, Button::Down),
+                (&self.left, Button::Left),
+                (&self.right, Button::Right),
+                (&self.a, Button::A),
+                (&self.b, Button::B),
+                (&self.start, Button::Start),
+                (&self.select, Button::Select),
+            ]
+            .into_iter()
+            .flat_map(|(pads, button)| pads.iter().map(move |&pad| (pad, button))),
+        )
+    }
+}
+
+impl Default for ControllerButtons {
+    fn default() -> Self {
+        Self::new(InputMap::default().controller())
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Settings {
+    pub keys: Keys,
+    pub controller: ControllerButtons,
+    pub video: PpuConfig,
+    /// A folder of ROMs to list in the side panel's library
+    pub rom_dir: Option<PathBuf>,
+    /// Where to look up titles and box art for the library, as a URL or path with `{crc32}` in it.
+    /// Nothing is looked up without one, but what was found before stays cached.
+    pub rom_database: Option<String>,
+    /// The token that connections to `--control-port` authenticate with
+    pub control_token: Option<String>,
+    /// Cartridge RAM sizes in bytes by game title, for games whose header gets it wrong or saves
+    /// from other emulators that expect a different size. Applies from the next game started.
+    pub ram_sizes: BTreeMap<String, usize>,
+    /// Scaling filters by game title, remembered whenever one is picked while the game is running
+    pub filters: BTreeMap<String, ScalingFilter>,
+    /// Stereo widths in percent by game title, remembered like the filters
+    pub stereo_widths: BTreeMap<String, u8>,
+}
+
+impl Settings {
+    /// Reads the settings file, or writes out the defaults if there isn't one yet.
+    pub fn load() -> Result<Self> {
+        let Some(settings) = store::load(KEY) else {
+            let settings = Self::default();
+            settings.save()?;
+            return Ok(settings);
+        };
+        toml::from_str(&settings).with_context(|| format!("Failed to parse {KEY}"))
+    }
+
+    pub fn save(&self) -> Result<()> {
+        store::save(KEY, &toml::to_string(self)?).with_context(|| format!("Failed to write {KEY}"))
+    }
+}
+
+/// Watches the settings file until dropped.
+pub struct SettingsWatcher {
+    _watcher: RecommendedWatcher,
+}
+
+impl SettingsWatcher {
+    /// Sends [`FrontendEvent::SettingsChanged`] whenever the settings file is written.
+    pub fn new(proxy: EventLoopProxy<FrontendEvent>) -> Result<Self> {
+        let path = store::path(KEY).ok_or(anyhow!("No config directory"))?;
+        // Editors often replace the file rather than writing to it, so watch its directory
+        let dir: PathBuf = path.parent().unwrap().into();
+        let mut watcher = notify::recommended_watcher(move |event: notify::Result<Event>| {
+            match event {
+                Ok(event)
+                    if event.paths.contains(&path)
+                        && (event.kind.is_create() || event.kind.is_modify()) =>
+                {
+                    // Sending only fails once the event loop is gone
+                    let _ = proxy.send_event(FrontendEvent::SettingsChanged);
+                }
+                Ok(_) => (),
+                Err(error) => log::warn!("Failed to watch {KEY}: {error}"),
+            }
+        })?;
+        watcher.watch(&dir, RecursiveMode::NonRecursive)?;
+        Ok(Self { _watcher: watcher })
+    }
+}
@@ -0,0 +1,167 @@
+// SPDX-License-Identifier: GPL-3.0-or-later
+// Copyright (C) 2023 Robert Hrusecky <jadedpastabowl@gmail.com>
+
+//! A cosmetic "handheld" mode that draws the game inside of a Game Boy Color shell. The shell is
+//! drawn into the pixel buffer at the same resolution as the game, so it is scaled right along
+//! with it and stays pixel perfect.
+
+use std::str::FromStr;
+
+use anyhow::{anyhow, Error};
+use iron_boy_core::system::{FrameBuffer, SCREEN_HEIGHT, SCREEN_WIDTH};
+
+type Color = [u8; 4];
+
+const LENS: Color = [0x30, 0x30, 0x38, 0xff];
+const LCD_OFF: Color = [0xc8, 0xcc, 0xb8, 0xff];
+const BUTTON: Color = [0x28, 0x28, 0x2c, 0xff];
+const POWER_LED: Color = [0xe0, 0x20, 0x30, 0xff];
+
+const SCREEN_X: usize = 24;
+const SCREEN_Y: usize = 24;
+
+/// Shell colors of the original CGB releases.
+const PRESETS: [(&str, Color); 6] = [
+    ("berry", [0xc0, 0x20, 0x58, 0xff]),
+    ("grape", [0x60, 0x38, 0xa0, 0xff]),
+    ("kiwi", [0x98, 0xc8, 0x30, 0xff]),
+    ("dandelion", [0xf0, 0xc8, 0x20, 0xff]),
+    ("teal", [0x20, 0x98, 0xa8, 0xff]),
+    ("atomic-purple", [0x70, 0x58, 0x98, 0xff]),
+];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ShellColor(Color);
+
+impl FromStr for ShellColor {
+    type Err = Error;
+
+    /// Parses either the name of a preset or an `#rrggbb` hex color.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if let Some(&(_, color)) = PRESETS.iter().find(|(name, _)| *name == s) {
+            return Ok(Self(color));
+        }
+        let hex = s
+            .strip_prefix('#')
+            .filter(|hex| hex.len() == 6)
+            .ok_or_else(|| anyhow!("Expected a preset name or #rrggbb, found {s:?}"))?;
+        let rgb = u32::from_str_radix(hex, 16)?;
+        let [_, r, g, b] = rgb.to_be_bytes();
+        Ok(Self([r, g, b, 0xff]))
+    }
+}
+
+#[derive(Clone, Copy)]
+struct Rect {
+    x: usize,
+    y: usize,
+    width: usize,
+    height: usize,
+}
+
+impl Rect {
+    const fn new(x: usize, y: usize, width: usize, height: usize) -> Self {
+        Self {
+            x,
+            y,
+            width,
+            height,
+        }
+    }
+
+    /// Whether the pixel at (`x`, `y`) is inside of the rectangle once its corners are rounded
+    /// off with `radius`.
+    fn contains(&self, x: usize, y: usize, radius: usize) -> bool {
+        if x < self.x || y < self.y || x >= self.x + self.width || y >= self.y + self.height {
+            return false;
+        }
+        // Distance into the nearest corner's bounding square, if any
+        let corner = |pos: usize, start: usize, len: usize| {
+            let (from_start, from_end) = (pos - start, start + len - 1 - pos);
+            radius.checked_sub(from_start.min(from_end))
+        };
+        match (
+            corner(x, self.x, self.width),
+            corner(y, self.y, self.height),
+        ) {
+            (Some(dx), Some(dy)) => dx * dx + dy * dy <= radius * radius,
+            _ => true,
+        }
+    }
+}
+
+pub struct Skin {
+    shell: Color,
+    screen: Box<FrameBuffer>,
+}
+
+impl Skin {
+    pub const WIDTH: usize = SCREEN_WIDTH + 2 * SCREEN_X;
+    pub const HEIGHT: usize = 336;
+    /// Of the game's screen's top left corner
+    pub const SCREEN_POSITION: (usize, usize) = (SCREEN_X, SCREEN_Y);
+
+    pub fn new(ShellColor(shell): ShellColor) -> Self {
+        Self {
+            shell,
+            screen: Box::new([[LCD_OFF; SCREEN_WIDTH]; SCREEN_HEIGHT]),
+        }
+    }
+
+    /// The frame buffer the emulator should render into. Call [`Self::present`] afterwards to
+    /// copy it into the shell.
+    pub fn screen_mut(&mut self) -> &mut FrameBuffer {
+        &mut self.screen
+    }
+
+    fn fill(frame: &mut [u8], rect: Rect, radius: usize, color: Color) {
+        for (i, pixel) in frame.chunks_exact_mut(4).enumerate() {
+            if rect.contains(i % Self::WIDTH, i / Self::WIDTH, radius) {
+                pixel.copy_from_slice(&color);
+            }
+        }
+    }
+
+    /// Draws the shell into `frame`, which must be [`Self::WIDTH`] by [`Self::HEIGHT`]. This only
+    /// needs to happen once, as the game only ever draws over the screen.
+    pub fn draw_shell(&self, frame: &mut [u8]) {
+        frame.fill(0);
+        Self::fill(
+            frame,
+            Rect::new(0, 0, Self::WIDTH, Self::HEIGHT),
+            16,
+            self.shell,
+        );
+        let lens_height = SCREEN_HEIGHT + 2 * SCREEN_Y;
+        Self::fill(
+            frame,
+            Rect::new(8, 8, Self::WIDTH - 16, lens_height - 16),
+            6,
+            LENS,
+        );
+        Self::fill(frame, Rect::new(12, SCREEN_Y + 56, 4, 4), 2, POWER_LED);
+
+        // D-pad
+        let (dpad_x, dpad_y) = (24, 232);
+        Self::fill(frame, Rect::new(dpad_x + 14, dpad_y, 14, 42), 2, BUTTON);
+        Self::fill(frame, Rect::new(dpad_x, dpad_y + 14, 42, 14), 2, BUTTON);
+
+        // A and B
+        Self::fill(frame, Rect::new(160, 228, 24, 24), 12, BUTTON);
+        Self::fill(frame, Rect::new(128, 244, 24, 24), 12, BUTTON);
+
+        // Select and Start
+        Self::fill(frame, Rect::new(68, 296, 24, 8), 4, BUTTON);
+        Self::fill(frame, Rect::new(116, 296, 24, 8), 4, BUTTON);
+
+        self.present(frame);
+    }
+
+    /// Copies the latest frame onto the screen of the shell in `frame`.
+    pub fn present(&self, frame: &mut [u8]) {
+        for (y, row) in self.screen.iter().enumerate() {
+            let start = ((SCREEN_Y + y) * Self::WIDTH + SCREEN_X) * 4;
+            frame[start..start + SCREEN_WIDTH * 4].copy_from_slice(row.as_flattened());
+        }
+    }
+}
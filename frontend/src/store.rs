@@ -0,0 +1,72 @@
+// SPDX-License-Identifier: GPL-3.0-or-later
+// Copyright (C) 2023 Robert Hrusecky <jadedpastabowl@gmail.com>
+
+//! A small key-value store for frontend state that should survive between sessions. On desktop,
+//! each key is a file in the user's config directory. On the web, keys live in local storage.
+
+#[cfg(not(target_arch = "wasm32"))]
+mod desktop {
+    use std::{fs, path::PathBuf};
+
+    use anyhow::{anyhow, Result};
+
+    /// Where `key` is stored.
+    pub fn path(key: &str) -> Option<PathBuf> {
+        Some(dirs_next::config_dir()?.join("iron-boy").join(key))
+    }
+
+    pub fn load(key: &str) -> Option<String> {
+        fs::read_to_string(path(key)?).ok()
+    }
+
+    pub fn save(key: &str, value: &str) -> Result<()> {
+        let path = path(key).ok_or(anyhow!("No config directory"))?;
+        fs::create_dir_all(path.parent().unwrap())?;
+        fs::write(path, value)?;
+        Ok(())
+    }
+
+    /// Forgets `key`, if it was ever saved.
+    pub fn remove(key: &str) -> Result<()> {
+        let path = path(key).ok_or(anyhow!("No config directory"))?;
+        match fs::remove_file(path) {
+            Err(error) if error.kind() != std::io::ErrorKind::NotFound => Err(error.into()),
+            _ => Ok(()),
+        }
+    }
+}
+#[cfg(not(target_arch = "wasm32"))]
+pub use desktop::*;
+
+#[cfg(target_arch = "wasm32")]
+mod web {
+    use anyhow::{anyhow, Result};
+    use web_sys::Storage;
+
+    const PREFIX: &str = "iron-boy.";
+
+    fn storage() -> Option<Storage> {
+        web_sys::window()?.local_storage().ok()?
+    }
+
+    pub fn load(key: &str) -> Option<String> {
+        storage()?.get_item(&format!("{PREFIX}{key}")).ok()?
+    }
+
+    pub fn save(key: &str, value: &str) -> Result<()> {
+        storage()
+            .ok_or(anyhow!("Local storage is unavailable"))?
+            .set_item(&format!("{PREFIX}{key}"), value)
+            .map_err(|error| anyhow!("Failed to write to local storage: {error:?}"))
+    }
+
+    /// Forgets `key`, if it was ever saved.
+    pub fn remove(key: &str) -> Result<()> {
+        storage()
+            .ok_or(anyhow!("Local storage is unavailable"))?
+            .remove_item(&format!("{PREFIX}{key}"))
+            .map_err(|error| anyhow!("Failed to write to local storage: {error:?}"))
+    }
+}
+#[cfg(target_arch = "wasm32")]
+pub use web::*;
@@ -0,0 +1,109 @@
+// SPDX-License-Identifier: GPL-3.0-or-later
+// Copyright (C) 2023 Robert Hrusecky <jadedpastabowl@gmail.com>
+
+//! Writing the APU's output to a WAV file, like for ripping game music or checking the APU against
+//! recordings from hardware.
+
+use std::{
+    fs::File,
+    io::{BufWriter, Seek, SeekFrom, Write},
+    path::Path,
+    time::Duration,
+};
+
+use anyhow::{Context as _, Result};
+use iron_boy_core::system::{AudioFrame, MachineCycle, AUDIO_FRAMES_PER_CYCLE};
+
+/// How many of the APU's audio frames are averaged into each sample
+const DOWNSAMPLE: usize = 64;
+/// 32768 Hz
+const SAMPLE_RATE: usize = MachineCycle::FREQ * AUDIO_FRAMES_PER_CYCLE / DOWNSAMPLE;
+const CHANNELS: u16 = 2;
+const BYTES_PER_SAMPLE: u16 = 2;
+
+/// A 16 bit stereo WAV, whose header is filled in with its length once it's finished. Audio
+/// frames are pushed from the APU as they come, and written out with [`Self::write_pending`].
+pub struct WavWriter {
+    file: BufWriter<File>,
+    data_len: u32,
+    /// Samples that haven't been written yet
+    pending: Vec<AudioFrame>,
+    /// The sum of the audio frames being averaged into the next sample
+    sum: AudioFrame,
+    summed: usize,
+}
+
+impl WavWriter {
+    pub fn create(path: &Path) -> Result<Self> {
+        let file = File::create(path).with_context(|| format!("Failed to create {path:?}"))?;
+        let mut wav = Self {
+            file: BufWriter::new(file),
+            data_len: 0,
+            pending: Vec::new(),
+            sum: [0.0; 2],
+            summed: 0,
+        };
+        wav.write_header()?;
+        Ok(wav)
+    }
+
+    fn write_header(&mut self) -> Result<()> {
+        let block_align = CHANNELS * BYTES_PER_SAMPLE;
+        let file = &mut self.file;
+        file.write_all(b"RIFF")?;
+        file.write_all(&(36 + self.data_len).to_le_bytes())?;
+        file.write_all(b"WAVEfmt ")?;
+        file.write_all(&16u32.to_le_bytes())?;
+        // PCM
+        file.write_all(&1u16.to_le_bytes())?;
+        file.write_all(&CHANNELS.to_le_bytes())?;
+        file.write_all(&(SAMPLE_RATE as u32).to_le_bytes())?;
+        file.write_all(&(SAMPLE_RATE as u32 * block_align as u32).to_le_bytes())?;
+        file.write_all(&block_align.to_le_bytes())?;
+        file.write_all(&(BYTES_PER_SAMPLE * 8).to_le_bytes())?;
+        file.write_all(b"data")?;
+        file.write_all(&self.data_len.to_le_bytes())?;
+        Ok(())
+    }
+
+    pub fn push_frame(&mut self, frame: AudioFrame) {
+        self.sum[0] += frame[0];
+        self.sum[1] += frame[1];
+        self.summed += 1;
+        if self.summed == DOWNSAMPLE {
+            self.pending
+                .push(self.sum.map(|sum| sum / DOWNSAMPLE as f32));
+            self.sum = [0.0; 2];
+            self.summed = 0;
+        }
+    }
+
+    /// Whether any samples have been pushed since they were last written.
+    pub fn has_pending(&self) -> bool {
+        !self.pending.is_empty()
+    }
+
+    pub fn push_silence(&mut self, duration: Duration) {
+        let len = (duration.as_secs_f64() * SAMPLE_RATE as f64).round() as usize;
+        self.pending.extend(std::iter::repeat_n([0.0; 2], len));
+    }
+
+    pub fn write_pending(&mut self) -> Result<()> {
+        for sample in self.pending.drain(..) {
+            for channel in sample {
+                let value = (channel.clamp(-1.0, 1.0) * i16::MAX as f32) as i16;
+                self.file.write_all(&value.to_le_bytes())?;
+            }
+            self.data_len += (CHANNELS * BYTES_PER_SAMPLE) as u32;
+        }
+        Ok(())
+    }
+
+    pub fn finish(mut self) -> Result<()> {
+        self.write_pending()?;
+        self.file.seek(SeekFrom::Start(0))?;
+        self.write_header()?;
+        self.file.flush()?;
+        Ok(())
+    }
+}
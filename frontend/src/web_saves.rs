@@ -0,0 +1,74 @@
+// SPDX-License-Identifier: GPL-3.0-or-later
+// Copyright (C) 2023 Robert Hrusecky <jadedpastabowl@gmail.com>
+
+//! Battery backed cartridge RAM and quick saves on the web, where there's no file next to the ROM
+//! to keep them in. They live in the store instead, under the game's ID, so that they're picked up
+//! again whenever the same ROM is chosen. Local storage only holds strings, so they're kept as hex.
+
+use std::fmt::Write as _;
+
+use anyhow::{anyhow, Context as _, Result};
+use iron_boy_core::cart::Cart;
+
+use crate::{bundle, store};
+
+fn key(cart: &Cart) -> String {
+    format!("saves/{}.cart", bundle::game_id(cart))
+}
+
+fn state_key(cart: &Cart) -> String {
+    format!("states/{}.state", bundle::game_id(cart))
+}
+
+pub fn encode(data: &[u8]) -> String {
+    data.iter().fold(String::new(), |mut hex, byte| {
+        let _ = write!(hex, "{byte:02x}");
+        hex
+    })
+}
+
+pub fn decode(hex: &str) -> Option<Vec<u8>> {
+    if hex.len() % 2 != 0 {
+        return None;
+    }
+    (0..hex.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(hex.get(i..i + 2)?, 16).ok())
+        .collect()
+}
+
+/// Loads the save for `cart`, if one has been stored. Does nothing for carts without a battery.
+pub fn load(cart: &mut Cart) -> Result<()> {
+    if !cart.battery_backed() {
+        return Ok(());
+    }
+    let Some(hex) = store::load(&key(cart)) else {
+        return Ok(());
+    };
+    let data = decode(&hex).ok_or(anyhow!("Stored save isn't valid hex"))?;
+    let save = bincode::deserialize(&data).context("Stored save is corrupt")?;
+    cart.load_from_save(save);
+    Ok(())
+}
+
+/// Stores the save for `cart`. Does nothing for carts without a battery.
+pub fn save(cart: &Cart) -> Result<()> {
+    let Some(save) = cart.save() else {
+        return Ok(());
+    };
+    let data = bincode::serialize(&save)?;
+    store::save(&key(cart), &encode(&data)).context("Failed to store save")
+}
+
+/// The quick save for `cart`, if one has been stored.
+pub fn load_state(cart: &Cart) -> Result<Option<Vec<u8>>> {
+    let Some(hex) = store::load(&state_key(cart)) else {
+        return Ok(None);
+    };
+    let state = decode(&hex).ok_or(anyhow!("Stored quick save isn't valid hex"))?;
+    Ok(Some(state))
+}
+
+pub fn save_state(cart: &Cart, state: &[u8]) -> Result<()> {
+    store::save(&state_key(cart), &encode(state)).context("Failed to store quick save")
+}
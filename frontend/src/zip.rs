@@ -0,0 +1,200 @@
+// SPDX-License-Identifier: GPL-3.0-or-later
+// Copyright (C) 2023 Robert Hrusecky <jadedpastabowl@gmail.com>
+
+//! Just enough of the zip format to write and read back save bundles, and to read zipped ROMs.
+//! Files are written without compression, which every zip tool can open. Stored and deflated files
+//! can be read, which covers what zip tools write by default.
+
+use anyhow::{anyhow, bail, ensure, Context, Result};
+
+const LOCAL_HEADER: u32 = 0x0403_4b50;
+const CENTRAL_HEADER: u32 = 0x0201_4b50;
+const END_OF_DIRECTORY: u32 = 0x0605_4b50;
+const LOCAL_HEADER_LEN: usize = 30;
+const CENTRAL_HEADER_LEN: usize = 46;
+const END_OF_DIRECTORY_LEN: usize = 22;
+/// 2.0, which is what tools expect for stored files
+const VERSION: u16 = 20;
+/// File names are UTF-8
+const FLAGS: u16 = 1 << 11;
+/// 1980-01-01, the earliest date that can be written, since bundles don't keep track of time
+const DATE: u16 = 0x21;
+const STORED: u16 = 0;
+const DEFLATED: u16 = 8;
+
+pub fn crc32(data: &[u8]) -> u32 {
+    let mut crc = !0u32;
+    for &byte in data {
+        crc ^= byte as u32;
+        for _ in 0..8 {
+            crc = (crc >> 1) ^ (0xedb8_8320 & (crc & 1).wrapping_neg());
+        }
+    }
+    !crc
+}
+
+/// The fields that the local and central headers share, from the version needed on.
+fn common_fields(out: &mut Vec<u8>, name: &str, data: &[u8]) {
+    for field in [VERSION, FLAGS, 0, 0, DATE] {
+        out.extend(field.to_le_bytes());
+    }
+    out.extend(crc32(data).to_le_bytes());
+    // Compressed and uncompressed sizes, which are the same
+    out.extend((data.len() as u32).to_le_bytes());
+    out.extend((data.len() as u32).to_le_bytes());
+    out.extend((name.len() as u16).to_le_bytes());
+    // No extra field
+    out.extend(0u16.to_le_bytes());
+}
+
+/// Stores `files`, given as names and contents, in a zip archive.
+pub fn write<'a>(files: impl IntoIterator<Item = (&'a str, &'a [u8])>) -> Vec<u8> {
+    let mut out = Vec::new();
+    let mut directory = Vec::new();
+    let mut count = 0u16;
+    for (name, data) in files {
+        let offset = out.len() as u32;
+        out.extend(LOCAL_HEADER.to_le_bytes());
+        common_fields(&mut out, name, data);
+        out.extend(name.as_bytes());
+        out.extend(data);
+
+        directory.extend(CENTRAL_HEADER.to_le_bytes());
+        directory.extend(VERSION.to_le_bytes());
+        common_fields(&mut directory, name, data);
+        // No comment, starts on disk 0, and no attributes
+        directory.extend([0; 10]);
+        directory.extend(offset.to_le_bytes());
+        directory.extend(name.as_bytes());
+        count += 1;
+    }
+
+    let directory_offset = out.len() as u32;
+    out.extend(&directory);
+    out.extend(END_OF_DIRECTORY.to_le_bytes());
+    // This disk and the one the directory starts on
+    out.extend([0; 4]);
+    out.extend(count.to_le_bytes());
+    out.extend(count.to_le_bytes());
+    out.extend((directory.len() as u32).to_le_bytes());
+    out.extend(directory_offset.to_le_bytes());
+    // No comment
+    out.extend(0u16.to_le_bytes());
+    out
+}
+
+fn u16_at(data: &[u8], offset: usize) -> Result<u16> {
+    let bytes = data
+        .get(offset..offset + 2)
+        .ok_or(anyhow!("Truncated zip"))?;
+    Ok(u16::from_le_bytes(bytes.try_into().unwrap()))
+}
+
+fn u32_at(data: &[u8], offset: usize) -> Result<u32> {
+    let bytes = data
+        .get(offset..offset + 4)
+        .ok_or(anyhow!("Truncated zip"))?;
+    Ok(u32::from_le_bytes(bytes.try_into().unwrap()))
+}
+
+fn bytes_at(data: &[u8], offset: usize, len: usize) -> Result<&[u8]> {
+    data.get(offset..offset + len)
+        .ok_or(anyhow!("Truncated zip"))
+}
+
+/// Whether `data` starts like a zip archive, to tell bundles apart from other files.
+pub fn is_zip(data: &[u8]) -> bool {
+    data.starts_with(&LOCAL_HEADER.to_le_bytes())
+}
+
+/// A file listed in the central directory, which has yet to be read.
+struct Entry {
+    name: String,
+    method: u16,
+    crc: u32,
+    compressed_len: usize,
+    len: usize,
+    /// Where its local header is
+    local: usize,
+}
+
+fn entries(data: &[u8]) -> Result<Vec<Entry>> {
+    // The end of the directory record is followed by a comment of up to 64 KiB
+    let end = (END_OF_DIRECTORY_LEN..=data.len().min(END_OF_DIRECTORY_LEN + 0xffff))
+        .map(|len| data.len() - len)
+        .find(|&offset| u32_at(data, offset).ok() == Some(END_OF_DIRECTORY))
+        .ok_or(anyhow!("Not a zip file"))?;
+    let count = u16_at(data, end + 10)?;
+    let mut offset = u32_at(data, end + 16)? as usize;
+
+    let mut entries = Vec::with_capacity(count.into());
+    for _ in 0..count {
+        ensure!(
+            u32_at(data, offset)? == CENTRAL_HEADER,
+            "Corrupt zip directory"
+        );
+        let name_len = u16_at(data, offset + 28)? as usize;
+        let extra_len = u16_at(data, offset + 30)? as usize;
+        let comment_len = u16_at(data, offset + 32)? as usize;
+        let name = bytes_at(data, offset + CENTRAL_HEADER_LEN, name_len)?;
+        entries.push(Entry {
+            name: String::from_utf8_lossy(name).into_owned(),
+            method: u16_at(data, offset + 10)?,
+            crc: u32_at(data, offset + 16)?,
+            compressed_len: u32_at(data, offset + 20)? as usize,
+            len: u32_at(data, offset + 24)? as usize,
+            local: u32_at(data, offset + 42)? as usize,
+        });
+        offset += CENTRAL_HEADER_LEN + name_len + extra_len + comment_len;
+    }
+    Ok(entries)
+}
+
+fn extract(data: &[u8], entry: &Entry) -> Result<Vec<u8>> {
+    let Entry {
+        name,
+        method,
+        crc,
+        compressed_len,
+        len,
+        local,
+    } = entry;
+    ensure!(u32_at(data, *local)? == LOCAL_HEADER, "Corrupt zip entry");
+    let start = local
+        + LOCAL_HEADER_LEN
+        + u16_at(data, local + 26)? as usize
+        + u16_at(data, local + 28)? as usize;
+    let compressed =
+        bytes_at(data, start, *compressed_len).with_context(|| format!("{name} is cut off"))?;
+    let contents = match *method {
+        STORED => compressed.to_vec(),
+        DEFLATED => miniz_oxide::inflate::decompress_to_vec_with_limit(compressed, *len)
+            .map_err(|error| anyhow!("{name} is corrupt: {error}"))?,
+        method => bail!("{name} is compressed with method {method}, which isn't supported"),
+    };
+    ensure!(
+        contents.len() == *len && crc32(&contents) == *crc,
+        "{name} is corrupt"
+    );
+    Ok(contents)
+}
+
+/// The names and contents of the files in a zip archive, in the order they're listed.
+pub fn read(data: &[u8]) -> Result<Vec<(String, Vec<u8>)>> {
+    entries(data)?
+        .into_iter()
+        .map(|entry| {
+            let contents = extract(data, &entry)?;
+            Ok((entry.name, contents))
+        })
+        .collect()
+}
+
+/// The name and contents of the first file in a zip archive whose name passes `filter`, if any.
+pub fn find(data: &[u8], filter: impl Fn(&str) -> bool) -> Result<Option<(String, Vec<u8>)>> {
+    let Some(entry) = entries(data)?.into_iter().find(|entry| filter(&entry.name)) else {
+        return Ok(None);
+    };
+    let contents = extract(data, &entry)?;
+    Ok(Some((entry.name, contents)))
+}
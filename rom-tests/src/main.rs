@@ -0,0 +1,166 @@
+// SPDX-License-Identifier: GPL-3.0-or-later
+// Copyright (C) 2023 Robert Hrusecky <jadedpastabowl@gmail.com>
+
+//! Runs a directory of blargg's and Mooneye's test ROMs on every core, and prints how they went.
+//! It can also write JSON and JUnit reports, so that CI can keep track of which pass over time.
+
+use std::{
+    fmt::Write as _,
+    fs,
+    path::{Path, PathBuf},
+    process::ExitCode,
+    time::Instant,
+};
+
+use anyhow::{Context, Result};
+use clap::Parser;
+use iron_boy_core::test_roms::{self, Outcome, TestResult};
+use serde_json::{json, Value};
+
+#[derive(Parser)]
+#[command(author, version, about, long_about = None)]
+struct Options {
+    /// Directory to search for .gb and .gbc files
+    dir: PathBuf,
+    /// How many ROMs to run at once [default: one per thread]
+    #[arg(short, long)]
+    jobs: Option<usize>,
+    /// How long to give each ROM to finish, in frames
+    #[arg(long, default_value_t = test_roms::MAX_FRAMES)]
+    max_frames: usize,
+    /// Write a JSON report here
+    #[arg(long)]
+    json: Option<PathBuf>,
+    /// Write a JUnit XML report here
+    #[arg(long)]
+    junit: Option<PathBuf>,
+}
+
+/// The path under the searched directory, to keep reports the same across machines.
+fn name(dir: &Path, result: &TestResult) -> String {
+    let path = result.path.strip_prefix(dir).unwrap_or(&result.path);
+    path.to_string_lossy().replace('\\', "/")
+}
+
+fn failures(results: &[TestResult]) -> usize {
+    results
+        .iter()
+        .filter(|result| result.outcome != Outcome::Passed)
+        .count()
+}
+
+fn json_report(dir: &Path, results: &[TestResult]) -> Value {
+    let entries: Vec<_> = results
+        .iter()
+        .map(|result| {
+            let (outcome, details) = match &result.outcome {
+                Outcome::Passed => ("passed", ""),
+                Outcome::Failed(details) => ("failed", details.as_str()),
+                Outcome::TimedOut => ("timed_out", ""),
+            };
+            json!({
+                "name": name(dir, result),
+                "outcome": outcome,
+                "details": details,
+                "seconds": result.duration.as_secs_f64(),
+            })
+        })
+        .collect();
+    json!({
+        "passed": results.len() - failures(results),
+        "total": results.len(),
+        "results": entries,
+    })
+}
+
+fn escape_xml(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&apos;"),
+            // Not allowed in XML at all, and test ROMs print whatever they like
+            c if c.is_control() && !matches!(c, '\n' | '\t') => escaped.push('\u{fffd}'),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+/// One test suite of every ROM, named by their paths.
+fn junit_report(dir: &Path, results: &[TestResult]) -> String {
+    let failures = failures(results);
+    let seconds: f64 = results
+        .iter()
+        .map(|result| result.duration.as_secs_f64())
+        .sum();
+    let mut xml = String::from("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n");
+    let _ = writeln!(
+        xml,
+        "<testsuite name=\"test-roms\" tests=\"{}\" failures=\"{failures}\" time=\"{seconds:.3}\">",
+        results.len(),
+    );
+    for result in results {
+        let _ = write!(
+            xml,
+            "  <testcase name=\"{}\" time=\"{:.3}\"",
+            escape_xml(&name(dir, result)),
+            result.duration.as_secs_f64(),
+        );
+        match &result.outcome {
+            Outcome::Passed => xml.push_str("/>\n"),
+            outcome => {
+                let _ = writeln!(
+                    xml,
+                    ">\n    <failure message=\"{}\"/>\n  </testcase>",
+                    escape_xml(&outcome.to_string()),
+                );
+            }
+        }
+    }
+    xml.push_str("</testsuite>\n");
+    xml
+}
+
+fn main() -> Result<ExitCode> {
+    let options = Options::parse();
+    let jobs = options.jobs.unwrap_or_else(test_roms::default_jobs);
+    let start = Instant::now();
+    let results = test_roms::run_dir(&options.dir, options.max_frames, jobs)
+        .with_context(|| format!("Failed to run the ROMs in {:?}", options.dir))?;
+
+    for result in &results {
+        println!(
+            "{}: {} ({:.1}s)",
+            name(&options.dir, result),
+            result.outcome,
+            result.duration.as_secs_f64(),
+        );
+    }
+    let failures = failures(&results);
+    println!(
+        "{} of {} passed in {:.1}s",
+        results.len() - failures,
+        results.len(),
+        start.elapsed().as_secs_f64(),
+    );
+
+    if let Some(path) = &options.json {
+        let report = json_report(&options.dir, &results);
+        fs::write(path, format!("{report:#}\n"))
+            .with_context(|| format!("Failed to write {path:?}"))?;
+    }
+    if let Some(path) = &options.junit {
+        fs::write(path, junit_report(&options.dir, &results))
+            .with_context(|| format!("Failed to write {path:?}"))?;
+    }
+
+    Ok(if failures == 0 {
+        ExitCode::SUCCESS
+    } else {
+        ExitCode::FAILURE
+    })
+}
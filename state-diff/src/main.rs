@@ -0,0 +1,95 @@
+// SPDX-License-Identifier: GPL-3.0-or-later
+// Copyright (C) 2023 Robert Hrusecky <jadedpastabowl@gmail.com>
+
+//! Lists the bytes of RAM that changed between two save states of the same game, for finding
+//! where a game keeps things like health or money, e.g. to write cheats for it. Saving a state,
+//! changing one thing in game, and saving another narrows it down quickly.
+
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+use anyhow::{Context, Result};
+use clap::{Parser, ValueEnum};
+use iron_boy_core::{
+    cart::Cart,
+    symbols::Symbols,
+    system::{BootRom, CgbSystem, MemoryRegion},
+};
+
+#[derive(Parser)]
+#[command(author, version, about, long_about = None)]
+struct Options {
+    rom: PathBuf,
+    /// The state from before
+    a: PathBuf,
+    /// The state from after
+    b: PathBuf,
+    /// Symbol file to name addresses with, in RGBDS format
+    #[arg(short, long)]
+    sym: Option<PathBuf>,
+    /// Only compare these regions
+    #[arg(short, long, value_delimiter = ',')]
+    region: Vec<Region>,
+}
+
+#[derive(Clone, Copy, PartialEq, ValueEnum)]
+enum Region {
+    Vram,
+    Sram,
+    Wram,
+}
+
+impl From<Region> for MemoryRegion {
+    fn from(region: Region) -> Self {
+        match region {
+            Region::Vram => MemoryRegion::Vram,
+            Region::Sram => MemoryRegion::Sram,
+            Region::Wram => MemoryRegion::Wram,
+        }
+    }
+}
+
+fn load(rom: &[u8], state: &Path) -> Result<CgbSystem> {
+    let cart = Cart::from_rom(rom.into()).context("Failed to parse ROM")?;
+    // The state replaces everything the boot ROM would set up
+    let mut system = CgbSystem::with_boot_rom(cart, BootRom::skip());
+    let data = fs::read(state).with_context(|| format!("Failed to read {state:?}"))?;
+    system
+        .load_state(&data)
+        .with_context(|| format!("Failed to load {state:?}"))?;
+    Ok(system)
+}
+
+fn main() -> Result<()> {
+    let options = Options::parse();
+    let rom =
+        fs::read(&options.rom).with_context(|| format!("Failed to read {:?}", options.rom))?;
+    let symbols = match &options.sym {
+        Some(path) => Symbols::parse(
+            &fs::read_to_string(path).with_context(|| format!("Failed to read {path:?}"))?,
+        ),
+        None => Symbols::default(),
+    };
+    let regions: Vec<MemoryRegion> = options.region.iter().map(|&r| r.into()).collect();
+
+    let (a, b) = (load(&rom, &options.a)?, load(&rom, &options.b)?);
+    let changes: Vec<_> = a
+        .diff_memory(&b)
+        .into_iter()
+        .filter(|change| regions.is_empty() || regions.contains(&change.region))
+        .collect();
+    for change in &changes {
+        print!(
+            "{} {}  {:02x} -> {:02x}",
+            change.region, change.addr, change.before, change.after
+        );
+        match symbols.annotate(change.addr) {
+            Some(label) => println!("  {label}"),
+            None => println!(),
+        }
+    }
+    println!("{} bytes changed", changes.len());
+    Ok(())
+}